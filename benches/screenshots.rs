@@ -0,0 +1,92 @@
+//! Measures the time taken to scale game screenshots, which are mostly large areas of flat colour
+//! with detailed sprites on top.
+//!
+//! Run with `cargo bench --bench screenshots`.
+use std::fs::File;
+use std::hint::black_box;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 20;
+
+fn read_rgba(name: &str) -> (Vec<u8>, usize, usize) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test/images")
+        .join(name);
+    let mut decoder = png::Decoder::new(File::open(path).unwrap());
+    decoder.set_transformations(
+        png::Transformations::normalize_to_color8() | png::Transformations::ALPHA,
+    );
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    buf.truncate(info.buffer_size());
+    (buf, info.width as usize, info.height as usize)
+}
+
+/// A 320x240 platformer scene: bands of sky, a tiled floor, a status bar and a few round sprites.
+fn platformer() -> (Vec<u8>, usize, usize) {
+    let (width, height): (usize, usize) = (320, 240);
+    let sprites = [(60, 150, 14), (170, 120, 10), (250, 160, 18)];
+    let image = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            let in_sprite = sprites
+                .iter()
+                .any(|&(sx, sy, r)| x.abs_diff(sx).pow(2) + y.abs_diff(sy).pow(2) <= r * r);
+            if y < 16 {
+                [20, 20, 20, 255]
+            } else if in_sprite {
+                [220, (x * 7 % 64) as u8 + 120, 40, 255]
+            } else if y < 192 {
+                let band = (y / 24) as u8;
+                [90 + band * 10, 150 + band * 8, 255, 255]
+            } else if x % 16 == 0 || y % 16 == 0 {
+                [60, 30, 10, 255]
+            } else {
+                [150, 90, 40, 255]
+            }
+        })
+        .collect();
+    (image, width, height)
+}
+
+/// Scales the image `RUNS` times, and prints the time per source pixel of the fastest run, which
+/// is the one least disturbed by anything else on the machine.
+fn bench(name: &str, (image, width, height): &(Vec<u8>, usize, usize), factor: usize) {
+    let config = xbrz::ScalerConfig::default();
+    let mut arena = xbrz::ScaleArena::new();
+    let mut scaled = vec![0; width * height * factor * factor * 4];
+    let fastest = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            xbrz::scale_rgba_into(
+                image,
+                *width,
+                *height,
+                factor,
+                &config,
+                &mut scaled,
+                &mut arena,
+            );
+            black_box(&scaled);
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or(Duration::MAX);
+    let per_pixel = fastest.as_nanos() as f64 / (width * height) as f64;
+    println!("{name:<24} {per_pixel:>8.1} ns/pixel");
+}
+
+fn main() {
+    let images = [
+        ("platformer", platformer()),
+        ("sample1", read_rgba("sample1.orig.png")),
+        ("sample2", read_rgba("sample2.orig.png")),
+    ];
+    for factor in [2, 4] {
+        for (name, image) in &images {
+            bench(&format!("{name} {factor}x"), image, factor);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Measures how many 16x16 sprites can be scaled per second, where the fixed cost of each call
+//! outweighs the cost of scaling the pixels themselves.
+//!
+//! Each result is compared against the same sprites laid out side by side and scaled as one large
+//! image, which pays the fixed cost only once. That is as fast as cutting the fixed cost of each
+//! call can ever make scaling sprites one at a time.
+//!
+//! Run with `cargo bench --bench small_images`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SPRITES: usize = 1024;
+const SIZE: usize = 16;
+const RUNS: usize = 20;
+const ROUNDS_PER_RUN: usize = 4;
+
+/// Pixel art made up of a few flat colours with diagonal edges, different for each seed.
+fn sprite(seed: usize) -> Vec<u8> {
+    (0..SIZE * SIZE)
+        .flat_map(|i| {
+            let (x, y) = (i % SIZE, i / SIZE);
+            match (x + y + seed) % 11 < 4 || (x * 3 + seed).is_multiple_of(7) {
+                true => [40, 90, 200, 255],
+                false if (y + seed).is_multiple_of(5) => [230, 200, 60, 255],
+                false => [0, 0, 0, 0],
+            }
+        })
+        .collect()
+}
+
+/// Runs `scale_all` over the sprites for a few seconds, and returns the sprites scaled per second in
+/// the fastest of its runs, which is the one least disturbed by anything else on the machine.
+fn bench(sprites: &[Vec<u8>], mut scale_all: impl FnMut(&[Vec<u8>])) -> f64 {
+    scale_all(sprites);
+    let fastest = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..ROUNDS_PER_RUN {
+                scale_all(sprites);
+            }
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or(Duration::MAX);
+    (ROUNDS_PER_RUN * sprites.len()) as f64 / fastest.as_secs_f64()
+}
+
+fn report(name: &str, per_second: f64, baseline: f64) {
+    println!(
+        "{name:<24} {per_second:>10.0} sprites/s {:>6.2}x the one image",
+        per_second / baseline
+    );
+}
+
+/// The sprites side by side in rows of `columns`, as one image.
+fn mosaic(sprites: &[Vec<u8>], columns: usize) -> Vec<u8> {
+    let width = columns * SIZE;
+    let mut image = vec![0; sprites.len() * SIZE * SIZE * 4];
+    for (i, sprite) in sprites.iter().enumerate() {
+        let (x, y) = (i % columns * SIZE, i / columns * SIZE);
+        for (row, pixels) in sprite.chunks_exact(SIZE * 4).enumerate() {
+            image[((y + row) * width + x) * 4..][..SIZE * 4].copy_from_slice(pixels);
+        }
+    }
+    image
+}
+
+fn main() {
+    let sprites: Vec<Vec<u8>> = (0..SPRITES).map(sprite).collect();
+    let columns = 32;
+    let (width, height) = (columns * SIZE, SPRITES / columns * SIZE);
+    let image = mosaic(&sprites, columns);
+    let config = xbrz::ScalerConfig {
+        max_threads: Some(1),
+        ..Default::default()
+    };
+
+    for factor in [2, 4] {
+        let mut arena = xbrz::ScaleArena::new();
+        let mut scaled = vec![0; width * height * factor * factor * 4];
+        let baseline = bench(&sprites, |_| {
+            xbrz::scale_rgba_into(
+                &image,
+                width,
+                height,
+                factor,
+                &config,
+                &mut scaled,
+                &mut arena,
+            );
+            black_box(&scaled);
+        });
+        report(&format!("one image {factor}x"), baseline, baseline);
+
+        let per_second = bench(&sprites, |sprites| {
+            for sprite in sprites {
+                black_box(xbrz::scale_rgba_with_config(
+                    sprite, SIZE, SIZE, factor, &config,
+                ));
+            }
+        });
+        report(&format!("scale_rgba {factor}x"), per_second, baseline);
+
+        let mut scaled = vec![0; SIZE * SIZE * factor * factor * 4];
+        let per_second = bench(&sprites, |sprites| {
+            for sprite in sprites {
+                xbrz::scale_rgba_into(sprite, SIZE, SIZE, factor, &config, &mut scaled, &mut arena);
+                black_box(&scaled);
+            }
+        });
+        report(&format!("scale_rgba_into {factor}x"), per_second, baseline);
+
+        let per_second = bench(&sprites, |sprites| {
+            let jobs: Vec<_> = sprites
+                .iter()
+                .map(|sprite| xbrz::ScaleJob::new(sprite, SIZE, SIZE, factor, &config))
+                .collect();
+            black_box(xbrz::scale_batch(&jobs));
+        });
+        report(&format!("scale_batch {factor}x"), per_second, baseline);
+    }
+}
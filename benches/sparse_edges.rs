@@ -0,0 +1,81 @@
+//! Measures the time taken to scale images with thin edges scattered over a shaded background,
+//! where most pixels have no blended corners and most of the rest have only one or two.
+//!
+//! Run with `cargo bench --bench sparse_edges`.
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 20;
+
+/// Line art: one pixel wide diagonal strokes and outlined boxes over a gentle gradient, which is
+/// never flat enough for whole runs to be filled but is too smooth to be blended.
+fn line_art() -> (Vec<u8>, usize, usize) {
+    let (width, height): (usize, usize) = (320, 240);
+    let image = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            let stroke = (x + 2 * y) % 97 == 0 || (3 * x).abs_diff(y * 2) % 131 == 0;
+            let outline = (x % 64 == 8 || y % 48 == 8) && x % 64 >= 8 && y % 48 >= 8;
+            if stroke || outline {
+                [30, 20, 60, 255]
+            } else {
+                [200 + (x / 8) as u8, 180 + (y / 8) as u8, 150, 255]
+            }
+        })
+        .collect();
+    (image, width, height)
+}
+
+/// Single pixel dots and short dashes on a flat background, as in a star field or a dithered sprite
+/// sheet, with edges in every direction.
+fn dots() -> (Vec<u8>, usize, usize) {
+    let (width, height): (usize, usize) = (320, 240);
+    let image = (0..width * height)
+        .flat_map(|i| {
+            let (x, y) = (i % width, i / width);
+            let hash = (i.wrapping_mul(2654435761) >> 11) % 64;
+            match hash {
+                0 => [255, 255, 220, 255],
+                1 if x % 2 == 0 => [120, 200, 255, 255],
+                _ => [10, 10, 30 + (y / 16) as u8, 255],
+            }
+        })
+        .collect();
+    (image, width, height)
+}
+
+/// Scales the image `RUNS` times, and prints the time per source pixel of the fastest run, which
+/// is the one least disturbed by anything else on the machine.
+fn bench(name: &str, (image, width, height): &(Vec<u8>, usize, usize), factor: usize) {
+    let config = xbrz::ScalerConfig::default();
+    let mut arena = xbrz::ScaleArena::new();
+    let mut scaled = vec![0; width * height * factor * factor * 4];
+    let fastest = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            xbrz::scale_rgba_into(
+                image,
+                *width,
+                *height,
+                factor,
+                &config,
+                &mut scaled,
+                &mut arena,
+            );
+            black_box(&scaled);
+            start.elapsed()
+        })
+        .min()
+        .unwrap_or(Duration::MAX);
+    let per_pixel = fastest.as_nanos() as f64 / (width * height) as f64;
+    println!("{name:<24} {per_pixel:>8.1} ns/pixel");
+}
+
+fn main() {
+    let images = [("line art", line_art()), ("dots", dots())];
+    for factor in [2, 4, 6] {
+        for (name, image) in &images {
+            bench(&format!("{name} {factor}x"), image, factor);
+        }
+    }
+}
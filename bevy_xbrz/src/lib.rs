@@ -0,0 +1,227 @@
+//! Bevy plugin that upscales pixel art textures with xBRZ as they are loaded.
+//!
+//! Textures are chosen by patterns matched against their asset paths, or one handle at a time:
+//!
+//! ```no_run
+//! use bevy_app::App;
+//! use bevy_xbrz::XbrzPlugin;
+//!
+//! App::new().add_plugins(XbrzPlugin::new().with_pattern("sprites/*.png", 4));
+//! ```
+//!
+//! Upscaled textures keep their format, and are scaled again when they are reloaded.
+//! Textures in formats other than 8-bit RGBA are converted to sRGB RGBA first.
+use std::collections::HashMap;
+
+use bevy_app::{App, Plugin, Update};
+use bevy_asset::{AssetEvent, AssetId, AssetServer, Assets};
+use bevy_ecs::prelude::*;
+use bevy_image::Image;
+use wgpu_types::TextureFormat;
+
+/// Adds the [`XbrzTextures`] resource, starting with the patterns given here, and upscales
+/// the textures it selects whenever they are loaded.
+#[derive(Debug, Default, Clone)]
+pub struct XbrzPlugin {
+    patterns: Vec<(String, usize)>,
+}
+
+impl XbrzPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Upscale every texture whose asset path matches `pattern` by `factor`. See
+    /// [`XbrzTextures::add_pattern`].
+    pub fn with_pattern(mut self, pattern: impl Into<String>, factor: usize) -> Self {
+        self.patterns.push((pattern.into(), factor));
+        self
+    }
+}
+
+impl Plugin for XbrzPlugin {
+    fn build(&self, app: &mut App) {
+        let mut textures = XbrzTextures::default();
+        for (pattern, factor) in &self.patterns {
+            textures.add_pattern(pattern.clone(), *factor);
+        }
+        app.insert_resource(textures)
+            .add_systems(Update, upscale_textures);
+    }
+}
+
+/// The textures to upscale, and by how much.
+#[derive(Resource, Debug, Default)]
+pub struct XbrzTextures {
+    patterns: Vec<(String, usize)>,
+    handles: HashMap<AssetId<Image>, usize>,
+    /// Textures already upscaled, and the size they were upscaled to, so that the change made by
+    /// upscaling them isn't taken for a reload.
+    upscaled: HashMap<AssetId<Image>, (u32, u32)>,
+    /// Handles added since their texture was loaded.
+    pending: Vec<AssetId<Image>>,
+}
+
+impl XbrzTextures {
+    /// Upscale every texture whose asset path, including any label after a `#`, matches `pattern`
+    /// by `factor`. A `*` in the pattern matches any run of characters, including `/`.
+    /// When several patterns match, the first one added is used.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn add_pattern(&mut self, pattern: impl Into<String>, factor: usize) {
+        assert!(
+            xbrz::ScaleFactor::new(factor).is_some(),
+            "scale factor must be between 1 and 6"
+        );
+        self.patterns.push((pattern.into(), factor));
+    }
+
+    /// Upscale the texture of `handle` by `factor`, instead of by any pattern it matches.
+    /// A texture that has already been loaded is upscaled straight away, but one that has already
+    /// been upscaled keeps its size until it is next reloaded. A factor of 1 leaves it as it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn set_factor(&mut self, handle: impl Into<AssetId<Image>>, factor: usize) {
+        assert!(
+            xbrz::ScaleFactor::new(factor).is_some(),
+            "scale factor must be between 1 and 6"
+        );
+        let id = handle.into();
+        self.handles.insert(id, factor);
+        self.pending.push(id);
+    }
+
+    fn factor(&self, id: AssetId<Image>, server: &AssetServer) -> usize {
+        if let Some(&factor) = self.handles.get(&id) {
+            return factor;
+        }
+        let Some(path) = server.get_path(id) else {
+            return 1;
+        };
+        let path = path.to_string();
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| matches_pattern(pattern, &path))
+            .map_or(1, |&(_, factor)| factor)
+    }
+}
+
+/// Whether `text` matches `pattern`, where a `*` in the pattern matches any run of characters.
+fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // no `*`, so the whole of the text must match
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Upscale `image` by `factor` in place. Returns `false`, leaving the image as it is, if it has
+/// no data on the CPU, or is compressed, layered or has mipmaps.
+pub fn upscale_image(image: &mut Image, factor: usize) -> bool {
+    let size = image.texture_descriptor.size;
+    if size.depth_or_array_layers != 1 || image.texture_descriptor.mip_level_count != 1 {
+        return false;
+    }
+    if !matches!(
+        image.texture_descriptor.format,
+        TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb
+    ) {
+        match image.convert(TextureFormat::Rgba8UnormSrgb) {
+            Some(converted) => *image = converted,
+            None => return false,
+        }
+    }
+    let Some(data) = &image.data else {
+        return false;
+    };
+
+    let (width, height) = (size.width as usize, size.height as usize);
+    image.data = Some(xbrz::scale_rgba(data, width, height, factor));
+    image.texture_descriptor.size.width *= factor as u32;
+    image.texture_descriptor.size.height *= factor as u32;
+    true
+}
+
+fn upscale_textures(
+    mut events: EventReader<AssetEvent<Image>>,
+    mut images: ResMut<Assets<Image>>,
+    mut textures: ResMut<XbrzTextures>,
+    server: Res<AssetServer>,
+) {
+    let mut loaded: Vec<AssetId<Image>> = std::mem::take(&mut textures.pending);
+    for event in events.read() {
+        match *event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => loaded.push(id),
+            AssetEvent::Removed { id } => {
+                textures.upscaled.remove(&id);
+            }
+            _ => {}
+        }
+    }
+
+    for id in loaded {
+        let factor = textures.factor(id, &server);
+        let Some(image) = images.get(id) else {
+            continue;
+        };
+        if factor == 1 || textures.upscaled.get(&id) == Some(&(image.width(), image.height())) {
+            continue;
+        }
+        let image = images.get_mut(id).expect("image was just found");
+        if upscale_image(image, factor) {
+            let size = (image.width(), image.height());
+            textures.upscaled.insert(id, size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy_asset::RenderAssetUsages;
+    use wgpu_types::{Extent3d, TextureDimension};
+
+    #[test]
+    fn upscales_rgba_textures() {
+        let size = Extent3d {
+            width: 3,
+            height: 2,
+            depth_or_array_layers: 1,
+        };
+        let mut image = Image::new_fill(
+            size,
+            TextureDimension::D2,
+            &[10, 20, 30, 255],
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        assert!(upscale_image(&mut image, 4));
+        assert_eq!((image.width(), image.height()), (12, 8));
+        assert_eq!(image.data.as_ref().unwrap().len(), 12 * 8 * 4);
+        assert_eq!(
+            image.texture_descriptor.format,
+            TextureFormat::Rgba8UnormSrgb
+        );
+
+        assert!(matches_pattern("sprites/*.png", "sprites/enemies/bat.png"));
+        assert!(matches_pattern("*#tiles", "sheet.png#tiles"));
+        assert!(!matches_pattern("sprites/*.png", "ui/button.png"));
+        assert!(!matches_pattern("a.png", "a.png.bak"));
+    }
+}
@@ -0,0 +1,37 @@
+//! Embeds the small colour distance tables for the default Rec.2020 matrix in the crate when the
+//! `static_lut` feature is enabled, so that they don't need to be worked out when a process first
+//! scales an image.
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+#[allow(dead_code)]
+#[path = "src/ycbcr_lookup/distance.rs"]
+mod distance;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/ycbcr_lookup/distance.rs");
+    if env::var_os("CARGO_FEATURE_STATIC_LUT").is_none() {
+        return;
+    }
+
+    // the tables are written out as array literals; floats are printed with enough digits to be
+    // read back exactly
+    let dists: Vec<f32> = (0..0x8000)
+        .map(|i| distance::small_table_dist(distance::REC_2020, i) as f32)
+        .collect();
+    let mut source = String::from("static SMALL_LUT: [f32; 0x8000] = [");
+    for d in &dists {
+        write!(source, "{d:?},").unwrap();
+    }
+    source.push_str("];\nstatic SMALL_FIXED_LUT: [u16; 0x8000] = [");
+    for &d in &dists {
+        write!(source, "{},", distance::to_fixed(d)).unwrap();
+    }
+    source.push_str("];\n");
+
+    let out_dir = env::var_os("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("small_lut.rs"), source).unwrap();
+}
@@ -0,0 +1,24 @@
+//! GStreamer plugin exposing the xBRZ scaler as the `xbrzscale` video filter element.
+//!
+//! ```text
+//! gst-launch-1.0 videotestsrc ! video/x-raw,width=80,height=60 ! xbrzscale factor=4 ! autovideosink
+//! ```
+use gst::glib;
+
+mod xbrzscale;
+
+fn plugin_init(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    xbrzscale::register(plugin)
+}
+
+gst::plugin_define!(
+    xbrz,
+    env!("CARGO_PKG_DESCRIPTION"),
+    plugin_init,
+    concat!(env!("CARGO_PKG_VERSION"), "-", env!("COMMIT_ID")),
+    "GPL",
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_NAME"),
+    env!("CARGO_PKG_REPOSITORY"),
+    env!("BUILD_REL_DATE")
+);
@@ -0,0 +1,268 @@
+use std::sync::{LazyLock, Mutex};
+
+use gst::glib;
+use gst::prelude::*;
+use gst::subclass::prelude::*;
+use gst_base::subclass::prelude::*;
+use gst_video::subclass::prelude::*;
+use xbrz::{ScaleArena, ScalerConfig};
+
+static CAT: LazyLock<gst::DebugCategory> = LazyLock::new(|| {
+    gst::DebugCategory::new(
+        "xbrzscale",
+        gst::DebugColorFlags::empty(),
+        Some("xBRZ pixel art upscaler"),
+    )
+});
+
+const DEFAULT_FACTOR: u32 = 2;
+
+#[derive(Debug, Copy, Clone)]
+struct Settings {
+    factor: u32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            factor: DEFAULT_FACTOR,
+        }
+    }
+}
+
+/// What the element keeps from one frame to the next, so that scaling a frame allocates nothing.
+#[derive(Default)]
+struct State {
+    config: ScalerConfig,
+    arena: ScaleArena,
+    /// A copy of the input frame without the padding at the end of each row, for inputs whose
+    /// rows are padded.
+    packed: Vec<u8>,
+}
+
+#[derive(Default)]
+pub struct XbrzScale {
+    settings: Mutex<Settings>,
+    state: Mutex<State>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for XbrzScale {
+    const NAME: &'static str = "GstXbrzScale";
+    type Type = super::XbrzScale;
+    type ParentType = gst_video::VideoFilter;
+}
+
+impl ObjectImpl for XbrzScale {
+    fn properties() -> &'static [glib::ParamSpec] {
+        static PROPERTIES: LazyLock<Vec<glib::ParamSpec>> = LazyLock::new(|| {
+            vec![glib::ParamSpecUInt::builder("factor")
+                .nick("Factor")
+                .blurb("Integer scaling factor")
+                .minimum(1)
+                .maximum(6)
+                .default_value(DEFAULT_FACTOR)
+                .mutable_ready()
+                .build()]
+        });
+
+        PROPERTIES.as_ref()
+    }
+
+    fn set_property(&self, _id: usize, value: &glib::Value, pspec: &glib::ParamSpec) {
+        match pspec.name() {
+            "factor" => {
+                let mut settings = self.settings.lock().unwrap();
+                let factor = value.get().expect("type checked upstream");
+                gst::info!(
+                    CAT,
+                    imp = self,
+                    "Changing factor from {} to {}",
+                    settings.factor,
+                    factor
+                );
+                settings.factor = factor;
+                drop(settings);
+                self.obj().reconfigure_src();
+            }
+            _ => unimplemented!(),
+        }
+    }
+
+    fn property(&self, _id: usize, pspec: &glib::ParamSpec) -> glib::Value {
+        match pspec.name() {
+            "factor" => self.settings.lock().unwrap().factor.to_value(),
+            _ => unimplemented!(),
+        }
+    }
+}
+
+impl GstObjectImpl for XbrzScale {}
+
+impl ElementImpl for XbrzScale {
+    fn metadata() -> Option<&'static gst::subclass::ElementMetadata> {
+        static ELEMENT_METADATA: LazyLock<gst::subclass::ElementMetadata> = LazyLock::new(|| {
+            gst::subclass::ElementMetadata::new(
+                "xBRZ scaler",
+                "Filter/Effect/Converter/Video/Scaler",
+                "Upscales pixel art video by an integer factor using the xBRZ algorithm",
+                "Thomas Bell <github.com/bell345>",
+            )
+        });
+
+        Some(&*ELEMENT_METADATA)
+    }
+
+    fn pad_templates() -> &'static [gst::PadTemplate] {
+        static PAD_TEMPLATES: LazyLock<Vec<gst::PadTemplate>> = LazyLock::new(|| {
+            let caps = gst_video::VideoCapsBuilder::new()
+                .format(gst_video::VideoFormat::Rgba)
+                .build();
+
+            let src_pad_template = gst::PadTemplate::new(
+                "src",
+                gst::PadDirection::Src,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            let sink_pad_template = gst::PadTemplate::new(
+                "sink",
+                gst::PadDirection::Sink,
+                gst::PadPresence::Always,
+                &caps,
+            )
+            .unwrap();
+
+            vec![src_pad_template, sink_pad_template]
+        });
+
+        PAD_TEMPLATES.as_ref()
+    }
+}
+
+/// Apply `map` to the width and height fields of every structure in `caps`,
+/// whether they are fixed values or ranges.
+fn map_dimensions(caps: &gst::Caps, map: impl Fn(i32) -> i32) -> gst::Caps {
+    let mut caps = caps.clone();
+    for s in caps.make_mut().iter_mut() {
+        for field in ["width", "height"] {
+            if let Ok(v) = s.get::<i32>(field) {
+                s.set(field, map(v).max(1));
+            } else if let Ok(range) = s.get::<gst::IntRange<i32>>(field) {
+                s.set(
+                    field,
+                    gst::IntRange::new(map(range.min()).max(1), map(range.max()).max(1)),
+                );
+            }
+        }
+    }
+    caps
+}
+
+impl BaseTransformImpl for XbrzScale {
+    const MODE: gst_base::subclass::BaseTransformMode =
+        gst_base::subclass::BaseTransformMode::NeverInPlace;
+    const PASSTHROUGH_ON_SAME_CAPS: bool = false;
+    const TRANSFORM_IP_ON_PASSTHROUGH: bool = false;
+
+    fn transform_caps(
+        &self,
+        direction: gst::PadDirection,
+        caps: &gst::Caps,
+        filter: Option<&gst::Caps>,
+    ) -> Option<gst::Caps> {
+        let factor = self.settings.lock().unwrap().factor as i32;
+
+        let other_caps = if direction == gst::PadDirection::Sink {
+            map_dimensions(caps, |v| v.saturating_mul(factor))
+        } else {
+            map_dimensions(caps, |v| v / factor)
+        };
+
+        gst::debug!(
+            CAT,
+            imp = self,
+            "Transformed caps from {} to {} in direction {:?}",
+            caps,
+            other_caps,
+            direction
+        );
+
+        if let Some(filter) = filter {
+            Some(filter.intersect_with_mode(&other_caps, gst::CapsIntersectMode::First))
+        } else {
+            Some(other_caps)
+        }
+    }
+}
+
+impl VideoFilterImpl for XbrzScale {
+    fn set_info(
+        &self,
+        incaps: &gst::Caps,
+        in_info: &gst_video::VideoInfo,
+        outcaps: &gst::Caps,
+        out_info: &gst_video::VideoInfo,
+    ) -> Result<(), gst::LoggableError> {
+        // size the scratch memory for the negotiated width up front, rather than on the first frame
+        let mut state = self.state.lock().unwrap();
+        state.arena = ScaleArena::with_max_width(in_info.width() as usize);
+        state.packed = Vec::new();
+        drop(state);
+
+        self.parent_set_info(incaps, in_info, outcaps, out_info)
+    }
+
+    fn transform_frame(
+        &self,
+        in_frame: &gst_video::VideoFrameRef<&gst::BufferRef>,
+        out_frame: &mut gst_video::VideoFrameRef<&mut gst::BufferRef>,
+    ) -> Result<gst::FlowSuccess, gst::FlowError> {
+        let factor = self.settings.lock().unwrap().factor as usize;
+
+        let width = in_frame.width() as usize;
+        let height = in_frame.height() as usize;
+        if out_frame.width() as usize != width * factor
+            || out_frame.height() as usize != height * factor
+        {
+            gst::element_imp_error!(
+                self,
+                gst::CoreError::Negotiation,
+                ["Output frame is not {}x the size of the input frame", factor]
+            );
+            return Err(gst::FlowError::NotNegotiated);
+        }
+
+        let in_stride = in_frame.plane_stride()[0] as usize;
+        let in_data = in_frame.plane_data(0).unwrap();
+        let out_stride = out_frame.plane_stride()[0] as usize;
+        let out_data = out_frame.plane_data_mut(0).unwrap();
+
+        let mut state = self.state.lock().unwrap();
+        let State {
+            config,
+            arena,
+            packed,
+        } = &mut *state;
+
+        // the scaler expects tightly packed rows
+        let row_len = width * 4;
+        let source = if in_stride == row_len {
+            &in_data[..row_len * height]
+        } else {
+            packed.clear();
+            for row in in_data.chunks(in_stride).take(height) {
+                packed.extend_from_slice(&row[..row_len]);
+            }
+            &packed[..]
+        };
+
+        xbrz::scale_rgba_into_with_stride(
+            source, width, height, factor, config, out_data, out_stride, arena,
+        );
+
+        Ok(gst::FlowSuccess::Ok)
+    }
+}
@@ -0,0 +1,18 @@
+use gst::glib;
+use gst::prelude::*;
+
+mod imp;
+
+glib::wrapper! {
+    pub struct XbrzScale(ObjectSubclass<imp::XbrzScale>)
+        @extends gst_video::VideoFilter, gst_base::BaseTransform, gst::Element, gst::Object;
+}
+
+pub fn register(plugin: &gst::Plugin) -> Result<(), glib::BoolError> {
+    gst::Element::register(
+        Some(plugin),
+        "xbrzscale",
+        gst::Rank::NONE,
+        XbrzScale::static_type(),
+    )
+}
@@ -0,0 +1,65 @@
+use gst::prelude::*;
+
+fn init() {
+    use std::sync::Once;
+    static INIT: Once = Once::new();
+
+    INIT.call_once(|| {
+        gst::init().unwrap();
+        gstxbrz::plugin_register_static().expect("xbrz plugin registers");
+    });
+}
+
+/// A harness around an `xbrzscale` element scaling by `factor`.
+fn harness(factor: u32) -> gst_check::Harness {
+    init();
+    let h = gst_check::Harness::new("xbrzscale");
+    h.element().unwrap().set_property("factor", factor);
+    h
+}
+
+#[test]
+fn upstream_is_asked_for_smaller_frames() {
+    let mut h = harness(4);
+    h.set_sink_caps_str("video/x-raw,format=RGBA,width=48,height=32,framerate=30/1");
+
+    let sink_pad = h.element().unwrap().static_pad("sink").unwrap();
+    let caps = sink_pad.query_caps(None);
+    let s = caps.structure(0).unwrap();
+    assert_eq!(s.get::<i32>("width").unwrap(), 12);
+    assert_eq!(s.get::<i32>("height").unwrap(), 8);
+}
+
+#[test]
+fn frames_are_scaled_into_the_negotiated_size() {
+    let (width, height, factor) = (7, 5, 3);
+    let mut h = harness(factor as u32);
+    h.set_src_caps_str(&format!(
+        "video/x-raw,format=RGBA,width={width},height={height},framerate=30/1"
+    ));
+
+    let pixels: Vec<u8> = (0..width * height)
+        .flat_map(|i| match (i % width + i / width) % 3 {
+            0 => [255, 255, 255, 255],
+            1 => [200, 40, 40, 255],
+            _ => [0, 0, 0, 255],
+        })
+        .collect();
+    let scaled = h
+        .push_and_pull(gst::Buffer::from_slice(pixels.clone()))
+        .unwrap();
+
+    let caps = h.sinkpad().unwrap().current_caps().unwrap();
+    let info = gst_video::VideoInfo::from_caps(&caps).unwrap();
+    assert_eq!(
+        (info.width() as usize, info.height() as usize),
+        (width * factor, height * factor)
+    );
+
+    let expected = xbrz::scale_rgba(&pixels, width, height, factor);
+    let row_len = width * factor * 4;
+    let stride = info.stride()[0] as usize;
+    let map = scaled.map_readable().unwrap();
+    let rows: Vec<&[u8]> = map.chunks(stride).map(|row| &row[..row_len]).collect();
+    assert_eq!(rows, expected.chunks_exact(row_len).collect::<Vec<_>>());
+}
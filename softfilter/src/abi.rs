@@ -0,0 +1,99 @@
+//! Declarations mirroring RetroArch's `softfilter.h`.
+use std::ffi::{c_char, c_float, c_int, c_uint, c_void};
+
+pub const SOFTFILTER_API_VERSION: c_uint = 2;
+
+pub const SOFTFILTER_FMT_NONE: c_uint = 0;
+pub const SOFTFILTER_FMT_RGB565: c_uint = 1 << 0;
+pub const SOFTFILTER_FMT_XRGB8888: c_uint = 1 << 1;
+
+pub type SoftfilterSimdMask = c_uint;
+
+#[repr(C)]
+pub struct SoftfilterConfig {
+    pub get_float:
+        Option<unsafe extern "C" fn(*mut c_void, *const c_char, *mut c_float, c_float) -> c_int>,
+    pub get_int:
+        Option<unsafe extern "C" fn(*mut c_void, *const c_char, *mut c_int, c_int) -> c_int>,
+    pub get_hex:
+        Option<unsafe extern "C" fn(*mut c_void, *const c_char, *mut c_uint, c_uint) -> c_int>,
+    pub get_float_array: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            *const c_char,
+            *mut *mut c_float,
+            *mut c_uint,
+            *const c_float,
+            c_uint,
+        ) -> c_int,
+    >,
+    pub get_int_array: Option<
+        unsafe extern "C" fn(
+            *mut c_void,
+            *const c_char,
+            *mut *mut c_int,
+            *mut c_uint,
+            *const c_int,
+            c_uint,
+        ) -> c_int,
+    >,
+    pub get_string: Option<
+        unsafe extern "C" fn(*mut c_void, *const c_char, *mut *mut c_char, *const c_char) -> c_int,
+    >,
+    pub free: Option<unsafe extern "C" fn(*mut c_void)>,
+}
+
+pub type SoftfilterWork = unsafe extern "C" fn(data: *mut c_void, thread_data: *mut c_void);
+
+#[repr(C)]
+pub struct SoftfilterWorkPacket {
+    pub work: Option<SoftfilterWork>,
+    pub thread_data: *mut c_void,
+}
+
+#[repr(C)]
+pub struct SoftfilterImplementation {
+    pub query_input_formats: Option<extern "C" fn() -> c_uint>,
+    pub query_output_formats: Option<extern "C" fn(input_format: c_uint) -> c_uint>,
+    pub create: Option<
+        unsafe extern "C" fn(
+            config: *const SoftfilterConfig,
+            in_fmt: c_uint,
+            out_fmt: c_uint,
+            max_width: c_uint,
+            max_height: c_uint,
+            threads: c_uint,
+            simd: SoftfilterSimdMask,
+            userdata: *mut c_void,
+        ) -> *mut c_void,
+    >,
+    pub destroy: Option<unsafe extern "C" fn(data: *mut c_void)>,
+    pub query_num_threads: Option<extern "C" fn(data: *mut c_void) -> c_uint>,
+    pub query_output_size: Option<
+        unsafe extern "C" fn(
+            data: *mut c_void,
+            out_width: *mut c_uint,
+            out_height: *mut c_uint,
+            width: c_uint,
+            height: c_uint,
+        ),
+    >,
+    pub get_work_packets: Option<
+        unsafe extern "C" fn(
+            data: *mut c_void,
+            packets: *mut SoftfilterWorkPacket,
+            output: *mut c_void,
+            output_stride: usize,
+            input: *const c_void,
+            width: c_uint,
+            height: c_uint,
+            input_stride: usize,
+        ),
+    >,
+    pub ident: *const c_char,
+    pub short_ident: *const c_char,
+    pub api_version: c_uint,
+}
+
+// SAFETY: the implementation table is immutable and its string pointers refer to static data
+unsafe impl Sync for SoftfilterImplementation {}
@@ -0,0 +1,253 @@
+//! RetroArch softfilter plugin exposing the xBRZ scaler as a CPU video filter.
+//!
+//! Build with `cargo build --release` and copy the resulting shared library, along with
+//! `xbrz.filt`, into RetroArch's `filters/video` directory.
+//! The scaling factor is read from the `xbrz_factor` key of the filter config.
+use std::ffi::{c_uint, c_void};
+use std::{ptr, slice};
+
+use xbrz::libretro::{scale_frame, RetroPixelFormat};
+
+use crate::abi::*;
+
+pub mod abi;
+
+const DEFAULT_FACTOR: usize = 3;
+
+/// Pointers to the frame currently being filtered, captured by `get_work_packets`.
+struct Frame {
+    output: *mut u8,
+    output_stride: usize,
+    input: *const u8,
+    input_stride: usize,
+    width: usize,
+    height: usize,
+}
+
+struct Filter {
+    factor: usize,
+    format: c_uint,
+    frame: Frame,
+}
+
+impl Filter {
+    fn retro_format(&self) -> RetroPixelFormat {
+        if self.format == SOFTFILTER_FMT_RGB565 {
+            RetroPixelFormat::Rgb565
+        } else {
+            RetroPixelFormat::Xrgb8888
+        }
+    }
+}
+
+extern "C" fn query_input_formats() -> c_uint {
+    SOFTFILTER_FMT_RGB565 | SOFTFILTER_FMT_XRGB8888
+}
+
+extern "C" fn query_output_formats(input_format: c_uint) -> c_uint {
+    input_format
+}
+
+unsafe extern "C" fn create(
+    config: *const SoftfilterConfig,
+    in_fmt: c_uint,
+    _out_fmt: c_uint,
+    _max_width: c_uint,
+    _max_height: c_uint,
+    _threads: c_uint,
+    _simd: SoftfilterSimdMask,
+    userdata: *mut c_void,
+) -> *mut c_void {
+    if in_fmt != SOFTFILTER_FMT_RGB565 && in_fmt != SOFTFILTER_FMT_XRGB8888 {
+        return ptr::null_mut();
+    }
+
+    let mut factor = DEFAULT_FACTOR as i32;
+    if let Some(get_int) = config.as_ref().and_then(|c| c.get_int) {
+        get_int(
+            userdata,
+            c"factor".as_ptr(),
+            &mut factor,
+            DEFAULT_FACTOR as i32,
+        );
+    }
+
+    let filter = Box::new(Filter {
+        factor: factor.clamp(2, 6) as usize,
+        format: in_fmt,
+        frame: Frame {
+            output: ptr::null_mut(),
+            output_stride: 0,
+            input: ptr::null(),
+            input_stride: 0,
+            width: 0,
+            height: 0,
+        },
+    });
+    Box::into_raw(filter) as *mut c_void
+}
+
+unsafe extern "C" fn destroy(data: *mut c_void) {
+    if !data.is_null() {
+        drop(Box::from_raw(data as *mut Filter));
+    }
+}
+
+extern "C" fn query_num_threads(_data: *mut c_void) -> c_uint {
+    1
+}
+
+unsafe extern "C" fn query_output_size(
+    data: *mut c_void,
+    out_width: *mut c_uint,
+    out_height: *mut c_uint,
+    width: c_uint,
+    height: c_uint,
+) {
+    let filter = &*(data as *const Filter);
+    *out_width = width * filter.factor as c_uint;
+    *out_height = height * filter.factor as c_uint;
+}
+
+unsafe extern "C" fn work(data: *mut c_void, _thread_data: *mut c_void) {
+    let filter = &*(data as *const Filter);
+    if filter.frame.width == 0 || filter.frame.height == 0 {
+        return;
+    }
+
+    let Frame {
+        output,
+        output_stride,
+        input,
+        input_stride,
+        width,
+        height,
+    } = filter.frame;
+    let format = filter.retro_format();
+    let row_len = width * format.bytes_per_pixel();
+
+    let input = slice::from_raw_parts(input, input_stride * (height - 1) + row_len);
+    let scaled = scale_frame(input, width, height, input_stride, format, filter.factor);
+
+    for (y, row) in scaled.data.chunks_exact(scaled.pitch).enumerate() {
+        let dst = slice::from_raw_parts_mut(output.add(y * output_stride), scaled.pitch);
+        dst.copy_from_slice(row);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+unsafe extern "C" fn get_work_packets(
+    data: *mut c_void,
+    packets: *mut SoftfilterWorkPacket,
+    output: *mut c_void,
+    output_stride: usize,
+    input: *const c_void,
+    width: c_uint,
+    height: c_uint,
+    input_stride: usize,
+) {
+    let filter = &mut *(data as *mut Filter);
+    filter.frame = Frame {
+        output: output as *mut u8,
+        output_stride,
+        input: input as *const u8,
+        input_stride,
+        width: width as usize,
+        height: height as usize,
+    };
+
+    *packets = SoftfilterWorkPacket {
+        work: Some(work),
+        thread_data: ptr::null_mut(),
+    };
+}
+
+static IMPLEMENTATION: SoftfilterImplementation = SoftfilterImplementation {
+    query_input_formats: Some(query_input_formats),
+    query_output_formats: Some(query_output_formats),
+    create: Some(create),
+    destroy: Some(destroy),
+    query_num_threads: Some(query_num_threads),
+    query_output_size: Some(query_output_size),
+    get_work_packets: Some(get_work_packets),
+    ident: c"xBRZ".as_ptr(),
+    short_ident: c"xbrz".as_ptr(),
+    api_version: SOFTFILTER_API_VERSION,
+};
+
+/// Entry point looked up by RetroArch when loading the filter.
+#[no_mangle]
+pub extern "C" fn softfilter_get_implementation(
+    _simd: SoftfilterSimdMask,
+) -> *const SoftfilterImplementation {
+    &IMPLEMENTATION
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_padded_frame() {
+        let implementation = unsafe { &*softfilter_get_implementation(0) };
+        assert_eq!(SOFTFILTER_API_VERSION, implementation.api_version);
+
+        let (width, height, factor) = (3usize, 2usize, DEFAULT_FACTOR);
+        let input_stride = 16;
+        let output_stride = width * factor * 4 + 8;
+        let input = vec![0xAAu8; input_stride * height];
+        let mut output = vec![0u8; output_stride * height * factor];
+
+        unsafe {
+            let data = implementation.create.unwrap()(
+                ptr::null(),
+                SOFTFILTER_FMT_XRGB8888,
+                SOFTFILTER_FMT_XRGB8888,
+                width as c_uint,
+                height as c_uint,
+                1,
+                0,
+                ptr::null_mut(),
+            );
+            assert!(!data.is_null());
+
+            let (mut out_width, mut out_height) = (0, 0);
+            implementation.query_output_size.unwrap()(
+                data,
+                &mut out_width,
+                &mut out_height,
+                width as c_uint,
+                height as c_uint,
+            );
+            assert_eq!(
+                (width * factor, height * factor),
+                (out_width as usize, out_height as usize)
+            );
+
+            let mut packet = SoftfilterWorkPacket {
+                work: None,
+                thread_data: ptr::null_mut(),
+            };
+            implementation.get_work_packets.unwrap()(
+                data,
+                &mut packet,
+                output.as_mut_ptr() as *mut c_void,
+                output_stride,
+                input.as_ptr() as *const c_void,
+                width as c_uint,
+                height as c_uint,
+                input_stride,
+            );
+            packet.work.unwrap()(data, packet.thread_data);
+            implementation.destroy.unwrap()(data);
+        }
+
+        for row in output.chunks_exact(output_stride) {
+            let (pixels, padding) = row.split_at(width * factor * 4);
+            assert!(pixels
+                .chunks_exact(4)
+                .all(|p| p == [0xAA, 0xAA, 0xAA, 0x00]));
+            assert!(padding.iter().all(|&b| b == 0));
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Scaling into memory from an allocator of the caller's own, such as a game engine's frame allocator.
+use allocator_api2::alloc::Allocator;
+use allocator_api2::vec::Vec;
+
+use crate::arena::Scratch;
+use crate::blend::Blend2x2;
+use crate::error::check_dimensions;
+use crate::pixel::Rgba8;
+use crate::ScalerConfig;
+
+/// The scaler's scratch memory, taken from an allocator.
+struct ScratchIn<A: Allocator>(Vec<Blend2x2, A>);
+
+impl<A: Allocator> Scratch for ScratchIn<A> {
+    fn pre_proc_buf(&mut self, src_width: usize) -> &mut [Blend2x2] {
+        if self.0.len() < src_width {
+            self.0.resize(src_width, Blend2x2::default());
+        }
+        &mut self.0[..src_width]
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, allocating the scaled image
+/// and all scratch memory from `alloc`.
+///
+/// This is the same as [`scale_rgba_with_config`](crate::scale_rgba_with_config), except that
+/// nothing is allocated on the global heap, so that engines can hand out memory from a bump or
+/// frame allocator and drop it all at once at the end of the frame. Allocators are given through
+/// the [`allocator_api2`] crate, which re-exports the standard library's unstable `Allocator` trait
+/// when its `nightly` feature is enabled. The image is scaled on the calling thread. With the
+/// `forbid_unsafe` feature, the images themselves are copied as they are scaled, which allocates on
+/// the global heap.
+///
+/// ```
+/// use allocator_api2::alloc::Global;
+///
+/// let (width, height) = (8, 8);
+/// let source = vec![255u8; width * height * 4];
+///
+/// let config = xbrz::ScalerConfig::default();
+/// let scaled = xbrz::scale_rgba_in(&source, width, height, 2, &config, Global);
+/// assert_eq!(scaled.len(), width * 2 * height * 2 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_in<A: Allocator + Clone>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    alloc: A,
+) -> Vec<u8, A> {
+    let dst_len =
+        check_dimensions(src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+    let mut scratch = ScratchIn(Vec::new_in(alloc.clone()));
+    let mut scaled = Vec::with_capacity_in(dst_len, alloc);
+    scaled.resize(dst_len, 0);
+    crate::scale_into::<Rgba8>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        config,
+        &mut scaled,
+        src_width * factor * 4,
+        &mut scratch,
+    );
+    scaled
+}
+
+// implementing an allocator to count allocations takes unsafe code
+#[cfg(all(test, not(feature = "forbid_unsafe")))]
+mod tests {
+    use super::*;
+    use allocator_api2::alloc::{AllocError, Global, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    /// Counts the allocations made through it.
+    #[derive(Clone)]
+    struct Counting<'a>(&'a Cell<usize>);
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn scales_with_memory_from_the_allocator() {
+        let (width, height) = (40, 12);
+        let source: std::vec::Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width + 2 * (i / width)) % 5 {
+                0 | 1 => [255, 255, 255, 255],
+                2 => [200, 40, 40, 255],
+                _ => [0, 0, 0, 255],
+            })
+            .collect();
+        let config = ScalerConfig::default();
+
+        let allocations = Cell::new(0);
+        let scaled = scale_rgba_in(&source, width, height, 3, &config, Counting(&allocations));
+        assert_eq!(
+            &scaled[..],
+            crate::scale_rgba_with_config(&source, width, height, 3, &config)
+        );
+        // the scaled image, and the scratch memory for an image wider than an arena holds inline
+        assert_eq!(allocations.get(), 2);
+    }
+}
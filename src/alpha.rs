@@ -0,0 +1,150 @@
+/// `255 * 2^16 / alpha`, rounded up, for turning a division by alpha into a multiplication.
+/// Rounding up makes the result exact for every colour and alpha, including halves, which round up.
+const UNPREMULTIPLY_FACTORS: [u32; 256] = {
+    let mut factors = [0; 256];
+    let mut alpha = 1;
+    while alpha < 256 {
+        factors[alpha] = (255u32 << 16).div_ceil(alpha as u32);
+        alpha += 1;
+    }
+    factors
+};
+
+/// `x / 255`, rounded to the nearest integer, for any `x` up to `255 * 255`.
+#[inline(always)]
+fn div_255(x: u16) -> u8 {
+    let x = x + 128;
+    ((x + (x >> 8)) >> 8) as u8
+}
+
+fn premultiply_pixels(rgba: &mut [u8]) {
+    for px in rgba.chunks_exact_mut(4) {
+        let alpha = px[3] as u16;
+        px[0] = div_255(px[0] as u16 * alpha);
+        px[1] = div_255(px[1] as u16 * alpha);
+        px[2] = div_255(px[2] as u16 * alpha);
+    }
+}
+
+/// Premultiply 4 pixels at a time with SSE2, which every x86-64 processor has.
+#[cfg(all(target_arch = "x86_64", not(feature = "forbid_unsafe")))]
+fn premultiply_pixels_sse2(rgba: &mut [u8]) {
+    use std::arch::x86_64::*;
+
+    let mut blocks = rgba.chunks_exact_mut(16);
+    for block in &mut blocks {
+        unsafe {
+            let pixels = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            let zero = _mm_setzero_si128();
+            let alpha_lanes = _mm_set_epi16(-1, 0, 0, 0, -1, 0, 0, 0);
+
+            // the same as div_255, on the channels of two pixels widened to 16 bits each
+            let premultiply = |px: __m128i| {
+                let alpha = _mm_shufflehi_epi16(_mm_shufflelo_epi16(px, 0xFF), 0xFF);
+                // the alpha channel is multiplied by 255, so that it comes out as it went in
+                let factors = _mm_or_si128(
+                    _mm_andnot_si128(alpha_lanes, alpha),
+                    _mm_and_si128(alpha_lanes, _mm_set1_epi16(255)),
+                );
+                let x = _mm_add_epi16(_mm_mullo_epi16(px, factors), _mm_set1_epi16(128));
+                _mm_srli_epi16(_mm_add_epi16(x, _mm_srli_epi16(x, 8)), 8)
+            };
+            let low = premultiply(_mm_unpacklo_epi8(pixels, zero));
+            let high = premultiply(_mm_unpackhi_epi8(pixels, zero));
+            _mm_storeu_si128(
+                block.as_mut_ptr() as *mut __m128i,
+                _mm_packus_epi16(low, high),
+            );
+        }
+    }
+    premultiply_pixels(blocks.into_remainder());
+}
+
+/// Multiply the colour channels of each pixel of an RGBA image by its alpha, in place, converting
+/// from straight alpha, as taken and returned by [`scale_rgba`](crate::scale_rgba), to premultiplied alpha,
+/// as used by most compositors.
+///
+/// Each channel is rounded to the nearest value. Any trailing bytes that don't make up a whole pixel
+/// are left as they are. On x86-64, several pixels are converted at once with SIMD instructions,
+/// unless the `forbid_unsafe` feature is enabled.
+pub fn premultiply_alpha(rgba: &mut [u8]) {
+    #[cfg(all(target_arch = "x86_64", not(feature = "forbid_unsafe")))]
+    premultiply_pixels_sse2(rgba);
+    #[cfg(not(all(target_arch = "x86_64", not(feature = "forbid_unsafe"))))]
+    premultiply_pixels(rgba);
+}
+
+/// Divide the colour channels of each pixel of an RGBA image by its alpha, in place, converting
+/// from premultiplied alpha back to straight alpha.
+///
+/// Each channel is rounded to the nearest value, and limited to 255 where it was larger than the alpha.
+/// Fully transparent pixels become `[0, 0, 0, 0]`. Any trailing bytes that don't make up a whole pixel
+/// are left as they are.
+pub fn unpremultiply_alpha(rgba: &mut [u8]) {
+    for px in rgba.chunks_exact_mut(4) {
+        let factor = UNPREMULTIPLY_FACTORS[px[3] as usize];
+        let unpremultiply = |c: u8| ((c as u32 * factor + (1 << 15)) >> 16).min(255) as u8;
+        px[0] = unpremultiply(px[0]);
+        px[1] = unpremultiply(px[1]);
+        px[2] = unpremultiply(px[2]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_value_is_rounded_to_nearest() {
+        // every colour with every alpha, converted all at once so that the vectorised path is used
+        let image: Vec<u8> = (0..=255u8)
+            .flat_map(|alpha| (0..=255u8).flat_map(move |c| [c, c, 255 - c, alpha]))
+            .collect();
+        let round = |numerator: u32, denominator: u32| match denominator {
+            0 => 0,
+            _ => ((numerator + denominator / 2) / denominator).min(255) as u8,
+        };
+
+        let mut premultiplied = image.clone();
+        premultiply_alpha(&mut premultiplied);
+        let mut unpremultiplied = image.clone();
+        unpremultiply_alpha(&mut unpremultiplied);
+
+        for ((px, pre), un) in image
+            .chunks_exact(4)
+            .zip(premultiplied.chunks_exact(4))
+            .zip(unpremultiplied.chunks_exact(4))
+        {
+            let alpha = px[3] as u32;
+            for c in 0..3 {
+                let value = px[c] as u32;
+                assert_eq!(pre[c], round(value * alpha, 255), "{px:?}");
+                assert_eq!(un[c], round(value * 255, alpha), "{px:?}");
+            }
+            assert_eq!((pre[3], un[3]), (px[3], px[3]));
+        }
+    }
+
+    #[test]
+    fn simd_matches_scalar() {
+        // an odd length, so that some pixels are left over after the vectorised blocks
+        let image: Vec<u8> = (0..4099 * 4)
+            .map(|i: u32| (i.wrapping_mul(2654435761) >> 11) as u8)
+            .collect();
+        let mut scalar = image.clone();
+        premultiply_pixels(&mut scalar);
+        let mut simd = image;
+        premultiply_alpha(&mut simd);
+        assert_eq!(simd, scalar);
+    }
+
+    #[test]
+    fn round_trip_keeps_opaque_pixels() {
+        let mut image: Vec<u8> = (0..=255).flat_map(|c| [c, 255 - c, c / 2, 255]).collect();
+        let original = image.clone();
+        premultiply_alpha(&mut image);
+        assert_eq!(image, original);
+        unpremultiply_alpha(&mut image);
+        assert_eq!(image, original);
+    }
+}
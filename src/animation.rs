@@ -0,0 +1,377 @@
+//! Scaling the frames of GIF and APNG style animations, which only redraw part of the image each
+//! frame.
+use std::time::Duration;
+
+use crate::{ScalerConfig, TemporalScaler};
+
+/// What happens to the area of a frame once it has been shown, before the next frame is drawn.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Disposal {
+    /// The frame is left as it is, and the next frame is drawn over it.
+    #[default]
+    Keep,
+    /// The area of the frame is cleared to transparent.
+    Background,
+    /// The area of the frame is put back to how it was before the frame was drawn.
+    Previous,
+}
+
+/// How the pixels of a frame are combined with the image under them.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FrameBlend {
+    /// The frame replaces the pixels under it, transparent pixels included.
+    Source,
+    /// The frame is alpha blended over the pixels under it, so that its transparent pixels leave
+    /// them as they are. This is how every GIF frame is drawn.
+    #[default]
+    Over,
+}
+
+/// One frame of an animation: an RGBA image drawn at an offset within the whole animation,
+/// as in a GIF or APNG file.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct Frame {
+    /// The pixels of the frame, as a flat RGBA image of `width * height` pixels.
+    pub buffer: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    /// The offset of the frame from the top left of the animation.
+    pub left: usize,
+    pub top: usize,
+    /// How long the frame is shown for.
+    pub delay: Duration,
+    pub disposal: Disposal,
+    pub blend: FrameBlend,
+}
+
+/// Use the xBRZ algorithm to scale up every frame of an animation by an integer factor.
+///
+/// Frames that only cover part of the animation, or depend on the frames before them, can't be
+/// scaled on their own, as the edges of the part would be smoothed against transparency rather
+/// than the pixels around them. Instead, the frames are drawn out into whole images, following
+/// their offsets, blending and disposal, and each of these is scaled. Each scaled frame is then
+/// cut back down to the smallest rectangle that changed since the frame before, to keep the
+/// animation small once it's encoded.
+///
+/// The animation covers every frame, from the top left corner to the furthest right and bottom
+/// edges of any frame, and is transparent wherever no frame has been drawn. One frame is returned
+/// for each frame given, with the same delay, all of them to be drawn with [`Disposal::Keep`].
+/// Frames where every changed pixel is opaque, or drawn where nothing was before, are drawn with
+/// [`FrameBlend::Over`], and have their unchanged pixels made transparent, as a GIF encoder
+/// expects. Frames that make some pixels more transparent use [`FrameBlend::Source`], which GIF
+/// can't represent, but APNG can.
+///
+/// ```
+/// use std::time::Duration;
+/// use xbrz::{Frame, ScalerConfig};
+///
+/// // a 4x4 red square, then a 2x2 blue patch drawn over its top left corner
+/// let red = Frame {
+///     buffer: [255, 0, 0, 255].repeat(16),
+///     width: 4,
+///     height: 4,
+///     delay: Duration::from_millis(100),
+///     ..Frame::default()
+/// };
+/// let blue = Frame {
+///     buffer: [0, 0, 255, 255].repeat(4),
+///     width: 2,
+///     height: 2,
+///     ..red.clone()
+/// };
+///
+/// let scaled = xbrz::scale_animation(&[red, blue], 3, &ScalerConfig::default());
+/// assert_eq!((scaled[0].width, scaled[0].height), (12, 12));
+/// assert_eq!(scaled[1].delay, Duration::from_millis(100));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `buffer` of any frame is not exactly `width * height * 4` bytes long,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_animation(frames: &[Frame], factor: usize, config: &ScalerConfig) -> Vec<Frame> {
+    scale_frames(frames, factor, |canvas, width, height| {
+        crate::scale_rgba_with_config(canvas, width, height, factor, config)
+    })
+}
+
+/// Use the xBRZ algorithm to scale up every frame of an animation by an integer factor, keeping
+/// the edges of each frame steady from one frame to the next.
+///
+/// This is the same as [`scale_animation`], except that the frames are scaled with a
+/// [`TemporalScaler`], so that anti-aliased and dithered areas don't shimmer. Edges only change
+/// between frames when the colours favour the change by more than `margin`.
+///
+/// # Panics
+///
+/// Panics if the `buffer` of any frame is not exactly `width * height * 4` bytes long,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_animation_temporal(
+    frames: &[Frame],
+    factor: usize,
+    config: &ScalerConfig,
+    margin: f64,
+) -> Vec<Frame> {
+    let mut scaler = TemporalScaler::new(config.clone(), margin);
+    scale_frames(frames, factor, |canvas, width, height| {
+        scaler.scale_frame(canvas, width, height, factor)
+    })
+}
+
+/// Draw out each frame in full, scale it with `scale`, and cut it back down to what changed.
+fn scale_frames(
+    frames: &[Frame],
+    factor: usize,
+    mut scale: impl FnMut(&[u8], usize, usize) -> Vec<u8>,
+) -> Vec<Frame> {
+    for frame in frames {
+        assert_eq!(frame.buffer.len(), frame.width * frame.height * 4);
+    }
+    let width = frames.iter().map(|f| f.left + f.width).max().unwrap_or(0);
+    let height = frames.iter().map(|f| f.top + f.height).max().unwrap_or(0);
+
+    let mut canvas = vec![0u8; width * height * 4];
+    let mut previous = vec![0u8; width * factor * height * factor * 4];
+    let mut scaled_frames = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let saved = (frame.disposal == Disposal::Previous).then(|| canvas.clone());
+        draw(&mut canvas, width, frame);
+
+        let scaled = scale(&canvas, width, height);
+        scaled_frames.push(difference(
+            &previous,
+            &scaled,
+            width * factor,
+            height * factor,
+            frame.delay,
+        ));
+        previous = scaled;
+
+        match frame.disposal {
+            Disposal::Keep => {}
+            Disposal::Background => clear(&mut canvas, width, frame),
+            Disposal::Previous => canvas = saved.expect("canvas is saved before drawing"),
+        }
+    }
+    scaled_frames
+}
+
+/// The rows of the area of `frame` within an image `width` pixels wide, as byte ranges.
+fn frame_rows(width: usize, frame: &Frame) -> impl Iterator<Item = std::ops::Range<usize>> + '_ {
+    (frame.top..frame.top + frame.height).map(move |y| {
+        let start = (y * width + frame.left) * 4;
+        start..start + frame.width * 4
+    })
+}
+
+fn draw(canvas: &mut [u8], width: usize, frame: &Frame) {
+    let rows = frame_rows(width, frame).zip(frame.buffer.chunks_exact(frame.width * 4));
+    for (range, src_row) in rows {
+        let dst_row = &mut canvas[range];
+        match frame.blend {
+            FrameBlend::Source => dst_row.copy_from_slice(src_row),
+            FrameBlend::Over => {
+                for (dst, src) in dst_row.chunks_exact_mut(4).zip(src_row.chunks_exact(4)) {
+                    blend_over(dst, src);
+                }
+            }
+        }
+    }
+}
+
+/// Blend a pixel over another, with straight rather than premultiplied alpha.
+fn blend_over(dst: &mut [u8], src: &[u8]) {
+    let (src_alpha, dst_alpha) = (src[3] as u32, dst[3] as u32);
+    // the share of the pixel below that shows through, out of 255 * 255
+    let below = dst_alpha * (255 - src_alpha);
+    let alpha = src_alpha * 255 + below;
+    if alpha == 0 {
+        dst.fill(0);
+        return;
+    }
+    for c in 0..3 {
+        let colour = src[c] as u32 * src_alpha * 255 + dst[c] as u32 * below;
+        dst[c] = ((colour + alpha / 2) / alpha) as u8;
+    }
+    dst[3] = ((alpha + 127) / 255) as u8;
+}
+
+fn clear(canvas: &mut [u8], width: usize, frame: &Frame) {
+    for range in frame_rows(width, frame) {
+        canvas[range].fill(0);
+    }
+}
+
+/// Cut a scaled image down to the rectangle that changed since the image before it, as a frame
+/// to be drawn over that image.
+fn difference(before: &[u8], after: &[u8], width: usize, height: usize, delay: Duration) -> Frame {
+    let pixel = |image: &[u8], i: usize| -> [u8; 4] { image[i * 4..][..4].try_into().unwrap() };
+    let changed = |i: usize| pixel(before, i) != pixel(after, i);
+    let (mut left, mut top, mut right, mut bottom) = (width, height, 0, 0);
+    for i in (0..width * height).filter(|&i| changed(i)) {
+        let (x, y) = (i % width, i / width);
+        (left, top) = (left.min(x), top.min(y));
+        (right, bottom) = (right.max(x + 1), bottom.max(y + 1));
+    }
+    if left >= right {
+        // nothing changed, which still needs a frame to keep the timing, so leave a single
+        // transparent pixel
+        let (width, height) = (width.min(1), height.min(1));
+        return Frame {
+            buffer: vec![0; width * height * 4],
+            width,
+            height,
+            delay,
+            ..Frame::default()
+        };
+    }
+
+    let pixels: Vec<usize> = (top..bottom)
+        .flat_map(|y| (left..right).map(move |x| y * width + x))
+        .collect();
+    // blending over gives the new pixel exactly if it's opaque, or if it's drawn over nothing
+    let over = pixels
+        .iter()
+        .filter(|&&i| changed(i))
+        .all(|&i| after[i * 4 + 3] == 255 || (before[i * 4 + 3] == 0 && after[i * 4 + 3] > 0));
+    let buffer = pixels
+        .iter()
+        .flat_map(|&i| match over && !changed(i) {
+            // pixels drawn over the same colour can be left transparent
+            true => [0; 4],
+            false => pixel(after, i),
+        })
+        .collect();
+    Frame {
+        buffer,
+        width: right - left,
+        height: bottom - top,
+        left,
+        top,
+        delay,
+        disposal: Disposal::Keep,
+        blend: if over {
+            FrameBlend::Over
+        } else {
+            FrameBlend::Source
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(colour: [u8; 4], width: usize, height: usize) -> Frame {
+        Frame {
+            buffer: colour.repeat(width * height),
+            width,
+            height,
+            delay: Duration::from_millis(50),
+            ..Frame::default()
+        }
+    }
+
+    /// Draw the scaled frames out again, as a decoder would.
+    fn play(frames: &[Frame], width: usize, height: usize) -> Vec<Vec<u8>> {
+        let mut canvas = vec![0u8; width * height * 4];
+        frames
+            .iter()
+            .map(|frame| {
+                assert_eq!(frame.disposal, Disposal::Keep);
+                draw(&mut canvas, width, frame);
+                canvas.clone()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_are_drawn_out_before_scaling() {
+        let config = ScalerConfig::default();
+        let background = solid([200, 40, 40, 255], 6, 6);
+        // a patch that only covers the middle of the animation, then goes away again
+        let patch = Frame {
+            left: 2,
+            top: 1,
+            disposal: Disposal::Previous,
+            ..solid([20, 20, 120, 255], 2, 3)
+        };
+        let empty = Frame {
+            blend: FrameBlend::Over,
+            ..solid([0, 0, 0, 0], 1, 1)
+        };
+        let frames = [background.clone(), patch.clone(), empty.clone(), empty];
+
+        let scaled = scale_animation(&frames, 3, &config);
+        assert_eq!(scaled.len(), 4);
+        let played = play(&scaled, 18, 18);
+
+        let mut canvas = background.buffer.clone();
+        let first = crate::scale_rgba_with_config(&canvas, 6, 6, 3, &config);
+        draw(&mut canvas, 6, &patch);
+        let second = crate::scale_rgba_with_config(&canvas, 6, 6, 3, &config);
+        assert_eq!(played, [first.clone(), second, first.clone(), first]);
+
+        // the patch is cut down to the area around it that xBRZ changed
+        assert!(scaled[1].width < 18 && scaled[1].height < 18);
+        assert_eq!(scaled[1].blend, FrameBlend::Over);
+        assert_eq!((scaled[3].width, scaled[3].height), (1, 1));
+        assert_eq!(scaled[3].buffer, [0; 4]);
+    }
+
+    #[test]
+    fn clearing_pixels_replaces_them() {
+        let config = ScalerConfig::default();
+        let frames = [
+            Frame {
+                disposal: Disposal::Background,
+                ..solid([90, 160, 30, 255], 4, 4)
+            },
+            Frame {
+                blend: FrameBlend::Source,
+                ..solid([90, 160, 30, 255], 2, 2)
+            },
+        ];
+
+        let scaled = scale_animation(&frames, 2, &config);
+        assert_eq!(scaled[1].blend, FrameBlend::Source);
+        let played = play(&scaled, 8, 8);
+
+        let mut canvas = vec![0u8; 4 * 4 * 4];
+        draw(&mut canvas, 4, &frames[1]);
+        assert_eq!(
+            played[1],
+            crate::scale_rgba_with_config(&canvas, 4, 4, 2, &config)
+        );
+    }
+
+    #[test]
+    fn temporal_frames_are_drawn_the_same_way() {
+        let config = ScalerConfig::default();
+        let frames = [
+            solid([200, 40, 40, 255], 5, 5),
+            Frame {
+                left: 1,
+                top: 2,
+                ..solid([20, 20, 120, 255], 2, 2)
+            },
+        ];
+
+        // with no margin, every frame is scaled as it would be on its own
+        assert_eq!(
+            scale_animation_temporal(&frames, 2, &config, 0.0),
+            scale_animation(&frames, 2, &config)
+        );
+    }
+
+    #[test]
+    fn blending_over_mixes_by_alpha() {
+        let mut dst = [0, 0, 255, 255];
+        blend_over(&mut dst, &[255, 0, 0, 128]);
+        assert_eq!(dst, [128, 0, 127, 255]);
+
+        let mut dst = [10, 20, 30, 0];
+        blend_over(&mut dst, &[0, 0, 0, 0]);
+        assert_eq!(dst, [0; 4]);
+    }
+}
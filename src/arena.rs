@@ -0,0 +1,63 @@
+use smallvec::SmallVec;
+
+use crate::blend::Blend2x2;
+
+/// The widest image, or tile, whose preprocessing buffer fits inside the arena itself.
+const INLINE_WIDTH: usize = 32;
+
+/// Reusable scratch memory for the scaling functions.
+///
+/// Scaling an image requires a small amount of working memory proportional to the width of the source image.
+/// Functions such as [`scale_rgba`](crate::scale_rgba) allocate this memory on every call;
+/// functions taking a `&mut ScaleArena`, such as [`scale_rgba_into`](crate::scale_rgba_into), instead
+/// keep it in the arena, so that repeated calls perform no heap allocations once the arena has
+/// grown to fit the widest image. Images up to 32 pixels wide, such as sprites, need no heap memory
+/// at all, so a new arena costs nothing to create for them.
+/// With the `forbid_unsafe` feature, the images themselves are copied as they are scaled, which
+/// allocates on every call.
+///
+/// ```
+/// let config = xbrz::ScalerConfig::default();
+/// let mut arena = xbrz::ScaleArena::new();
+/// let mut scaled = vec![0u8; 16 * 16 * 4];
+///
+/// for frame in [[0u8; 8 * 8 * 4], [255u8; 8 * 8 * 4]] {
+///     xbrz::scale_rgba_into(&frame, 8, 8, 2, &config, &mut scaled, &mut arena);
+/// }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct ScaleArena {
+    pre_proc_buf: SmallVec<[Blend2x2; INLINE_WIDTH]>,
+}
+
+impl ScaleArena {
+    /// Creates an empty arena. No memory is allocated until it is first used.
+    pub const fn new() -> Self {
+        Self {
+            pre_proc_buf: SmallVec::new_const(),
+        }
+    }
+
+    /// Creates an arena with enough memory to scale images up to `max_width` pixels wide
+    /// without further allocation.
+    pub fn with_max_width(max_width: usize) -> Self {
+        Self {
+            pre_proc_buf: SmallVec::from_elem(Blend2x2::default(), max_width),
+        }
+    }
+}
+
+/// Somewhere for the scaler to take its scratch memory from.
+pub(crate) trait Scratch {
+    /// The preprocessing buffer, holding the corner blending decisions for one row of source pixels.
+    fn pre_proc_buf(&mut self, src_width: usize) -> &mut [Blend2x2];
+}
+
+impl Scratch for ScaleArena {
+    fn pre_proc_buf(&mut self, src_width: usize) -> &mut [Blend2x2] {
+        if self.pre_proc_buf.len() < src_width {
+            self.pre_proc_buf.resize(src_width, Blend2x2::default());
+        }
+        &mut self.pre_proc_buf[..src_width]
+    }
+}
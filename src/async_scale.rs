@@ -0,0 +1,145 @@
+//! Scaling on a background thread pool, for use from async code.
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::check_source;
+use crate::pixel::Rgba8;
+use crate::{scale_stripe_into, ScaleArena, ScalerConfig};
+
+/// The number of source rows scaled between checks of the cancellation token.
+const STRIPE_HEIGHT: usize = 16;
+
+/// A handle used to cancel an in-progress call to [`scale_rgba_async`].
+///
+/// Cloned tokens share the same state, so one clone can be handed to the scaling task while
+/// another is kept around to cancel it.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request that any scaling using this token stops as soon as possible.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// The error returned when scaling was stopped by a [`CancellationToken`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("scaling was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, without blocking the
+/// calling task.
+///
+/// This takes the same arguments as [`scale_rgba_with_config`](crate::scale_rgba_with_config),
+/// but the work is moved onto a thread pool reserved for blocking operations, which works with
+/// any async runtime. The image is scaled a few rows at a time, and `cancel` is checked before
+/// each batch of rows; once it is cancelled, the partially scaled image is discarded and
+/// [`Cancelled`] is returned.
+///
+/// # Panics
+///
+/// Panics if the `source` length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub async fn scale_rgba_async(
+    source: Vec<u8>,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: ScalerConfig,
+    cancel: CancellationToken,
+) -> Result<Vec<u8>, Cancelled> {
+    check_source(&source, src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+
+    blocking::unblock(move || {
+        let mut destination = vec![0u8; src_width * factor * src_height * factor * 4];
+        if src_width == 0 {
+            return Ok(destination);
+        }
+
+        let mut arena = ScaleArena::with_max_width(src_width);
+        let stripe_len = src_width * factor * STRIPE_HEIGHT * factor * 4;
+        for (i, dst_stripe) in destination.chunks_mut(stripe_len).enumerate() {
+            if cancel.is_cancelled() {
+                return Err(Cancelled);
+            }
+
+            let y_first = i * STRIPE_HEIGHT;
+            let y_last = (y_first + STRIPE_HEIGHT).min(src_height);
+            scale_stripe_into::<Rgba8>(
+                &source,
+                src_width,
+                src_height,
+                factor,
+                y_first..y_last,
+                &config,
+                dst_stripe,
+                &mut arena,
+            );
+        }
+
+        Ok(destination)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::scale_rgba;
+
+    #[test]
+    fn matches_synchronous_output() {
+        let (width, height) = (10, 40);
+        let source: Vec<u8> = (0..width * height * 4)
+            .map(|i| (i * 37 % 251) as u8)
+            .collect();
+
+        let scaled = block_on(scale_rgba_async(
+            source.clone(),
+            width,
+            height,
+            3,
+            ScalerConfig::default(),
+            CancellationToken::new(),
+        ));
+        assert_eq!(Ok(scale_rgba(&source, width, height, 3)), scaled);
+    }
+
+    #[test]
+    fn cancelled_token_stops_scaling() {
+        let cancel = CancellationToken::new();
+        cancel.clone().cancel();
+
+        let scaled = block_on(scale_rgba_async(
+            vec![0u8; 8 * 8 * 4],
+            8,
+            8,
+            2,
+            ScalerConfig::default(),
+            cancel,
+        ));
+        assert_eq!(Err(Cancelled), scaled);
+    }
+}
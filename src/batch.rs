@@ -0,0 +1,118 @@
+use crate::error::{check_dimensions, check_source};
+use crate::{PixelFormat, ScaleArena, ScaleError, ScaledImage, ScalerConfig};
+
+/// An RGBA image to be scaled by [`scale_batch`], along with how to scale it.
+#[derive(Debug, Copy, Clone)]
+pub struct ScaleJob<'a> {
+    /// The pixels of the image, which should be `width * height * 4` bytes long.
+    pub source: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    pub factor: usize,
+    pub config: &'a ScalerConfig,
+}
+
+impl<'a> ScaleJob<'a> {
+    pub fn new(
+        source: &'a [u8],
+        width: usize,
+        height: usize,
+        factor: usize,
+        config: &'a ScalerConfig,
+    ) -> Self {
+        Self {
+            source,
+            width,
+            height,
+            factor,
+            config,
+        }
+    }
+}
+
+/// Use the xBRZ algorithm to scale up many RGBA images, each with its own factor and config,
+/// returning the scaled images in the same order as the `jobs`.
+///
+/// With the `rayon` feature enabled, the jobs are shared out across rayon's thread pool, with each
+/// image scaled on a single thread. This keeps every thread busy with batches of small images, such
+/// as thousands of sprites, which are too small to be split up between threads themselves. Large
+/// images are better scaled one at a time with [`scale_rgba_with_config`](crate::scale_rgba_with_config).
+///
+/// A job that can't be scaled, because its source has the wrong length, its factor isn't one of
+/// 1, 2, 3, 4, 5 or 6, or its scaled image would be too large, gives a [`ScaleError`] without
+/// affecting the other jobs.
+pub fn scale_batch(jobs: &[ScaleJob<'_>]) -> Vec<Result<ScaledImage, ScaleError>> {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+
+        jobs.par_iter()
+            .map_init(ScaleArena::new, scale_job)
+            .collect()
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    {
+        let mut arena = ScaleArena::new();
+        jobs.iter().map(|job| scale_job(&mut arena, job)).collect()
+    }
+}
+
+fn scale_job(arena: &mut ScaleArena, job: &ScaleJob<'_>) -> Result<ScaledImage, ScaleError> {
+    let dst_len = check_dimensions(job.width, job.height, job.factor, 4)?;
+    check_source(job.source, job.width, job.height, job.factor, 4)?;
+    let mut data = vec![0; dst_len];
+    crate::scale_rgba_into(
+        job.source, job.width, job.height, job.factor, job.config, &mut data, arena,
+    );
+    let (width, height) = (job.width * job.factor, job.height * job.factor);
+    Ok(ScaledImage::new(data, width, height, PixelFormat::Rgba8)
+        .expect("scaled image has the scaled dimensions"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Preset;
+
+    #[test]
+    fn batch_matches_scaling_each_image() {
+        let sprites: Vec<Vec<u8>> = (0..40)
+            .map(|seed: usize| {
+                (0..16 * 16 * 4)
+                    .map(|i: usize| ((i ^ seed).wrapping_mul(2654435761) >> 9) as u8 & 0xE0)
+                    .collect()
+            })
+            .collect();
+        let configs = [
+            ScalerConfig::default(),
+            ScalerConfig::preset(Preset::Smooth),
+        ];
+        let mut jobs: Vec<ScaleJob> = sprites
+            .iter()
+            .enumerate()
+            .map(|(i, sprite)| ScaleJob::new(sprite, 16, 16, 2 + i % 5, &configs[i % 2]))
+            .collect();
+        // a job with the wrong size doesn't stop the others
+        jobs.insert(3, ScaleJob::new(&sprites[0], 16, 15, 2, &configs[0]));
+
+        let results = scale_batch(&jobs);
+        assert_eq!(results.len(), jobs.len());
+        for (job, result) in jobs.iter().zip(results) {
+            let expected = crate::try_scale_rgba_with_config(
+                job.source, job.width, job.height, job.factor, job.config,
+            );
+            match (result, expected) {
+                (Ok(image), Ok(expected)) => {
+                    assert_eq!(
+                        (image.width(), image.height()),
+                        (16 * job.factor, 16 * job.factor)
+                    );
+                    assert_eq!(image.into_vec(), expected);
+                }
+                (Err(e), Err(expected)) => assert_eq!(e, expected),
+                (result, expected) => panic!("{result:?} != {expected:?}"),
+            }
+        }
+    }
+}
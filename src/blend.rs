@@ -1,12 +1,18 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::fmt::{Debug, Formatter};
 
 use crate::kernel::Rotation;
 
-#[derive(Default, Copy, Clone, PartialEq, Eq)]
-pub(crate) enum BlendType {
+/// How strongly a corner of a source pixel is blended with its neighbours.
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendType {
+    /// The corner is not blended.
     #[default]
     None = 0,
+    /// The corner lies on an edge, and is blended.
     Normal,
+    /// The corner lies on an edge running much more strongly in one diagonal direction than the other,
+    /// and is blended along the whole length of the edge.
     Dominant,
 }
 
@@ -24,8 +30,13 @@ impl Debug for BlendType {
     }
 }
 
-#[derive(Default, Copy, Clone, PartialEq, Eq)]
-pub(crate) struct Blend2x2 {
+/// The blending decided for each of the four corners of a source pixel.
+///
+/// The [`Debug`] representation draws the corners as a 2x2 grid, with `.` for
+/// [`None`](BlendType::None), `N` for [`Normal`](BlendType::Normal) and `D` for [`Dominant`](BlendType::Dominant).
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Blend2x2 {
     // blend_f
     pub top_left: BlendType,
     // blend_g
@@ -48,7 +59,8 @@ impl Blend2x2 {
         *self = Self::default();
     }
 
-    pub(crate) fn blending_needed(&self) -> bool {
+    /// Whether any corner of the pixel is blended.
+    pub fn blending_needed(&self) -> bool {
         self != &Self::default()
     }
 
@@ -76,3 +88,49 @@ impl Blend2x2 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn blend_type() -> impl Strategy<Value = BlendType> {
+        prop_oneof![
+            Just(BlendType::None),
+            Just(BlendType::Normal),
+            Just(BlendType::Dominant),
+        ]
+    }
+
+    fn blend_2x2() -> impl Strategy<Value = Blend2x2> {
+        (blend_type(), blend_type(), blend_type(), blend_type()).prop_map(
+            |(top_left, top_right, bottom_left, bottom_right)| Blend2x2 {
+                top_left,
+                top_right,
+                bottom_left,
+                bottom_right,
+            },
+        )
+    }
+
+    proptest! {
+        #[test]
+        fn rotations_compose(blend in blend_2x2()) {
+            let cw90 = |b: Blend2x2| b.rotate(Rotation::Clockwise90);
+            prop_assert_eq!(blend.rotate(Rotation::None), blend);
+            prop_assert_eq!(blend.rotate(Rotation::Clockwise180), cw90(cw90(blend)));
+            prop_assert_eq!(blend.rotate(Rotation::Clockwise270), cw90(cw90(cw90(blend))));
+            prop_assert_eq!(cw90(cw90(cw90(cw90(blend)))), blend);
+        }
+
+        #[test]
+        fn each_rotation_views_a_different_corner(blend in blend_2x2()) {
+            // each corner is blended as the bottom right one of a rotated view: the top right at 90
+            // degrees, the top left at 180 and the bottom left at 270
+            prop_assert_eq!(blend.rotate(Rotation::Clockwise90).bottom_right, blend.top_right);
+            prop_assert_eq!(blend.rotate(Rotation::Clockwise180).bottom_right, blend.top_left);
+            prop_assert_eq!(blend.rotate(Rotation::Clockwise270).bottom_right, blend.bottom_left);
+        }
+    }
+}
@@ -0,0 +1,421 @@
+use std::ops::Range;
+
+use crate::blend::{Blend2x2, BlendType};
+use crate::config::{PixelConfig, ScalerConfig};
+use crate::kernel::Kernel4x4;
+use crate::oob_reader::{OobReader, OobReaderTransparent};
+use crate::pixel::{cast_pixels, Pixel, Rgba8};
+use crate::ycbcr_lookup::YCbCrLookup;
+
+/// A step of the walk over an image made by [`for_each_blend`] or [`for_each_cached_blend`].
+pub(crate) enum Visit<'k, P: Pixel> {
+    /// A single pixel, with the kernel centred on it and the blending decided for its corners.
+    Pixel(&'k Kernel4x4<P>, Blend2x2),
+    /// A run of this many pixels of the given colour, none of which have a blended corner, such as
+    /// part of a flat background. It can be filled in directly.
+    Run(P, usize),
+}
+
+/// Walk the source pixels in `x_range` and `y_range`, calling `visit` with each pixel, or run of
+/// flat pixels, and the position of the first pixel.
+///
+/// Pixels are visited in left to right, then top to bottom order, and `on_row` is called after
+/// the last pixel of each row. The `pre_proc_buf` must be as long as `x_range`. Each corner is
+/// decided with the config of the pixel above and to the left of it, measuring colour distances
+/// with `ycbcr`, which callers resolve once rather than on every tile.
+///
+/// Kernels that are all one colour, or fully transparent, are never blended, so they aren't
+/// preprocessed. Consecutive pixels with uniform kernels are visited as a single [`Visit::Run`],
+/// and the kernel is only checked one new column at a time along the run.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+pub(crate) fn for_each_blend<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+    source: &'src OOB::Source,
+    src_width: usize,
+    src_height: usize,
+    config: &C,
+    x_range: Range<usize>,
+    y_range: Range<usize>,
+    pre_proc_buf: &mut [Blend2x2],
+    ycbcr: &YCbCrLookup,
+    mut visit: impl FnMut(Visit<'_, P>, usize, usize),
+    mut on_row: Option<&mut (dyn FnMut() + '_)>,
+) {
+    let x_first = x_range.start;
+    let x_last = x_range.end.min(src_width);
+    let y_first = y_range.start;
+    let y_last = y_range.end.min(src_height);
+    assert!(x_first < x_last);
+    assert!(y_first < y_last);
+    assert!(src_width > 0);
+    assert!(src_height > 0);
+    assert_eq!(pre_proc_buf.len(), x_last - x_first);
+
+    // initialise preprocessing buffer for first row of current stripe:
+    // detect upper left and right corner blending
+    // this cannot be optimised for adjacent processing stripes; we must not allow for a
+    // memory race condition!
+    {
+        let oob_reader = OOB::new(source, src_width, src_height, y_first as isize - 1);
+        let mut kernel = Kernel4x4::init_row(&oob_reader, x_first as isize);
+
+        let Blend2x2 { bottom_right, .. } = kernel
+            .pre_process_corners(config.at(x_first as isize - 1, y_first as isize - 1), ycbcr);
+        pre_proc_buf[0].clear();
+        pre_proc_buf[0].top_left = bottom_right;
+
+        for x in x_first..x_last {
+            let i = x - x_first;
+            kernel.next_column(&oob_reader, x as isize);
+            let Blend2x2 {
+                bottom_right,
+                bottom_left,
+                ..
+            } = kernel.pre_process_corners(config.at(x as isize, y_first as isize - 1), ycbcr);
+            pre_proc_buf[i].top_right = bottom_left;
+
+            if x + 1 < x_last {
+                pre_proc_buf[i + 1].clear();
+                pre_proc_buf[i + 1].top_left = bottom_right;
+            }
+        }
+    }
+
+    for y in y_first..y_last {
+        let oob_reader = OOB::new(source, src_width, src_height, y as isize);
+        let mut kernel = Kernel4x4::init_row(&oob_reader, x_first as isize);
+
+        // corner blending for current (x, y + 1) position
+        let Blend2x2 {
+            bottom_right,
+            top_right,
+            ..
+        } = kernel.pre_process_corners(config.at(x_first as isize - 1, y as isize), ycbcr);
+        // set 1st known corner for (x_first, y + 1) and buffer for use on next column
+        let mut blend_xy1 = Blend2x2 {
+            top_left: bottom_right,
+            ..Default::default()
+        };
+        if oob_reader.is_left_edge(x_first as isize) {
+            // the reference implementation keeps this in the top left corner of the first column,
+            // rather than the bottom left, and its output depends on it
+            pre_proc_buf[0].top_left = top_right;
+        } else {
+            // set 3rd known corner for (x_first, y), as the tile to the left would have
+            pre_proc_buf[0].bottom_left = top_right;
+        }
+        // the first pixel and colour of a run of uniform kernels, which is filled in one go
+        let mut run: Option<(usize, P)> = None;
+        let mut flat_columns = kernel.flat_columns();
+
+        for x in x_first..x_last {
+            let i = x - x_first;
+            match run {
+                Some((_, colour)) => {
+                    // shifting a uniform kernel along leaves it the same, apart from its new column
+                    oob_reader.fill_dhlp(&mut kernel, x as isize);
+                    if [kernel.d, kernel.h, kernel.l, kernel.p] == [colour; 4] {
+                        pre_proc_buf[i] = Blend2x2::default();
+                        if x + 1 < x_last {
+                            pre_proc_buf[i + 1].bottom_left = BlendType::None;
+                        }
+                        continue;
+                    }
+                    flat_columns = kernel.next_flat_columns(4);
+                }
+                None => {
+                    kernel.next_column(&oob_reader, x as isize);
+                    flat_columns = kernel.next_flat_columns(flat_columns);
+                }
+            }
+
+            let mut blend_xy = pre_proc_buf[i];
+            let uniform = flat_columns == 4;
+            {
+                let Blend2x2 {
+                    top_left,
+                    top_right,
+                    bottom_left,
+                    bottom_right,
+                } = if uniform || kernel.is_transparent() {
+                    // the common case in backgrounds and sprites, which are mostly transparent
+                    Blend2x2::default()
+                } else {
+                    kernel.pre_process_corners(config.at(x as isize, y as isize), ycbcr)
+                };
+
+                // all four corners of (x, y) have been determined at this point
+                blend_xy.bottom_right = top_left;
+                // set 2nd known corner for (x, y + 1)
+                blend_xy1.top_right = bottom_left;
+                pre_proc_buf[i] = blend_xy1;
+
+                if x + 1 < x_last {
+                    blend_xy1.clear();
+                    // set 1st known corner for (x + 1, y + 1) and buffer for use on next column
+                    blend_xy1.top_left = bottom_right;
+                    // set 3rd known corner for (x + 1, y)
+                    pre_proc_buf[i + 1].bottom_left = top_right;
+                }
+            }
+
+            // every corner of the pixel is within its kernel, so none of them are blended either
+            if uniform {
+                // neighbouring kernels overlap, so a run of uniform kernels is all one colour
+                run.get_or_insert((x, kernel.f));
+                continue;
+            }
+            if let Some((start, colour)) = run.take() {
+                visit(Visit::Run(colour, x - start), start, y);
+            }
+            visit(Visit::Pixel(&kernel, blend_xy), x, y);
+        }
+        if let Some((start, colour)) = run {
+            visit(Visit::Run(colour, x_last - start), start, y);
+        }
+
+        if let Some(on_row) = on_row.as_deref_mut() {
+            on_row();
+        }
+    }
+}
+
+/// Walk the source pixels in the same way as [`for_each_blend`], but with the blending of each pixel
+/// taken from a `blend_map` computed beforehand for the whole image.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+pub(crate) fn for_each_cached_blend<'src, P: Pixel, OOB: OobReader<'src, P>>(
+    source: &'src OOB::Source,
+    src_width: usize,
+    src_height: usize,
+    x_range: Range<usize>,
+    y_range: Range<usize>,
+    blend_map: &[Blend2x2],
+    mut visit: impl FnMut(Visit<'_, P>, usize, usize),
+    mut on_row: Option<&mut (dyn FnMut() + '_)>,
+) {
+    let x_first = x_range.start;
+    let x_last = x_range.end.min(src_width);
+    let y_first = y_range.start;
+    let y_last = y_range.end.min(src_height);
+    assert!(x_first < x_last);
+    assert!(y_first < y_last);
+    assert_eq!(blend_map.len(), src_width * src_height);
+
+    for y in y_first..y_last {
+        let oob_reader = OOB::new(source, src_width, src_height, y as isize);
+        let mut kernel = Kernel4x4::init_row(&oob_reader, x_first as isize);
+        let blend_row = &blend_map[y * src_width + x_first..y * src_width + x_last];
+
+        for (x, &blend_xy) in (x_first..x_last).zip(blend_row) {
+            kernel.next_column(&oob_reader, x as isize);
+            visit(Visit::Pixel(&kernel, blend_xy), x, y);
+        }
+
+        if let Some(on_row) = on_row.as_deref_mut() {
+            on_row();
+        }
+    }
+}
+
+/// The blending decided for each pixel of an image, in the same order as the source pixels.
+pub(crate) fn blend_map_pixels<'src, P: Pixel, OOB: OobReader<'src, P>>(
+    source: &'src OOB::Source,
+    src_width: usize,
+    src_height: usize,
+    config: &ScalerConfig,
+) -> Vec<Blend2x2> {
+    let mut blend_map = vec![Blend2x2::default(); src_width * src_height];
+    if src_width == 0 || src_height == 0 {
+        return blend_map;
+    }
+
+    let mut pre_proc_buf = vec![Blend2x2::default(); src_width];
+    for_each_blend::<P, OOB, _>(
+        source,
+        src_width,
+        src_height,
+        config,
+        0..src_width,
+        0..src_height,
+        &mut pre_proc_buf,
+        config.lookup(),
+        |visit, x, y| match visit {
+            Visit::Pixel(_, blend) => blend_map[y * src_width + x] = blend,
+            Visit::Run(_, len) => blend_map[y * src_width + x..][..len].fill(Blend2x2::default()),
+        },
+        None,
+    );
+    blend_map
+}
+
+/// Work out how each corner of each pixel of an image would be blended, without scaling it.
+///
+/// The `source` is a flat RGBA image, as for [`scale_rgba`](crate::scale_rgba). The result holds
+/// one [`Blend2x2`] per source pixel, in the same left to right, then top to bottom order.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`.
+pub fn compute_blend_map(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    config: &ScalerConfig,
+) -> Vec<Blend2x2> {
+    assert_eq!(source.len(), src_width * src_height * 4);
+
+    let pixels = cast_pixels::<Rgba8>(source);
+    assert_eq!(pixels.len(), src_width * src_height);
+    blend_map_pixels::<Rgba8, OobReaderTransparent<Rgba8>>(&pixels, src_width, src_height, config)
+}
+
+/// Render the blending decided for each pixel of an image, to help with tuning [`ScalerConfig`].
+///
+/// Each source pixel becomes a 2x2 block in the returned RGBA image, one pixel per corner of the source pixel,
+/// so the image is `src_width * 2` by `src_height * 2` pixels.
+/// Corners that are blended as a dominant edge are drawn in red, corners with a normal blend are drawn in yellow,
+/// and corners that are not blended show the source pixel, greyed out and dimmed.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`.
+pub fn debug_blend_map(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    assert_eq!(source.len(), src_width * src_height * 4);
+
+    let blend_map = compute_blend_map(source, src_width, src_height, config);
+
+    let dest_width = src_width * 2;
+    let mut out = vec![0u8; dest_width * src_height * 2 * 4];
+    for (i, (blend, pixel)) in blend_map.iter().zip(source.chunks_exact(4)).enumerate() {
+        let (x, y) = (i % src_width, i / src_width);
+        let corners = [
+            (0, 0, blend.top_left),
+            (1, 0, blend.top_right),
+            (0, 1, blend.bottom_left),
+            (1, 1, blend.bottom_right),
+        ];
+        for (dx, dy, blend_type) in corners {
+            let colour = match blend_type {
+                BlendType::None => {
+                    let luma =
+                        (pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114)
+                            / 1000;
+                    let dimmed = (luma / 3) as u8;
+                    [dimmed, dimmed, dimmed, 255]
+                }
+                BlendType::Normal => [255, 220, 0, 255],
+                BlendType::Dominant => [255, 0, 0, 255],
+            };
+            let offset = ((y * 2 + dy) * dest_width + x * 2 + dx) * 4;
+            out[offset..offset + 4].copy_from_slice(&colour);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_image_has_no_blending() {
+        let source = [200, 100, 50, 255].repeat(6 * 5);
+        let map = debug_blend_map(&source, 6, 5, &ScalerConfig::default());
+
+        assert_eq!(12 * 10 * 4, map.len());
+        let luma = ((200 * 299 + 100 * 587 + 50 * 114) / 1000 / 3) as u8;
+        // the edges of the image border on transparent pixels, so only the middle is flat
+        for y in 4..6 {
+            for x in 4..8 {
+                let offset = (y * 12 + x) * 4;
+                assert_eq!([luma, luma, luma, 255], map[offset..offset + 4]);
+            }
+        }
+    }
+
+    #[test]
+    fn runs_match_preprocessing_every_pixel() {
+        // flat backgrounds broken up by a diagonal and stray dots, with transparent pixels of
+        // leftover colours
+        let (width, height) = (40, 24);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                match (x, y) {
+                    _ if x + y == 20 || x == y + 9 => [250, 250, 250, 255],
+                    (24, 3) | (27, 4..=5) | (3, 18) | (37, 20) => [0, 0, 0, 255],
+                    _ if (30..36).contains(&x) => [(x * 40) as u8, (y * 9) as u8, 7, 0],
+                    _ if y < 12 => [40, 90, 200, 255],
+                    _ => [150, 90, 40, 255],
+                }
+            })
+            .collect();
+        let config = ScalerConfig::default();
+        let pixels: Vec<Rgba8> = source
+            .chunks_exact(4)
+            .map(|px| Rgba8::from_rgba([px[0], px[1], px[2], px[3]]))
+            .collect();
+
+        // decide every corner of every pixel separately, from the kernel that it's the centre of
+        let corners = |x: isize, y: isize| {
+            let oob_reader = OobReaderTransparent::new(&pixels, width, height, y);
+            let mut kernel = Kernel4x4::init_row(&oob_reader, x);
+            kernel.next_column(&oob_reader, x);
+            kernel.pre_process_corners(&config, config.lookup())
+        };
+        let expected: Vec<Blend2x2> = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as isize, (i / width) as isize);
+                let top_right = corners(x, y - 1).bottom_left;
+                let bottom_right = corners(x, y).top_left;
+                match x {
+                    // the reference implementation keeps the corner to the left of the first
+                    // column in its top left corner
+                    0 => Blend2x2 {
+                        top_left: corners(x - 1, y).top_right,
+                        top_right,
+                        bottom_left: BlendType::None,
+                        bottom_right,
+                    },
+                    _ => Blend2x2 {
+                        top_left: corners(x - 1, y - 1).bottom_right,
+                        top_right,
+                        bottom_left: corners(x - 1, y).top_right,
+                        bottom_right,
+                    },
+                }
+            })
+            .collect();
+
+        assert_eq!(compute_blend_map(&source, width, height, &config), expected);
+        for factor in [2, 5] {
+            assert_eq!(
+                crate::scale_rgba_with_config(&source, width, height, factor, &config),
+                crate::scale_rgba_with_blend_map(
+                    &source, width, height, factor, &config, &expected
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn diagonal_edge_is_blended() {
+        let (width, height) = (8, 8);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match i % width > i / width {
+                true => [255, 255, 255, 255],
+                false => [0, 0, 0, 255],
+            })
+            .collect();
+
+        let blend_map = compute_blend_map(&source, width, height, &ScalerConfig::default());
+        assert!(blend_map[3 * width + 3].blending_needed());
+        assert!(!blend_map[6 * width + 1].blending_needed());
+    }
+}
@@ -1,17 +1,541 @@
+use crate::scaler::BlendTable;
+use crate::ycbcr_lookup::{YCbCrLookup, YCbCrMatrix};
+
+/// Tuning parameters for the xBRZ algorithm.
+///
+/// The [`Default`] values match those used by the original C++ implementation.
+/// Values tuned for other kinds of content are available through [`ScalerConfig::preset`].
+///
+/// With the `serde` feature enabled, configs are serialized along with a format version. Fields missing
+/// from a serialized config take the default value for that version, so configs saved by earlier releases
+/// keep their behaviour even when new fields are added or defaults change.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(
+        into = "serialized::SerializedConfig",
+        try_from = "serialized::SerializedConfig"
+    )
+)]
 pub struct ScalerConfig {
+    /// Maximum YCbCr distance between two colours for them to be considered equal.
     pub equal_color_tolerance: f64,
+    /// Weight given to the distance between the two central pixels when determining the direction of an edge.
     pub center_direction_bias: f64,
+    /// How many times stronger one diagonal direction must be than the other for its
+    /// edge to be blended as a dominant edge.
     pub dominant_direction_threshold: f64,
+    /// How many times stronger one edge must be than the other for it to be blended as a
+    /// steep or shallow line, instead of a 45 degree diagonal.
     pub steep_direction_threshold: f64,
+    /// Side length of the square tiles, in source pixels, that the image is processed in.
+    ///
+    /// Working through the image tile by tile keeps the destination rows being written in cache,
+    /// which matters for very wide images scaled by large factors. `None` picks a tile size
+    /// automatically based on the width of the image. The output is the same for any tile size.
+    pub tile_size: Option<usize>,
+    /// The most threads that the scaling of an image is shared between, when the `rayon` feature is
+    /// enabled. `None` uses every thread of the rayon pool, and `Some(1)` scales on a single thread.
+    ///
+    /// Applications that budget their threads, such as an emulator with its own audio and emulation
+    /// threads, can use this to keep scaling from taking over every core. Images are scaled on the
+    /// rayon pool that the call is made from, so calling a scaling function inside
+    /// `ThreadPool::install` scales on a pool of the application's own. The output is the same for
+    /// any number of threads.
+    pub max_threads: Option<usize>,
+    /// Keep thin outlines of a given colour from being thinned or broken up by blending. `None` treats
+    /// outlines like any other pixels.
+    pub preserve_outlines: Option<OutlineConfig>,
+    /// Stop pixels that are part of a dither pattern from being blended as lines.
+    ///
+    /// Checkerboard dithering contains many short diagonal edges, which would otherwise be smoothed
+    /// into blotchy diagonal streaks where the dithering meets other colours. A pixel is treated as
+    /// dithered when it is part of a checkerboard at least 2x3 pixels in size. Dithered pixels
+    /// still have their corners blended.
+    pub suppress_dither: bool,
+    /// Keep lines one pixel wide, such as ladders and wires, from being eaten into or doubled by blending.
+    ///
+    /// A pixel is treated as part of such a line when it is in a horizontal or vertical run of four
+    /// equal colours crossing the whole 4x4 kernel, with different colours on both sides of it. Corners
+    /// next to these lines are left unblended.
+    pub preserve_thin_lines: bool,
+    /// Maximum YCbCr distance between two nearly grey colours for them to be considered equal, used instead
+    /// of `equal_color_tolerance` when both colours are nearly grey. `None` uses `equal_color_tolerance`
+    /// for every colour.
+    ///
+    /// Anti-aliased text is drawn with many shades of grey along the edges of its glyphs, which a higher
+    /// tolerance keeps from being blended as edges of their own.
+    pub grey_tolerance: Option<f64>,
+    /// Leave glyph-like areas unblended: those where the 4x4 kernel around a pixel holds only two colours,
+    /// at least this YCbCr distance apart. `None` blends these areas like any other.
+    pub glyph_contrast: Option<f64>,
+    /// How strongly edges are blended, from 0.0 for the hard edges of nearest neighbour scaling to 1.0
+    /// for the full blending of the original implementation. Values outside of this range are clamped.
+    pub blend_strength: f32,
+    /// The set of weights that edges are blended with.
+    pub blend_weights: BlendWeights,
+    /// At a factor of 1, blend the corners of pixels on edges instead of copying the image as it is,
+    /// as a subtle anti-aliasing filter. Only corner blends are used, so no edge is moved or thickened.
+    /// Has no effect at other factors.
+    pub antialias_at_1x: bool,
+    /// How the alpha channel of the scaled image is produced.
+    pub alpha_mode: AlphaMode,
+    /// The YCbCr matrix that colour distances are measured in. Each matrix has a lookup table of
+    /// its own, built the first time it is used. Where configs vary across an image, the matrix of
+    /// the base config is used for all of it.
+    pub ycbcr_matrix: YCbCrMatrix,
 }
 
-impl Default for ScalerConfig {
+/// Describes the outlines kept intact by [`ScalerConfig::preserve_outlines`].
+///
+/// Where an outline pixel borders a pixel that is not part of an outline, only the corner of the outline pixel
+/// is blended, rather than a whole line along the edge, so that the outline keeps its full thickness.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OutlineConfig {
+    /// The RGB colour of the outlines.
+    pub color: [u8; 3],
+    /// Maximum YCbCr distance from `color` for an opaque pixel to be considered part of an outline.
+    pub tolerance: f64,
+}
+
+impl Default for OutlineConfig {
+    /// Black outlines, matching near-black pixels as well.
     fn default() -> Self {
         Self {
-            equal_color_tolerance: 30.0,
-            center_direction_bias: 4.0,
-            dominant_direction_threshold: 3.6,
-            steep_direction_threshold: 2.2,
+            color: [0, 0, 0],
+            tolerance: 30.0,
+        }
+    }
+}
+
+/// The sets of weights that [`ScalerConfig::blend_weights`] can blend edges with.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BlendWeights {
+    /// The weights of the original C++ implementation.
+    #[default]
+    Classic,
+    /// Spreads diagonal edges and corners over more of each scaled pixel, for softer shapes.
+    Soft,
+}
+
+/// The ways that [`ScalerConfig::alpha_mode`] can produce the alpha channel of the scaled image.
+///
+/// Colours are always blended by their alpha, so that transparent pixels don't tint their neighbours.
+/// The modes other than [`AlphaMode::Blend`] avoid semi-transparent fringes around sprites drawn
+/// by engines that can't blend alpha.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AlphaMode {
+    /// Alpha is blended along with the colour.
+    #[default]
+    Blend,
+    /// Blended alpha at or above the threshold becomes fully opaque, and below it fully transparent.
+    Threshold(u8),
+    /// Pixels given any coverage by blending take the largest alpha of the source pixel they were
+    /// scaled from and its eight neighbours, and the rest stay fully transparent.
+    Max,
+}
+
+/// Named sets of tuning parameters for common kinds of content.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Preset {
+    /// The parameters of the original C++ implementation, which suit most sprites and tiles.
+    #[default]
+    Faithful,
+    /// Blends fewer edges, keeping small details and textures crisp.
+    Sharp,
+    /// Treats more colours as equal and blends more edges, for softer shapes and smoother gradients.
+    Smooth,
+    /// Only blends clear, high contrast edges, so that thin strokes of UI text stay legible.
+    Text,
+    /// Keeps bitmap text and HUDs as sharp as the source, leaving two-colour glyphs unblended and
+    /// treating the grey shades of anti-aliased text as equal.
+    CrispText,
+}
+
+/// Images at most this wide are processed in whole rows when the tile size is chosen automatically.
+const AUTO_TILE_MAX_WIDTH: usize = 1024;
+const AUTO_TILE_SIZE: usize = 256;
+
+impl ScalerConfig {
+    /// The tuning parameters for a named [`Preset`], with the tile size chosen automatically.
+    pub fn preset(preset: Preset) -> Self {
+        let (
+            equal_color_tolerance,
+            center_direction_bias,
+            dominant_direction_threshold,
+            steep_direction_threshold,
+        ) = match preset {
+            Preset::Faithful => (30.0, 4.0, 3.6, 2.2),
+            Preset::Sharp => (20.0, 4.0, 4.4, 2.6),
+            Preset::Smooth => (42.0, 3.5, 3.0, 1.8),
+            Preset::Text => (14.0, 5.0, 5.0, 3.0),
+            Preset::CrispText => (14.0, 5.0, 5.0, 3.0),
+        };
+        let (grey_tolerance, glyph_contrast) = match preset {
+            Preset::CrispText => (Some(40.0), Some(100.0)),
+            _ => (None, None),
+        };
+
+        Self {
+            equal_color_tolerance,
+            center_direction_bias,
+            dominant_direction_threshold,
+            steep_direction_threshold,
+            tile_size: None,
+            max_threads: None,
+            preserve_outlines: None,
+            suppress_dither: false,
+            preserve_thin_lines: false,
+            grey_tolerance,
+            glyph_contrast,
+            blend_strength: 1.0,
+            blend_weights: BlendWeights::Classic,
+            antialias_at_1x: false,
+            alpha_mode: AlphaMode::Blend,
+            ycbcr_matrix: YCbCrMatrix::Rec2020,
+        }
+    }
+
+    /// The tile size to use for an image `src_width` pixels wide.
+    pub(crate) fn tile_size_for(&self, src_width: usize) -> usize {
+        match self.tile_size {
+            Some(size) => size.clamp(1, src_width.max(1)),
+            None if src_width > AUTO_TILE_MAX_WIDTH => AUTO_TILE_SIZE,
+            None => src_width,
+        }
+    }
+
+    /// The number of threads to share an image between, in the rayon pool that is current.
+    #[cfg(feature = "rayon")]
+    pub(crate) fn thread_count(&self) -> usize {
+        let threads = rayon::current_num_threads();
+        self.max_threads
+            .map_or(threads, |max| max.clamp(1, threads))
+    }
+}
+
+impl Default for ScalerConfig {
+    fn default() -> Self {
+        Self::preset(Preset::Faithful)
+    }
+}
+
+impl From<Preset> for ScalerConfig {
+    fn from(preset: Preset) -> Self {
+        Self::preset(preset)
+    }
+}
+
+/// Chooses the [`ScalerConfig`] used for each source pixel.
+pub(crate) trait PixelConfig: Sync {
+    /// The config for settings that apply to the whole image, such as the tile size.
+    fn base(&self) -> &ScalerConfig;
+
+    /// The config for the source pixel at `(x, y)`. Positions outside of the image are clamped to its edges.
+    fn at(&self, x: isize, y: isize) -> &ScalerConfig;
+
+    /// The table used to measure the distance between colours.
+    #[inline(always)]
+    fn lookup(&self) -> &YCbCrLookup {
+        YCbCrLookup::instance(self.base().ycbcr_matrix)
+    }
+
+    /// The table to blend edges with instead of the one chosen by each config's `blend_weights`.
+    #[inline(always)]
+    fn blend_table(&self) -> Option<&BlendTable<'_>> {
+        None
+    }
+}
+
+impl PixelConfig for ScalerConfig {
+    #[inline(always)]
+    fn base(&self) -> &ScalerConfig {
+        self
+    }
+
+    #[inline(always)]
+    fn at(&self, _x: isize, _y: isize) -> &ScalerConfig {
+        self
+    }
+}
+
+/// A config that measures distances with its own lookup table, rather than the shared one.
+pub(crate) struct LookupConfig<'a> {
+    pub(crate) config: &'a ScalerConfig,
+    pub(crate) lookup: &'a YCbCrLookup,
+}
+
+impl PixelConfig for LookupConfig<'_> {
+    #[inline(always)]
+    fn base(&self) -> &ScalerConfig {
+        self.config
+    }
+
+    #[inline(always)]
+    fn at(&self, _x: isize, _y: isize) -> &ScalerConfig {
+        self.config
+    }
+
+    #[inline(always)]
+    fn lookup(&self) -> &YCbCrLookup {
+        self.lookup
+    }
+}
+
+/// A config that blends edges with its own table, rather than one of the built in tables.
+#[cfg(feature = "unstable")]
+pub(crate) struct TableConfig<'a> {
+    pub(crate) config: &'a ScalerConfig,
+    pub(crate) table: &'a BlendTable<'a>,
+}
+
+#[cfg(feature = "unstable")]
+impl PixelConfig for TableConfig<'_> {
+    #[inline(always)]
+    fn base(&self) -> &ScalerConfig {
+        self.config
+    }
+
+    #[inline(always)]
+    fn at(&self, _x: isize, _y: isize) -> &ScalerConfig {
+        self.config
+    }
+
+    #[inline(always)]
+    fn blend_table(&self) -> Option<&BlendTable<'_>> {
+        Some(self.table)
+    }
+}
+
+/// Selects between two configs with a per-pixel mask: zero selects the first config and
+/// anything else selects the second.
+pub(crate) struct RegionConfig<'a> {
+    pub(crate) configs: [&'a ScalerConfig; 2],
+    pub(crate) mask: &'a [u8],
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+}
+
+impl PixelConfig for RegionConfig<'_> {
+    fn base(&self) -> &ScalerConfig {
+        self.configs[0]
+    }
+
+    #[inline]
+    fn at(&self, x: isize, y: isize) -> &ScalerConfig {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.configs[(self.mask[y * self.width + x] != 0) as usize]
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serialized {
+    use serde::{Deserialize, Serialize};
+
+    use super::{AlphaMode, BlendWeights, OutlineConfig, ScalerConfig, YCbCrMatrix};
+
+    /// The current version of the serialized config format.
+    const CONFIG_VERSION: u32 = 1;
+
+    /// The serialized form of [`ScalerConfig`], in which every field is optional.
+    #[derive(Serialize, Deserialize)]
+    pub(super) struct SerializedConfig {
+        #[serde(default = "first_version")]
+        version: u32,
+        equal_color_tolerance: Option<f64>,
+        center_direction_bias: Option<f64>,
+        dominant_direction_threshold: Option<f64>,
+        steep_direction_threshold: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        tile_size: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        max_threads: Option<usize>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        preserve_outlines: Option<OutlineConfig>,
+        suppress_dither: Option<bool>,
+        preserve_thin_lines: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        grey_tolerance: Option<f64>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        glyph_contrast: Option<f64>,
+        blend_strength: Option<f32>,
+        blend_weights: Option<BlendWeights>,
+        antialias_at_1x: Option<bool>,
+        alpha_mode: Option<AlphaMode>,
+        ycbcr_matrix: Option<YCbCrMatrix>,
+    }
+
+    fn first_version() -> u32 {
+        1
+    }
+
+    /// The config that missing fields are taken from, as it was in the given version.
+    ///
+    /// Each version's defaults are written out in full, rather than taken from
+    /// [`ScalerConfig::default`], so that changing a default later doesn't change how configs
+    /// saved by earlier releases behave.
+    fn defaults_for_version(version: u32) -> Result<ScalerConfig, String> {
+        match version {
+            1 => Ok(ScalerConfig {
+                equal_color_tolerance: 30.0,
+                center_direction_bias: 4.0,
+                dominant_direction_threshold: 3.6,
+                steep_direction_threshold: 2.2,
+                tile_size: None,
+                max_threads: None,
+                preserve_outlines: None,
+                suppress_dither: false,
+                preserve_thin_lines: false,
+                grey_tolerance: None,
+                glyph_contrast: None,
+                blend_strength: 1.0,
+                blend_weights: BlendWeights::Classic,
+                antialias_at_1x: false,
+                alpha_mode: AlphaMode::Blend,
+                ycbcr_matrix: YCbCrMatrix::Rec2020,
+            }),
+            _ => Err(format!(
+                "unsupported config version {version}, expected at most {CONFIG_VERSION}"
+            )),
+        }
+    }
+
+    impl From<ScalerConfig> for SerializedConfig {
+        fn from(config: ScalerConfig) -> Self {
+            Self {
+                version: CONFIG_VERSION,
+                equal_color_tolerance: Some(config.equal_color_tolerance),
+                center_direction_bias: Some(config.center_direction_bias),
+                dominant_direction_threshold: Some(config.dominant_direction_threshold),
+                steep_direction_threshold: Some(config.steep_direction_threshold),
+                tile_size: config.tile_size,
+                max_threads: config.max_threads,
+                preserve_outlines: config.preserve_outlines,
+                suppress_dither: Some(config.suppress_dither),
+                preserve_thin_lines: Some(config.preserve_thin_lines),
+                grey_tolerance: config.grey_tolerance,
+                glyph_contrast: config.glyph_contrast,
+                blend_strength: Some(config.blend_strength),
+                blend_weights: Some(config.blend_weights),
+                antialias_at_1x: Some(config.antialias_at_1x),
+                alpha_mode: Some(config.alpha_mode),
+                ycbcr_matrix: Some(config.ycbcr_matrix),
+            }
+        }
+    }
+
+    impl TryFrom<SerializedConfig> for ScalerConfig {
+        type Error = String;
+
+        fn try_from(serialized: SerializedConfig) -> Result<Self, Self::Error> {
+            let defaults = defaults_for_version(serialized.version)?;
+            Ok(Self {
+                equal_color_tolerance: serialized
+                    .equal_color_tolerance
+                    .unwrap_or(defaults.equal_color_tolerance),
+                center_direction_bias: serialized
+                    .center_direction_bias
+                    .unwrap_or(defaults.center_direction_bias),
+                dominant_direction_threshold: serialized
+                    .dominant_direction_threshold
+                    .unwrap_or(defaults.dominant_direction_threshold),
+                steep_direction_threshold: serialized
+                    .steep_direction_threshold
+                    .unwrap_or(defaults.steep_direction_threshold),
+                tile_size: serialized.tile_size.or(defaults.tile_size),
+                max_threads: serialized.max_threads.or(defaults.max_threads),
+                preserve_outlines: serialized.preserve_outlines.or(defaults.preserve_outlines),
+                suppress_dither: serialized
+                    .suppress_dither
+                    .unwrap_or(defaults.suppress_dither),
+                preserve_thin_lines: serialized
+                    .preserve_thin_lines
+                    .unwrap_or(defaults.preserve_thin_lines),
+                grey_tolerance: serialized.grey_tolerance.or(defaults.grey_tolerance),
+                glyph_contrast: serialized.glyph_contrast.or(defaults.glyph_contrast),
+                blend_strength: serialized.blend_strength.unwrap_or(defaults.blend_strength),
+                blend_weights: serialized.blend_weights.unwrap_or(defaults.blend_weights),
+                antialias_at_1x: serialized
+                    .antialias_at_1x
+                    .unwrap_or(defaults.antialias_at_1x),
+                alpha_mode: serialized.alpha_mode.unwrap_or(defaults.alpha_mode),
+                ycbcr_matrix: serialized.ycbcr_matrix.unwrap_or(defaults.ycbcr_matrix),
+            })
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_round_trips_through_json() {
+        for preset in [
+            Preset::Faithful,
+            Preset::Sharp,
+            Preset::Smooth,
+            Preset::Text,
+            Preset::CrispText,
+        ] {
+            let config = ScalerConfig {
+                tile_size: Some(64),
+                max_threads: Some(2),
+                preserve_outlines: Some(OutlineConfig::default()),
+                ..ScalerConfig::preset(preset)
+            };
+            let json = serde_json::to_string(&config).unwrap();
+            assert_eq!(config, serde_json::from_str(&json).unwrap());
         }
     }
+
+    #[test]
+    fn missing_fields_take_defaults() {
+        let config: ScalerConfig =
+            serde_json::from_str(r#"{"equal_color_tolerance": 12.5}"#).unwrap();
+        assert_eq!(
+            ScalerConfig {
+                equal_color_tolerance: 12.5,
+                ..Default::default()
+            },
+            config
+        );
+    }
+
+    #[test]
+    fn version_1_defaults_are_frozen() {
+        let config: ScalerConfig = serde_json::from_str(r#"{"version": 1}"#).unwrap();
+        assert_eq!(
+            config,
+            ScalerConfig {
+                equal_color_tolerance: 30.0,
+                center_direction_bias: 4.0,
+                dominant_direction_threshold: 3.6,
+                steep_direction_threshold: 2.2,
+                tile_size: None,
+                max_threads: None,
+                preserve_outlines: None,
+                suppress_dither: false,
+                preserve_thin_lines: false,
+                grey_tolerance: None,
+                glyph_contrast: None,
+                blend_strength: 1.0,
+                blend_weights: BlendWeights::Classic,
+                antialias_at_1x: false,
+                alpha_mode: AlphaMode::Blend,
+                ycbcr_matrix: YCbCrMatrix::Rec2020,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_future_versions() {
+        assert!(serde_json::from_str::<ScalerConfig>(r#"{"version": 999}"#).is_err());
+    }
 }
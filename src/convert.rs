@@ -0,0 +1,180 @@
+//! Conversions between the pixel formats of [`PixelFormat`].
+//!
+//! These are the same conversions the scaler uses internally, so an image can be converted into the
+//! format a scaling function takes, or a scaled image into the format a display or file needs,
+//! without a hand-written loop over its pixels. 5 and 6-bit channels are widened by repeating their
+//! highest bits, so that full intensity stays at 255, and narrowed by dropping their lowest bits.
+//! Formats without an alpha channel read as opaque and drop the alpha channel when written.
+use crate::pixel::{Argb8, Bgra8, Mask8, PackedPixel, Rgb565, Rgb8, Rgba8, Xrgb8888, RGB555};
+use crate::PixelFormat;
+
+/// Convert an image from one pixel format to another, returning a newly allocated image in the `to` format.
+///
+/// Converting to [`PixelFormat::Gray8`] takes the luma of each pixel, using the Rec. 601 weights;
+/// converting from it gives an opaque grey.
+///
+/// ```
+/// use xbrz::convert::convert;
+/// use xbrz::PixelFormat;
+///
+/// let bgra = [0x10, 0x20, 0x30, 0xFF];
+/// assert_eq!(convert(&bgra, PixelFormat::Bgra8, PixelFormat::Rgba8), [0x30, 0x20, 0x10, 0xFF]);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the length of `source` is not a multiple of `from.bytes_per_pixel()`.
+pub fn convert(source: &[u8], from: PixelFormat, to: PixelFormat) -> Vec<u8> {
+    assert!(source.len().is_multiple_of(from.bytes_per_pixel()));
+    if from == to {
+        return source.to_vec();
+    }
+
+    match from {
+        PixelFormat::Rgba8 => convert_from::<Rgba8>(source, to),
+        PixelFormat::Argb8 => convert_from::<Argb8>(source, to),
+        PixelFormat::Bgra8 => convert_from::<Bgra8>(source, to),
+        PixelFormat::Rgb8 => convert_from::<Rgb8>(source, to),
+        PixelFormat::Xrgb8888 => convert_from::<Xrgb8888>(source, to),
+        PixelFormat::Rgb565 => convert_from::<Rgb565>(source, to),
+        PixelFormat::Rgb555 => convert_from::<RGB555>(source, to),
+        PixelFormat::Gray8 => convert_from::<Mask8>(source, to),
+    }
+}
+
+/// Convert an image to RGBA, the format taken by [`scale_rgba`](crate::scale_rgba).
+///
+/// This is the same as [`convert`] with `to` set to [`PixelFormat::Rgba8`].
+///
+/// # Panics
+///
+/// Panics if the length of `source` is not a multiple of `from.bytes_per_pixel()`.
+pub fn to_rgba(source: &[u8], from: PixelFormat) -> Vec<u8> {
+    convert(source, from, PixelFormat::Rgba8)
+}
+
+/// Convert an RGBA image, such as one returned by [`scale_rgba`](crate::scale_rgba), to another format.
+///
+/// This is the same as [`convert`] with `from` set to [`PixelFormat::Rgba8`].
+///
+/// # Panics
+///
+/// Panics if the length of `source` is not a multiple of 4.
+pub fn from_rgba(source: &[u8], to: PixelFormat) -> Vec<u8> {
+    convert(source, PixelFormat::Rgba8, to)
+}
+
+/// Swap the red and blue channels of each pixel in place, converting between RGBA and BGRA
+/// without allocating. Any trailing bytes that don't make up a whole pixel are left as they are.
+pub fn swap_red_blue(pixels: &mut [u8]) {
+    for px in pixels.chunks_exact_mut(4) {
+        px.swap(0, 2);
+    }
+}
+
+fn convert_from<F: PackedPixel>(source: &[u8], to: PixelFormat) -> Vec<u8> {
+    match to {
+        PixelFormat::Rgba8 => convert_pixels::<F, Rgba8>(source),
+        PixelFormat::Argb8 => convert_pixels::<F, Argb8>(source),
+        PixelFormat::Bgra8 => convert_pixels::<F, Bgra8>(source),
+        PixelFormat::Rgb8 => convert_pixels::<F, Rgb8>(source),
+        PixelFormat::Xrgb8888 => convert_pixels::<F, Xrgb8888>(source),
+        PixelFormat::Rgb565 => convert_pixels::<F, Rgb565>(source),
+        PixelFormat::Rgb555 => convert_pixels::<F, RGB555>(source),
+        PixelFormat::Gray8 => source
+            .chunks_exact(F::PACKED_SIZE)
+            .map(|px| {
+                let [r, g, b] = F::read(px).to_rgb().map(u32::from);
+                ((r * 77 + g * 150 + b * 29 + 128) >> 8) as u8
+            })
+            .collect(),
+    }
+}
+
+fn convert_pixels<F: PackedPixel, T: PackedPixel>(source: &[u8]) -> Vec<u8> {
+    let pixels = source.len() / F::PACKED_SIZE;
+    let mut out = Vec::with_capacity(pixels * T::PACKED_SIZE);
+    for px in source.chunks_exact(F::PACKED_SIZE) {
+        let px = F::read(px);
+        let [r, g, b] = px.to_rgb();
+        T::from_rgba([r, g, b, px.alpha()]).write(&mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATS: [PixelFormat; 8] = [
+        PixelFormat::Rgba8,
+        PixelFormat::Argb8,
+        PixelFormat::Bgra8,
+        PixelFormat::Rgb8,
+        PixelFormat::Xrgb8888,
+        PixelFormat::Rgb565,
+        PixelFormat::Rgb555,
+        PixelFormat::Gray8,
+    ];
+
+    #[test]
+    fn channels_end_up_in_place() {
+        let rgba = [0xF8, 0x80, 0x08, 0xFF];
+        let expected = |format| -> Vec<u8> {
+            match format {
+                PixelFormat::Rgba8 => vec![0xF8, 0x80, 0x08, 0xFF],
+                PixelFormat::Argb8 => vec![0xFF, 0xF8, 0x80, 0x08],
+                PixelFormat::Bgra8 => vec![0x08, 0x80, 0xF8, 0xFF],
+                PixelFormat::Rgb8 => vec![0xF8, 0x80, 0x08],
+                PixelFormat::Xrgb8888 => 0x00F8_8008u32.to_ne_bytes().to_vec(),
+                PixelFormat::Rgb565 => 0xFC01u16.to_ne_bytes().to_vec(),
+                PixelFormat::Rgb555 => 0x7E01u16.to_ne_bytes().to_vec(),
+                PixelFormat::Gray8 => vec![0x97],
+            }
+        };
+        for format in FORMATS {
+            assert_eq!(from_rgba(&rgba, format), expected(format), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn formats_with_every_channel_round_trip() {
+        let rgba: Vec<u8> = (0..=255).flat_map(|c| [c, 255 - c, c / 3, 255]).collect();
+        for format in [
+            PixelFormat::Rgba8,
+            PixelFormat::Argb8,
+            PixelFormat::Bgra8,
+            PixelFormat::Rgb8,
+            PixelFormat::Xrgb8888,
+        ] {
+            assert_eq!(
+                to_rgba(&from_rgba(&rgba, format), format),
+                rgba,
+                "{format:?}"
+            );
+        }
+
+        for format in FORMATS {
+            for other in FORMATS {
+                let converted = from_rgba(&rgba, format);
+                let there_and_back = convert(&convert(&converted, format, other), other, format);
+                // formats at least as precise as the first one lose nothing on the way
+                if other.bytes_per_pixel() >= format.bytes_per_pixel()
+                    && format != PixelFormat::Gray8
+                    && other != PixelFormat::Gray8
+                    && !(format == PixelFormat::Rgb565 && other == PixelFormat::Rgb555)
+                {
+                    assert_eq!(there_and_back, converted, "{format:?} via {other:?}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn swap_matches_convert() {
+        let mut pixels: Vec<u8> = (0..64).collect();
+        let expected = convert(&pixels, PixelFormat::Rgba8, PixelFormat::Bgra8);
+        swap_red_blue(&mut pixels);
+        assert_eq!(pixels, expected);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::check_source;
+use crate::oob_reader::{OobReaderWindow, Window};
+use crate::pixel::{cast_pixels, pixels_into_bytes, Rgba8};
+use crate::{scale_pixels, ScalerConfig};
+
+/// A rectangle of pixels within an image, with its top left corner at `(x, y)`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Use the xBRZ algorithm to scale up part of an image by an integer factor, returning only the
+/// scaled part.
+///
+/// The result is the part of [`scale_rgba`](crate::scale_rgba)'s result covered by `crop`, with
+/// the edges of the crop blended with the pixels around it, but only the pixels of the crop and
+/// the two pixels around it on each side are read, and only the crop is scaled. This suits
+/// magnifiers and sprite editors showing a zoomed in selection of a much larger image.
+///
+/// ```
+/// use xbrz::Rect;
+///
+/// let (width, height) = (64, 64);
+/// let source = vec![255u8; width * height * 4];
+///
+/// let scaled = xbrz::scale_rgba_cropped(&source, width, height, Rect::new(10, 20, 8, 6), 3);
+/// assert_eq!(scaled.len(), 8 * 3 * 6 * 3 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `crop` doesn't fit within the image, or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_cropped(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    crop: Rect,
+    factor: usize,
+) -> Vec<u8> {
+    scale_rgba_cropped_with_config(
+        source,
+        src_width,
+        src_height,
+        crop,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up part of an image by an integer factor, with custom tuning
+/// parameters.
+///
+/// This is the same as [`scale_rgba_cropped`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `crop` doesn't fit within the image, or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_cropped_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    crop: Rect,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    check_source(source, src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+    let fits =
+        |start: usize, len: usize, size| start.checked_add(len).is_some_and(|end| end <= size);
+    if !fits(crop.x, crop.width, src_width) || !fits(crop.y, crop.height, src_height) {
+        panic!("crop {crop:?} doesn't fit within the {src_width}x{src_height} image");
+    }
+
+    let pixels = cast_pixels::<Rgba8>(source);
+    let window = Window {
+        pixels: &pixels,
+        image_width: src_width,
+        image_height: src_height,
+        x: crop.x,
+        y: crop.y,
+    };
+    let scaled = scale_pixels::<Rgba8, OobReaderWindow<Rgba8>, _>(
+        &window,
+        crop.width,
+        crop.height,
+        factor,
+        config,
+        None,
+        None,
+    );
+    pixels_into_bytes(scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crop_matches_the_same_part_of_the_whole_image() {
+        let (width, height) = (23, 19);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width + 2 * (i / width)) % 6 {
+                0 | 1 => [255, 255, 255, 255],
+                2 => [200, 40, 40, 255],
+                3 => [30, 60, 200, 128],
+                _ => [0, 0, 0, 0],
+            })
+            .collect();
+
+        for factor in 1..=6 {
+            let config = ScalerConfig {
+                antialias_at_1x: factor == 1,
+                alpha_mode: crate::AlphaMode::Max,
+                ..Default::default()
+            };
+            let whole = crate::scale_rgba_with_config(&source, width, height, factor, &config);
+            let row_len = width * factor * 4;
+            for crop in [
+                Rect::new(0, 0, width, height),
+                Rect::new(5, 4, 9, 7),
+                Rect::new(0, 12, 4, 7),
+                Rect::new(22, 0, 1, 1),
+                Rect::new(3, 3, 0, 0),
+            ] {
+                let scaled =
+                    scale_rgba_cropped_with_config(&source, width, height, crop, factor, &config);
+                let crop_row_len = crop.width * factor * 4;
+                let expected: Vec<u8> = whole
+                    .chunks_exact(row_len)
+                    .skip(crop.y * factor)
+                    .take(crop.height * factor)
+                    .flat_map(|row| &row[crop.x * factor * 4..][..crop_row_len])
+                    .copied()
+                    .collect();
+                assert!(scaled == expected, "{factor}x, {crop:?}");
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit within the 4x4 image")]
+    fn crop_must_fit_within_the_image() {
+        scale_rgba_cropped(&[0; 4 * 4 * 4], 4, 4, Rect::new(2, 0, 3, 1), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit within the 4x4 image")]
+    fn crop_past_the_end_of_memory_does_not_wrap() {
+        scale_rgba_cropped(&[0; 4 * 4 * 4], 4, 4, Rect::new(2, 1, usize::MAX, 1), 2);
+    }
+}
@@ -0,0 +1,236 @@
+//! Scaling frames within a time budget, for frontends that can't afford to miss a vsync.
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::error::{check_dimensions, check_source};
+use crate::{ScaleArena, ScalerConfig};
+
+/// The number of source rows scaled at a time, and so how often the deadline is checked.
+const STRIPE_HEIGHT: usize = 8;
+
+/// Scales the frames of a game or video one after another, each within a time budget.
+///
+/// Each frame is scaled a stripe of rows at a time. Once the budget has run out, the stripes not
+/// yet started are scaled with nearest neighbour instead, which takes a fraction of the time,
+/// so the frame is ready on time with a band of soft, blocky pixels rather than late. The next
+/// frame starts from the first stripe that was left soft, so every part of the image is scaled
+/// properly before long even when the budget is too tight for a whole frame. The first stripe of
+/// every frame is always scaled with xBRZ, however little budget there is.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// let (width, height) = (256, 224);
+/// let frame = vec![128u8; width * height * 4];
+///
+/// // most of a 60 Hz frame, leaving time for the rest of the frontend
+/// let budget = Duration::from_millis(12);
+/// let mut scaler = xbrz::DeadlineScaler::new(xbrz::ScalerConfig::default(), budget);
+/// let scaled = scaler.scale_frame(&frame, width, height, 3);
+/// # assert_eq!(scaled.len(), width * 3 * height * 3 * 4);
+/// if scaler.soft_rows() > 0 {
+///     // the frame ran over its budget
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct DeadlineScaler {
+    config: ScalerConfig,
+    budget: Duration,
+    /// The stripe that the next frame starts from.
+    first_stripe: usize,
+    soft_rows: usize,
+}
+
+impl DeadlineScaler {
+    pub fn new(config: ScalerConfig, budget: Duration) -> Self {
+        Self {
+            config,
+            budget,
+            first_stripe: 0,
+            soft_rows: 0,
+        }
+    }
+
+    /// Change the time given to each frame, such as when the display's refresh rate changes.
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget;
+    }
+
+    /// The number of source rows of the last frame that were scaled with nearest neighbour,
+    /// because the budget ran out before they were reached.
+    pub fn soft_rows(&self) -> usize {
+        self.soft_rows
+    }
+
+    /// Use the xBRZ algorithm to scale up the next frame by an integer factor, falling back to
+    /// nearest neighbour for whatever is left of the frame once the budget has run out.
+    ///
+    /// The budget is counted from the start of the call. With the `rayon` feature enabled, the
+    /// stripes are shared between up to [`ScalerConfig::max_threads`] threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+    /// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn scale_frame(
+        &mut self,
+        source: &[u8],
+        src_width: usize,
+        src_height: usize,
+        factor: usize,
+    ) -> Vec<u8> {
+        let deadline = Instant::now().checked_add(self.budget);
+        let dst_len =
+            check_dimensions(src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+        check_source(source, src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+        let mut scaled = vec![0; dst_len];
+        self.soft_rows = 0;
+        if src_width == 0 || src_height == 0 {
+            return scaled;
+        }
+
+        // the stripes in the order they are scaled in, starting from where the last frame ran out
+        let mut stripes: Vec<&mut [u8]> = scaled
+            .chunks_mut(STRIPE_HEIGHT * src_width * factor * factor * 4)
+            .collect();
+        let count = stripes.len();
+        let first_stripe = self.first_stripe % count;
+        stripes.rotate_left(first_stripe);
+
+        let first_soft_turn = AtomicUsize::new(usize::MAX);
+        let soft_rows = AtomicUsize::new(0);
+        let config = &self.config;
+        let scale_stripe = |arena: &mut ScaleArena, turn: usize, stripe: &mut [u8]| {
+            let i = (first_stripe + turn) % count;
+            let y_range = i * STRIPE_HEIGHT..((i + 1) * STRIPE_HEIGHT).min(src_height);
+            let late = turn > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            if late {
+                first_soft_turn.fetch_min(turn, Ordering::Relaxed);
+                soft_rows.fetch_add(y_range.len(), Ordering::Relaxed);
+                scale_nearest(source, src_width, factor, y_range, stripe);
+            } else {
+                crate::scale_rgba_stripe(
+                    source, src_width, src_height, factor, y_range, config, stripe, arena,
+                );
+            }
+        };
+
+        // stripes are handed out strictly in turn, so that those left soft are always the last
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+
+            let queue = parking_lot::Mutex::new(stripes.into_iter().enumerate());
+            (0..config.thread_count().min(count))
+                .into_par_iter()
+                .for_each_init(ScaleArena::new, |arena, _| loop {
+                    let next = queue.lock().next();
+                    match next {
+                        Some((turn, stripe)) => scale_stripe(arena, turn, stripe),
+                        None => break,
+                    }
+                });
+        }
+
+        #[cfg(not(feature = "rayon"))]
+        {
+            let mut arena = ScaleArena::new();
+            for (turn, stripe) in stripes.into_iter().enumerate() {
+                scale_stripe(&mut arena, turn, stripe);
+            }
+        }
+
+        self.soft_rows = soft_rows.into_inner();
+        self.first_stripe = match first_soft_turn.into_inner() {
+            usize::MAX => 0,
+            turn => (first_stripe + turn) % count,
+        };
+        scaled
+    }
+}
+
+/// Scale the source rows in `y_range` into `destination` by repeating each pixel `factor` times
+/// across and down.
+fn scale_nearest(
+    source: &[u8],
+    src_width: usize,
+    factor: usize,
+    y_range: Range<usize>,
+    destination: &mut [u8],
+) {
+    let row_len = src_width * 4;
+    let dst_row_len = row_len * factor;
+    for (y, dst_rows) in y_range.zip(destination.chunks_exact_mut(dst_row_len * factor)) {
+        let (first, rest) = dst_rows.split_at_mut(dst_row_len);
+        let row = &source[y * row_len..][..row_len];
+        for (pixel, out) in row.chunks_exact(4).zip(first.chunks_exact_mut(factor * 4)) {
+            for out in out.chunks_exact_mut(4) {
+                out.copy_from_slice(pixel);
+            }
+        }
+        for copy in rest.chunks_exact_mut(dst_row_len) {
+            copy.copy_from_slice(first);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stripes(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| match (i % width + i / width) % 4 {
+                0 => [255, 255, 255, 255],
+                1 => [200, 40, 40, 255],
+                _ => [0, 0, 0, 255],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_within_budget_are_scaled_in_full() {
+        let (width, height) = (20, 30);
+        let source = stripes(width, height);
+        let config = ScalerConfig::default();
+        let mut scaler = DeadlineScaler::new(config.clone(), Duration::MAX);
+        assert_eq!(
+            scaler.scale_frame(&source, width, height, 3),
+            crate::scale_rgba_with_config(&source, width, height, 3, &config)
+        );
+        assert_eq!(scaler.soft_rows(), 0);
+    }
+
+    #[test]
+    fn late_stripes_are_finished_next_frame() {
+        let (width, height, factor) = (20, 30, 2);
+        let source = stripes(width, height);
+        let config = ScalerConfig::default();
+        let full = crate::scale_rgba_with_config(&source, width, height, factor, &config);
+        let mut nearest = vec![0; full.len()];
+        scale_nearest(&source, width, factor, 0..height, &mut nearest);
+        assert_ne!(full, nearest);
+
+        // with no budget, only the first stripe of each frame is scaled with xBRZ
+        let dst_stripe_len = STRIPE_HEIGHT * width * factor * factor * 4;
+        let mut scaler = DeadlineScaler::new(config, Duration::ZERO);
+        for turn in 0..5 {
+            let scaled = scaler.scale_frame(&source, width, height, factor);
+            let i = turn % 4;
+            let last = if i == 3 {
+                height % STRIPE_HEIGHT
+            } else {
+                STRIPE_HEIGHT
+            };
+            assert_eq!(scaler.soft_rows(), height - last);
+            for (j, stripe) in scaled.chunks(dst_stripe_len).enumerate() {
+                let expected = if i == j { &full } else { &nearest };
+                assert!(
+                    stripe == &expected[j * dst_stripe_len..][..stripe.len()],
+                    "frame {turn}, stripe {j}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Hashes of scaled images, for checking whether scaled assets are up to date.
+use crate::ScalerConfig;
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, and return a BLAKE3 hash of
+/// the result instead of the image itself.
+///
+/// This is the same as [`digest_rgba`] of the image returned by [`scale_rgba`](crate::scale_rgba),
+/// and so changes whenever the scaled image does. The scaled image is the same on every platform
+/// for a given version of this crate, except that the `large_lut` and `fixed_point` features give
+/// slightly different results, so hashes can be compared across machines as long as they agree on
+/// those features.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn digest(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> [u8; 32] {
+    digest_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, with custom tuning
+/// parameters, and return a BLAKE3 hash of the result.
+///
+/// This is the same as [`digest`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn digest_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> [u8; 32] {
+    let scaled = crate::scale_rgba_with_config(source, src_width, src_height, factor, config);
+    digest_rgba(&scaled, src_width * factor, src_height * factor)
+}
+
+/// A BLAKE3 hash of an RGBA image, such as one that has been scaled and then processed further.
+///
+/// The hash covers the width and height, each as a little-endian `u64`, followed by the pixels, so
+/// images with the same pixels in a different shape have different hashes.
+///
+/// # Panics
+///
+/// Panics if the `rgba` slice length is not exactly equal to `width * height * 4`.
+pub fn digest_rgba(rgba: &[u8], width: usize, height: usize) -> [u8; 32] {
+    assert_eq!(rgba.len(), width * height * 4);
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&(width as u64).to_le_bytes());
+    hasher.update(&(height as u64).to_le_bytes());
+    hasher.update(rgba);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_follows_the_scaled_image() {
+        let source: Vec<u8> = (0..6 * 4 * 4).map(|i| (i * 53 % 256) as u8).collect();
+        let hash = digest(&source, 6, 4, 3);
+        assert_eq!(hash, digest(&source, 6, 4, 3));
+        assert_eq!(
+            hash,
+            digest_rgba(&crate::scale_rgba(&source, 6, 4, 3), 18, 12)
+        );
+        assert_ne!(hash, digest(&source, 6, 4, 2));
+        assert_ne!(hash, digest(&source, 4, 6, 3));
+
+        // the same bytes in another shape
+        let scaled = crate::scale_rgba(&source, 6, 4, 3);
+        assert_ne!(digest_rgba(&scaled, 18, 12), digest_rgba(&scaled, 12, 18));
+    }
+}
@@ -0,0 +1,240 @@
+//! The shape of the edge blended into each corner of each pixel, for shaders and other effects
+//! that build on xBRZ's edge analysis.
+use crate::blend::{Blend2x2, BlendType};
+use crate::blend_map::{for_each_cached_blend, Visit};
+use crate::config::PixelConfig;
+use crate::kernel::{Kernel4x4, RotKernel3x3, Rotation};
+use crate::oob_reader::OobReaderTransparent;
+use crate::pixel::{cast_pixels, Pixel, Rgba8};
+use crate::scaler::corner_shape;
+use crate::ycbcr_lookup::YCbCrLookup;
+use crate::ScalerConfig;
+
+/// The shape of the edge that a corner of a source pixel is blended along, as found by xBRZ.
+///
+/// Shapes are given in the orientation of the image: a shallow edge is closer to horizontal than
+/// to a 45 degree diagonal, and a steep edge closer to vertical. Each shape is stored as a byte
+/// by [`edge_shapes`], with the value of its discriminant.
+#[repr(u8)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EdgeShape {
+    /// The corner is not blended.
+    #[default]
+    None = 0,
+    /// Only the corner itself is blended, as it isn't part of a longer line.
+    Corner = 1,
+    /// A 45 degree diagonal edge.
+    Diagonal = 2,
+    /// An edge closer to horizontal than to a diagonal.
+    Shallow = 3,
+    /// An edge closer to vertical than to a diagonal.
+    Steep = 4,
+    /// An edge that is both shallow and steep, such as the end of a line one pixel wide.
+    SteepAndShallow = 5,
+}
+
+impl EdgeShape {
+    /// Returns `None` if `value` is not the discriminant of a shape.
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => EdgeShape::None,
+            1 => EdgeShape::Corner,
+            2 => EdgeShape::Diagonal,
+            3 => EdgeShape::Shallow,
+            4 => EdgeShape::Steep,
+            5 => EdgeShape::SteepAndShallow,
+            _ => return None,
+        })
+    }
+
+    /// The shape seen from a quarter turn away, where shallow edges become steep and the other
+    /// way around.
+    fn turned(self) -> Self {
+        match self {
+            EdgeShape::Shallow => EdgeShape::Steep,
+            EdgeShape::Steep => EdgeShape::Shallow,
+            shape => shape,
+        }
+    }
+}
+
+/// Work out the shape of the edge blended into each corner of each pixel of an image, without
+/// scaling it.
+///
+/// The `source` is a flat RGBA image, as for [`scale_rgba`](crate::scale_rgba). The result holds
+/// four bytes per source pixel, in the same left to right, then top to bottom order: the
+/// [`EdgeShape`] of its top left, top right, bottom left and bottom right corners. It can be
+/// uploaded as an RGBA texture the size of the source image, for a shader to read alongside the
+/// scaled image, such as to guide motion interpolation along edges. The shapes are those that
+/// xBRZ blends with at factors of 2 and up.
+///
+/// ```
+/// use xbrz::{EdgeShape, ScalerConfig};
+///
+/// let (width, height) = (6, 6);
+/// // a white triangle below a black diagonal
+/// let source: Vec<u8> = (0..width * height)
+///     .flat_map(|i| match i % width < i / width {
+///         true => [255, 255, 255, 255],
+///         false => [0, 0, 0, 255],
+///     })
+///     .collect();
+///
+/// let shapes = xbrz::edge_shapes(&source, width, height, &ScalerConfig::default());
+/// let pixel = (3 * width + 2) * 4;
+/// assert_eq!(EdgeShape::from_u8(shapes[pixel + 1]), Some(EdgeShape::Diagonal));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`.
+pub fn edge_shapes(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    let blend_map = crate::compute_blend_map(source, src_width, src_height, config);
+    shapes_from_blend_map(source, src_width, src_height, config, &blend_map)
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, returning the scaled image
+/// along with the shape of the edge blended into each corner of each source pixel.
+///
+/// The shapes are the same as [`edge_shapes`] gives, and the scaled image the same as
+/// [`scale_rgba_with_config`](crate::scale_rgba_with_config) gives, but the blending of the image
+/// is only worked out once for both.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_edge_shapes(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> (Vec<u8>, Vec<u8>) {
+    let blend_map = crate::compute_blend_map(source, src_width, src_height, config);
+    let scaled =
+        crate::scale_rgba_with_blend_map(source, src_width, src_height, factor, config, &blend_map);
+    let shapes = shapes_from_blend_map(source, src_width, src_height, config, &blend_map);
+    (scaled, shapes)
+}
+
+fn shapes_from_blend_map(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    config: &ScalerConfig,
+    blend_map: &[Blend2x2],
+) -> Vec<u8> {
+    let mut shapes = vec![EdgeShape::None as u8; src_width * src_height * 4];
+    if src_width == 0 || src_height == 0 {
+        return shapes;
+    }
+
+    let pixels = cast_pixels::<Rgba8>(source);
+    let ycbcr = config.lookup();
+    for_each_cached_blend::<Rgba8, OobReaderTransparent<Rgba8>>(
+        &pixels,
+        src_width,
+        src_height,
+        0..src_width,
+        0..src_height,
+        blend_map,
+        |visit, x, y| {
+            if let Visit::Pixel(kernel, blend) = visit {
+                let corners = pixel_shapes(kernel, blend, config, ycbcr);
+                shapes[(y * src_width + x) * 4..][..4].copy_from_slice(&corners.map(|s| s as u8));
+            }
+        },
+        None,
+    );
+    shapes
+}
+
+/// The shapes of the top left, top right, bottom left and bottom right corners of the centre
+/// pixel of a kernel. Each corner is seen by xBRZ in the rotation that turns it to the bottom
+/// right, so shapes seen a quarter turn away are turned back.
+fn pixel_shapes<P: Pixel>(
+    kernel: &Kernel4x4<P>,
+    blend: Blend2x2,
+    config: &ScalerConfig,
+    ycbcr: &YCbCrLookup,
+) -> [EdgeShape; 4] {
+    macro_rules! shape {
+        ($corner:ident, $rotation:ident) => {
+            match blend.$corner {
+                BlendType::None => EdgeShape::None,
+                _ => {
+                    let rotated = RotKernel3x3::<P, { Rotation::$rotation as u8 }>::new(kernel);
+                    corner_shape(&rotated, blend, config, ycbcr).0
+                }
+            }
+        };
+    }
+    [
+        shape!(top_left, Clockwise180),
+        shape!(top_right, Clockwise90).turned(),
+        shape!(bottom_left, Clockwise270).turned(),
+        shape!(bottom_right, None),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_image_has_no_edges() {
+        let source = [90, 120, 30, 255].repeat(5 * 4);
+        let shapes = edge_shapes(&source, 5, 4, &ScalerConfig::default());
+        assert_eq!(shapes.len(), 5 * 4 * 4);
+        // the edges of the image border on transparent pixels, so only the middle is flat
+        for i in [6, 7, 8, 11, 12, 13] {
+            assert_eq!(shapes[i * 4..][..4], [0; 4]);
+        }
+    }
+
+    #[test]
+    fn shallow_and_steep_edges_keep_their_orientation() {
+        // a line rising one pixel every three across, and the same line turned a quarter turn
+        let (width, height) = (12, 12);
+        let shallow: Vec<u8> = (0..width * height)
+            .flat_map(|i| match i / width > 6 - (i % width) / 3 {
+                true => [255, 255, 255, 255],
+                false => [0, 0, 0, 255],
+            })
+            .collect();
+        let mut steep = vec![0; shallow.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let (from, to) = ((y * width + x) * 4, (x * width + y) * 4);
+                steep[to..to + 4].copy_from_slice(&shallow[from..from + 4]);
+            }
+        }
+
+        let config = ScalerConfig::default();
+        let count =
+            |shapes: &[u8], shape: EdgeShape| shapes.iter().filter(|&&s| s == shape as u8).count();
+        let (_, shallow_shapes) = scale_rgba_with_edge_shapes(&shallow, width, height, 3, &config);
+        let steep_shapes = edge_shapes(&steep, width, height, &config);
+        assert!(count(&shallow_shapes, EdgeShape::Shallow) > 0);
+        assert_eq!(count(&shallow_shapes, EdgeShape::Steep), 0);
+        assert_eq!(
+            count(&steep_shapes, EdgeShape::Steep),
+            count(&shallow_shapes, EdgeShape::Shallow)
+        );
+    }
+
+    #[test]
+    fn shapes_round_trip_through_bytes() {
+        for value in 0..=5 {
+            assert_eq!(EdgeShape::from_u8(value).map(|s| s as u8), Some(value));
+        }
+        assert_eq!(EdgeShape::from_u8(6), None);
+    }
+}
@@ -0,0 +1,266 @@
+//! A side by side comparison of an image and its scaled copy, as an [egui](https://docs.rs/egui)
+//! widget.
+//!
+//! Enabled by the `egui` feature.
+use ::egui::{
+    pos2, vec2, Align2, Color32, Context, Rect, Response, Sense, TextStyle, TextureHandle,
+    TextureOptions, Ui, Vec2,
+};
+
+use crate::ScalerConfig;
+
+/// The range of zoom levels, in screen points per pixel of the scaled image.
+const MIN_ZOOM: f32 = 1.0 / 64.0;
+const MAX_ZOOM: f32 = 64.0;
+
+/// A widget showing an image beside its xBRZ scaled copy, with the two panned and zoomed together
+/// so that the same part of each is always in view.
+///
+/// The original is drawn with nearest neighbour filtering at the size of the scaled copy, so that
+/// each of its pixels lines up with the block of pixels that xBRZ scaled it to. Dragging either
+/// side pans both, scrolling or pinching zooms both about the pointer, and double clicking fits
+/// the whole image back in view.
+///
+/// The view keeps its own textures and pan and zoom, so it should be created once and kept
+/// between frames, then drawn with [`show`](Self::show) each frame:
+///
+/// ```no_run
+/// # fn ui(ui: &mut egui::Ui, view: &mut xbrz::egui::ComparisonView) {
+/// view.show(ui);
+/// # }
+/// ```
+pub struct ComparisonView {
+    source: Vec<u8>,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    original: TextureHandle,
+    scaled: TextureHandle,
+    /// Screen points per pixel of the scaled image, or `None` to fit the whole image in view.
+    zoom: Option<f32>,
+    /// The point of the scaled image shown in the centre of each side, in its pixels.
+    center: Vec2,
+}
+
+impl ComparisonView {
+    /// Scale an RGBA image by `factor` with the default configuration, and upload it and its
+    /// scaled copy as textures for comparing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+    /// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn new(
+        ctx: &Context,
+        source: &[u8],
+        src_width: usize,
+        src_height: usize,
+        factor: usize,
+    ) -> Self {
+        Self::with_config(
+            ctx,
+            source,
+            src_width,
+            src_height,
+            factor,
+            &ScalerConfig::default(),
+        )
+    }
+
+    /// Scale an RGBA image by `factor` with a [`ScalerConfig`], and upload it and its scaled copy
+    /// as textures for comparing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+    /// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn with_config(
+        ctx: &Context,
+        source: &[u8],
+        src_width: usize,
+        src_height: usize,
+        factor: usize,
+        config: &ScalerConfig,
+    ) -> Self {
+        let scaled = scaled_image(source, src_width, src_height, factor, config);
+        Self {
+            original: ctx.load_texture(
+                "xbrz original",
+                egui::ColorImage::from_rgba_unmultiplied([src_width, src_height], source),
+                TextureOptions::NEAREST,
+            ),
+            scaled: ctx.load_texture("xbrz scaled", scaled, TextureOptions::NEAREST),
+            source: source.to_vec(),
+            src_width,
+            src_height,
+            factor,
+            zoom: None,
+            center: Vec2::ZERO,
+        }
+    }
+
+    /// Scale the image again with a new factor or configuration, such as when a setting is
+    /// changed, keeping the same part of it in view.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn set_scaling(&mut self, factor: usize, config: &ScalerConfig) {
+        let scaled = scaled_image(
+            &self.source,
+            self.src_width,
+            self.src_height,
+            factor,
+            config,
+        );
+        self.scaled.set(scaled, TextureOptions::NEAREST);
+        let change = factor as f32 / self.factor as f32;
+        self.center *= change;
+        self.zoom = self.zoom.map(|zoom| zoom / change);
+        self.factor = factor;
+    }
+
+    /// The factor that the image is scaled by.
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// The size of the scaled image in pixels.
+    pub fn scaled_size(&self) -> [usize; 2] {
+        self.scaled.size()
+    }
+
+    /// Fit the whole image in view again, as when the view was created.
+    pub fn reset_view(&mut self) {
+        self.zoom = None;
+    }
+
+    /// Draw the view into all of the space left in `ui`, and pan and zoom it by any dragging,
+    /// scrolling or pinching over it.
+    pub fn show(&mut self, ui: &mut Ui) -> Response {
+        let (rect, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let (left, right) = rect.split_left_right_at_fraction(0.5);
+        let [width, height] = self.scaled.size();
+        let scaled_size = vec2(width as f32, height as f32);
+
+        if response.double_clicked() {
+            self.reset_view();
+        }
+        let zoom = match self.zoom {
+            Some(zoom) => zoom,
+            None => {
+                self.center = scaled_size / 2.0;
+                (left.width() / scaled_size.x)
+                    .min(left.height() / scaled_size.y)
+                    .clamp(MIN_ZOOM, MAX_ZOOM)
+            }
+        };
+        self.zoom = Some(zoom);
+        if response.dragged() {
+            self.center -= response.drag_delta() / zoom;
+        }
+        if let Some(pointer) = response.hover_pos() {
+            let (scroll, pinch) = ui.input(|i| (i.smooth_scroll_delta.y, i.zoom_delta()));
+            let change = pinch * (scroll / 200.0).exp();
+            if change != 1.0 {
+                let side = if left.contains(pointer) { left } else { right };
+                self.zoom_about(pointer - side.center(), change);
+            }
+        }
+        self.center = self.center.clamp(Vec2::ZERO, scaled_size);
+
+        let zoom = self.zoom.unwrap_or(zoom);
+        let labels = ["Original".to_owned(), format!("xBRZ {}x", self.factor)];
+        let uv = Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0));
+        for ((side, texture), label) in [(left, &self.original), (right, &self.scaled)]
+            .into_iter()
+            .zip(labels)
+        {
+            let painter = ui.painter_at(side);
+            let origin = side.center() - self.center * zoom;
+            let image = Rect::from_min_size(origin, scaled_size * zoom);
+            painter.image(texture.id(), image, uv, Color32::WHITE);
+
+            let font = TextStyle::Body.resolve(ui.style());
+            let galley = painter.layout_no_wrap(label, font, ui.visuals().strong_text_color());
+            let label_rect = Align2::LEFT_TOP
+                .anchor_size(side.left_top() + vec2(4.0, 4.0), galley.size())
+                .expand(2.0);
+            painter.rect_filled(label_rect, 2.0, ui.visuals().extreme_bg_color);
+            painter.galley(label_rect.min + vec2(2.0, 2.0), galley, Color32::WHITE);
+        }
+        ui.painter().vline(
+            left.right(),
+            rect.y_range(),
+            ui.visuals().widgets.noninteractive.bg_stroke,
+        );
+        response
+    }
+
+    /// Multiply the zoom by `change`, keeping the point of the image at `offset` from the centre
+    /// of a side where it is.
+    fn zoom_about(&mut self, offset: Vec2, change: f32) {
+        let zoom = self.zoom.unwrap_or(1.0);
+        let point = self.center + offset / zoom;
+        let zoom = (zoom * change).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.center = point - offset / zoom;
+        self.zoom = Some(zoom);
+    }
+}
+
+fn scaled_image(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> egui::ColorImage {
+    let scaled = crate::scale_rgba_with_config(source, src_width, src_height, factor, config);
+    egui::ColorImage::from_rgba_unmultiplied([src_width * factor, src_height * factor], &scaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(factor: usize) -> ComparisonView {
+        let source: Vec<u8> = (0..5 * 3 * 4).map(|i| (i * 41) as u8).collect();
+        ComparisonView::new(&Context::default(), &source, 5, 3, factor)
+    }
+
+    #[test]
+    fn scaled_texture_has_the_scaled_size() {
+        let mut view = view(3);
+        assert_eq!(view.scaled_size(), [15, 9]);
+        assert_eq!(view.original.size(), [5, 3]);
+
+        view.set_scaling(4, &ScalerConfig::default());
+        assert_eq!((view.factor(), view.scaled_size()), (4, [20, 12]));
+    }
+
+    #[test]
+    fn zooming_keeps_the_point_under_the_pointer() {
+        let mut view = view(2);
+        view.zoom = Some(2.0);
+        view.center = vec2(5.0, 3.0);
+        let offset = vec2(8.0, -4.0);
+        let under_pointer = |view: &ComparisonView| view.center + offset / view.zoom.unwrap();
+        let before = under_pointer(&view);
+
+        view.zoom_about(offset, 1.5);
+        assert_eq!(view.zoom, Some(3.0));
+        assert!((under_pointer(&view) - before).length() < 1e-5);
+    }
+
+    #[test]
+    fn rescaling_keeps_the_same_part_in_view() {
+        let mut view = view(2);
+        view.zoom = Some(4.0);
+        view.center = vec2(6.0, 2.0);
+
+        view.set_scaling(4, &ScalerConfig::default());
+        // the same source pixel is in the centre, drawn at the same size on screen
+        assert_eq!(view.center, vec2(12.0, 4.0));
+        assert_eq!(view.zoom, Some(2.0));
+    }
+}
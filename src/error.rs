@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::factor::{InvalidScaleFactor, ScaleFactor};
+
+/// The error returned by the `try_` scaling functions when an image can't be scaled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ScaleError {
+    /// The factor is not one of 1, 2, 3, 4, 5 or 6.
+    InvalidFactor(InvalidScaleFactor),
+    /// The length of the source doesn't match its dimensions.
+    SourceLength { expected: usize, actual: usize },
+    /// The scaled image would be too large to fit in memory on this target,
+    /// as described by [`max_supported_dimensions`].
+    TooLarge {
+        width: usize,
+        height: usize,
+        factor: usize,
+    },
+}
+
+impl fmt::Display for ScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScaleError::InvalidFactor(e) => e.fmt(f),
+            ScaleError::SourceLength { expected, actual } => write!(
+                f,
+                "source is {actual} bytes long, but its dimensions need {expected} bytes"
+            ),
+            ScaleError::TooLarge {
+                width,
+                height,
+                factor,
+            } => write!(
+                f,
+                "a {width}x{height} image scaled by {factor} is too large for this target"
+            ),
+        }
+    }
+}
+
+impl Error for ScaleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ScaleError::InvalidFactor(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<InvalidScaleFactor> for ScaleError {
+    fn from(e: InvalidScaleFactor) -> Self {
+        ScaleError::InvalidFactor(e)
+    }
+}
+
+/// The number of bytes taken by `width * height` pixels of `pixel_size` bytes, or `None` if that
+/// would be more than the largest allocation allowed, `isize::MAX` bytes.
+fn image_len(width: usize, height: usize, pixel_size: usize) -> Option<usize> {
+    width
+        .checked_mul(height)?
+        .checked_mul(pixel_size)
+        .filter(|&len| len <= isize::MAX as usize)
+}
+
+/// The largest width and height of an RGBA image that can be scaled up by `factor`
+/// on this target. Any image no wider and no taller than this can be scaled, as long as there is
+/// enough memory for it.
+///
+/// The limit is on the number of bytes in the scaled image, which can be no more than
+/// `isize::MAX`, so images much wider than they are tall, or the other way around, can go past
+/// one of these dimensions; the `try_` scaling functions such as [`try_scale_rgba`](crate::try_scale_rgba)
+/// check the exact dimensions. On 64-bit targets the limit is far beyond any real image,
+/// but on 32-bit targets it is 5792x5792 for a factor of 4.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn max_supported_dimensions(factor: usize) -> (usize, usize) {
+    let factor = ScaleFactor::new(factor)
+        .expect("scale factor must be between 1 and 6")
+        .get();
+    let side = (isize::MAX as usize / 4).isqrt() / factor;
+    (side, side)
+}
+
+/// Check that an image of `src_width * src_height` pixels of `pixel_size` bytes can be scaled up
+/// by `factor`, returning the length of the scaled image in bytes.
+pub(crate) fn check_dimensions(
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    pixel_size: usize,
+) -> Result<usize, ScaleError> {
+    let factor = ScaleFactor::try_from(factor)?.get();
+    let too_large = ScaleError::TooLarge {
+        width: src_width,
+        height: src_height,
+        factor,
+    };
+    let dst_width = src_width.checked_mul(factor).ok_or(too_large)?;
+    let dst_height = src_height.checked_mul(factor).ok_or(too_large)?;
+    image_len(dst_width, dst_height, pixel_size).ok_or(too_large)
+}
+
+/// Check that `source` holds exactly `src_width * src_height` pixels of `pixel_size` bytes,
+/// and that the image can be scaled up by `factor`.
+pub(crate) fn check_source(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    pixel_size: usize,
+) -> Result<(), ScaleError> {
+    check_dimensions(src_width, src_height, factor, pixel_size)?;
+    // the source is no larger than the scaled image, so this can't overflow
+    let expected = src_width * src_height * pixel_size;
+    match source.len() == expected {
+        true => Ok(()),
+        false => Err(ScaleError::SourceLength {
+            expected,
+            actual: source.len(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_supported_dimensions_fit() {
+        for factor in 1..=6 {
+            let (width, height) = max_supported_dimensions(factor);
+            assert!(check_dimensions(width, height, factor, 4).is_ok());
+            assert!(check_dimensions(width + 1, height + 1, factor, 4).is_err());
+        }
+    }
+
+    #[test]
+    fn overflowing_dimensions_are_too_large() {
+        let half = 1 << (usize::BITS / 2);
+        assert_eq!(
+            check_dimensions(half, half, 2, 4),
+            Err(ScaleError::TooLarge {
+                width: half,
+                height: half,
+                factor: 2
+            })
+        );
+        assert!(matches!(
+            check_dimensions(usize::MAX, 1, 2, 1),
+            Err(ScaleError::TooLarge { .. })
+        ));
+        assert!(matches!(
+            check_dimensions(1, 1, 7, 4),
+            Err(ScaleError::InvalidFactor(_))
+        ));
+    }
+
+    #[test]
+    fn try_scale_rgba_reports_bad_input() {
+        assert_eq!(
+            crate::try_scale_rgba(&[0; 4], 2, 1, 2),
+            Err(ScaleError::SourceLength {
+                expected: 8,
+                actual: 4
+            })
+        );
+        assert_eq!(
+            crate::try_scale_rgba(&[0; 8], 2, 1, 0),
+            Err(ScaleError::InvalidFactor(InvalidScaleFactor(0)))
+        );
+        assert_eq!(
+            crate::try_scale_rgba(&[0; 8], 2, 1, 3),
+            Ok(crate::scale_rgba(&[0; 8], 2, 1, 3))
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::error::Error;
+use std::fmt;
+
+/// A scaling factor supported by xBRZ, from 1 to 6.
+///
+/// The scaling functions take the factor as a plain `usize`; this type is for places where
+/// a factor is stored or read from user input, so that it is validated up front.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "usize", into = "usize")
+)]
+pub struct ScaleFactor(u8);
+
+impl ScaleFactor {
+    pub const MIN: ScaleFactor = ScaleFactor(1);
+    pub const MAX: ScaleFactor = ScaleFactor(6);
+
+    /// Returns `None` if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub const fn new(factor: usize) -> Option<Self> {
+        match factor {
+            1..=6 => Some(ScaleFactor(factor as u8)),
+            _ => None,
+        }
+    }
+
+    pub const fn get(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for ScaleFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl TryFrom<usize> for ScaleFactor {
+    type Error = InvalidScaleFactor;
+
+    fn try_from(factor: usize) -> Result<Self, Self::Error> {
+        ScaleFactor::new(factor).ok_or(InvalidScaleFactor(factor))
+    }
+}
+
+impl From<ScaleFactor> for usize {
+    fn from(factor: ScaleFactor) -> Self {
+        factor.get()
+    }
+}
+
+/// The error returned when converting a number outside of 1 to 6 into a [`ScaleFactor`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InvalidScaleFactor(pub usize);
+
+impl fmt::Display for InvalidScaleFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "scale factor must be between 1 and 6, got {}", self.0)
+    }
+}
+
+impl Error for InvalidScaleFactor {}
+
+/// Pick the scaling factor for showing a `src_width` by `src_height` image as large as possible
+/// within a `target_width` by `target_height` display, keeping its aspect ratio.
+///
+/// Returns the factor, and whether the scaled image then needs to be scaled down to fit. When the
+/// image can be scaled up to exactly fill the width or height of the target, that factor is returned
+/// and nothing else needs to be done. Otherwise the returned factor is the smallest one that is
+/// larger than the target, as scaling down from a larger image keeps more of the detail that xBRZ
+/// adds than scaling up from a smaller one; when scaling down isn't wanted, such as for pixel-perfect
+/// output, use one less than the returned factor instead, which fits without it (unless that is 0,
+/// when the image is larger than the target to begin with).
+///
+/// The factor is never more than 6, in which case the scaled image may still be smaller than the
+/// target. An empty source or target gives a factor of 1.
+///
+/// ```
+/// // 320x240 fills 1280x960 exactly at 4x
+/// assert_eq!(xbrz::best_factor(320, 240, 1280, 960), (4, false));
+/// // 1920x1080 is between 4x and 5x of 400x240, so scale by 5 and then down
+/// assert_eq!(xbrz::best_factor(400, 240, 1920, 1080), (5, true));
+/// ```
+pub fn best_factor(
+    src_width: usize,
+    src_height: usize,
+    target_width: usize,
+    target_height: usize,
+) -> (usize, bool) {
+    if src_width == 0 || src_height == 0 || target_width == 0 || target_height == 0 {
+        return (1, false);
+    }
+    let fits = |factor: usize| {
+        src_width.saturating_mul(factor) <= target_width
+            && src_height.saturating_mul(factor) <= target_height
+    };
+    let largest_fit = (1..=ScaleFactor::MAX.get())
+        .rev()
+        .find(|&factor| fits(factor))
+        .unwrap_or(0);
+    let fills =
+        src_width * largest_fit == target_width || src_height * largest_fit == target_height;
+
+    match largest_fit {
+        0 => (1, true),
+        6 => (6, false),
+        _ if fills => (largest_fit, false),
+        _ => (largest_fit + 1, true),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_factor_fills_or_covers_the_target() {
+        assert_eq!(best_factor(256, 224, 1024, 896), (4, false));
+        // limited by the height
+        assert_eq!(best_factor(256, 224, 1920, 1080), (5, true));
+        assert_eq!(best_factor(160, 144, 1000, 432), (3, false));
+        // limited by the width
+        assert_eq!(best_factor(100, 10, 250, 1000), (3, true));
+        assert_eq!(best_factor(100, 10, 200, 1000), (2, false));
+        // too large, or too small, to scale to fit
+        assert_eq!(best_factor(640, 480, 320, 240), (1, true));
+        assert_eq!(best_factor(640, 480, 640, 240), (1, true));
+        assert_eq!(best_factor(16, 16, 1000, 1000), (6, false));
+        assert_eq!(best_factor(0, 16, 1000, 1000), (1, false));
+        assert_eq!(best_factor(usize::MAX, 1, 2, 1), (1, true));
+    }
+}
@@ -0,0 +1,120 @@
+/// The layout of the pixels passed to [`scale`](crate::scale), for callers that only know the
+/// format of an image at runtime.
+///
+/// Multi-byte words are in native-endian order, matching the pixel formats of most frame buffers and
+/// emulator cores. Formats without an alpha channel are treated as opaque, and their edges are extended
+/// outwards when scaling rather than faded out to transparent.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PixelFormat {
+    /// 4 bytes per pixel in R, G, B, A order, as taken by [`scale_rgba`](crate::scale_rgba).
+    Rgba8,
+    /// 4 bytes per pixel in A, R, G, B order.
+    Argb8,
+    /// 4 bytes per pixel in B, G, R, A order, as used by Windows bitmaps.
+    Bgra8,
+    /// 3 bytes per pixel in R, G, B order.
+    Rgb8,
+    /// 32-bit words of the form `0x00RRGGBB`, the highest byte unused.
+    Xrgb8888,
+    /// 16-bit words with 5 bits for red and blue and 6 bits for green.
+    Rgb565,
+    /// 16-bit words with 5 bits per channel, the highest bit unused.
+    Rgb555,
+    /// 1 byte per pixel, scaled in the same way as [`scale_mask`](crate::scale_mask).
+    Gray8,
+}
+
+impl PixelFormat {
+    /// The number of bytes taken up by a single pixel of this format.
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgba8
+            | PixelFormat::Argb8
+            | PixelFormat::Bgra8
+            | PixelFormat::Xrgb8888 => 4,
+            PixelFormat::Rgb8 => 3,
+            PixelFormat::Rgb565 | PixelFormat::Rgb555 => 2,
+            PixelFormat::Gray8 => 1,
+        }
+    }
+}
+
+/// The order that the pixels of an image are stored in.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Layout {
+    /// Each row of pixels follows the last, from left to right, then top to bottom.
+    /// This is the layout taken by every other scaling function.
+    #[default]
+    RowMajor,
+    /// Each column of pixels follows the last, from top to bottom, then left to right,
+    /// as used by many plotting and numerical libraries.
+    ColumnMajor,
+    /// Each row of pixels follows the last, from left to right, then bottom to top,
+    /// as used by BMP files and OpenGL readbacks.
+    BottomUp,
+}
+
+/// A turn or flip of an image, applied as it is scaled. Turns are clockwise.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// The image as it is.
+    #[default]
+    Normal,
+    /// A quarter turn clockwise.
+    Rotate90,
+    /// A half turn.
+    Rotate180,
+    /// A quarter turn anticlockwise.
+    Rotate270,
+    /// Mirrored left to right.
+    FlipHorizontal,
+    /// Mirrored top to bottom.
+    FlipVertical,
+    /// Mirrored along the diagonal from the top left corner, so that rows become columns.
+    Transpose,
+    /// Mirrored along the diagonal from the top right corner.
+    Transverse,
+}
+
+impl Orientation {
+    /// Whether the width and height of an image swap over when it is turned this way.
+    pub const fn swaps_dimensions(self) -> bool {
+        matches!(
+            self,
+            Orientation::Rotate90
+                | Orientation::Rotate270
+                | Orientation::Transpose
+                | Orientation::Transverse
+        )
+    }
+
+    /// The width and height of a `width` by `height` image once it is turned this way.
+    pub const fn dimensions(self, width: usize, height: usize) -> (usize, usize) {
+        match self.swaps_dimensions() {
+            true => (height, width),
+            false => (width, height),
+        }
+    }
+
+    /// The position in a `width` by `height` image of the pixel that ends up at `(x, y)` once
+    /// the image is turned this way.
+    pub(crate) const fn source_xy(
+        self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> (usize, usize) {
+        match self {
+            Orientation::Normal => (x, y),
+            Orientation::Rotate90 => (y, height - 1 - x),
+            Orientation::Rotate180 => (width - 1 - x, height - 1 - y),
+            Orientation::Rotate270 => (width - 1 - y, x),
+            Orientation::FlipHorizontal => (width - 1 - x, y),
+            Orientation::FlipVertical => (x, height - 1 - y),
+            Orientation::Transpose => (y, x),
+            Orientation::Transverse => (width - 1 - y, height - 1 - x),
+        }
+    }
+}
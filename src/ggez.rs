@@ -0,0 +1,71 @@
+//! Helpers for scaling images into [ggez](https://docs.rs/ggez) images.
+//!
+//! Enabled by the `ggez` feature.
+use ::ggez::context::Has;
+use ::ggez::graphics::{GraphicsContext, Image, ImageFormat};
+use ::ggez::{GameError, GameResult};
+
+/// Use the xBRZ algorithm to scale up an RGBA image by an integer factor, and upload the result
+/// as a ggez [`Image`] in the sRGB `Rgba8UnormSrgb` format, as used for images loaded by ggez.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn image_from_rgba(
+    gfx: &impl Has<GraphicsContext>,
+    source: &[u8],
+    src_width: u32,
+    src_height: u32,
+    factor: usize,
+) -> Image {
+    let scaled = crate::scale_rgba(source, src_width as usize, src_height as usize, factor);
+    Image::from_pixels(
+        gfx,
+        &scaled,
+        ImageFormat::Rgba8UnormSrgb,
+        src_width * factor as u32,
+        src_height * factor as u32,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up a ggez [`Image`] by an integer factor, returning a new image
+/// in the same format.
+///
+/// The pixels of the image are read back from the GPU, so this is best done once, while loading,
+/// rather than every frame. Images in RGBA and BGRA formats are supported, and an error is returned
+/// for any other.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_image(
+    gfx: &impl Has<GraphicsContext>,
+    image: &Image,
+    factor: usize,
+) -> GameResult<Image> {
+    let format = image.format();
+    let bgra = match format {
+        ImageFormat::Rgba8Unorm | ImageFormat::Rgba8UnormSrgb => false,
+        ImageFormat::Bgra8Unorm | ImageFormat::Bgra8UnormSrgb => true,
+        _ => {
+            return Err(GameError::RenderError(format!(
+                "can't scale an image in the {format:?} format"
+            )))
+        }
+    };
+
+    let (width, height) = (image.width(), image.height());
+    let pixels = image.to_pixels(gfx)?;
+    let scaled = match bgra {
+        true => crate::scale_bgra(&pixels, width as usize, height as usize, factor),
+        false => crate::scale_rgba(&pixels, width as usize, height as usize, factor),
+    };
+    Ok(Image::from_pixels(
+        gfx,
+        &scaled,
+        format,
+        width * factor as u32,
+        height * factor as u32,
+    ))
+}
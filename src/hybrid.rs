@@ -0,0 +1,220 @@
+//! xBRZ mixed with a bicubic upscale, for a softer result that keeps xBRZ's shapes.
+use crate::ScalerConfig;
+
+/// The ways of scaling an image that [`scale_rgba_with_algorithm`] can choose between, so that
+/// frontends can keep the choice in their settings alongside the [`ScalerConfig`].
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Algorithm {
+    /// Plain xBRZ, as [`scale_rgba_with_config`](crate::scale_rgba_with_config).
+    #[default]
+    Xbrz,
+    /// xBRZ mixed with a bicubic upscale, as [`scale_rgba_hybrid`], with `softness` the share of
+    /// the bicubic image.
+    XbrzHybrid { softness: f32 },
+}
+
+/// Scale up an image by an integer factor with the chosen `algorithm`.
+///
+/// ```
+/// let (width, height) = (4, 4);
+/// let source = vec![255u8; width * height * 4];
+///
+/// let config = xbrz::ScalerConfig::default();
+/// let algorithm = xbrz::Algorithm::XbrzHybrid { softness: 0.3 };
+/// let scaled = xbrz::scale_rgba_with_algorithm(&source, width, height, 2, &config, algorithm);
+/// assert_eq!(scaled.len(), width * 2 * height * 2 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `factor` is not one of 1, 2, 3, 4, 5 or 6, or if the softness of a hybrid is not finite.
+pub fn scale_rgba_with_algorithm(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    algorithm: Algorithm,
+) -> Vec<u8> {
+    match algorithm {
+        Algorithm::Xbrz => {
+            crate::scale_rgba_with_config(source, src_width, src_height, factor, config)
+        }
+        Algorithm::XbrzHybrid { softness } => {
+            scale_rgba_hybrid(source, src_width, src_height, factor, config, softness)
+        }
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, and mix the result with a
+/// bicubic upscale of the same image.
+///
+/// `softness` is the share of the bicubic image in the result, from 0, which is plain xBRZ, to 1,
+/// which is plain bicubic, and values outside of that range are clamped to it. Values around 0.3
+/// soften the hard edges of xBRZ without blurring them away. The bicubic upscale uses the Catmull-Rom spline, repeating the edge pixels past the edges
+/// of the image. Colours are weighted by their alpha, so transparent pixels don't darken their
+/// neighbours.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `factor` is not one of 1, 2, 3, 4, 5 or 6, or if `softness` is not finite.
+pub fn scale_rgba_hybrid(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    softness: f32,
+) -> Vec<u8> {
+    // NaN would otherwise get through the clamp and turn every pixel transparent
+    assert!(softness.is_finite(), "softness {softness} is not finite");
+    let mut scaled = crate::scale_rgba_with_config(source, src_width, src_height, factor, config);
+    let softness = softness.clamp(0.0, 1.0);
+    if softness == 0.0 || scaled.is_empty() {
+        return scaled;
+    }
+
+    let bicubic = bicubic(source, src_width, src_height, factor);
+    for (px, smooth) in scaled.chunks_exact_mut(4).zip(bicubic.chunks_exact(4)) {
+        let alpha = lerp(px[3] as f32, smooth[3], softness);
+        for c in 0..3 {
+            let colour = lerp(px[c] as f32 * px[3] as f32, smooth[c], softness);
+            px[c] = if alpha > 0.0 {
+                to_u8(colour / alpha)
+            } else {
+                0
+            };
+        }
+        px[3] = to_u8(alpha);
+    }
+    scaled
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn to_u8(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// The first of the four source pixels read for the output pixel at each position within a scaled
+/// pixel, relative to the source pixel, and their weights.
+fn taps(factor: usize) -> Vec<(isize, [f32; 4])> {
+    (0..factor)
+        .map(|phase| {
+            // the centre of the output pixel, in source pixels, relative to the source pixel's centre
+            let t = (phase as f32 + 0.5) / factor as f32 - 0.5;
+            let base = t.floor();
+            let x = t - base;
+            // Catmull-Rom, which passes through the source pixels
+            let weights = [
+                ((-x + 2.0) * x - 1.0) * x / 2.0,
+                ((3.0 * x - 5.0) * x * x + 2.0) / 2.0,
+                ((-3.0 * x + 4.0) * x + 1.0) * x / 2.0,
+                (x - 1.0) * x * x / 2.0,
+            ];
+            (base as isize - 1, weights)
+        })
+        .collect()
+}
+
+/// A bicubic upscale of an RGBA image, with its colours premultiplied by alpha and not yet rounded.
+fn bicubic(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<f32> {
+    let taps = taps(factor);
+    let dst_width = src_width * factor;
+    let clamp = |i: isize, len: usize| i.clamp(0, len as isize - 1) as usize;
+
+    // scale each row horizontally, then the columns of the result vertically
+    let mut rows = vec![0.0; src_height * dst_width * 4];
+    for (y, row) in rows.chunks_exact_mut(dst_width * 4).enumerate() {
+        for (x, out) in row.chunks_exact_mut(4).enumerate() {
+            let (first, weights) = taps[x % factor];
+            let mut sum = [0.0; 4];
+            for (k, weight) in weights.iter().enumerate() {
+                let sx = clamp((x / factor) as isize + first + k as isize, src_width);
+                let px = &source[(y * src_width + sx) * 4..][..4];
+                let alpha = px[3] as f32;
+                for c in 0..3 {
+                    sum[c] += weight * px[c] as f32 * alpha;
+                }
+                sum[3] += weight * alpha;
+            }
+            out.copy_from_slice(&sum);
+        }
+    }
+
+    let mut scaled = vec![0.0; src_height * factor * dst_width * 4];
+    for (y, row) in scaled.chunks_exact_mut(dst_width * 4).enumerate() {
+        let (first, weights) = taps[y % factor];
+        for (k, weight) in weights.iter().enumerate() {
+            let sy = clamp((y / factor) as isize + first + k as isize, src_height);
+            for (out, &value) in row
+                .iter_mut()
+                .zip(&rows[sy * dst_width * 4..][..dst_width * 4])
+            {
+                *out += weight * value;
+            }
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn softness_mixes_in_bicubic() {
+        // a hard vertical edge between two opaque colours
+        let (width, height) = (4, 4);
+        let image: Vec<u8> = (0..width * height)
+            .flat_map(|i| match i % width < 2 {
+                true => [200, 40, 40, 255],
+                false => [20, 20, 120, 255],
+            })
+            .collect();
+        let config = ScalerConfig::default();
+        let xbrz = crate::scale_rgba_with_config(&image, width, height, 3, &config);
+
+        assert_eq!(
+            scale_rgba_hybrid(&image, width, height, 3, &config, 0.0),
+            xbrz
+        );
+
+        let soft = scale_rgba_hybrid(&image, width, height, 3, &config, 1.0);
+        let row = &soft[3 * width * 3 * 4..][..width * 3 * 4];
+        // far from the edge the colours are kept, and next to it they are mixed
+        assert_eq!(&row[..4], &[200, 40, 40, 255]);
+        assert_eq!(&row[row.len() - 4..], &[20, 20, 120, 255]);
+        let next_to_edge = &row[5 * 4..][..4];
+        assert!((20..200).contains(&next_to_edge[0]) && next_to_edge[0] != xbrz[5 * 4]);
+        assert!(soft.chunks_exact(4).all(|px| px[3] == 255));
+    }
+
+    #[test]
+    fn algorithms_match_their_scalers() {
+        let (width, height) = (5, 3);
+        let image: Vec<u8> = (0..width * height)
+            .flat_map(|i| [(i * 40) as u8, 40, 200, 255])
+            .collect();
+        let config = ScalerConfig::default();
+        assert_eq!(
+            scale_rgba_with_algorithm(&image, width, height, 2, &config, Algorithm::default()),
+            crate::scale_rgba_with_config(&image, width, height, 2, &config)
+        );
+        let hybrid = Algorithm::XbrzHybrid { softness: 0.4 };
+        assert_eq!(
+            scale_rgba_with_algorithm(&image, width, height, 2, &config, hybrid),
+            scale_rgba_hybrid(&image, width, height, 2, &config, 0.4)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "softness NaN is not finite")]
+    fn nan_softness_is_rejected() {
+        scale_rgba_hybrid(&[0; 4 * 4], 2, 2, 2, &ScalerConfig::default(), f32::NAN);
+    }
+}
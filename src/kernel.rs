@@ -1,5 +1,3 @@
-use std::mem;
-
 use crate::blend::{Blend2x2, BlendType};
 use crate::config::ScalerConfig;
 use crate::oob_reader::OobReader;
@@ -44,45 +42,30 @@ pub(crate) struct Kernel4x4<P: Pixel> {
     pub(crate) p: P,
 }
 
-#[repr(C)]
-struct Kernel3x3<P: Pixel> {
-    pub(crate) a: P,
-    pub(crate) b: P,
-    pub(crate) c: P,
-
-    pub(crate) d: P,
-    pub(crate) e: P,
-    pub(crate) f: P,
-
-    pub(crate) g: P,
-    pub(crate) h: P,
-    pub(crate) i: P,
-}
-
 impl<P: Pixel> Kernel4x4<P> {
     #[inline]
-    pub(crate) fn init_row<'src>(oob: &impl OobReader<'src, P>) -> Self {
+    pub(crate) fn init_row<'src>(oob: &impl OobReader<'src, P>, x_first: isize) -> Self {
         let mut kernel = Self::default();
 
-        oob.fill_dhlp(&mut kernel, -4);
+        oob.fill_dhlp(&mut kernel, x_first - 4);
         kernel.a = kernel.d;
         kernel.e = kernel.h;
         kernel.i = kernel.l;
         kernel.m = kernel.p;
 
-        oob.fill_dhlp(&mut kernel, -3);
+        oob.fill_dhlp(&mut kernel, x_first - 3);
         kernel.b = kernel.d;
         kernel.f = kernel.h;
         kernel.j = kernel.l;
         kernel.n = kernel.p;
 
-        oob.fill_dhlp(&mut kernel, -2);
+        oob.fill_dhlp(&mut kernel, x_first - 2);
         kernel.c = kernel.d;
         kernel.g = kernel.h;
         kernel.k = kernel.l;
         kernel.o = kernel.p;
 
-        oob.fill_dhlp(&mut kernel, -1);
+        oob.fill_dhlp(&mut kernel, x_first - 1);
 
         kernel
     }
@@ -107,70 +90,318 @@ impl<P: Pixel> Kernel4x4<P> {
         oob.fill_dhlp(self, x);
     }
 
+    /// How many of the columns at the right of the kernel, up to all four, are the same single
+    /// colour. The kernel is uniform when all four are, and no corner of a uniform kernel is ever
+    /// blended, whatever the config, so it can be skipped without preprocessing it.
     #[inline]
-    pub(crate) fn pre_process_corners(&self, cfg: &ScalerConfig) -> Blend2x2 {
-        let mut result = Blend2x2::default();
-        let ycbcr = YCbCrLookup::instance();
+    pub(crate) fn flat_columns(&self) -> usize {
+        let columns = [
+            [self.a, self.e, self.i, self.m],
+            [self.b, self.f, self.j, self.n],
+            [self.c, self.g, self.k, self.o],
+            [self.d, self.h, self.l, self.p],
+        ];
+        columns
+            .iter()
+            .rev()
+            .take_while(|column| column.iter().all(|&px| px == self.d))
+            .count()
+    }
+
+    /// The [`flat_columns`](Self::flat_columns) of the kernel after [`next_column`](Self::next_column),
+    /// given their number before it, which only needs the new column to be checked.
+    #[inline(always)]
+    pub(crate) fn next_flat_columns(&self, flat_columns: usize) -> usize {
+        let column_flat = self.h == self.d && self.l == self.d && self.p == self.d;
+        match column_flat {
+            false => 0,
+            true if flat_columns > 0 && self.c == self.d => (flat_columns + 1).min(4),
+            true => 1,
+        }
+    }
+
+    /// Whether the whole kernel is fully transparent. As with a uniform kernel, none of its corners
+    /// are blended, since fully transparent pixels are all the same distance, 0, from each other.
+    #[inline(always)]
+    pub(crate) fn is_transparent(&self) -> bool {
+        self.pixels().iter().all(|px| px.alpha() == 0)
+    }
+
+    #[inline(always)]
+    fn pixels(&self) -> [P; 16] {
+        [
+            self.a, self.b, self.c, self.d, self.e, self.f, self.g, self.h, self.i, self.j, self.k,
+            self.l, self.m, self.n, self.o, self.p,
+        ]
+    }
+
+    #[inline]
+    pub(crate) fn pre_process_corners(&self, cfg: &ScalerConfig, ycbcr: &YCbCrLookup) -> Blend2x2 {
+        if self.is_never_blended(cfg, ycbcr) {
+            return Blend2x2::default();
+        }
 
+        let edge = match fixed_point_weights(cfg) {
+            Some((c_bias, dir_thresh)) if ycbcr.is_fixed_point() => {
+                let (jg, fk) = self.distances_fixed(c_bias, ycbcr);
+                Edge::new(jg, fk, |low, high| dir_thresh * low < high << 8)
+            }
+            _ => {
+                let dir_thresh = cfg.dominant_direction_threshold as f32;
+                let (jg, fk) = self.distances(cfg, ycbcr);
+                Edge::new(jg, fk, |low, high| dir_thresh * low < high)
+            }
+        };
+        self.blended_corners(edge)
+    }
+
+    /// [`pre_process_corners`](Self::pre_process_corners) for a frame of an animation, where `held`
+    /// is the edge found here in the frame before. The held edge is kept unless the distances
+    /// favour another by more than `margin`, as a fraction of the lower distance.
+    pub(crate) fn pre_process_corners_held(
+        &self,
+        cfg: &ScalerConfig,
+        ycbcr: &YCbCrLookup,
+        held: Edge,
+        margin: f64,
+    ) -> Blend2x2 {
+        if self.is_never_blended(cfg, ycbcr) {
+            return Blend2x2::default();
+        }
+
+        let (jg, fk, dir_thresh) = match fixed_point_weights(cfg) {
+            Some((c_bias, dir_thresh)) if ycbcr.is_fixed_point() => {
+                let (jg, fk) = self.distances_fixed(c_bias, ycbcr);
+                // the threshold has 8 fractional bits, as the distances do
+                (jg as f64, fk as f64, dir_thresh as f64 / 256.0)
+            }
+            _ => {
+                let (jg, fk) = self.distances(cfg, ycbcr);
+                (jg as f64, fk as f64, cfg.dominant_direction_threshold)
+            }
+        };
+        self.blended_corners(Edge::held(jg, fk, dir_thresh, held, margin))
+    }
+
+    /// Whether no corner of the central 2x2 pixels can be blended, whatever the colour distances:
+    /// they form a flat area or straight edge, or are part of a thin line or glyph that the config
+    /// leaves alone.
+    #[inline(always)]
+    fn is_never_blended(&self, cfg: &ScalerConfig, ycbcr: &YCbCrLookup) -> bool {
         if self.f == self.g && self.j == self.k {
-            return result;
+            return true;
         }
 
         if self.f == self.j && self.g == self.k {
-            return result;
+            return true;
         }
 
+        if cfg.preserve_thin_lines && self.has_thin_line(cfg, ycbcr) {
+            return true;
+        }
+
+        match cfg.glyph_contrast {
+            Some(contrast) => self.is_glyph_like(contrast as f32, ycbcr),
+            None => false,
+        }
+    }
+
+    /// The corners cut off by an edge, leaving out those of pixels that match a pixel next to them
+    /// on the edge.
+    #[inline(always)]
+    fn blended_corners(&self, edge: Edge) -> Blend2x2 {
+        let mut result = Blend2x2::default();
+        match edge {
+            Edge::AlongJG(blend_mode) => {
+                if self.f != self.g && self.f != self.j {
+                    result.top_left = blend_mode;
+                }
+
+                if self.k != self.j && self.k != self.g {
+                    result.bottom_right = blend_mode;
+                }
+            }
+            Edge::AlongFK(blend_mode) => {
+                if self.j != self.f && self.j != self.k {
+                    result.bottom_left = blend_mode;
+                }
+
+                if self.g != self.f && self.g != self.k {
+                    result.top_right = blend_mode;
+                }
+            }
+            Edge::None => {}
+        }
+
+        result
+    }
+
+    /// The colour distances across the JG and FK diagonals of the central 2x2 pixels, with the
+    /// distance between the central pixels weighed by the center direction bias. The edge runs
+    /// along the diagonal with the lower distance.
+    #[inline(always)]
+    fn distances(&self, cfg: &ScalerConfig, ycbcr: &YCbCrLookup) -> (f32, f32) {
         macro_rules! dist {
             ($x:ident, $y:ident) => {
-                ycbcr.dist(self.$x, self.$y)
+                self.$x.dist(self.$y, ycbcr)
             };
         }
 
         let c_bias = cfg.center_direction_bias as f32;
-        let dir_thresh = cfg.dominant_direction_threshold as f32;
 
         let jg = dist!(i, f) + dist!(f, c) + dist!(n, k) + dist!(k, h) + c_bias * dist!(j, g);
         let fk = dist!(e, j) + dist!(j, o) + dist!(b, g) + dist!(g, l) + c_bias * dist!(f, k);
+        (jg, fk)
+    }
 
-        if jg < fk {
-            let blend_mode = if dir_thresh * jg < fk {
-                BlendType::Dominant
-            } else {
-                BlendType::Normal
+    /// [`distances`](Self::distances) worked out with integers, from fixed-point distances and a
+    /// bias with 8 fractional bits.
+    #[inline(always)]
+    fn distances_fixed(&self, c_bias: u64, ycbcr: &YCbCrLookup) -> (u64, u64) {
+        macro_rules! dist {
+            ($x:ident, $y:ident) => {
+                self.$x.dist_fixed(self.$y, ycbcr) as u64
             };
+        }
 
-            if self.f != self.g && self.f != self.j {
-                result.top_left = blend_mode;
-            }
+        let jg =
+            ((dist!(i, f) + dist!(f, c) + dist!(n, k) + dist!(k, h)) << 8) + c_bias * dist!(j, g);
+        let fk =
+            ((dist!(e, j) + dist!(j, o) + dist!(b, g) + dist!(g, l)) << 8) + c_bias * dist!(f, k);
+        (jg, fk)
+    }
 
-            if self.k != self.j && self.k != self.g {
-                result.bottom_right = blend_mode;
-            }
-        } else if fk < jg {
-            let blend_mode = if dir_thresh * fk < jg {
-                BlendType::Dominant
-            } else {
-                BlendType::Normal
-            };
+    /// Whether a horizontal or vertical line one pixel wide runs across the whole kernel through
+    /// the central 2x2 pixels, with different colours on both sides of it.
+    fn has_thin_line(&self, cfg: &ScalerConfig, ycbcr: &YCbCrLookup) -> bool {
+        let tolerance = cfg.equal_color_tolerance as f32;
+        let eq = |x: P, y: P| x.dist(y, ycbcr) < tolerance;
+        // the line, then the pixels on either side of its two central pixels
+        let is_line = |[w, x, y, z]: [P; 4], before: [P; 2], after: [P; 2]| {
+            eq(w, x)
+                && eq(x, y)
+                && eq(y, z)
+                && !eq(x, before[0])
+                && !eq(y, before[1])
+                && !eq(x, after[0])
+                && !eq(y, after[1])
+        };
+
+        is_line(
+            [self.b, self.f, self.j, self.n],
+            [self.e, self.i],
+            [self.g, self.k],
+        ) || is_line(
+            [self.c, self.g, self.k, self.o],
+            [self.f, self.j],
+            [self.h, self.l],
+        ) || is_line(
+            [self.e, self.f, self.g, self.h],
+            [self.b, self.c],
+            [self.j, self.k],
+        ) || is_line(
+            [self.i, self.j, self.k, self.l],
+            [self.f, self.g],
+            [self.n, self.o],
+        )
+    }
 
-            if self.j != self.f && self.j != self.k {
-                result.bottom_left = blend_mode;
-            }
+    /// Whether the kernel holds only two colours, at least `contrast` apart, as bitmap text does.
+    fn is_glyph_like(&self, contrast: f32, ycbcr: &YCbCrLookup) -> bool {
+        let pixels = [
+            self.a, self.b, self.c, self.d, self.e, self.f, self.g, self.h, self.i, self.j, self.k,
+            self.l, self.m, self.n, self.o, self.p,
+        ];
+        let first = pixels[0];
+        let Some(&second) = pixels.iter().find(|&&p| p != first) else {
+            return false;
+        };
+        pixels.iter().all(|&p| p == first || p == second) && first.dist(second, ycbcr) >= contrast
+    }
+}
 
-            if self.g != self.f && self.g != self.k {
-                result.top_right = blend_mode;
-            }
-        }
+/// The diagonal of the central 2x2 pixels of a kernel that an edge runs along, and how strongly.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Edge {
+    /// The edge runs from J to G, cutting off the corners of F and K.
+    AlongJG(BlendType),
+    /// The edge runs from F to K, cutting off the corners of J and G.
+    AlongFK(BlendType),
+    None,
+}
 
-        result
+impl Edge {
+    /// The edge runs along the diagonal whose pixels are more alike, and is dominant when
+    /// `is_dominant(low, high)` for the lower and higher of the two distances.
+    #[inline(always)]
+    fn new<T: PartialOrd + Copy>(jg: T, fk: T, is_dominant: impl Fn(T, T) -> bool) -> Self {
+        let blend_mode = |low, high| match is_dominant(low, high) {
+            true => BlendType::Dominant,
+            false => BlendType::Normal,
+        };
+        if jg < fk {
+            Edge::AlongJG(blend_mode(jg, fk))
+        } else if fk < jg {
+            Edge::AlongFK(blend_mode(fk, jg))
+        } else {
+            Edge::None
+        }
     }
 
-    fn as_3x3(&self) -> &Kernel3x3<P> {
-        // SAFETY: memory layout of 3x3 is smaller than layout of 4x4
-        unsafe { &*(self as *const Kernel4x4<P> as *const Kernel3x3<P>) }
+    /// The edge for distances of `jg` and `fk`, leaning towards the `held` edge of the frame before:
+    /// the diagonal it ran along needs to lose by more than `margin` for the edge to move, and any
+    /// other needs to win by more than it. Whether the edge is dominant leans towards `held` in the
+    /// same way. With a margin of 0, this is the same as [`new`](Self::new).
+    pub(crate) fn held(jg: f64, fk: f64, dir_thresh: f64, held: Edge, margin: f64) -> Self {
+        let lean = 1.0 + margin.max(0.0);
+        let (jg_lean, fk_lean) = match held {
+            Edge::AlongJG(_) => (1.0 / lean, lean),
+            Edge::AlongFK(_) => (lean, 1.0 / lean),
+            Edge::None => (lean, lean),
+        };
+        let blend_mode = |low: f64, high: f64, held_mode: Option<BlendType>| {
+            let lean = match held_mode {
+                Some(BlendType::Dominant) => 1.0 / lean,
+                Some(_) => lean,
+                None => 1.0,
+            };
+            match dir_thresh * low * lean < high {
+                true => BlendType::Dominant,
+                false => BlendType::Normal,
+            }
+        };
+        if jg * jg_lean < fk {
+            let held_mode = match held {
+                Edge::AlongJG(mode) => Some(mode),
+                _ => None,
+            };
+            Edge::AlongJG(blend_mode(jg, fk, held_mode))
+        } else if fk * fk_lean < jg {
+            let held_mode = match held {
+                Edge::AlongFK(mode) => Some(mode),
+                _ => None,
+            };
+            Edge::AlongFK(blend_mode(fk, jg, held_mode))
+        } else {
+            Edge::None
+        }
     }
 }
 
+/// The `center_direction_bias` and `dominant_direction_threshold` of a config with 8 fractional
+/// bits, if they are in a range where fixed-point distances weighed by them fit into 64 bits.
+fn fixed_point_weights(cfg: &ScalerConfig) -> Option<(u64, u64)> {
+    let fixed = |v: f64| {
+        (0.0..=4096.0)
+            .contains(&v)
+            .then(|| (v * 256.0).round() as u64)
+    };
+    Some((
+        fixed(cfg.center_direction_bias)?,
+        fixed(cfg.dominant_direction_threshold)?,
+    ))
+}
+
 #[repr(u8)]
 #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub(crate) enum Rotation {
@@ -184,45 +415,26 @@ pub(crate) enum Rotation {
 impl Rotation {
     #[inline]
     pub(crate) const fn from_u8(value: u8) -> Self {
-        assert!(value <= Rotation::Clockwise270 as u8);
-        unsafe { mem::transmute(value) }
-    }
-
-    #[inline]
-    pub(crate) const fn is_none(self) -> bool {
-        matches!(self, Rotation::None)
-    }
-
-    /*
-    #[inline]
-    pub(crate) const fn rotate_cw(self) -> Self {
-        match self {
-            Rotation::None => Rotation::Clockwise90,
-            Rotation::Clockwise90 => Rotation::Clockwise180,
-            Rotation::Clockwise180 => Rotation::Clockwise270,
-            Rotation::Clockwise270 => Rotation::None,
-        }
-    }*/
-
-    #[inline]
-    pub(crate) const fn rotate_ccw(self) -> Self {
-        match self {
-            Rotation::None => Rotation::Clockwise270,
-            Rotation::Clockwise90 => Rotation::None,
-            Rotation::Clockwise180 => Rotation::Clockwise90,
-            Rotation::Clockwise270 => Rotation::Clockwise180,
+        match value {
+            0 => Rotation::None,
+            1 => Rotation::Clockwise90,
+            2 => Rotation::Clockwise180,
+            3 => Rotation::Clockwise270,
+            _ => panic!("rotation out of range"),
         }
     }
 }
 
-pub(crate) struct RotKernel3x3<'ker, P: Pixel, const R: u8>(&'ker Kernel3x3<P>);
+/// The top left 3x3 pixels of a kernel, from A to K, read as if rotated clockwise by `R`. The pixels
+/// are named A to I, in the same way as the 4x4 kernel, with E in the centre.
+pub(crate) struct RotKernel3x3<'ker, P: Pixel, const R: u8>(&'ker Kernel4x4<P>);
 
 macro_rules! impl_getter {
-    ($x:ident, $rot90:ident, $rot180:ident, $rot270:ident) => {
+    ($x:ident => $rot0:ident, $rot90:ident, $rot180:ident, $rot270:ident) => {
         #[inline]
         pub(crate) fn $x(&self) -> P {
             if R == Rotation::None as u8 {
-                self.0.$x
+                self.0.$rot0
             } else if R == Rotation::Clockwise90 as u8 {
                 self.0.$rot90
             } else if R == Rotation::Clockwise180 as u8 {
@@ -238,16 +450,81 @@ impl<'ker, P: Pixel, const R: u8> RotKernel3x3<'ker, P, R> {
     #[inline]
     pub(crate) fn new(kernel: &'ker Kernel4x4<P>) -> Self {
         assert!(R <= Rotation::Clockwise270 as u8);
-        Self(kernel.as_3x3())
-    }
-
-    /*impl_getter!(a, g, i, c);*/
-    impl_getter!(b, d, h, f);
-    impl_getter!(c, a, g, i);
-    impl_getter!(d, h, f, b);
-    impl_getter!(e, e, e, e);
-    impl_getter!(f, b, d, h);
-    impl_getter!(g, i, c, a);
-    impl_getter!(h, f, b, d);
-    impl_getter!(i, c, a, g);
+        Self(kernel)
+    }
+
+    impl_getter!(a => a, i, k, c);
+    impl_getter!(b => b, e, j, g);
+    impl_getter!(c => c, a, i, k);
+    impl_getter!(d => e, j, g, b);
+    impl_getter!(e => f, f, f, f);
+    impl_getter!(f => g, b, e, j);
+    impl_getter!(g => i, k, c, a);
+    impl_getter!(h => j, g, b, e);
+    impl_getter!(i => k, c, a, i);
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::pixel::{Mask8, Rgba8};
+    use crate::Preset;
+
+    type Grid = [[Mask8; 3]; 3];
+
+    /// The 3x3 kernel as read through a rotation of `R`.
+    fn read<const R: u8>(kernel: &Kernel4x4<Mask8>) -> Grid {
+        let k = RotKernel3x3::<Mask8, R>::new(kernel);
+        [
+            [k.a(), k.b(), k.c()],
+            [k.d(), k.e(), k.f()],
+            [k.g(), k.h(), k.i()],
+        ]
+    }
+
+    /// The grid turned a quarter clockwise.
+    fn turn(grid: Grid) -> Grid {
+        std::array::from_fn(|row| std::array::from_fn(|col| grid[2 - col][row]))
+    }
+
+    proptest! {
+        #[test]
+        fn rotated_reads_match_rotated_kernels(pixels in any::<[u8; 9]>()) {
+            let [a, b, c, e, f, g, i, j, k] = pixels.map(|v| Mask8::from_rgba([v, v, v, 255]));
+            let kernel = Kernel4x4 { a, b, c, e, f, g, i, j, k, ..Default::default() };
+
+            let unrotated = read::<{ Rotation::None as u8 }>(&kernel);
+            prop_assert_eq!(unrotated, [[a, b, c], [e, f, g], [i, j, k]]);
+            prop_assert_eq!(read::<{ Rotation::Clockwise90 as u8 }>(&kernel), turn(unrotated));
+            prop_assert_eq!(read::<{ Rotation::Clockwise180 as u8 }>(&kernel), turn(turn(unrotated)));
+            prop_assert_eq!(
+                read::<{ Rotation::Clockwise270 as u8 }>(&kernel),
+                turn(turn(turn(unrotated)))
+            );
+            prop_assert_eq!(turn(turn(turn(turn(unrotated)))), unrotated);
+        }
+
+        #[test]
+        fn flat_kernels_are_never_blended(
+            colours in any::<[[u8; 4]; 16]>(),
+            preset in prop_oneof![Just(Preset::Faithful), Just(Preset::Smooth), Just(Preset::Text)],
+        ) {
+            let config = ScalerConfig {
+                preserve_thin_lines: true,
+                ..ScalerConfig::preset(preset)
+            };
+            // fully transparent, with each pixel keeping its own colour, or all one colour
+            let transparent = colours.map(|[r, g, b, _]| Rgba8::from_rgba([r, g, b, 0]));
+            let single = [Rgba8::from_rgba(colours[0]); 16];
+
+            for [a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p] in [transparent, single] {
+                let kernel = Kernel4x4 { a, b, c, d, e, f, g, h, i, j, k, l, m, n, o, p };
+                prop_assert!(kernel.flat_columns() == 4 || kernel.is_transparent());
+                let corners = kernel.pre_process_corners(&config, YCbCrLookup::instance(config.ycbcr_matrix));
+                prop_assert_eq!(corners, Blend2x2::default());
+            }
+        }
+    }
 }
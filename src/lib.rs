@@ -5,21 +5,125 @@
 //!
 //! This project is a direct port of xBRZ version 1.8 into Rust.
 //!
-use std::mem;
+#![cfg_attr(
+    all(feature = "forbid_unsafe", not(feature = "mmap")),
+    forbid(unsafe_code)
+)]
+// files can't be memory-mapped safely, so with the mmap feature that is the only unsafe code allowed
+#![cfg_attr(all(feature = "forbid_unsafe", feature = "mmap"), deny(unsafe_code))]
 
-use crate::config::ScalerConfig;
-use crate::oob_reader::OobReaderTransparent;
-use crate::pixel::{Pixel, Rgba8};
-use crate::scaler::{Scaler, Scaler2x, Scaler3x, Scaler4x, Scaler5x, Scaler6x};
+use std::ops::Range;
 
+#[cfg(feature = "allocator_api")]
+pub use crate::allocator::scale_rgba_in;
+pub use crate::alpha::{premultiply_alpha, unpremultiply_alpha};
+pub use crate::animation::{
+    scale_animation, scale_animation_temporal, Disposal, Frame, FrameBlend,
+};
+pub use crate::arena::ScaleArena;
+use crate::arena::Scratch;
+#[cfg(feature = "async")]
+pub use crate::async_scale::{scale_rgba_async, CancellationToken, Cancelled};
+pub use crate::batch::{scale_batch, ScaleJob};
+pub use crate::blend::{Blend2x2, BlendType};
+pub use crate::blend_map::{compute_blend_map, debug_blend_map};
+pub use crate::config::{AlphaMode, BlendWeights, OutlineConfig, Preset, ScalerConfig};
+use crate::config::{LookupConfig, PixelConfig, RegionConfig};
+pub use crate::crop::{scale_rgba_cropped, scale_rgba_cropped_with_config, Rect};
+pub use crate::deadline::DeadlineScaler;
+#[cfg(feature = "digest")]
+pub use crate::digest::{digest, digest_rgba, digest_with_config};
+pub use crate::edge_shape::{edge_shapes, scale_rgba_with_edge_shapes, EdgeShape};
+use crate::error::{check_dimensions, check_source};
+pub use crate::error::{max_supported_dimensions, ScaleError};
+pub use crate::factor::{best_factor, InvalidScaleFactor, ScaleFactor};
+pub use crate::format::{Layout, Orientation, PixelFormat};
+pub use crate::hybrid::{scale_rgba_hybrid, scale_rgba_with_algorithm, Algorithm};
+use crate::kernel::Kernel4x4;
+pub use crate::mips::{generate_mips, generate_mips_with_config};
+#[cfg(feature = "mmap")]
+pub use crate::mmap::scale_rgba_file;
+pub use crate::nine_slice::{scale_rgba_nine_slice, scale_rgba_nine_slice_with_config, NineSlice};
+use crate::oob_reader::{
+    OobReader, OobReaderBottomUp, OobReaderColumnMajor, OobReaderOriented, OobReaderPacked,
+    OobReaderTransparent, Oriented, PackedRows,
+};
+pub use crate::output::{scale_rgba_to_buffer, scale_rgba_to_buffer_with_config, OutputBuffer};
+pub use crate::palette::{scale_rgba_with_palette, Dither, Palette};
+use crate::pixel::{
+    cast_pixels, pixels_into_bytes, with_pixels_mut, Argb8, Bgra8, BytePixel, Mask8, Normal8,
+    PackedPixel, Pixel, Rgb565, Rgb8, Rgba8, Xrgb8888, RGB555,
+};
+pub use crate::scaled_image::{scale_rgba_to_image, scale_rgba_to_image_with_config, ScaledImage};
+use crate::scaler::{
+    strided_len, Scaler, Scaler1x, Scaler2x, Scaler3x, Scaler4x, Scaler5x, Scaler6x,
+};
+#[cfg(feature = "sharpen")]
+pub use crate::sharpen::{scale_rgba_sharpened, sharpen_rgba, SharpenConfig};
+pub use crate::temporal::TemporalScaler;
+pub use crate::version::{version_info, DistanceMode, LutPrecision, VersionInfo};
+pub use crate::window::{
+    scale_into_window_buffer, scale_into_window_buffer_with_placement, WindowAlign, WindowPlacement,
+};
+pub use crate::ycbcr_lookup::{YCbCrLookup, YCbCrMatrix};
+pub use crate::yuv::{scale_nv12, scale_yuv420};
+pub use crate::zoom::ZoomCache;
+
+#[cfg(feature = "allocator_api")]
+mod allocator;
+mod alpha;
+mod animation;
+mod arena;
+#[cfg(feature = "async")]
+mod async_scale;
+mod batch;
 mod blend;
+mod blend_map;
 mod config;
+pub mod convert;
+mod crop;
+mod deadline;
+#[cfg(feature = "digest")]
+mod digest;
+mod edge_shape;
+#[cfg(feature = "egui")]
+pub mod egui;
+mod error;
+mod factor;
+mod format;
+#[cfg(feature = "ggez")]
+pub mod ggez;
+mod hybrid;
 mod kernel;
+pub mod libretro;
+#[cfg(feature = "macroquad")]
+pub mod macroquad;
 mod matrix;
+pub mod metrics;
+mod mips;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod nine_slice;
 mod oob_reader;
+mod output;
+mod palette;
 mod pixel;
+mod scaled_image;
 mod scaler;
+#[cfg(feature = "sdl2")]
+pub mod sdl2;
+#[cfg(feature = "sharpen")]
+mod sharpen;
+mod temporal;
+#[cfg(feature = "unstable")]
+pub mod unstable;
+mod version;
+#[cfg(feature = "wgpu")]
+pub mod wgpu;
+mod window;
 mod ycbcr_lookup;
+mod yuv;
+mod zoom;
 
 /// Use the xBRZ algorithm to scale up an image by an integer factor.
 ///
@@ -37,121 +141,2077 @@ mod ycbcr_lookup;
 /// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
 /// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
 pub fn scale_rgba(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<u8> {
-    scale::<Rgba8>(source, src_width, src_height, factor)
+    scale_bytes::<Rgba8, _>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+        None,
+        None,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor fixed at compile time.
+///
+/// This is the same as [`scale_rgba`], except that the `FACTOR` is a const generic parameter. When it is
+/// known up front, as in most emulators and embedded frontends, the scaler for that factor is the only one
+/// compiled in, with no dispatch on the factor at runtime, and can be inlined into the caller.
+///
+/// ```
+/// let (width, height) = (4, 4);
+/// let source = vec![255u8; width * height * 4];
+/// let scaled = xbrz::scale_rgba_const::<3>(&source, width, height);
+/// # assert_eq!(scaled, xbrz::scale_rgba(&source, width, height, 3));
+/// ```
+///
+/// Factors outside of 1 to 6 are rejected when compiling:
+///
+/// ```compile_fail
+/// let scaled = xbrz::scale_rgba_const::<7>(&[0; 4], 1, 1);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`.
+#[inline]
+pub fn scale_rgba_const<const FACTOR: usize>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+) -> Vec<u8> {
+    const {
+        assert!(
+            1 <= FACTOR && FACTOR <= 6,
+            "scale factor must be between 1 and 6"
+        )
+    };
+    scale_bytes::<Rgba8, _>(
+        source,
+        src_width,
+        src_height,
+        FACTOR,
+        &ScalerConfig::default(),
+        None,
+        None,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image with pixels in BGRA order by an integer factor.
+///
+/// This is the same as [`scale_rgba`], except that the subpixels of `source` and of the returned image
+/// are arranged in BGRA order, as used by Windows bitmaps and many GPU surfaces.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_bgra(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<u8> {
+    scale_bytes::<Bgra8, _>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+        None,
+        None,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, with custom tuning parameters.
+///
+/// This is the same as [`scale_rgba`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    scale_bytes::<Rgba8, _>(source, src_width, src_height, factor, config, None, None)
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, with its pixels stored in the
+/// given layouts.
+///
+/// This is the same as [`scale_rgba_with_config`], except that the `source` is stored in `source_layout`
+/// and the returned image in `output_layout`. Images are read in their own layout rather than being
+/// transposed or flipped first, so the result is the same as converting the `source` to
+/// [`Layout::RowMajor`], scaling it and converting the scaled image to `output_layout`.
+///
+/// ```
+/// use xbrz::{Layout, ScalerConfig};
+///
+/// // a bottom-up bitmap, scaled into a top-down image ready to be saved
+/// let (width, height) = (4, 4);
+/// let bitmap = vec![255u8; width * height * 4];
+/// let scaled = xbrz::scale_rgba_with_layout(
+///     &bitmap,
+///     width,
+///     height,
+///     2,
+///     &ScalerConfig::default(),
+///     Layout::BottomUp,
+///     Layout::RowMajor,
+/// );
+/// # assert_eq!(scaled.len(), width * 2 * height * 2 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_layout(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    source_layout: Layout,
+    output_layout: Layout,
+) -> Vec<u8> {
+    if source_layout == Layout::RowMajor && output_layout == Layout::RowMajor {
+        return scale_rgba_with_config(source, src_width, src_height, factor, config);
+    }
+    if src_width == 0 || src_height == 0 {
+        return vec![];
+    }
+
+    check_source(source, src_width, src_height, factor, Rgba8::SIZE)
+        .unwrap_or_else(|e| panic!("{e}"));
+    let src_argb = cast_pixels::<Rgba8>(source);
+    assert_eq!(src_argb.len(), src_width * src_height);
+
+    let (w, h) = (src_width, src_height);
+    let mut scaled = match source_layout {
+        Layout::RowMajor => scale_pixels::<Rgba8, OobReaderTransparent<Rgba8>, _>(
+            &src_argb, w, h, factor, config, None, None,
+        ),
+        Layout::ColumnMajor => scale_pixels::<Rgba8, OobReaderColumnMajor<Rgba8>, _>(
+            &src_argb, w, h, factor, config, None, None,
+        ),
+        Layout::BottomUp => scale_pixels::<Rgba8, OobReaderBottomUp<Rgba8>, _>(
+            &src_argb, w, h, factor, config, None, None,
+        ),
+    };
+
+    let (dst_width, dst_height) = (src_width * factor, src_height * factor);
+    match output_layout {
+        Layout::RowMajor => {}
+        Layout::ColumnMajor => scaled = transpose(&scaled, dst_width, dst_height),
+        Layout::BottomUp => flip_rows(&mut scaled, dst_width),
+    }
+    pixels_into_bytes(scaled)
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, turning or flipping it as it
+/// is scaled.
+///
+/// This is the same as [`scale_rgba_with_config`] with the `source` turned or flipped by
+/// `orientation` first, but the source is read in its new orientation as it is scaled, so that
+/// neither it nor the scaled image needs a second pass to turn it. This suits generating the
+/// mirrored and rotated variants of sprites. Quarter turns and diagonal flips swap the width and
+/// height of the scaled image over, as given by [`Orientation::dimensions`].
+///
+/// ```
+/// use xbrz::{Orientation, ScalerConfig};
+///
+/// // a sprite facing right, scaled into one facing left
+/// let (width, height) = (8, 4);
+/// let sprite = vec![255u8; width * height * 4];
+/// let config = ScalerConfig::default();
+/// let facing_left =
+///     xbrz::scale_rgba_oriented(&sprite, width, height, 3, &config, Orientation::FlipHorizontal);
+/// # assert_eq!(facing_left.len(), width * 3 * height * 3 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_oriented(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    orientation: Orientation,
+) -> Vec<u8> {
+    if orientation == Orientation::Normal {
+        return scale_rgba_with_config(source, src_width, src_height, factor, config);
+    }
+
+    check_source(source, src_width, src_height, factor, Rgba8::SIZE)
+        .unwrap_or_else(|e| panic!("{e}"));
+    let src_argb = cast_pixels::<Rgba8>(source);
+    let oriented = Oriented {
+        pixels: &src_argb,
+        width: src_width,
+        height: src_height,
+        orientation,
+    };
+    let (width, height) = orientation.dimensions(src_width, src_height);
+    let scaled = scale_pixels::<Rgba8, OobReaderOriented<Rgba8>, _>(
+        &oriented, width, height, factor, config, None, None,
+    );
+    pixels_into_bytes(scaled)
+}
+
+/// Reverse the order of the rows of an image `width` pixels wide, in place.
+fn flip_rows<P: Pixel>(pixels: &mut [P], width: usize) {
+    let height = pixels.len() / width;
+    let (top, bottom) = pixels.split_at_mut(height / 2 * width);
+    // the middle row of an image with an odd height stays where it is
+    let bottom = &mut bottom[(height % 2) * width..];
+    for (top_row, bottom_row) in top
+        .chunks_exact_mut(width)
+        .zip(bottom.chunks_exact_mut(width).rev())
+    {
+        top_row.swap_with_slice(bottom_row);
+    }
+}
+
+/// Transpose an image of `width` by `height` pixels, stored in rows, into one stored in columns.
+fn transpose<P: Pixel>(pixels: &[P], width: usize, height: usize) -> Vec<P> {
+    // square blocks are copied at a time, so that both images are read and written a few cache lines at a time
+    const BLOCK: usize = 32;
+
+    let mut transposed = vec![P::default(); pixels.len()];
+    for y_block in (0..height).step_by(BLOCK) {
+        for x_block in (0..width).step_by(BLOCK) {
+            for y in y_block..(y_block + BLOCK).min(height) {
+                for x in x_block..(x_block + BLOCK).min(width) {
+                    transposed[x * height + y] = pixels[y * width + x];
+                }
+            }
+        }
+    }
+    transposed
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, returning an error rather than
+/// panicking if the image can't be scaled.
+///
+/// This is the same as [`scale_rgba`], except that a [`ScaleError`] is returned if the `source` slice
+/// length is not exactly equal to `src_width * src_height * 4`, if `factor` is not one of 1, 2, 3, 4, 5 or 6,
+/// or if the scaled image would be too large to address on this target.
+pub fn try_scale_rgba(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+) -> Result<Vec<u8>, ScaleError> {
+    try_scale_rgba_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, with custom tuning parameters,
+/// returning an error rather than panicking if the image can't be scaled.
+///
+/// This is the same as [`try_scale_rgba`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+pub fn try_scale_rgba_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Result<Vec<u8>, ScaleError> {
+    check_source(source, src_width, src_height, factor, Rgba8::SIZE)?;
+    Ok(scale_bytes::<Rgba8, _>(
+        source, src_width, src_height, factor, config, None, None,
+    ))
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, using only what is passed in.
+///
+/// This is the same as [`scale_rgba_with_config`], except that colours are compared with `lookup`
+/// instead of the table shared by the whole process, and the image is scaled on the calling thread
+/// rather than on a thread pool. Nothing outside of the arguments is read or written, so the result
+/// depends on nothing else, and calls with different lookup tables can run side by side.
+///
+/// ```
+/// use xbrz::{ScalerConfig, YCbCrLookup};
+///
+/// let lookup = YCbCrLookup::new_small();
+/// let (width, height) = (16, 16);
+/// let source = vec![255u8; width * height * 4];
+/// let scaled = xbrz::scale_rgba_pure(&source, width, height, 3, &ScalerConfig::default(), &lookup);
+/// assert_eq!(scaled.len(), width * 3 * height * 3 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_pure(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    lookup: &YCbCrLookup,
+) -> Vec<u8> {
+    check_source(source, src_width, src_height, factor, Rgba8::SIZE)
+        .unwrap_or_else(|e| panic!("{e}"));
+    let mut destination = vec![0u8; src_width * factor * src_height * factor * Rgba8::SIZE];
+
+    let src_argb = cast_pixels::<Rgba8>(source);
+    assert_eq!(src_argb.len(), src_width * src_height);
+    with_pixels_mut::<Rgba8, _>(&mut destination, |dst_argb| {
+        assert_eq!(dst_argb.len(), src_width * factor * src_height * factor);
+
+        scale_pixels_into::<Rgba8, OobReaderTransparent<Rgba8>, _>(
+            &src_argb,
+            dst_argb,
+            src_width * factor,
+            src_width,
+            src_height,
+            factor,
+            &LookupConfig { config, lookup },
+            &mut ScaleArena::with_max_width(src_width),
+        );
+    });
+    destination
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, reporting progress as it goes.
+///
+/// This is the same as [`scale_rgba_with_config`], except that `progress` is called with
+/// `(rows_done, rows_total)` each time another row of the source image has been scaled,
+/// where `rows_total` is `src_height`. The last call is always `(src_height, src_height)`.
+///
+/// Rows may be scaled in parallel, in which case they are not necessarily finished in order,
+/// but the calls to `progress` never overlap and `rows_done` always counts up by one.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_progress(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    mut progress: impl FnMut(usize, usize) + Send,
+) -> Vec<u8> {
+    scale_bytes::<Rgba8, _>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        config,
+        None,
+        Some(&mut progress),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, reusing the blending decided
+/// by an earlier call to [`compute_blend_map`].
+///
+/// Deciding which corners to blend takes up a good part of the work of scaling an image, but doesn't depend
+/// on the scaling factor. When the same image is scaled by several factors, its blend map can be computed
+/// once, and passed to this function for each factor. The result is the same as [`scale_rgba_with_config`],
+/// as long as `blend_map` was computed from the same `source` and `config`.
+///
+/// ```
+/// let (width, height) = (4, 4);
+/// let source = vec![255u8; width * height * 4];
+/// let config = xbrz::ScalerConfig::default();
+///
+/// let blend_map = xbrz::compute_blend_map(&source, width, height, &config);
+/// let thumbnail = xbrz::scale_rgba_with_blend_map(&source, width, height, 2, &config, &blend_map);
+/// let preview = xbrz::scale_rgba_with_blend_map(&source, width, height, 6, &config, &blend_map);
+/// # assert_eq!(preview, xbrz::scale_rgba_with_config(&source, width, height, 6, &config));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if the `blend_map` length is not exactly equal to `src_width * src_height`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_blend_map(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    blend_map: &[Blend2x2],
+) -> Vec<u8> {
+    assert_eq!(blend_map.len(), src_width * src_height);
+    scale_bytes::<Rgba8, _>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        config,
+        Some(blend_map),
+        None,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, with different tuning parameters
+/// for different regions of the image.
+///
+/// The `mask` holds one value per source pixel, in the same order as the pixels of `source`. Pixels where
+/// the mask is zero are scaled with `configs[0]`, and all other pixels are scaled with `configs[1]`.
+/// This lets a frontend scale HUD text with [`Preset::Text`] and the rest of the screen with the defaults
+/// in a single pass. Each corner shared by several pixels is decided with the config of the pixel above and
+/// to the left of it, and settings that apply to the whole image, such as [`ScalerConfig::tile_size`],
+/// are taken from `configs[0]`.
+///
+/// ```
+/// let (width, height) = (8, 8);
+/// let source = vec![255u8; width * height * 4];
+/// // the top two rows hold the HUD
+/// let mask: Vec<u8> = (0..width * height).map(|i| (i < width * 2) as u8).collect();
+///
+/// let playfield = xbrz::ScalerConfig::default();
+/// let hud = xbrz::ScalerConfig::preset(xbrz::Preset::Text);
+/// let scaled = xbrz::scale_rgba_with_regions(&source, width, height, 3, [&playfield, &hud], &mask);
+/// # assert_eq!(scaled.len(), width * 3 * height * 3 * 4);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if the `mask` length is not exactly equal to `src_width * src_height`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_regions(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    configs: [&ScalerConfig; 2],
+    mask: &[u8],
+) -> Vec<u8> {
+    assert_eq!(mask.len(), src_width * src_height);
+    let config = RegionConfig {
+        configs,
+        mask,
+        width: src_width,
+        height: src_height,
+    };
+    scale_bytes::<Rgba8, _>(source, src_width, src_height, factor, &config, None, None)
+}
+
+/// Use the xBRZ algorithm to scale up an 8-bit mask by an integer factor.
+///
+/// The `source` is specified as a flat array of mask values, such as alpha, coverage or collision masks,
+/// ordered in left to right, then top to bottom order. Edges are found and smoothed according to
+/// the mask values alone, in the same way that [`scale_rgba`] treats an opaque greyscale image.
+///
+/// A newly allocated mask is returned, with dimensions `src_width * factor` by `src_height * factor`.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_mask(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<u8> {
+    scale_mask_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
 }
 
-fn scale<P: Pixel>(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<u8> {
-    const U8_SIZE: usize = mem::size_of::<u8>();
+/// Use the xBRZ algorithm to scale up an 8-bit mask by an integer factor, with custom tuning parameters.
+///
+/// This is the same as [`scale_mask`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_mask_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    scale_bytes::<Mask8, _>(source, src_width, src_height, factor, config, None, None)
+}
 
+/// Use the xBRZ algorithm to scale up a normal map by an integer factor.
+///
+/// The `source` is laid out as for [`scale_rgba`], with the R, G and B channels of each pixel holding the
+/// X, Y and Z components of a unit normal mapped from [-1, 1] to [0, 255]. Alpha is treated as opacity.
+///
+/// Rather than comparing colours, edges are found from the angle between neighbouring normals, and
+/// each blended normal is scaled back up to unit length, so that the scaled map lights the same way
+/// as the original. The thresholds of a [`ScalerConfig`] apply to angles on a scale where 180 degrees is 255.
+///
+/// A newly allocated normal map is returned in the same layout, with dimensions
+/// `src_width * factor` by `src_height * factor`.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_normal_map(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+) -> Vec<u8> {
+    scale_normal_map_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up a normal map by an integer factor, with custom tuning parameters.
+///
+/// This is the same as [`scale_normal_map`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_normal_map_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    scale_bytes::<Normal8, _>(source, src_width, src_height, factor, config, None, None)
+}
+
+/// Use the xBRZ algorithm to scale up an image in any supported pixel format by an integer factor.
+///
+/// The `source` is specified as a flat array of pixels in the given `format`, ordered in left to right,
+/// then top to bottom order. This allows the format to be chosen at runtime, such as by a plugin host or
+/// across an FFI boundary; when the format is known up front, [`scale_rgba`] and friends can be used instead.
+///
+/// A newly allocated image in the same format is returned, with image dimensions
+/// `src_width * factor` by `src_height * factor`.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to
+/// `src_width * src_height * format.bytes_per_pixel()`, or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale(
+    source: &[u8],
+    format: PixelFormat,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+) -> Vec<u8> {
+    scale_with_config(
+        source,
+        format,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image in any supported pixel format, with custom tuning parameters.
+///
+/// This is the same as [`scale`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to
+/// `src_width * src_height * format.bytes_per_pixel()`, or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_with_config(
+    source: &[u8],
+    format: PixelFormat,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    assert_eq!(
+        source.len(),
+        src_width * src_height * format.bytes_per_pixel()
+    );
+    let (w, h) = (src_width, src_height);
+    let pitch = w * format.bytes_per_pixel();
+
+    match format {
+        PixelFormat::Rgba8 => scale_bytes::<Rgba8, _>(source, w, h, factor, config, None, None),
+        PixelFormat::Argb8 => scale_bytes::<Argb8, _>(source, w, h, factor, config, None, None),
+        PixelFormat::Bgra8 => scale_bytes::<Bgra8, _>(source, w, h, factor, config, None, None),
+        PixelFormat::Gray8 => scale_bytes::<Mask8, _>(source, w, h, factor, config, None, None),
+        PixelFormat::Rgb8 => scale_packed::<Rgb8>(source, w, h, pitch, factor, config),
+        PixelFormat::Xrgb8888 => scale_packed::<Xrgb8888>(source, w, h, pitch, factor, config),
+        PixelFormat::Rgb565 => scale_packed::<Rgb565>(source, w, h, pitch, factor, config),
+        PixelFormat::Rgb555 => scale_packed::<RGB555>(source, w, h, pitch, factor, config),
+    }
+}
+
+fn scale_bytes<P: BytePixel, C: PixelConfig + ?Sized>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &C,
+    blend_map: Option<&[Blend2x2]>,
+    progress: Option<&mut (dyn FnMut(usize, usize) + Send)>,
+) -> Vec<u8> {
     if src_width == 0 || src_height == 0 {
         return vec![];
     }
 
-    assert_eq!(source.len(), src_width * src_height * P::SIZE);
-    let (_, src_argb, _) = unsafe { source.align_to::<P>() };
+    check_source(source, src_width, src_height, factor, P::SIZE).unwrap_or_else(|e| panic!("{e}"));
+    let src_argb = cast_pixels::<P>(source);
+    assert_eq!(src_argb.len(), src_width * src_height);
+
+    let dst_argb = scale_pixels::<P, OobReaderTransparent<P>, _>(
+        &src_argb, src_width, src_height, factor, config, blend_map, progress,
+    );
+    pixels_into_bytes(dst_argb)
+}
+
+/// Scale an opaque image made up of `height` rows of `width` packed pixels, with each row starting
+/// `pitch` bytes after the last. The final row does not need to be padded out to the full pitch.
+/// The scaled image is returned tightly packed.
+fn scale_packed<P: PackedPixel>(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    pitch: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    // opaque images are extended at their edges rather than faded out to transparent
+    let source = PackedRows { data, pitch };
+    let scaled = scale_pixels::<P, OobReaderPacked<P>, _>(
+        &source, width, height, factor, config, None, None,
+    );
+
+    let mut out = Vec::with_capacity(scaled.len() * P::PACKED_SIZE);
+    for p in scaled {
+        p.write(&mut out);
+    }
+    out
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, writing into an existing buffer.
+///
+/// This is the same as [`scale_rgba_with_config`], except that the scaled image is written to `destination`,
+/// and all scratch memory is taken from the `arena`. Once the arena has been used for an image
+/// at least as wide as `src_width`, this function performs no heap allocations.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if the `destination` slice length is not exactly equal to
+/// `src_width * factor * src_height * factor * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_into(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    destination: &mut [u8],
+    arena: &mut ScaleArena,
+) {
+    let dst_len = check_dimensions(src_width, src_height, factor, Rgba8::SIZE)
+        .unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(destination.len(), dst_len);
+    scale_into::<Rgba8>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        config,
+        destination,
+        src_width * factor * 4,
+        arena,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, writing into an existing buffer
+/// with padded rows.
+///
+/// This is the same as [`scale_rgba_into`], except that each row of the scaled image starts
+/// `dst_stride_bytes` bytes after the last, so that it can be written straight into a buffer with
+/// the row alignment needed for a GPU texture upload, such as wgpu's `bytes_per_row`. The padding
+/// at the end of each row is left untouched. The `destination` may be longer than needed,
+/// for example if the final row is padded as well.
+///
+/// ```
+/// let (width, height, factor) = (3, 2, 2);
+/// let source = vec![255u8; width * height * 4];
+/// let stride = 256;
+/// let mut texture = vec![0u8; stride * height * factor];
+/// let mut arena = xbrz::ScaleArena::new();
+/// let config = xbrz::ScalerConfig::default();
+/// xbrz::scale_rgba_into_with_stride(&source, width, height, factor, &config, &mut texture, stride, &mut arena);
+/// // the padding after the 24 bytes of each row is left as it was
+/// assert!(texture.chunks(stride).all(|row| row[24..].iter().all(|&b| b == 0)));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `dst_stride_bytes` is not a multiple of 4 or is less than `src_width * factor * 4`,
+/// if the `destination` slice is too short to hold `src_height * factor` rows at that stride,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+#[allow(clippy::too_many_arguments)]
+pub fn scale_rgba_into_with_stride(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    destination: &mut [u8],
+    dst_stride_bytes: usize,
+    arena: &mut ScaleArena,
+) {
+    scale_into::<Rgba8>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        config,
+        destination,
+        dst_stride_bytes,
+        arena,
+    )
+}
+
+/// Use the xBRZ algorithm to scale up a horizontal stripe of an image.
+///
+/// This allows the work of scaling one image to be split up into independent jobs, for example to be
+/// scheduled on an existing thread pool. A stripe covers the source rows in `y_range`, and its
+/// `destination` holds exactly the corresponding rows of output: `y_range.len() * factor` rows
+/// of `src_width * factor` pixels, or `src_width * factor * y_range.len() * factor * 4` bytes.
+///
+/// Each stripe reads from the whole of `source`, as the rows on either side of `y_range` are needed to
+/// detect edges, but only ever writes to its own `destination`. Stripes with non-overlapping `y_range`s
+/// can therefore run concurrently, with their destinations split off a single output buffer at multiples of
+/// `src_width * factor * factor * 4` bytes using [`chunks_mut`](slice::chunks_mut) or
+/// [`split_at_mut`](slice::split_at_mut). Stripes covering all of `0..src_height` produce exactly the same
+/// image as [`scale_rgba_with_config`].
+///
+/// ```
+/// use std::thread;
+///
+/// let (width, height, factor) = (16, 16, 3);
+/// let source = vec![255u8; width * height * 4];
+/// let config = xbrz::ScalerConfig::default();
+///
+/// let stripe_height = 4;
+/// let mut scaled = vec![0u8; width * factor * height * factor * 4];
+/// thread::scope(|s| {
+///     let stripes = scaled.chunks_mut(width * factor * stripe_height * factor * 4);
+///     for (i, destination) in stripes.enumerate() {
+///         let (source, config) = (&source, &config);
+///         s.spawn(move || {
+///             let y_range = i * stripe_height..(i + 1) * stripe_height;
+///             let mut arena = xbrz::ScaleArena::new();
+///             xbrz::scale_rgba_stripe(
+///                 source, width, height, factor, y_range, config, destination, &mut arena,
+///             );
+///         });
+///     }
+/// });
+///
+/// assert_eq!(scaled, xbrz::scale_rgba(&source, width, height, factor));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `y_range` is empty or extends past `src_height`,
+/// if the `destination` slice length is not exactly equal to
+/// `src_width * factor * y_range.len() * factor * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+#[allow(clippy::too_many_arguments)]
+pub fn scale_rgba_stripe(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    y_range: Range<usize>,
+    config: &ScalerConfig,
+    destination: &mut [u8],
+    arena: &mut ScaleArena,
+) {
+    scale_stripe_into::<Rgba8>(
+        source,
+        src_width,
+        src_height,
+        factor,
+        y_range,
+        config,
+        destination,
+        arena,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn scale_into<P: BytePixel>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    destination: &mut [u8],
+    dst_stride_bytes: usize,
+    arena: &mut impl Scratch,
+) {
+    check_source(source, src_width, src_height, factor, P::SIZE).unwrap_or_else(|e| panic!("{e}"));
+    assert!(dst_stride_bytes.is_multiple_of(P::SIZE));
+    let dst_stride = dst_stride_bytes / P::SIZE;
+    assert!(dst_stride >= src_width * factor);
+    let dst_len = strided_len(src_height * factor, src_width * factor, dst_stride);
+    assert!(destination.len() >= dst_len * P::SIZE);
+    if src_width == 0 || src_height == 0 {
+        return;
+    }
+
+    let src_argb = cast_pixels::<P>(source);
+    assert_eq!(src_argb.len(), src_width * src_height);
+    with_pixels_mut::<P, _>(&mut destination[..dst_len * P::SIZE], |dst_argb| {
+        assert_eq!(dst_argb.len(), dst_len);
+        scale_pixels_into::<P, OobReaderTransparent<P>, _>(
+            &src_argb, dst_argb, dst_stride, src_width, src_height, factor, config, arena,
+        );
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn scale_stripe_into<P: BytePixel>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    y_range: Range<usize>,
+    config: &ScalerConfig,
+    destination: &mut [u8],
+    arena: &mut ScaleArena,
+) {
+    check_source(source, src_width, src_height, factor, P::SIZE).unwrap_or_else(|e| panic!("{e}"));
+    assert!(y_range.start < y_range.end);
+    assert!(y_range.end <= src_height);
+    assert_eq!(
+        destination.len(),
+        src_width * factor * y_range.len() * factor * P::SIZE
+    );
+    if src_width == 0 {
+        return;
+    }
+
+    let src_argb = cast_pixels::<P>(source);
     assert_eq!(src_argb.len(), src_width * src_height);
+    with_pixels_mut::<P, _>(destination, |dst_argb| {
+        scale_stripe_pixels::<P, OobReaderTransparent<P>, _>(
+            &src_argb,
+            dst_argb,
+            src_width * factor,
+            src_width,
+            src_height,
+            factor,
+            y_range,
+            config,
+            arena,
+            None,
+            None,
+        );
+    });
+}
+
+fn scale_pixels<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+    src_argb: &'src OOB::Source,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &C,
+    blend_map: Option<&[Blend2x2]>,
+    progress: Option<&mut (dyn FnMut(usize, usize) + Send)>,
+) -> Vec<P> {
+    if src_width == 0 || src_height == 0 {
+        return vec![];
+    }
+
+    let dst_len = check_dimensions(src_width, src_height, factor, P::SIZE)
+        .unwrap_or_else(|e| panic!("{e}"))
+        / P::SIZE;
+    let mut dst_argb = vec![P::default(); dst_len];
+    scale_pixels_to::<P, OOB, _>(
+        src_argb,
+        &mut dst_argb,
+        src_width,
+        src_height,
+        factor,
+        config,
+        blend_map,
+        progress,
+    );
+    dst_argb
+}
+
+/// Scale a whole image into `dst_argb`, which holds exactly the scaled image, splitting it up
+/// between rayon's threads if it is large enough.
+#[allow(clippy::too_many_arguments)]
+fn scale_pixels_to<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+    src_argb: &'src OOB::Source,
+    dst_argb: &mut [P],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &C,
+    blend_map: Option<&[Blend2x2]>,
+    progress: Option<&mut (dyn FnMut(usize, usize) + Send)>,
+) {
+    // tiny images, such as sprites, are scaled before another thread could even pick them up
+    #[cfg(feature = "rayon")]
+    let threads = config.base().thread_count();
+    #[cfg(feature = "rayon")]
+    if src_width * src_height > SEQUENTIAL_MAX_PIXELS && threads > 1 {
+        use rayon::prelude::*;
+
+        // rows finish on several threads at once, so the count is kept alongside the callback
+        let progress = progress.map(|callback| parking_lot::Mutex::new((0, callback)));
+        let stripe_height = parallel_stripe_height(src_height, threads);
+        dst_argb
+            .par_chunks_mut(stripe_height * factor * src_width * factor)
+            .enumerate()
+            .for_each_init(ScaleArena::new, |arena, (i, dst_stripe)| {
+                let mut on_row = || {
+                    if let Some(progress) = &progress {
+                        let (rows_done, callback) = &mut *progress.lock();
+                        *rows_done += 1;
+                        callback(*rows_done, src_height);
+                    }
+                };
+                let on_row: Option<&mut dyn FnMut()> = match progress {
+                    Some(_) => Some(&mut on_row),
+                    None => None,
+                };
+
+                let y_first = i * stripe_height;
+                let y_last = (y_first + stripe_height).min(src_height);
+                scale_stripe_pixels::<P, OOB, _>(
+                    src_argb,
+                    dst_stripe,
+                    src_width * factor,
+                    src_width,
+                    src_height,
+                    factor,
+                    y_first..y_last,
+                    config,
+                    arena,
+                    blend_map,
+                    on_row,
+                );
+            });
+        return;
+    }
+
+    let mut rows_done = 0;
+    let mut on_row = progress.map(|callback| {
+        move || {
+            rows_done += 1;
+            callback(rows_done, src_height);
+        }
+    });
+    scale_stripe_pixels::<P, OOB, _>(
+        src_argb,
+        dst_argb,
+        src_width * factor,
+        src_width,
+        src_height,
+        factor,
+        0..src_height,
+        config,
+        &mut ScaleArena::new(),
+        blend_map,
+        on_row.as_mut().map(|f| f as &mut dyn FnMut()),
+    );
+}
+
+/// The largest image, in source pixels, that is scaled on the calling thread rather than being
+/// split up between rayon's threads.
+#[cfg(feature = "rayon")]
+const SEQUENTIAL_MAX_PIXELS: usize = 32 * 32;
+
+/// The number of source rows given to each parallel task: enough stripes to keep every thread
+/// busy, but not so many that the repeated preprocessing of each stripe's first row dominates.
+///
+/// When `threads` is fewer than the threads of the pool, each is given a single stripe, so that no
+/// more than `threads` of them work on the image at once.
+#[cfg(feature = "rayon")]
+fn parallel_stripe_height(src_height: usize, threads: usize) -> usize {
+    const MIN_STRIPE_HEIGHT: usize = 8;
+
+    let stripes = match threads < rayon::current_num_threads() {
+        true => threads,
+        false => threads * 4,
+    };
+    src_height.div_ceil(stripes).max(MIN_STRIPE_HEIGHT)
+}
+
+/// Scale a whole image into `dst_argb`, with each output row starting `dst_stride` pixels after the last.
+#[allow(clippy::too_many_arguments)]
+fn scale_pixels_into<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+    src_argb: &'src OOB::Source,
+    dst_argb: &mut [P],
+    dst_stride: usize,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &C,
+    arena: &mut impl Scratch,
+) {
+    if src_width == 0 || src_height == 0 {
+        return;
+    }
 
     assert!(factor > 0);
     assert!(factor <= 6);
+    assert!(dst_stride >= src_width * factor);
+    assert_eq!(
+        dst_argb.len(),
+        strided_len(src_height * factor, src_width * factor, dst_stride)
+    );
 
-    let config = ScalerConfig::default();
-
-    let dst_argb = if factor == 1 {
-        src_argb.to_owned()
-    } else {
-        let mut dst_argb = vec![P::default(); src_width * src_height * factor * factor];
-        match factor {
-            0 => unreachable!(),
-            1 => unreachable!(),
-            2 => Scaler2x::scale_image::<P, OobReaderTransparent<P>>(
-                src_argb,
-                dst_argb.as_mut_slice(),
-                src_width,
-                src_height,
-                &config,
-                0..src_height,
-            ),
-            3 => Scaler3x::scale_image::<P, OobReaderTransparent<P>>(
-                src_argb,
-                dst_argb.as_mut_slice(),
-                src_width,
-                src_height,
-                &config,
-                0..src_height,
-            ),
-            4 => Scaler4x::scale_image::<P, OobReaderTransparent<P>>(
-                src_argb,
-                dst_argb.as_mut_slice(),
-                src_width,
-                src_height,
-                &config,
-                0..src_height,
-            ),
-            5 => Scaler5x::scale_image::<P, OobReaderTransparent<P>>(
-                src_argb,
-                dst_argb.as_mut_slice(),
-                src_width,
-                src_height,
-                &config,
-                0..src_height,
-            ),
-            6 => Scaler6x::scale_image::<P, OobReaderTransparent<P>>(
-                src_argb,
-                dst_argb.as_mut_slice(),
-                src_width,
-                src_height,
-                &config,
-                0..src_height,
-            ),
-            7.. => unreachable!(),
-        };
-        dst_argb
+    scale_stripe_pixels::<P, OOB, _>(
+        src_argb,
+        dst_argb,
+        dst_stride,
+        src_width,
+        src_height,
+        factor,
+        0..src_height,
+        config,
+        arena,
+        None,
+        None,
+    );
+}
+
+/// Scale the source rows in `y_range` into `dst_stripe`, which holds only the output rows
+/// belonging to that range, each starting `dst_stride` pixels after the last. If a `blend_map` is
+/// given, it is used instead of preprocessing the corners again. `on_row` is called each time one
+/// of those rows is finished.
+#[allow(clippy::too_many_arguments)]
+#[inline]
+fn scale_stripe_pixels<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+    src_argb: &'src OOB::Source,
+    dst_stripe: &mut [P],
+    dst_stride: usize,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    y_range: Range<usize>,
+    config: &C,
+    arena: &mut impl Scratch,
+    blend_map: Option<&[Blend2x2]>,
+    mut on_row: Option<&mut (dyn FnMut() + '_)>,
+) {
+    let alpha_rows = y_range.clone();
+    if factor == 1 && !config.base().antialias_at_1x {
+        for (y, dst_row) in y_range.zip(dst_stripe.chunks_mut(dst_stride)) {
+            let oob_reader = OOB::new(src_argb, src_width, src_height, y as isize);
+            match oob_reader.row() {
+                Some(row) => dst_row[..src_width].copy_from_slice(row),
+                // the source is only readable through the reader, so each pixel is read as the
+                // centre of a kernel
+                None => {
+                    let mut kernel = Kernel4x4::init_row(&oob_reader, 0);
+                    for (x, dst) in dst_row[..src_width].iter_mut().enumerate() {
+                        kernel.next_column(&oob_reader, x as isize);
+                        *dst = kernel.f;
+                    }
+                }
+            }
+            if let Some(on_row) = on_row.as_deref_mut() {
+                on_row();
+            }
+        }
+        apply_alpha_mode::<P, OOB>(
+            src_argb,
+            dst_stripe,
+            dst_stride,
+            src_width,
+            src_height,
+            factor,
+            alpha_rows,
+            config.base().alpha_mode,
+        );
+        return;
+    }
+
+    match factor {
+        0 => unreachable!(),
+        1 => Scaler1x::scale_tiled::<P, OOB, _>(
+            src_argb, dst_stripe, dst_stride, src_width, src_height, config, y_range, arena,
+            blend_map, on_row,
+        ),
+        2 => Scaler2x::scale_tiled::<P, OOB, _>(
+            src_argb, dst_stripe, dst_stride, src_width, src_height, config, y_range, arena,
+            blend_map, on_row,
+        ),
+        3 => Scaler3x::scale_tiled::<P, OOB, _>(
+            src_argb, dst_stripe, dst_stride, src_width, src_height, config, y_range, arena,
+            blend_map, on_row,
+        ),
+        4 => Scaler4x::scale_tiled::<P, OOB, _>(
+            src_argb, dst_stripe, dst_stride, src_width, src_height, config, y_range, arena,
+            blend_map, on_row,
+        ),
+        5 => Scaler5x::scale_tiled::<P, OOB, _>(
+            src_argb, dst_stripe, dst_stride, src_width, src_height, config, y_range, arena,
+            blend_map, on_row,
+        ),
+        6 => Scaler6x::scale_tiled::<P, OOB, _>(
+            src_argb, dst_stripe, dst_stride, src_width, src_height, config, y_range, arena,
+            blend_map, on_row,
+        ),
+        7.. => unreachable!(),
     };
+    apply_alpha_mode::<P, OOB>(
+        src_argb,
+        dst_stripe,
+        dst_stride,
+        src_width,
+        src_height,
+        factor,
+        alpha_rows,
+        config.base().alpha_mode,
+    );
+}
 
-    unsafe {
-        let mut dst_nodrop = mem::ManuallyDrop::new(dst_argb);
-        Vec::from_raw_parts(
-            dst_nodrop.as_mut_ptr() as *mut u8,
-            dst_nodrop.len() * P::SIZE / U8_SIZE,
-            dst_nodrop.capacity() * P::SIZE / U8_SIZE,
-        )
+/// Replace the alpha of the scaled pixels in `dst_stripe`, the output rows of the source rows in
+/// `y_range`, as described by `mode`.
+#[allow(clippy::too_many_arguments)]
+fn apply_alpha_mode<'src, P: Pixel, OOB: OobReader<'src, P>>(
+    src_argb: &'src OOB::Source,
+    dst_stripe: &mut [P],
+    dst_stride: usize,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    y_range: Range<usize>,
+    mode: AlphaMode,
+) {
+    match mode {
+        AlphaMode::Blend => {}
+        AlphaMode::Threshold(threshold) => {
+            for dst_row in dst_stripe.chunks_mut(dst_stride) {
+                for px in &mut dst_row[..src_width * factor] {
+                    *px = px.with_alpha(if px.alpha() >= threshold { 255 } else { 0 });
+                }
+            }
+        }
+        AlphaMode::Max => {
+            for (y, dst_rows) in y_range.zip(dst_stripe.chunks_mut(dst_stride * factor)) {
+                let oob_reader = OOB::new(src_argb, src_width, src_height, y as isize);
+                let mut kernel = Kernel4x4::init_row(&oob_reader, 0);
+                for x in 0..src_width {
+                    kernel.next_column(&oob_reader, x as isize);
+                    let k = &kernel;
+                    let max_alpha = [k.a, k.b, k.c, k.e, k.f, k.g, k.i, k.j, k.k]
+                        .map(P::alpha)
+                        .into_iter()
+                        .max()
+                        .unwrap_or(0);
+                    for dst_row in dst_rows.chunks_mut(dst_stride) {
+                        for px in &mut dst_row[x * factor..][..factor] {
+                            if px.alpha() > 0 {
+                                *px = px.with_alpha(max_alpha);
+                            }
+                        }
+                    }
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::mem;
-
-    use crate::pixel::Argb8;
+    use crate::pixel::{cast_pixels, pixels_into_bytes, with_pixels_mut, Argb8};
+    use crate::{
+        compute_blend_map, scale, scale_bgra, scale_mask, scale_normal_map, scale_rgba,
+        scale_rgba_into_with_stride, scale_rgba_oriented, scale_rgba_pure, scale_rgba_stripe,
+        scale_rgba_with_blend_map, scale_rgba_with_config, scale_rgba_with_layout,
+        scale_rgba_with_progress, scale_rgba_with_regions, AlphaMode, BlendWeights, Layout,
+        Orientation, OutlineConfig, PixelFormat, Preset, ScaleArena, ScalerConfig, YCbCrLookup,
+    };
 
     #[test]
     fn reinterpret_as_argb() {
-        let arr = vec![0u8, 1, 2, 3, 4, 5, 6, 7];
-        let (p, b, s) = unsafe { arr.align_to::<Argb8>() };
-        assert_eq!(p.len(), 0);
-        assert_eq!(s.len(), 0);
+        let arr = [0u8, 1, 2, 3, 4, 5, 6, 7];
+        let b = cast_pixels::<Argb8>(&arr);
         assert_eq!(b.len(), 2);
         assert_eq!((1, 2, 3, 0), b[0].to_rgba_parts());
         assert_eq!((5, 6, 7, 4), b[1].to_rgba_parts());
     }
 
+    /// A small image made up of a few flat colours with diagonal edges, plus some noise.
+    fn test_image(width: usize, height: usize) -> Vec<u8> {
+        const PALETTE: [[u8; 4]; 4] = [
+            [0, 0, 0, 255],
+            [255, 255, 255, 255],
+            [200, 40, 40, 255],
+            [0, 0, 0, 0],
+        ];
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                let noise = (x * 7 + y * 13) % 11 == 0;
+                PALETTE[((x + 2 * y) / 5 + noise as usize) % PALETTE.len()]
+            })
+            .collect()
+    }
+
+    /// The total difference of an image scaled by `factor` from `source` scaled by nearest neighbour.
+    fn blended_amount(source: &[u8], width: usize, scaled: &[u8], factor: usize) -> usize {
+        scaled
+            .chunks_exact(4)
+            .enumerate()
+            .map(|(i, p)| {
+                let (x, y) = (i % (width * factor), i / (width * factor));
+                let s = &source[(y / factor * width + x / factor) * 4..][..4];
+                (0..4).map(|c| p[c].abs_diff(s[c]) as usize).sum::<usize>()
+            })
+            .sum()
+    }
+
+    #[cfg(feature = "rayon")]
     #[test]
-    fn transmute_argb_vec() {
-        let original = vec![0u8, 1, 2, 3, 4, 5, 6, 7];
-        let new_u8 = {
-            let (_, argb_slice, _) = unsafe { original.align_to::<Argb8>() };
-
-            let new_argb = argb_slice.to_owned();
-            unsafe {
-                let mut argb_nodrop = mem::ManuallyDrop::new(new_argb);
-                Vec::from_raw_parts(
-                    argb_nodrop.as_mut_ptr() as *mut u8,
-                    argb_nodrop.len() * 4,
-                    argb_nodrop.capacity() * 4,
+    fn thread_limit_does_not_change_output() {
+        let (width, height) = (96, 80);
+        let source = test_image(width, height);
+        let expected = scale_rgba(&source, width, height, 3);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+
+        for max_threads in [Some(0), Some(1), Some(2), Some(4), Some(16), None] {
+            let config = ScalerConfig {
+                max_threads,
+                ..Default::default()
+            };
+            let threads = parking_lot::Mutex::new(std::collections::HashSet::new());
+            let actual = pool.install(|| {
+                scale_rgba_with_progress(&source, width, height, 3, &config, |_, _| {
+                    threads.lock().insert(rayon::current_thread_index());
+                })
+            });
+            assert!(
+                expected == actual,
+                "output differs with {max_threads:?} threads"
+            );
+            let limit = max_threads.map_or(4, |max| max.clamp(1, 4));
+            assert!(threads.lock().len() <= limit, "{max_threads:?} threads");
+        }
+    }
+
+    #[test]
+    fn tiling_does_not_change_output() {
+        let (width, height) = (37, 23);
+        let source = test_image(width, height);
+
+        for factor in 2..=6 {
+            let expected = scale_rgba(&source, width, height, factor);
+            for tile_size in [1, 2, 5, 16, 36, 100] {
+                let config = ScalerConfig {
+                    tile_size: Some(tile_size),
+                    ..Default::default()
+                };
+                let actual = scale_rgba_with_config(&source, width, height, factor, &config);
+                assert!(
+                    expected == actual,
+                    "{factor}x output differs with tile size {tile_size}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tile_size_fits_empty_images() {
+        let config = ScalerConfig {
+            tile_size: Some(16),
+            ..Default::default()
+        };
+        assert_eq!(config.tile_size_for(0), 1);
+        assert_eq!(config.tile_size_for(10), 10);
+    }
+
+    #[test]
+    fn strided_output_matches_packed_rows() {
+        const PADDING: u8 = 0xAB;
+        let (width, height) = (37, 23);
+        let source = test_image(width, height);
+        let mut arena = ScaleArena::new();
+
+        for factor in 1..=6 {
+            let expected = scale_rgba(&source, width, height, factor);
+            let row_bytes = width * factor * 4;
+            let stride = row_bytes.next_multiple_of(256);
+            let config = ScalerConfig {
+                tile_size: Some(5),
+                ..Default::default()
+            };
+            let mut destination = vec![PADDING; stride * height * factor];
+            scale_rgba_into_with_stride(
+                &source,
+                width,
+                height,
+                factor,
+                &config,
+                &mut destination,
+                stride,
+                &mut arena,
+            );
+
+            for (expected_row, row) in expected
+                .chunks_exact(row_bytes)
+                .zip(destination.chunks_exact(stride))
+            {
+                assert_eq!(expected_row, &row[..row_bytes], "x{factor}");
+                assert!(row[row_bytes..].iter().all(|&b| b == PADDING), "x{factor}");
+            }
+        }
+    }
+
+    #[test]
+    fn automatic_tiling_of_wide_images() {
+        let (width, height) = (1500, 3);
+        let source = test_image(width, height);
+        let untiled = ScalerConfig {
+            tile_size: Some(width),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            scale_rgba_with_config(&source, width, height, 3, &untiled),
+            scale_rgba(&source, width, height, 3)
+        );
+    }
+
+    #[test]
+    fn stitched_stripes_match_whole_image() {
+        let (width, height, factor) = (23, 17, 4);
+        let source = test_image(width, height);
+        let config = ScalerConfig::default();
+        let mut arena = ScaleArena::new();
+
+        let mut stitched = vec![0u8; width * factor * height * factor * 4];
+        let row_len = width * factor * factor * 4;
+        let mut remaining = &mut stitched[..];
+        for y_range in [0..1, 1..6, 6..16, 16..17] {
+            let (destination, rest) = remaining.split_at_mut(y_range.len() * row_len);
+            scale_rgba_stripe(
+                &source,
+                width,
+                height,
+                factor,
+                y_range,
+                &config,
+                destination,
+                &mut arena,
+            );
+            remaining = rest;
+        }
+
+        assert_eq!(stitched, scale_rgba(&source, width, height, factor));
+    }
+
+    #[test]
+    fn progress_counts_every_row() {
+        let (width, height) = (12, 70);
+        let source = test_image(width, height);
+        let tiled = ScalerConfig {
+            tile_size: Some(5),
+            ..Default::default()
+        };
+
+        for factor in [1, 4] {
+            let mut calls = vec![];
+            let scaled =
+                scale_rgba_with_progress(&source, width, height, factor, &tiled, |done, total| {
+                    calls.push((done, total))
+                });
+
+            assert_eq!(scaled, scale_rgba(&source, width, height, factor));
+            assert_eq!(calls, (1..=height).map(|y| (y, height)).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn cached_blend_map_matches_direct_scaling() {
+        let (width, height) = (1100, 9);
+        let source = test_image(width, height);
+        let config = ScalerConfig::default();
+        let blend_map = compute_blend_map(&source, width, height, &config);
+
+        for factor in 1..=6 {
+            assert_eq!(
+                scale_rgba_with_config(&source, width, height, factor, &config),
+                scale_rgba_with_blend_map(&source, width, height, factor, &config, &blend_map)
+            );
+        }
+    }
+
+    #[test]
+    fn column_major_matches_transposed_scaling() {
+        let (width, height) = (37, 23);
+        let source = test_image(width, height);
+        let pixel = |image: &[u8], i: usize| image[i * 4..i * 4 + 4].to_vec();
+        let column_major: Vec<u8> = (0..width * height)
+            .flat_map(|i| pixel(&source, (i % height) * width + i / height))
+            .collect();
+
+        for factor in 1..=6 {
+            let (dst_width, dst_height) = (width * factor, height * factor);
+            let scaled = scale_rgba_with_layout(
+                &column_major,
+                width,
+                height,
+                factor,
+                &ScalerConfig::default(),
+                Layout::ColumnMajor,
+                Layout::ColumnMajor,
+            );
+            let row_major: Vec<u8> = (0..dst_width * dst_height)
+                .flat_map(|i| pixel(&scaled, (i % dst_width) * dst_height + i / dst_width))
+                .collect();
+            assert!(
+                row_major == scale_rgba(&source, width, height, factor),
+                "x{factor}"
+            );
+        }
+    }
+
+    const ORIENTATIONS: [Orientation; 8] = [
+        Orientation::Normal,
+        Orientation::Rotate90,
+        Orientation::Rotate180,
+        Orientation::Rotate270,
+        Orientation::FlipHorizontal,
+        Orientation::FlipVertical,
+        Orientation::Transpose,
+        Orientation::Transverse,
+    ];
+
+    #[test]
+    fn orientations_move_pixels_into_place() {
+        // a b c
+        // d e f
+        let source: Vec<u8> = (b'a'..=b'f').flat_map(|c| [c, 0, 0, 255]).collect();
+        let expected = [
+            "abcdef", "daebfc", "fedcba", "cfbead", "cbafed", "defabc", "adbecf", "fcebda",
+        ];
+        let config = ScalerConfig::default();
+        for (orientation, expected) in ORIENTATIONS.into_iter().zip(expected) {
+            let scaled = scale_rgba_oriented(&source, 3, 2, 1, &config, orientation);
+            let pixels: String = scaled.chunks_exact(4).map(|px| px[0] as char).collect();
+            assert_eq!(pixels, expected, "{orientation:?}");
+        }
+    }
+
+    #[test]
+    fn oriented_scaling_matches_scaling_the_turned_image() {
+        let (width, height) = (37, 23);
+        let source = test_image(width, height);
+        let config = ScalerConfig::default();
+
+        for orientation in ORIENTATIONS {
+            let turned = scale_rgba_oriented(&source, width, height, 1, &config, orientation);
+            let (turned_width, turned_height) = orientation.dimensions(width, height);
+            for factor in 2..=6 {
+                assert!(
+                    scale_rgba_oriented(&source, width, height, factor, &config, orientation)
+                        == scale_rgba(&turned, turned_width, turned_height, factor),
+                    "x{factor} {orientation:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bottom_up_matches_flipped_scaling() {
+        let (width, height) = (37, 23);
+        let source = test_image(width, height);
+        let flip = |image: &[u8], width: usize| -> Vec<u8> {
+            image
+                .chunks_exact(width * 4)
+                .rev()
+                .flatten()
+                .copied()
+                .collect()
+        };
+        let bottom_up = flip(&source, width);
+        let config = ScalerConfig::default();
+
+        for factor in 1..=6 {
+            let expected = scale_rgba(&source, width, height, factor);
+            let scale = |source: &[u8], source_layout, output_layout| {
+                scale_rgba_with_layout(
+                    source,
+                    width,
+                    height,
+                    factor,
+                    &config,
+                    source_layout,
+                    output_layout,
                 )
+            };
+            assert!(
+                scale(&bottom_up, Layout::BottomUp, Layout::RowMajor) == expected,
+                "x{factor} bottom-up source"
+            );
+            assert!(
+                flip(
+                    &scale(&source, Layout::RowMajor, Layout::BottomUp),
+                    width * factor
+                ) == expected,
+                "x{factor} bottom-up output"
+            );
+            assert!(
+                flip(
+                    &scale(&bottom_up, Layout::BottomUp, Layout::BottomUp),
+                    width * factor
+                ) == expected,
+                "x{factor} bottom-up source and output"
+            );
+        }
+    }
+
+    #[test]
+    fn pure_scaling_matches_shared_lookup() {
+        let (width, height) = (37, 23);
+        let source = test_image(width, height);
+        let config = ScalerConfig::default();
+        let lookup = match (cfg!(feature = "large_lut"), cfg!(feature = "fixed_point")) {
+            (false, false) => YCbCrLookup::new_small(),
+            (true, false) => YCbCrLookup::new_large(),
+            (false, true) => YCbCrLookup::new_small_fixed(),
+            (true, true) => YCbCrLookup::new_large_fixed(),
+        };
+
+        for factor in 1..=6 {
+            assert_eq!(
+                scale_rgba_with_config(&source, width, height, factor, &config),
+                scale_rgba_pure(&source, width, height, factor, &config, &lookup)
+            );
+        }
+    }
+
+    #[test]
+    fn mask_scales_like_greyscale_image() {
+        // outside the image, masks are zero while images are transparent, so the
+        // comparison is limited to the middle of the image, away from the edges
+        let (width, height, border) = (13, 11, 3);
+        let mask: Vec<u8> = (0..width * height)
+            .map(|i| match (i % width + i / width) % 5 {
+                0 | 1 => 255,
+                2 => 128,
+                _ => 0,
+            })
+            .collect();
+        let grey: Vec<u8> = mask.iter().flat_map(|&m| [m, m, m, 255]).collect();
+
+        for factor in 2..=6 {
+            let scaled_grey = scale_rgba(&grey, width, height, factor);
+            let scaled_mask = scale_mask(&mask, width, height, factor);
+            assert_eq!(width * factor * height * factor, scaled_mask.len());
+
+            let dest_width = width * factor;
+            for y in border * factor..(height - border) * factor {
+                for x in border * factor..(width - border) * factor {
+                    let i = y * dest_width + x;
+                    assert_eq!(scaled_grey[i * 4], scaled_mask[i], "({x}, {y}) x{factor}");
+                }
             }
+        }
+    }
+
+    #[test]
+    fn preserved_outlines_keep_their_thickness() {
+        // a red blob with a one pixel black outline on a white background
+        let (width, height) = (16, 16);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = ((i % width) as isize - 8, (i / width) as isize - 8);
+                match x * x + y * y {
+                    ..20 => [255, 0, 0, 255],
+                    20..36 => [0, 0, 0, 255],
+                    _ => [255, 255, 255, 255],
+                }
+            })
+            .collect();
+        let count_outline = |scaled: &[u8]| {
+            scaled
+                .chunks_exact(4)
+                .filter(|p| p == &[0, 0, 0, 255])
+                .count()
+        };
+
+        let preserving = ScalerConfig {
+            preserve_outlines: Some(OutlineConfig::default()),
+            ..Default::default()
         };
+        for factor in 2..=6 {
+            let default_outline = count_outline(&scale_rgba(&source, width, height, factor));
+            let preserved_outline = count_outline(&scale_rgba_with_config(
+                &source,
+                width,
+                height,
+                factor,
+                &preserving,
+            ));
+            assert!(preserved_outline > default_outline, "x{factor}");
+        }
+    }
+
+    #[test]
+    fn suppressed_dither_blends_less() {
+        // a solid blue area bordering on blue and green checkerboard dithering
+        let (width, height) = (12, 12);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                match (x * 2 < y + 6, (x + y) % 2) {
+                    (true, _) | (false, 0) => [0, 0, 160, 255],
+                    (false, _) => [0, 160, 0, 255],
+                }
+            })
+            .collect();
+        let suppressing = ScalerConfig {
+            suppress_dither: true,
+            ..Default::default()
+        };
+        for factor in 2..=6 {
+            let default_blended = blended_amount(
+                &source,
+                width,
+                &scale_rgba(&source, width, height, factor),
+                factor,
+            );
+            let suppressed_blended = blended_amount(
+                &source,
+                width,
+                &scale_rgba_with_config(&source, width, height, factor, &suppressing),
+                factor,
+            );
+            assert!(suppressed_blended < default_blended, "x{factor}");
+        }
+    }
+
+    #[test]
+    fn preserved_thin_lines_stay_one_pixel_wide() {
+        // a black wire running down in front of grass, with a red roof sloping away to its right
+        let (width, height) = (16, 16);
+        let wire_x = 7;
+        let wire = [0, 0, 0, 255];
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                match x {
+                    _ if x == wire_x => wire,
+                    _ if x > wire_x && y + wire_x <= x + 8 => [200, 60, 60, 255],
+                    _ => [40, 160, 40, 255],
+                }
+            })
+            .collect();
+        // only the rows of the wire that have the wire above and below them across a whole kernel
+        let wire_kept = |scaled: &[u8], factor: usize| {
+            let row_len = width * factor;
+            (2 * factor..(height - 2) * factor).all(|y| {
+                (0..row_len).all(|x| {
+                    let p = &scaled[(y * row_len + x) * 4..][..4];
+                    (x / factor == wire_x) == (p == wire)
+                })
+            })
+        };
+
+        let preserving = ScalerConfig {
+            preserve_thin_lines: true,
+            ..Default::default()
+        };
+        for factor in 2..=6 {
+            let default = scale_rgba(&source, width, height, factor);
+            let preserved = scale_rgba_with_config(&source, width, height, factor, &preserving);
+            // without preserving them, lines are only eaten into at the larger factors
+            if factor >= 4 {
+                assert!(!wire_kept(&default, factor), "x{factor}");
+            }
+            assert!(wire_kept(&preserved, factor), "x{factor}");
+        }
+    }
+
+    #[test]
+    fn crisp_text_leaves_glyphs_unblended() {
+        // "HI" in a 5x7 bitmap font, white on a dark blue HUD, with enough of a margin that the transparent
+        // pixels beyond the edges don't reach the kernels around the glyphs
+        let glyphs = [
+            "                  ",
+            "                  ",
+            "   #   #  #####   ",
+            "   #   #    #     ",
+            "   #   #    #     ",
+            "   #####    #     ",
+            "   #   #    #     ",
+            "   #   #    #     ",
+            "   #   #  #####   ",
+            "                  ",
+            "                  ",
+        ];
+        let (width, height) = (glyphs[0].len(), glyphs.len());
+        let source: Vec<u8> = glyphs
+            .iter()
+            .flat_map(|row| row.bytes())
+            .flat_map(|c| match c {
+                b'#' => [255, 255, 255, 255],
+                _ => [20, 30, 90, 255],
+            })
+            .collect();
+        let nearest = |factor: usize| -> Vec<u8> {
+            (0..height * factor)
+                .flat_map(|y| (0..width * factor).map(move |x| (x / factor, y / factor)))
+                .flat_map(|(x, y)| source[(y * width + x) * 4..][..4].to_vec())
+                .collect()
+        };
+
+        let crisp = ScalerConfig::preset(Preset::CrispText);
+        let text = ScalerConfig::preset(Preset::Text);
+        for factor in 2..=6 {
+            assert_eq!(
+                scale_rgba_with_config(&source, width, height, factor, &crisp),
+                nearest(factor),
+                "x{factor}"
+            );
+            assert_ne!(
+                scale_rgba_with_config(&source, width, height, factor, &text),
+                nearest(factor),
+                "x{factor}"
+            );
+        }
+    }
+
+    #[test]
+    fn grey_tolerance_only_applies_to_grey() {
+        let (width, height) = (16, 16);
+        let image = |channels: fn(u8) -> [u8; 4]| -> Vec<u8> {
+            (0..width * height)
+                .flat_map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    // bands of shades a little further apart than the default tolerance, meeting at a diagonal
+                    let shade = match x > y {
+                        true => 40 + (x / 4) as u8 * 45,
+                        false => 60 + (y / 4) as u8 * 45,
+                    };
+                    channels(shade)
+                })
+                .collect()
+        };
+        let grey = image(|v| [v, v, v, 255]);
+        let colourful = image(|v| [v, 255 - v, 0, 255]);
+
+        let default = ScalerConfig::default();
+        let lenient = ScalerConfig {
+            grey_tolerance: Some(60.0),
+            ..Default::default()
+        };
+        for factor in 2..=6 {
+            let scaled = |source: &[u8], config| {
+                scale_rgba_with_config(source, width, height, factor, config)
+            };
+            assert_ne!(
+                scaled(&grey, &lenient),
+                scaled(&grey, &default),
+                "x{factor}"
+            );
+            assert_eq!(
+                scaled(&colourful, &lenient),
+                scaled(&colourful, &default),
+                "x{factor}"
+            );
+        }
+    }
+
+    #[test]
+    fn blend_strength_scales_blending() {
+        let (width, height) = (20, 12);
+        let source = test_image(width, height);
+        let with_strength = |blend_strength| ScalerConfig {
+            blend_strength,
+            ..Default::default()
+        };
+
+        for factor in 2..=6 {
+            let scaled = |strength| {
+                scale_rgba_with_config(&source, width, height, factor, &with_strength(strength))
+            };
+            assert_eq!(scaled(1.0), scale_rgba(&source, width, height, factor));
+            assert_eq!(
+                blended_amount(&source, width, &scaled(0.0), factor),
+                0,
+                "x{factor}"
+            );
+
+            let amounts =
+                [0.25, 0.5, 0.75, 1.0].map(|s| blended_amount(&source, width, &scaled(s), factor));
+            assert!(amounts.windows(2).all(|w| w[0] < w[1]), "x{factor}");
+        }
+    }
+
+    #[test]
+    fn soft_weights_blend_more() {
+        let (width, height) = (20, 12);
+        let source = test_image(width, height);
+        let soft = ScalerConfig {
+            blend_weights: BlendWeights::Soft,
+            ..Default::default()
+        };
+        for factor in 2..=6 {
+            let classic_blended = blended_amount(
+                &source,
+                width,
+                &scale_rgba(&source, width, height, factor),
+                factor,
+            );
+            let soft_blended = blended_amount(
+                &source,
+                width,
+                &scale_rgba_with_config(&source, width, height, factor, &soft),
+                factor,
+            );
+            assert!(soft_blended > classic_blended, "x{factor}");
+        }
+    }
+
+    #[test]
+    fn antialiasing_at_1x_only_softens_edges() {
+        let (width, height) = (20, 12);
+        let mut source = test_image(width, height);
+        // opaque, so that colours are blended by their weights alone
+        source.iter_mut().skip(3).step_by(4).for_each(|a| *a = 255);
+        let antialiasing = ScalerConfig {
+            antialias_at_1x: true,
+            ..Default::default()
+        };
+
+        assert_eq!(scale_rgba(&source, width, height, 1), source);
+        let cleaned = scale_rgba_with_config(&source, width, height, 1, &antialiasing);
+        assert_eq!(cleaned.len(), source.len());
+        assert_ne!(cleaned, source);
+        // each of the four corners is blended by an eighth, so no channel moves by more than
+        // 255 * (1 - (7/8)^4) and some rounding; pixels on the border are left out, as they are
+        // blended with the transparency beyond the edges
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                let i = (y * width + x) * 4;
+                for (a, b) in cleaned[i..i + 4].iter().zip(&source[i..i + 4]) {
+                    assert!(a.abs_diff(*b) <= 108, "({x}, {y}) {a} {b}");
+                }
+            }
+        }
+
+        // other factors are unaffected
+        assert_eq!(
+            scale_rgba_with_config(&source, width, height, 2, &antialiasing),
+            scale_rgba(&source, width, height, 2)
+        );
+    }
+
+    #[test]
+    fn alpha_modes_leave_no_partial_transparency() {
+        let (width, height) = (20, 12);
+        let source = test_image(width, height);
+        let scale = |alpha_mode| {
+            let config = ScalerConfig {
+                alpha_mode,
+                ..Default::default()
+            };
+            scale_rgba_with_config(&source, width, height, 3, &config)
+        };
+
+        let blended = scale(AlphaMode::Blend);
+        assert!(blended.chunks_exact(4).any(|px| !matches!(px[3], 0 | 255)));
+        let thresholded = scale(AlphaMode::Threshold(128));
+        let max = scale(AlphaMode::Max);
+        for ((b, t), m) in blended
+            .chunks_exact(4)
+            .zip(thresholded.chunks_exact(4))
+            .zip(max.chunks_exact(4))
+        {
+            assert_eq!(
+                (&t[..3], &m[..3]),
+                (&b[..3], &b[..3]),
+                "colours are unchanged"
+            );
+            assert_eq!(t[3], if b[3] >= 128 { 255 } else { 0 });
+            // every source pixel is either opaque or transparent, so any coverage becomes opaque
+            assert_eq!(m[3], if b[3] > 0 { 255 } else { 0 });
+        }
+    }
+
+    #[test]
+    fn regions_use_their_own_config() {
+        let (width, height) = (40, 30);
+        let source = test_image(width, height);
+        let default = ScalerConfig::default();
+        let text = ScalerConfig::preset(Preset::Text);
+        // the top half of the image is a text region
+        let mask: Vec<u8> = (0..width * height)
+            .map(|i| (i / width < height / 2) as u8)
+            .collect();
+
+        for factor in 2..=6 {
+            let with_default = scale_rgba_with_config(&source, width, height, factor, &default);
+            let with_text = scale_rgba_with_config(&source, width, height, factor, &text);
+            assert_ne!(with_default, with_text);
+
+            let all_default = vec![0; width * height];
+            let all_text = vec![1; width * height];
+            let scaled = |mask: &[u8]| {
+                scale_rgba_with_regions(&source, width, height, factor, [&default, &text], mask)
+            };
+            assert_eq!(with_default, scaled(&all_default), "x{factor}");
+            assert_eq!(with_text, scaled(&all_text), "x{factor}");
+
+            // away from the boundary, each region matches scaling with its config alone
+            let mixed = scaled(&mask);
+            let row_bytes = width * factor * 4;
+            let top = ..(height / 2 - 2) * factor * row_bytes;
+            let bottom = (height / 2 + 2) * factor * row_bytes..;
+            assert_eq!(with_text[top], mixed[top], "x{factor}");
+            assert_eq!(with_default[bottom.clone()], mixed[bottom], "x{factor}");
+        }
+    }
+
+    #[test]
+    fn runtime_formats_match_typed_scaling() {
+        let (width, height) = (20, 12);
+        let rgba = test_image(width, height);
+        let opaque_rgb: Vec<u8> = rgba
+            .chunks_exact(4)
+            .flat_map(|p| [p[0], p[1], p[2]])
+            .collect();
+        let opaque_xrgb: Vec<u8> = opaque_rgb
+            .chunks_exact(3)
+            .flat_map(|p| u32::from_be_bytes([0, p[0], p[1], p[2]]).to_ne_bytes())
+            .collect();
+        let grey: Vec<u8> = rgba.chunks_exact(4).map(|p| p[0]).collect();
+
+        for factor in 1..=6 {
+            let expected = scale_rgba(&rgba, width, height, factor);
+            assert_eq!(
+                expected,
+                scale(&rgba, PixelFormat::Rgba8, width, height, factor)
+            );
+
+            let argb: Vec<u8> = rgba
+                .chunks_exact(4)
+                .flat_map(|p| [p[3], p[0], p[1], p[2]])
+                .collect();
+            let to_rgba: Vec<u8> = scale(&argb, PixelFormat::Argb8, width, height, factor)
+                .chunks_exact(4)
+                .flat_map(|p| [p[1], p[2], p[3], p[0]])
+                .collect();
+            assert_eq!(expected, to_rgba, "x{factor}");
+
+            let bgra: Vec<u8> = rgba
+                .chunks_exact(4)
+                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                .collect();
+            let scaled_bgra = scale_bgra(&bgra, width, height, factor);
+            assert_eq!(
+                scaled_bgra,
+                scale(&bgra, PixelFormat::Bgra8, width, height, factor)
+            );
+            let to_rgba: Vec<u8> = scaled_bgra
+                .chunks_exact(4)
+                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                .collect();
+            assert_eq!(expected, to_rgba, "x{factor}");
+
+            assert_eq!(
+                scale_mask(&grey, width, height, factor),
+                scale(&grey, PixelFormat::Gray8, width, height, factor),
+                "x{factor}"
+            );
+
+            let from_rgb = scale(&opaque_rgb, PixelFormat::Rgb8, width, height, factor);
+            let from_xrgb: Vec<u8> =
+                scale(&opaque_xrgb, PixelFormat::Xrgb8888, width, height, factor)
+                    .chunks_exact(4)
+                    .flat_map(|p| {
+                        let [_, r, g, b] = u32::from_ne_bytes(p.try_into().unwrap()).to_be_bytes();
+                        [r, g, b]
+                    })
+                    .collect();
+            assert_eq!(from_rgb.len(), width * height * factor * factor * 3);
+            assert_eq!(from_rgb, from_xrgb, "x{factor}");
+        }
+    }
+
+    #[test]
+    fn normal_map_blends_stay_unit_length() {
+        // the normals of a dome, quantised to a few directions so that there are edges to blend
+        let (width, height) = (12, 12);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let x = ((i % width) as f32 - 5.5) / 6.0;
+                let y = ((i / width) as f32 - 5.5) / 6.0;
+                let (x, y) = ((x * 2.0).round() / 2.0, (y * 2.0).round() / 2.0);
+                let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+                let len = (x * x + y * y + z * z).sqrt();
+                let [r, g, b] = [x, y, z].map(|c| ((c / len + 1.0) * 127.5).round() as u8);
+                [r, g, b, 255]
+            })
+            .collect();
+
+        for factor in 2..=6 {
+            let scaled = scale_normal_map(&source, width, height, factor);
+            assert_eq!(scaled.len(), source.len() * factor * factor);
+            for px in scaled.chunks_exact(4).filter(|px| px[3] == 255) {
+                let len = px[..3]
+                    .iter()
+                    .map(|&c| (c as f32 / 127.5 - 1.0).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                assert!(
+                    (len - 1.0).abs() < 0.02,
+                    "x{factor} {px:?} has length {len}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pixels_into_bytes_keeps_the_bytes() {
+        let original = vec![0u8, 1, 2, 3, 4, 5, 6, 7];
+        let argb = cast_pixels::<Argb8>(&original).into_owned();
+        assert_eq!(pixels_into_bytes(argb), original);
 
-        assert_eq!(original, new_u8);
+        let mut bytes = original.clone();
+        with_pixels_mut::<Argb8, _>(&mut bytes, |argb| argb.swap(0, 1));
+        assert_eq!(bytes, [4, 5, 6, 7, 0, 1, 2, 3]);
     }
 }
@@ -0,0 +1,149 @@
+//! Helpers for scaling frames handed over by libretro cores.
+//!
+//! A libretro `video_refresh` callback receives a frame in one of three pixel formats together with its
+//! pitch (the length of each row in bytes, including any padding). [`scale_frame`] accepts the frame
+//! exactly as given, and returns the scaled frame in the same pixel format, along with its new
+//! dimensions and pitch.
+use crate::pixel::{Rgb565, Xrgb8888, RGB555};
+use crate::{scale_packed, ScalerConfig};
+
+/// Pixel formats supported by the libretro API, with the same discriminants as `retro_pixel_format`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum RetroPixelFormat {
+    /// `RETRO_PIXEL_FORMAT_0RGB1555`: native-endian 16-bit pixels with 5 bits per channel,
+    /// the highest bit unused
+    Rgb1555 = 0,
+    /// `RETRO_PIXEL_FORMAT_XRGB8888`: native-endian 32-bit pixels with 8 bits per channel,
+    /// the highest byte unused
+    Xrgb8888 = 1,
+    /// `RETRO_PIXEL_FORMAT_RGB565`: native-endian 16-bit pixels with 5 bits for red and blue
+    /// and 6 bits for green
+    Rgb565 = 2,
+}
+
+impl RetroPixelFormat {
+    /// The number of bytes taken up by a single pixel of this format.
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            RetroPixelFormat::Rgb1555 | RetroPixelFormat::Rgb565 => 2,
+            RetroPixelFormat::Xrgb8888 => 4,
+        }
+    }
+}
+
+impl TryFrom<u32> for RetroPixelFormat {
+    type Error = u32;
+
+    /// Converts a raw `retro_pixel_format` value, returning it unchanged if it is not recognised.
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(RetroPixelFormat::Rgb1555),
+            1 => Ok(RetroPixelFormat::Xrgb8888),
+            2 => Ok(RetroPixelFormat::Rgb565),
+            _ => Err(value),
+        }
+    }
+}
+
+/// A scaled frame, ready to be presented or passed on to a frontend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScaledFrame {
+    /// The pixel data, in the same format as the source frame.
+    pub data: Vec<u8>,
+    pub width: usize,
+    pub height: usize,
+    /// The length of each row of `data` in bytes. Scaled frames are tightly packed,
+    /// so this is always `width * format.bytes_per_pixel()`.
+    pub pitch: usize,
+    pub format: RetroPixelFormat,
+}
+
+/// Use the xBRZ algorithm to scale up a libretro frame by an integer factor.
+///
+/// The `data` holds `height` rows of `width` pixels in the given `format`,
+/// with each row starting `pitch` bytes after the last.
+/// The final row does not need to be padded out to the full pitch.
+///
+/// The `factor` may be one of 1, 2, 3, 4, 5 or 6.
+///
+/// # Panics
+///
+/// Panics if `pitch` is smaller than a row of `width` pixels, if `data` is too short to
+/// contain `height` rows, or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_frame(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    pitch: usize,
+    format: RetroPixelFormat,
+    factor: usize,
+) -> ScaledFrame {
+    let config = ScalerConfig::default();
+    let scaled = match format {
+        RetroPixelFormat::Rgb1555 => {
+            scale_packed::<RGB555>(data, width, height, pitch, factor, &config)
+        }
+        RetroPixelFormat::Xrgb8888 => {
+            scale_packed::<Xrgb8888>(data, width, height, pitch, factor, &config)
+        }
+        RetroPixelFormat::Rgb565 => {
+            scale_packed::<Rgb565>(data, width, height, pitch, factor, &config)
+        }
+    };
+
+    ScaledFrame {
+        data: scaled,
+        width: width * factor,
+        height: height * factor,
+        pitch: width * factor * format.bytes_per_pixel(),
+        format,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Pixel;
+
+    #[test]
+    fn scales_padded_rgb565() {
+        let (width, height, pitch) = (3, 2, 10);
+        let pixel = 0xF81Fu16.to_ne_bytes();
+        let mut data = vec![0u8; pitch * (height - 1) + width * 2];
+        for y in 0..height {
+            for x in 0..width {
+                data[y * pitch + x * 2..][..2].copy_from_slice(&pixel);
+            }
+        }
+
+        let frame = scale_frame(&data, width, height, pitch, RetroPixelFormat::Rgb565, 3);
+        assert_eq!((9, 6, 18), (frame.width, frame.height, frame.pitch));
+        assert_eq!(frame.data.len(), frame.pitch * frame.height);
+        assert!(frame.data.chunks_exact(2).all(|p| p == pixel));
+    }
+
+    #[test]
+    fn preserves_xrgb8888_solid_colour() {
+        let data: Vec<u8> = [0x00123456u32; 16]
+            .iter()
+            .flat_map(|p| p.to_ne_bytes())
+            .collect();
+
+        let frame = scale_frame(&data, 4, 4, 16, RetroPixelFormat::Xrgb8888, 2);
+        assert_eq!(32, frame.pitch);
+        assert!(frame
+            .data
+            .chunks_exact(4)
+            .all(|p| u32::from_ne_bytes(p.try_into().unwrap()) == 0x00123456));
+    }
+
+    #[test]
+    fn rgb1555_round_trips_through_rgba() {
+        for value in [0x0000u16, 0x7FFF, 0x7C00, 0x03E0, 0x001F, 0x1234] {
+            let p = RGB555::from(value);
+            let [r, g, b] = p.to_rgb();
+            assert_eq!(value, u16::from(RGB555::from_rgba([r, g, b, 255])));
+        }
+    }
+}
@@ -0,0 +1,53 @@
+//! Helpers for scaling images into [macroquad](https://docs.rs/macroquad) textures.
+//!
+//! Enabled by the `macroquad` feature.
+use ::macroquad::texture::{Image, Texture2D};
+
+/// Use the xBRZ algorithm to scale up a macroquad [`Image`] by an integer factor.
+///
+/// This doesn't need a graphics context, so it can be done while loading, before the window opens.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6, or if the scaled image would be wider or
+/// taller than the 65535 pixels a macroquad image can hold.
+pub fn scale_image(image: &Image, factor: usize) -> Image {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let scaled_width = u16::try_from(width * factor).expect("scaled image is too wide");
+    let scaled_height = u16::try_from(height * factor).expect("scaled image is too tall");
+    Image {
+        bytes: crate::scale_rgba(&image.bytes, width, height, factor),
+        width: scaled_width,
+        height: scaled_height,
+    }
+}
+
+/// Use the xBRZ algorithm to scale up a macroquad [`Image`] by an integer factor, and upload the
+/// result as a [`Texture2D`].
+///
+/// The texture uses the default filter mode of the context, as with [`Texture2D::from_image`].
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6, or if the scaled image would be wider or
+/// taller than 65535 pixels.
+pub fn scale_to_texture(image: &Image, factor: usize) -> Texture2D {
+    Texture2D::from_image(&scale_image(image, factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_image_matches_scale_rgba() {
+        let image = Image {
+            bytes: (0..5 * 3 * 4).map(|i| (i * 41) as u8).collect(),
+            width: 5,
+            height: 3,
+        };
+        let scaled = scale_image(&image, 3);
+        assert_eq!((scaled.width, scaled.height), (15, 9));
+        assert_eq!(scaled.bytes, crate::scale_rgba(&image.bytes, 5, 3, 3));
+    }
+}
@@ -1,12 +1,14 @@
 use crate::kernel::Rotation;
 use crate::pixel::Pixel;
 
-#[inline]
+/// The index in an `n` by `n` matrix of the cell at `(i, j)` once the matrix has been rotated by `rot`.
+#[inline(always)]
 const fn rotate_index(i: usize, j: usize, n: usize, rot: Rotation) -> (usize, usize) {
-    if rot.is_none() {
-        (i, j)
-    } else {
-        rotate_index(n - 1 - j, i, n, rot.rotate_ccw())
+    match rot {
+        Rotation::None => (i, j),
+        Rotation::Clockwise90 => (n - 1 - j, i),
+        Rotation::Clockwise180 => (n - 1 - i, n - 1 - j),
+        Rotation::Clockwise270 => (j, n - 1 - i),
     }
 }
 
@@ -22,22 +24,79 @@ impl<'out, P: Pixel, const N: usize, const R: u8> OutputMatrix<'out, P, N, R> {
         Self { inner, out_width }
     }
 
-    #[inline]
-    pub(crate) const fn rotated_index<const I: usize, const J: usize>(&self) -> (usize, usize) {
-        rotate_index(I, J, N, Rotation::from_u8(R))
+    #[inline(always)]
+    pub(crate) const fn rotated_index(&self, i: usize, j: usize) -> (usize, usize) {
+        rotate_index(i, j, N, Rotation::from_u8(R))
     }
 
     #[inline]
-    pub(crate) fn rotated_ref<'a, const I: usize, const J: usize>(&'a mut self) -> &'a mut P
+    pub(crate) fn rotated_ref<'a>(&'a mut self, i: usize, j: usize) -> &'a mut P
     where
         'out: 'a,
     {
-        let (i, j) = self.rotated_index::<I, J>();
+        let (i, j) = self.rotated_index(i, j);
         &mut self.inner[j + i * self.out_width]
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::pixel::Mask8;
+
+    /// Where the cell at `(i, j)` of an `N` by `N` block is written to when rotated by `R`.
+    fn index<const N: usize, const R: u8>(i: usize, j: usize) -> (usize, usize) {
+        let mut block = vec![Mask8::default(); N * N];
+        OutputMatrix::<Mask8, N, R>::new(&mut block, N).rotated_index(i, j)
+    }
+
+    fn check_rotations<const N: usize>(i: usize, j: usize) -> Result<(), TestCaseError> {
+        let (i, j) = (i % N, j % N);
+        let cw90 = |(i, j)| index::<N, { Rotation::Clockwise90 as u8 }>(i, j);
+        prop_assert_eq!(index::<N, { Rotation::None as u8 }>(i, j), (i, j));
+        prop_assert_eq!(
+            index::<N, { Rotation::Clockwise180 as u8 }>(i, j),
+            cw90(cw90((i, j)))
+        );
+        prop_assert_eq!(
+            index::<N, { Rotation::Clockwise270 as u8 }>(i, j),
+            cw90(cw90(cw90((i, j))))
+        );
+        prop_assert_eq!(cw90(cw90(cw90(cw90((i, j))))), (i, j));
+        // the top row of a view turned a quarter clockwise is the left hand column, read from the bottom up
+        prop_assert_eq!(cw90((0, j)), (N - 1 - j, 0));
+        Ok(())
+    }
+
+    proptest! {
+        #[test]
+        fn rotated_indices_compose(i in 0..6usize, j in 0..6usize) {
+            check_rotations::<2>(i, j)?;
+            check_rotations::<3>(i, j)?;
+            check_rotations::<4>(i, j)?;
+            check_rotations::<5>(i, j)?;
+            check_rotations::<6>(i, j)?;
+        }
+    }
 
-    pub(crate) fn into_inner(self) -> &'out mut [P] {
-        let Self { inner, .. } = self;
-        inner
+    #[test]
+    fn bottom_right_corner_matches_rotated_blends() {
+        // the corner written to by each rotation is the one that Blend2x2::rotate views as the bottom right
+        let last = 3;
+        assert_eq!(index::<4, { Rotation::None as u8 }>(last, last), (3, 3));
+        assert_eq!(
+            index::<4, { Rotation::Clockwise90 as u8 }>(last, last),
+            (0, 3)
+        );
+        assert_eq!(
+            index::<4, { Rotation::Clockwise180 as u8 }>(last, last),
+            (0, 0)
+        );
+        assert_eq!(
+            index::<4, { Rotation::Clockwise270 as u8 }>(last, last),
+            (3, 0)
+        );
     }
 }
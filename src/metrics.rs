@@ -0,0 +1,175 @@
+//! Measures of the difference between two RGBA images.
+//!
+//! These are useful for checking scaled output against reference images, and for judging the effect of
+//! changes to the [`ScalerConfig`](crate::ScalerConfig) parameters. All functions take flat RGBA buffers in the
+//! same layout as [`scale_rgba`](crate::scale_rgba).
+
+/// Side length of the windows that SSIM is computed over.
+const SSIM_WINDOW: usize = 8;
+/// Distance between the top left corners of adjacent SSIM windows.
+const SSIM_STEP: usize = 4;
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// The peak signal-to-noise ratio between two images in decibels, taken over all four channels.
+///
+/// Higher values mean the images are more similar. Identical images return [`f64::INFINITY`].
+///
+/// # Panics
+///
+/// Panics if the two slices are not the same length, or if their length is not a multiple of 4.
+pub fn psnr(a: &[u8], b: &[u8]) -> f64 {
+    assert_eq!(a.len(), b.len());
+    assert!(a.len().is_multiple_of(4));
+
+    let squared_error: u64 = a
+        .iter()
+        .zip(b)
+        .map(|(&a, &b)| (a.abs_diff(b) as u64).pow(2))
+        .sum();
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+
+    let mse = squared_error as f64 / a.len() as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// The mean structural similarity index between two images of size `width` by `height`.
+///
+/// The index is computed over the luma of each pixel after premultiplying by alpha, so that all fully
+/// transparent pixels compare equal. It is averaged over 8x8 windows spaced 4 pixels apart, or over the
+/// whole image if it is smaller than a single window. The result lies between -1 and 1,
+/// where 1 means the images are identical.
+///
+/// # Panics
+///
+/// Panics if either slice length is not exactly equal to `width * height * 4`.
+pub fn ssim(a: &[u8], b: &[u8], width: usize, height: usize) -> f64 {
+    assert_eq!(a.len(), width * height * 4);
+    assert_eq!(b.len(), width * height * 4);
+    if width == 0 || height == 0 {
+        return 1.0;
+    }
+
+    let luma_a = premultiplied_luma(a);
+    let luma_b = premultiplied_luma(b);
+
+    let window_w = SSIM_WINDOW.min(width);
+    let window_h = SSIM_WINDOW.min(height);
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    for y in (0..=height - window_h).step_by(SSIM_STEP) {
+        for x in (0..=width - window_w).step_by(SSIM_STEP) {
+            let pixels = (y..y + window_h)
+                .flat_map(|y| (x..x + window_w).map(move |x| y * width + x))
+                .map(|i| (luma_a[i], luma_b[i]));
+            total += window_ssim(pixels, window_w * window_h);
+            windows += 1;
+        }
+    }
+
+    total / windows as f64
+}
+
+fn premultiplied_luma(rgba: &[u8]) -> Vec<f64> {
+    rgba.chunks_exact(4)
+        .map(|p| {
+            let luma = 0.299 * p[0] as f64 + 0.587 * p[1] as f64 + 0.114 * p[2] as f64;
+            luma * p[3] as f64 / 255.0
+        })
+        .collect()
+}
+
+fn window_ssim(pixels: impl Iterator<Item = (f64, f64)> + Clone, count: usize) -> f64 {
+    let n = count as f64;
+    let (sum_a, sum_b) = pixels
+        .clone()
+        .fold((0.0, 0.0), |(sa, sb), (a, b)| (sa + a, sb + b));
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+
+    let (var_a, var_b, covar) = pixels.fold((0.0, 0.0, 0.0), |(va, vb, cv), (a, b)| {
+        let (da, db) = (a - mean_a, b - mean_b);
+        (va + da * da, vb + db * db, cv + da * db)
+    });
+    let (var_a, var_b, covar) = (var_a / n, var_b / n, covar / n);
+
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covar + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2))
+}
+
+/// An opaque RGBA image highlighting where two images differ.
+///
+/// Each output pixel is coloured by the largest difference between any channel of the corresponding input
+/// pixels, running from black for identical pixels through red and yellow up to white for the
+/// largest possible difference.
+///
+/// # Panics
+///
+/// Panics if the two slices are not the same length, or if their length is not a multiple of 4.
+pub fn diff_heatmap(a: &[u8], b: &[u8]) -> Vec<u8> {
+    assert_eq!(a.len(), b.len());
+    assert!(a.len().is_multiple_of(4));
+
+    a.chunks_exact(4)
+        .zip(b.chunks_exact(4))
+        .flat_map(|(a, b)| {
+            let diff = (0..4).map(|c| a[c].abs_diff(b[c])).max().unwrap_or(0);
+            heat_colour(diff)
+        })
+        .collect()
+}
+
+/// Maps 0..=255 onto a black-red-yellow-white ramp.
+fn heat_colour(value: u8) -> [u8; 4] {
+    let v = value as u32 * 3;
+    let channel = |offset: u32| v.saturating_sub(offset).min(255) as u8;
+    [channel(0), channel(255), channel(510), 255]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                [(x * 16) as u8, (y * 16) as u8, 128, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_images() {
+        let image = gradient(12, 10);
+        assert_eq!(f64::INFINITY, psnr(&image, &image));
+        assert!((ssim(&image, &image, 12, 10) - 1.0).abs() < 1e-9);
+        assert!(diff_heatmap(&image, &image)
+            .chunks_exact(4)
+            .all(|p| p == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn differing_images() {
+        let a = gradient(12, 10);
+        let mut b = a.clone();
+        b[0] ^= 0xFF;
+
+        let psnr = psnr(&a, &b);
+        assert!(psnr.is_finite() && psnr > 20.0);
+        assert!(ssim(&a, &b, 12, 10) < 1.0);
+
+        let heatmap = diff_heatmap(&a, &b);
+        assert_eq!([255, 255, 255, 255], heatmap[..4]);
+        assert_eq!([0, 0, 0, 255], heatmap[4..8]);
+    }
+
+    #[test]
+    fn transparent_pixels_compare_equal() {
+        let a = [10, 20, 30, 0].repeat(4);
+        let b = [200, 100, 0, 0].repeat(4);
+        assert!((ssim(&a, &b, 2, 2) - 1.0).abs() < 1e-9);
+    }
+}
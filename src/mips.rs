@@ -0,0 +1,115 @@
+//! An image scaled by every factor up to a limit, for textures shown at several zoom levels.
+use crate::error::check_source;
+use crate::{PixelFormat, ScaledImage, ScalerConfig};
+
+/// Use the xBRZ algorithm to scale up an image by every factor from 1 to `max_factor`, returning
+/// the scaled images in order, so that the image scaled by `factor` is at index `factor - 1`.
+///
+/// This suits engines that show crisp pixel art at several zoom levels, and pick the level
+/// closest to the zoom rather than filtering a single large texture. The blending of the image is
+/// decided once with [`compute_blend_map`](crate::compute_blend_map) and shared by every level,
+/// so the chain takes less time than scaling the image by each factor separately, and
+/// each level is the same as [`scale_rgba`](crate::scale_rgba) would give for its factor.
+///
+/// ```
+/// let (width, height) = (4, 4);
+/// let source = vec![255u8; width * height * 4];
+///
+/// let mips = xbrz::generate_mips(&source, width, height, 4);
+/// assert_eq!(mips.len(), 4);
+/// assert_eq!((mips[2].width(), mips[2].height()), (12, 12));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `max_factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn generate_mips(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    max_factor: usize,
+) -> Vec<ScaledImage> {
+    generate_mips_with_config(
+        source,
+        src_width,
+        src_height,
+        max_factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by every factor from 1 to `max_factor`, with
+/// custom tuning parameters.
+///
+/// This is the same as [`generate_mips`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `max_factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn generate_mips_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    max_factor: usize,
+    config: &ScalerConfig,
+) -> Vec<ScaledImage> {
+    // checking the largest level up front saves scaling the smaller ones before panicking
+    check_source(source, src_width, src_height, max_factor, 4).unwrap_or_else(|e| panic!("{e}"));
+
+    let blend_map = crate::compute_blend_map(source, src_width, src_height, config);
+    (1..=max_factor)
+        .map(|factor| {
+            let data = crate::scale_rgba_with_blend_map(
+                source, src_width, src_height, factor, config, &blend_map,
+            );
+            ScaledImage::new(
+                data,
+                src_width * factor,
+                src_height * factor,
+                PixelFormat::Rgba8,
+            )
+            .expect("scaled image has the scaled dimensions")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_level_matches_scaling_by_its_factor() {
+        let (width, height) = (7, 5);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width + i / width) % 3 {
+                0 => [255, 255, 255, 255],
+                1 => [30, 60, 200, 255],
+                _ => [0, 0, 0, 0],
+            })
+            .collect();
+        let config = ScalerConfig::preset(crate::Preset::Sharp);
+
+        let mips = generate_mips_with_config(&source, width, height, 6, &config);
+        assert_eq!(mips.len(), 6);
+        for (level, factor) in mips.iter().zip(1..) {
+            assert_eq!(
+                (level.width(), level.height()),
+                (width * factor, height * factor)
+            );
+            assert_eq!(
+                **level,
+                crate::scale_rgba_with_config(&source, width, height, factor, &config),
+                "x{factor}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "scale factor must be between 1 and 6")]
+    fn max_factor_is_checked() {
+        generate_mips(&[0; 4], 1, 1, 7);
+    }
+}
@@ -0,0 +1,166 @@
+//! Scaling raw image files through memory maps, for images too large to hold in memory.
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+use memmap2::{Mmap, MmapMut};
+
+use crate::error::check_dimensions;
+use crate::{ScaleArena, ScalerConfig};
+
+/// The most output bytes written before they are flushed to the file, which bounds the memory
+/// taken up by pages that have been written but not yet saved.
+const BATCH_BYTES: usize = 64 << 20;
+
+/// Use the xBRZ algorithm to scale up a raw RGBA image file by an integer factor, writing the
+/// scaled image to a raw RGBA file at `output`, which is created or replaced.
+///
+/// Both files are memory-mapped, and the image is scaled a batch of rows at a time, with each
+/// batch flushed to `output` before the next is started. Only the rows being scaled need to be in
+/// memory at once, so images far larger than the available memory can be scaled. The files hold
+/// rows of `width * 4` bytes from top to bottom, with no header, as written by
+/// `ffmpeg -pix_fmt rgba` or ImageMagick's `rgba:` format.
+///
+/// The scaled image is the same as [`scale_rgba_with_config`](crate::scale_rgba_with_config) gives.
+///
+/// # Errors
+///
+/// Returns an error of kind [`InvalidInput`](io::ErrorKind::InvalidInput) if `factor` is not one
+/// of 1, 2, 3, 4, 5 or 6, if the scaled image is too large for this target, or if the input file
+/// is not exactly `src_width * src_height * 4` bytes long, along with any error from reading,
+/// writing or mapping the files.
+pub fn scale_rgba_file(
+    input: &Path,
+    output: &Path,
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> io::Result<()> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let dst_len = check_dimensions(src_width, src_height, factor, 4).map_err(invalid)?;
+
+    let source = File::open(input)?;
+    let expected = src_width * src_height * 4;
+    let actual = source.metadata()?.len();
+    if actual != expected as u64 {
+        return Err(invalid(crate::ScaleError::SourceLength {
+            expected,
+            actual: actual as usize,
+        }));
+    }
+    let destination = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output)?;
+    destination.set_len(dst_len as u64)?;
+    if dst_len == 0 {
+        return Ok(());
+    }
+
+    // SAFETY: the files must not be changed by anything else while they are mapped
+    #[allow(unsafe_code)]
+    let source = unsafe { Mmap::map(&source)? };
+    #[allow(unsafe_code)]
+    let mut destination = unsafe { MmapMut::map_mut(&destination)? };
+
+    let dst_row_len = src_width * factor * factor * 4;
+    let batch_rows = (BATCH_BYTES / dst_row_len).max(1);
+    for y_first in (0..src_height).step_by(batch_rows) {
+        let y_last = (y_first + batch_rows).min(src_height);
+        let (offset, len) = (y_first * dst_row_len, (y_last - y_first) * dst_row_len);
+        let batch = &mut destination[offset..offset + len];
+        scale_batch(
+            &source,
+            src_width,
+            src_height,
+            factor,
+            y_first..y_last,
+            config,
+            batch,
+        );
+        destination.flush_range(offset, len)?;
+    }
+    Ok(())
+}
+
+/// Scale the source rows in `y_range` into `batch`, in parallel if possible.
+fn scale_batch(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    y_range: Range<usize>,
+    config: &ScalerConfig,
+    batch: &mut [u8],
+) {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+
+        let dst_row_len = src_width * factor * factor * 4;
+        let stripe_height = crate::parallel_stripe_height(y_range.len(), config.thread_count());
+        batch
+            .par_chunks_mut(stripe_height * dst_row_len)
+            .enumerate()
+            .for_each_init(ScaleArena::new, |arena, (i, stripe)| {
+                let y_start = y_range.start + i * stripe_height;
+                let y_end = (y_start + stripe_height).min(y_range.end);
+                crate::scale_rgba_stripe(
+                    source,
+                    src_width,
+                    src_height,
+                    factor,
+                    y_start..y_end,
+                    config,
+                    stripe,
+                    arena,
+                );
+            });
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    crate::scale_rgba_stripe(
+        source,
+        src_width,
+        src_height,
+        factor,
+        y_range,
+        config,
+        batch,
+        &mut ScaleArena::new(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg_attr(miri, ignore = "Miri can't memory-map files")]
+    fn scaled_file_matches_scaled_image() {
+        let (width, height, factor) = (37, 23, 3);
+        let source: Vec<u8> = (0..width * height * 4)
+            .map(|i: usize| (i.wrapping_mul(2654435761) >> 7) as u8 & 0xC0)
+            .collect();
+        let dir = std::env::temp_dir().join(format!("xbrz-mmap-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (input, output) = (dir.join("in.rgba"), dir.join("out.rgba"));
+        std::fs::write(&input, &source).unwrap();
+        let config = ScalerConfig::default();
+
+        scale_rgba_file(&input, &output, width, height, factor, &config).unwrap();
+        let scaled = std::fs::read(&output).unwrap();
+        let short = scale_rgba_file(&input, &output, width + 1, height, factor, &config);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            scaled,
+            crate::scale_rgba_with_config(&source, width, height, factor, &config)
+        );
+        assert_eq!(short.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::oob_reader::{OobReaderRegion, Region};
+use crate::pixel::{Pixel, Rgba8};
+use crate::scaler::strided_len;
+use crate::{scale_pixels_into, ScaleArena, ScalerConfig};
+
+/// The borders of a 9-slice image, in pixels from each edge.
+///
+/// The borders split the image into four corners, which are drawn as they are, four edges, which
+/// are stretched or repeated along their length, and a centre, which is stretched or repeated
+/// in both directions to fill the space between them.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct NineSlice {
+    pub left: usize,
+    pub top: usize,
+    pub right: usize,
+    pub bottom: usize,
+}
+
+impl NineSlice {
+    pub const fn new(left: usize, top: usize, right: usize, bottom: usize) -> Self {
+        Self {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// The same border on every side.
+    pub const fn uniform(border: usize) -> Self {
+        Self::new(border, border, border, border)
+    }
+
+    /// The borders of an image scaled up by `factor`, as given to the game engine alongside
+    /// the image returned by [`scale_rgba_nine_slice`].
+    pub const fn scaled(self, factor: usize) -> Self {
+        Self::new(
+            self.left * factor,
+            self.top * factor,
+            self.right * factor,
+            self.bottom * factor,
+        )
+    }
+}
+
+/// Use the xBRZ algorithm to scale up a 9-slice image by an integer factor, scaling each of its
+/// nine regions on its own.
+///
+/// Scaling the whole image at once lets the corners, edges and centre blend into each other,
+/// which shows up as seams once the engine stretches or repeats the edges and centre. Instead,
+/// each region is scaled as if the regions next to it were its own pixels repeated, while the
+/// outer edges of the image fade out to transparent as they do with [`scale_rgba`](crate::scale_rgba).
+///
+/// The image is returned in the same layout as [`scale_rgba`](crate::scale_rgba), with its borders
+/// given by [`NineSlice::scaled`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if the borders of `slice` don't fit within the image,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_nine_slice(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    slice: NineSlice,
+) -> Vec<u8> {
+    scale_rgba_nine_slice_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        slice,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up a 9-slice image by an integer factor, scaling each of its
+/// nine regions on its own.
+///
+/// This is the same as [`scale_rgba_nine_slice`], except that the algorithm is configured by
+/// `config` instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if the borders of `slice` don't fit within the image,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_nine_slice_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    slice: NineSlice,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    assert_eq!(source.len(), src_width * src_height * Rgba8::SIZE);
+    assert!(slice.left + slice.right <= src_width);
+    assert!(slice.top + slice.bottom <= src_height);
+    assert!(factor > 0);
+    assert!(factor <= 6);
+
+    let pixels: Vec<Rgba8> = source
+        .chunks_exact(Rgba8::SIZE)
+        .map(|px| Rgba8::from_rgba(px.try_into().unwrap()))
+        .collect();
+    let dst_width = src_width * factor;
+    let mut dst = vec![Rgba8::default(); dst_width * src_height * factor];
+
+    // the start and length of each column and row of regions
+    let columns = [
+        (0, slice.left),
+        (slice.left, src_width - slice.left - slice.right),
+        (src_width - slice.right, slice.right),
+    ];
+    let rows = [
+        (0, slice.top),
+        (slice.top, src_height - slice.top - slice.bottom),
+        (src_height - slice.bottom, slice.bottom),
+    ];
+
+    let mut arena = ScaleArena::new();
+    for &(y, height) in &rows {
+        for &(x, width) in &columns {
+            if width == 0 || height == 0 {
+                continue;
+            }
+            let region = Region {
+                pixels: &pixels,
+                image_width: src_width,
+                x,
+                y,
+                extend: [x > 0, y > 0, x + width < src_width, y + height < src_height],
+            };
+            let start = y * factor * dst_width + x * factor;
+            let len = strided_len(height * factor, width * factor, dst_width);
+            scale_pixels_into::<Rgba8, OobReaderRegion<Rgba8>, _>(
+                &region,
+                &mut dst[start..start + len],
+                dst_width,
+                width,
+                height,
+                factor,
+                config,
+                &mut arena,
+            );
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(dst.len() * Rgba8::SIZE);
+    for px in dst {
+        let [r, g, b] = px.to_rgb();
+        bytes.extend_from_slice(&[r, g, b, px.alpha()]);
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn no_borders_matches_scale_rgba() {
+        let (width, height) = (7, 5);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width + i / width) % 3 {
+                0 => RED,
+                _ => BLUE,
+            })
+            .collect();
+
+        for factor in 1..=6 {
+            assert_eq!(
+                crate::scale_rgba(&source, width, height, factor),
+                scale_rgba_nine_slice(&source, width, height, factor, NineSlice::default()),
+                "x{factor}"
+            );
+        }
+    }
+
+    #[test]
+    fn centre_is_scaled_with_its_edges_extended() {
+        let (width, height) = (12, 11);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width * 3 + i / width * 5) % 7 < 3 {
+                true => RED,
+                false => BLUE,
+            })
+            .collect();
+        let slice = NineSlice::new(3, 2, 4, 3);
+        let (centre_width, centre_height) = (5, 6);
+
+        // the centre with its edge pixels repeated far enough out that the transparent border is never seen
+        const PAD: usize = 4;
+        let (padded_width, padded_height) = (centre_width + PAD * 2, centre_height + PAD * 2);
+        let padded: Vec<u8> = (0..padded_width * padded_height)
+            .flat_map(|i| {
+                let x = (i % padded_width).clamp(PAD, PAD + centre_width - 1) - PAD + slice.left;
+                let y = (i / padded_width).clamp(PAD, PAD + centre_height - 1) - PAD + slice.top;
+                let offset = (y * width + x) * 4;
+                source[offset..offset + 4].to_vec()
+            })
+            .collect();
+
+        for factor in 2..=6 {
+            let scaled = scale_rgba_nine_slice(&source, width, height, factor, slice);
+            let expected = crate::scale_rgba(&padded, padded_width, padded_height, factor);
+            for y in 0..centre_height * factor {
+                let row = (slice.top * factor + y) * width * factor + slice.left * factor;
+                let expected_row = ((PAD * factor + y) * padded_width + PAD) * factor;
+                assert_eq!(
+                    scaled[row * 4..(row + centre_width * factor) * 4],
+                    expected[expected_row * 4..(expected_row + centre_width * factor) * 4],
+                    "x{factor} row {y}"
+                );
+            }
+        }
+    }
+}
@@ -1,90 +1,492 @@
 use std::marker::PhantomData;
-use std::ops::Range;
-use std::ptr;
 
+use crate::format::Orientation;
 use crate::kernel::Kernel4x4;
-use crate::pixel::Pixel;
+use crate::pixel::{PackedPixel, Pixel};
 
 pub(crate) trait OobReader<'src, P: Pixel> {
-    fn new(src: &'src [P], width: usize, height: usize, y: isize) -> Self;
+    /// The image read from, which is usually a slice of `width * height` pixels.
+    type Source: ?Sized + Sync;
+
+    fn new(src: &'src Self::Source, width: usize, height: usize, y: isize) -> Self;
     fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize);
+
+    /// Whether column `x` is the left edge of the whole image, rather than of a part of it.
+    fn is_left_edge(&self, x: isize) -> bool {
+        x == 0
+    }
+
+    /// Row `y` of the image, if the reader holds it as a slice of its pixels in order, so that it
+    /// can be copied as it is where nothing is scaled.
+    fn row(&self) -> Option<&'src [P]> {
+        None
+    }
 }
 
+/// Reads an image stored in rows, fading out to transparent past its edges.
+///
+/// The rows are looked up once, when the reader is made for a row of kernels, and each pixel is
+/// read from them with a bounds check.
 pub(crate) struct OobReaderTransparent<'src, P: Pixel> {
-    src_ym1: *const P,
-    src_y: *const P,
-    src_yp1: *const P,
-    src_yp2: *const P,
-    x_range: Range<isize>,
-    _marker: PhantomData<&'src [P]>,
+    /// The rows from `y - 1` to `y + 2`, each either `width` pixels long or empty if it is outside
+    /// of the image.
+    rows: [&'src [P]; 4],
 }
 
 impl<'src, P: Pixel> OobReader<'src, P> for OobReaderTransparent<'src, P> {
+    type Source = [P];
+
     fn new(src: &'src [P], width: usize, height: usize, y: isize) -> Self {
         assert_eq!(src.len(), width * height);
-        let src = src.as_ptr();
-        let x_range = 0..(width as isize);
-        let y_range = 0..(height as isize);
-        unsafe {
-            Self {
-                src_ym1: if y_range.contains(&(y - 1)) {
-                    src.offset(width as isize * (y - 1))
-                } else {
-                    ptr::null()
-                },
-                src_y: if y_range.contains(&y) {
-                    src.offset(width as isize * y)
-                } else {
-                    ptr::null()
-                },
-                src_yp1: if y_range.contains(&(y + 1)) {
-                    src.offset(width as isize * (y + 1))
-                } else {
-                    ptr::null()
-                },
-                src_yp2: if y_range.contains(&(y + 2)) {
-                    src.offset(width as isize * (y + 2))
-                } else {
-                    ptr::null()
-                },
-                x_range,
-                _marker: PhantomData,
-            }
+        let row = |y: isize| match usize::try_from(y) {
+            Ok(y) if y < height => &src[y * width..(y + 1) * width],
+            _ => &[],
+        };
+        Self {
+            rows: [row(y - 1), row(y), row(y + 1), row(y + 2)],
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let [ym1, y, yp1, yp2] = self.rows;
+        let read = |row: &[P]| match usize::try_from(x + 2) {
+            Ok(x) => row.get(x).copied().unwrap_or_default(),
+            Err(_) => P::default(),
+        };
+        kernel.d = read(ym1);
+        kernel.h = read(y);
+        kernel.l = read(yp1);
+        kernel.p = read(yp2);
+    }
+
+    fn row(&self) -> Option<&'src [P]> {
+        Some(self.rows[1])
+    }
+}
+
+pub(crate) struct OobReaderDuplicate<'src, P: Pixel> {
+    src_ym1: &'src [P],
+    src_y: &'src [P],
+    src_yp1: &'src [P],
+    src_yp2: &'src [P],
+    width: usize,
+}
+
+impl<'src, P: Pixel> OobReader<'src, P> for OobReaderDuplicate<'src, P> {
+    type Source = [P];
+
+    fn new(src: &'src [P], width: usize, height: usize, y: isize) -> Self {
+        assert_eq!(src.len(), width * height);
+        let row = |y: isize| {
+            let y = y.clamp(0, height as isize - 1) as usize;
+            &src[y * width..(y + 1) * width]
+        };
+        Self {
+            src_ym1: row(y - 1),
+            src_y: row(y),
+            src_yp1: row(y + 1),
+            src_yp2: row(y + 2),
+            width,
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let x_p2 = (x + 2).clamp(0, self.width as isize - 1) as usize;
+
+        kernel.d = self.src_ym1[x_p2];
+        kernel.h = self.src_y[x_p2];
+        kernel.l = self.src_yp1[x_p2];
+        kernel.p = self.src_yp2[x_p2];
+    }
+
+    fn row(&self) -> Option<&'src [P]> {
+        Some(self.src_y)
+    }
+}
+
+/// Reads an image stored in column-major order, with each column of `height` pixels following the last,
+/// as if it were stored in rows. As with [`OobReaderTransparent`], the edges fade out to transparent.
+pub(crate) struct OobReaderColumnMajor<'src, P: Pixel> {
+    src: &'src [P],
+    width: usize,
+    height: usize,
+    y: isize,
+}
+
+impl<'src, P: Pixel> OobReader<'src, P> for OobReaderColumnMajor<'src, P> {
+    type Source = [P];
+
+    fn new(src: &'src [P], width: usize, height: usize, y: isize) -> Self {
+        assert_eq!(src.len(), width * height);
+        Self {
+            src,
+            width,
+            height,
+            y,
         }
     }
 
     fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
-        let zero = P::default();
         let x_p2 = x + 2;
+        let read = |y: isize| {
+            if (0..self.width as isize).contains(&x_p2) && (0..self.height as isize).contains(&y) {
+                self.src[x_p2 as usize * self.height + y as usize]
+            } else {
+                P::default()
+            }
+        };
+
+        kernel.d = read(self.y - 1);
+        kernel.h = read(self.y);
+        kernel.l = read(self.y + 1);
+        kernel.p = read(self.y + 2);
+    }
+}
+
+/// Reads an image with its rows stored from bottom to top, as if they were stored from top to bottom.
+/// As with [`OobReaderTransparent`], the edges fade out to transparent.
+pub(crate) struct OobReaderBottomUp<'src, P: Pixel> {
+    src_ym1: Option<&'src [P]>,
+    src_y: Option<&'src [P]>,
+    src_yp1: Option<&'src [P]>,
+    src_yp2: Option<&'src [P]>,
+}
+
+impl<'src, P: Pixel> OobReader<'src, P> for OobReaderBottomUp<'src, P> {
+    type Source = [P];
+
+    fn new(src: &'src [P], width: usize, height: usize, y: isize) -> Self {
+        assert_eq!(src.len(), width * height);
+        let row = |y: isize| {
+            let y = usize::try_from(y).ok().filter(|&y| y < height)?;
+            let start = (height - 1 - y) * width;
+            Some(&src[start..start + width])
+        };
+        Self {
+            src_ym1: row(y - 1),
+            src_y: row(y),
+            src_yp1: row(y + 1),
+            src_yp2: row(y + 2),
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let x_p2 = usize::try_from(x + 2).ok();
+        let read = |row: Option<&[P]>| {
+            row.zip(x_p2)
+                .and_then(|(row, x)| row.get(x).copied())
+                .unwrap_or_default()
+        };
+
+        kernel.d = read(self.src_ym1);
+        kernel.h = read(self.src_y);
+        kernel.l = read(self.src_yp1);
+        kernel.p = read(self.src_yp2);
+    }
+    fn row(&self) -> Option<&'src [P]> {
+        self.src_y
+    }
+}
+
+/// An image stored in rows, read as if it had been turned or flipped by `orientation`.
+/// The `width` and `height` are those of the image as it is stored.
+pub(crate) struct Oriented<'a, P> {
+    pub(crate) pixels: &'a [P],
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) orientation: Orientation,
+}
+
+/// Reads an [`Oriented`] image, looking up where each pixel of the turned image is stored. As
+/// with [`OobReaderTransparent`], the edges fade out to transparent.
+pub(crate) struct OobReaderOriented<'src, P: Pixel> {
+    src: &'src Oriented<'src, P>,
+    /// The width and height of the turned image.
+    width: usize,
+    height: usize,
+    y: isize,
+}
+
+impl<'src, P: Pixel> OobReader<'src, P> for OobReaderOriented<'src, P> {
+    type Source = Oriented<'src, P>;
+
+    fn new(src: &'src Oriented<'src, P>, width: usize, height: usize, y: isize) -> Self {
+        assert_eq!(src.pixels.len(), src.width * src.height);
+        assert_eq!(
+            (width, height),
+            src.orientation.dimensions(src.width, src.height)
+        );
+        Self {
+            src,
+            width,
+            height,
+            y,
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let x = usize::try_from(x + 2).ok().filter(|&x| x < self.width);
+        let read = |y: isize| {
+            let y = usize::try_from(y).ok().filter(|&y| y < self.height);
+            match x.zip(y) {
+                Some((x, y)) => {
+                    let src = self.src;
+                    let (x, y) = src.orientation.source_xy(x, y, src.width, src.height);
+                    src.pixels[y * src.width + x]
+                }
+                None => P::default(),
+            }
+        };
+
+        kernel.d = read(self.y - 1);
+        kernel.h = read(self.y);
+        kernel.l = read(self.y + 1);
+        kernel.p = read(self.y + 2);
+    }
+}
+
+/// Rows of packed pixels in a byte slice, each starting `pitch` bytes after the last.
+/// The final row does not need to be padded out to the full pitch.
+pub(crate) struct PackedRows<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) pitch: usize,
+}
+
+/// Reads packed pixels straight out of their rows, converting each one as the kernel is filled,
+/// so that the source doesn't need to be converted up front. As with [`OobReaderDuplicate`],
+/// the edges of the image are extended outwards.
+pub(crate) struct OobReaderPacked<'src, P: PackedPixel> {
+    src_ym1: &'src [u8],
+    src_y: &'src [u8],
+    src_yp1: &'src [u8],
+    src_yp2: &'src [u8],
+    width: usize,
+    _marker: PhantomData<P>,
+}
+
+impl<'src, P: PackedPixel> OobReader<'src, P> for OobReaderPacked<'src, P> {
+    type Source = PackedRows<'src>;
+
+    fn new(src: &'src PackedRows<'src>, width: usize, height: usize, y: isize) -> Self {
+        let row_len = width * P::PACKED_SIZE;
+        assert!(src.pitch >= row_len);
+        assert!(src.data.len() >= src.pitch * (height - 1) + row_len);
+        let row = |y: isize| {
+            let start = y.clamp(0, height as isize - 1) as usize * src.pitch;
+            &src.data[start..start + row_len]
+        };
+        Self {
+            src_ym1: row(y - 1),
+            src_y: row(y),
+            src_yp1: row(y + 1),
+            src_yp2: row(y + 2),
+            width,
+            _marker: PhantomData,
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let offset = (x + 2).clamp(0, self.width as isize - 1) as usize * P::PACKED_SIZE;
+        let pixel = offset..offset + P::PACKED_SIZE;
+
+        kernel.d = P::read(&self.src_ym1[pixel.clone()]);
+        kernel.h = P::read(&self.src_y[pixel.clone()]);
+        kernel.l = P::read(&self.src_yp1[pixel.clone()]);
+        kernel.p = P::read(&self.src_yp2[pixel]);
+    }
+}
+
+/// A rectangle of `width` by `height` pixels within a larger image, read as an image of its own.
+/// The top left corner is at `(x, y)` and each row of the whole image is `image_width` pixels long.
+pub(crate) struct Region<'a, P> {
+    pub(crate) pixels: &'a [P],
+    pub(crate) image_width: usize,
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    /// Whether each side is extended outwards rather than faded out to transparent,
+    /// in left, top, right, bottom order.
+    pub(crate) extend: [bool; 4],
+}
+
+/// Reads a [`Region`] without ever reading the pixels around it. Each side is either extended
+/// outwards as with [`OobReaderDuplicate`] or faded out as with [`OobReaderTransparent`].
+pub(crate) struct OobReaderRegion<'src, P: Pixel> {
+    src_ym1: Option<&'src [P]>,
+    src_y: Option<&'src [P]>,
+    src_yp1: Option<&'src [P]>,
+    src_yp2: Option<&'src [P]>,
+    width: usize,
+    extend_left: bool,
+    extend_right: bool,
+}
+
+impl<'src, P: Pixel> OobReader<'src, P> for OobReaderRegion<'src, P> {
+    type Source = Region<'src, P>;
+
+    fn new(src: &'src Region<'src, P>, width: usize, height: usize, y: isize) -> Self {
+        assert!(src.x + width <= src.image_width);
+        assert!(src.pixels.len() >= (src.y + height) * src.image_width);
+        let [extend_left, extend_top, extend_right, extend_bottom] = src.extend;
+        let row = |y: isize| {
+            let y = match y {
+                y if y < 0 && !extend_top => return None,
+                y if y >= height as isize && !extend_bottom => return None,
+                y => y.clamp(0, height as isize - 1) as usize,
+            };
+            let start = (src.y + y) * src.image_width + src.x;
+            Some(&src.pixels[start..start + width])
+        };
+        Self {
+            src_ym1: row(y - 1),
+            src_y: row(y),
+            src_yp1: row(y + 1),
+            src_yp2: row(y + 2),
+            width,
+            extend_left,
+            extend_right,
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let x_p2 = match x + 2 {
+            x if x < 0 && !self.extend_left => None,
+            x if x >= self.width as isize && !self.extend_right => None,
+            x => Some(x.clamp(0, self.width as isize - 1) as usize),
+        };
+        let read = |row: Option<&[P]>| match (row, x_p2) {
+            (Some(row), Some(x)) => row[x],
+            _ => P::default(),
+        };
+
+        kernel.d = read(self.src_ym1);
+        kernel.h = read(self.src_y);
+        kernel.l = read(self.src_yp1);
+        kernel.p = read(self.src_yp2);
+    }
+    fn row(&self) -> Option<&'src [P]> {
+        self.src_y
+    }
+}
+
+/// A rectangle of `width` by `height` pixels within a larger image, read as an image of its own but
+/// with the pixels around it taken from the rest of the image. The top left corner is at `(x, y)`
+/// in an image of `image_width` by `image_height` pixels.
+pub(crate) struct Window<'a, P> {
+    pub(crate) pixels: &'a [P],
+    pub(crate) image_width: usize,
+    pub(crate) image_height: usize,
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+}
+
+/// Reads a [`Window`], so that it is scaled just as it would be as part of the whole image. Past
+/// the edges of the whole image, it fades out to transparent as with [`OobReaderTransparent`].
+pub(crate) struct OobReaderWindow<'src, P: Pixel> {
+    /// The rows of the whole image from `y - 1` to `y + 2` of the window, each either
+    /// `image_width` pixels long or empty if it is outside of the image.
+    rows: [&'src [P]; 4],
+    x: isize,
+}
+
+impl<'src, P: Pixel> OobReader<'src, P> for OobReaderWindow<'src, P> {
+    type Source = Window<'src, P>;
+
+    fn new(src: &'src Window<'src, P>, width: usize, height: usize, y: isize) -> Self {
+        assert!(src.x + width <= src.image_width);
+        assert!(src.y + height <= src.image_height);
+        assert_eq!(src.pixels.len(), src.image_width * src.image_height);
+        let row = |y: isize| match usize::try_from(src.y as isize + y) {
+            Ok(y) if y < src.image_height => &src.pixels[y * src.image_width..][..src.image_width],
+            _ => &[],
+        };
+        Self {
+            rows: [row(y - 1), row(y), row(y + 1), row(y + 2)],
+            x: src.x as isize,
+        }
+    }
+
+    fn fill_dhlp(&self, kernel: &mut Kernel4x4<P>, x: isize) {
+        let x_p2 = usize::try_from(self.x + x + 2);
+        let read = |row: &[P]| match x_p2 {
+            Ok(x) => row.get(x).copied().unwrap_or_default(),
+            Err(_) => P::default(),
+        };
+        let [ym1, y, yp1, yp2] = self.rows;
+
+        kernel.d = read(ym1);
+        kernel.h = read(y);
+        kernel.l = read(yp1);
+        kernel.p = read(yp2);
+    }
+
+    fn is_left_edge(&self, x: isize) -> bool {
+        self.x + x == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Rgba8;
+
+    #[test]
+    fn transparent_reader_fades_out_past_every_edge() {
+        for (width, height) in [(1, 1), (3, 2), (2, 4), (5, 7)] {
+            let pixels: Vec<Rgba8> = (0..width * height)
+                .map(|i| Rgba8::from_rgba([i as u8, 1, 2, 255]))
+                .collect();
+            let expected = |x: isize, y: isize| match (usize::try_from(x), usize::try_from(y)) {
+                (Ok(x), Ok(y)) if x < width && y < height => pixels[y * width + x],
+                _ => Rgba8::default(),
+            };
+
+            for y in -3..height as isize + 2 {
+                let reader = OobReaderTransparent::new(&pixels, width, height, y);
+                for x in -6..width as isize + 2 {
+                    let mut kernel = Kernel4x4::default();
+                    reader.fill_dhlp(&mut kernel, x);
+                    assert_eq!(
+                        [kernel.d, kernel.h, kernel.l, kernel.p],
+                        [-1, 0, 1, 2].map(|dy| expected(x + 2, y + dy)),
+                        "({x}, {y}) in {width}x{height}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn window_reader_reads_around_the_window() {
+        let (width, height) = (6, 5);
+        let pixels: Vec<Rgba8> = (0..width * height)
+            .map(|i| Rgba8::from_rgba([i as u8, 1, 2, 255]))
+            .collect();
+        let expected = |x: isize, y: isize| match (usize::try_from(x), usize::try_from(y)) {
+            (Ok(x), Ok(y)) if x < width && y < height => pixels[y * width + x],
+            _ => Rgba8::default(),
+        };
 
-        if self.x_range.contains(&x_p2) {
-            unsafe {
-                kernel.d = if self.src_ym1.is_null() {
-                    zero
-                } else {
-                    *self.src_ym1.offset(x_p2)
-                };
-                kernel.h = if self.src_y.is_null() {
-                    zero
-                } else {
-                    *self.src_y.offset(x_p2)
-                };
-                kernel.l = if self.src_yp1.is_null() {
-                    zero
-                } else {
-                    *self.src_yp1.offset(x_p2)
-                };
-                kernel.p = if self.src_yp2.is_null() {
-                    zero
-                } else {
-                    *self.src_yp2.offset(x_p2)
-                };
+        for (x0, y0, window_width, window_height) in [(0, 0, 6, 5), (2, 1, 3, 3), (5, 4, 1, 1)] {
+            let window = Window {
+                pixels: &pixels,
+                image_width: width,
+                image_height: height,
+                x: x0,
+                y: y0,
+            };
+            for y in -3..window_height as isize + 2 {
+                let reader = OobReaderWindow::new(&window, window_width, window_height, y);
+                for x in -6..window_width as isize + 2 {
+                    let mut kernel = Kernel4x4::default();
+                    reader.fill_dhlp(&mut kernel, x);
+                    let (x, y) = (x0 as isize + x, y0 as isize + y);
+                    assert_eq!(
+                        [kernel.d, kernel.h, kernel.l, kernel.p],
+                        [-1, 0, 1, 2].map(|dy| expected(x + 2, y + dy)),
+                        "({x}, {y}) in the window at ({x0}, {y0})"
+                    );
+                }
             }
-        } else {
-            kernel.d = zero;
-            kernel.h = zero;
-            kernel.l = zero;
-            kernel.p = zero;
         }
     }
 }
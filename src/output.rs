@@ -0,0 +1,191 @@
+//! Scaling straight into the caller's choice of buffer.
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::error::{check_dimensions, check_source};
+use crate::oob_reader::OobReaderTransparent;
+use crate::pixel::{cast_pixels, with_pixels_mut, Rgba8};
+use crate::ScalerConfig;
+
+/// A buffer that a scaled image can be written straight into, without copying it out of a
+/// `Vec<u8>` afterwards.
+///
+/// Implemented for `Vec<u8>`, `Box<[u8]>`, `Arc<[u8]>` and `Rc<[u8]>`, so that a scaled image can
+/// be handed to other threads or across an FFI boundary in whatever form they expect. Implement it
+/// for a pooled buffer type to have images scaled into buffers taken from the pool.
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// let (width, height) = (4, 4);
+/// let source = vec![255u8; width * height * 4];
+///
+/// let scaled: Arc<[u8]> = xbrz::scale_rgba_to_buffer(&source, width, height, 2);
+/// assert_eq!(scaled.len(), width * 2 * height * 2 * 4);
+/// ```
+pub trait OutputBuffer {
+    /// Make a buffer of `len` bytes, and have `write` fill in every one of them.
+    fn new_with(len: usize, write: impl FnOnce(&mut [u8])) -> Self;
+}
+
+impl OutputBuffer for Vec<u8> {
+    fn new_with(len: usize, write: impl FnOnce(&mut [u8])) -> Self {
+        let mut buffer = vec![0; len];
+        write(&mut buffer);
+        buffer
+    }
+}
+
+impl OutputBuffer for Box<[u8]> {
+    fn new_with(len: usize, write: impl FnOnce(&mut [u8])) -> Self {
+        // the vector is exactly `len` bytes long, so it is boxed without reallocating
+        Vec::new_with(len, write).into_boxed_slice()
+    }
+}
+
+impl OutputBuffer for Arc<[u8]> {
+    fn new_with(len: usize, write: impl FnOnce(&mut [u8])) -> Self {
+        // collecting an iterator of known length allocates the shared slice directly
+        let mut buffer: Arc<[u8]> = std::iter::repeat_n(0, len).collect();
+        write(Arc::get_mut(&mut buffer).expect("a new Arc is not shared"));
+        buffer
+    }
+}
+
+impl OutputBuffer for Rc<[u8]> {
+    fn new_with(len: usize, write: impl FnOnce(&mut [u8])) -> Self {
+        let mut buffer: Rc<[u8]> = std::iter::repeat_n(0, len).collect();
+        write(Rc::get_mut(&mut buffer).expect("a new Rc is not shared"));
+        buffer
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, returning the scaled image in
+/// any [`OutputBuffer`].
+///
+/// This is the same as [`scale_rgba`](crate::scale_rgba), except for the type of buffer returned.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_to_buffer<B: OutputBuffer>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+) -> B {
+    scale_rgba_to_buffer_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, with custom tuning
+/// parameters, returning the scaled image in any [`OutputBuffer`].
+///
+/// This is the same as [`scale_rgba_with_config`](crate::scale_rgba_with_config), except for the
+/// type of buffer returned.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_to_buffer_with_config<B: OutputBuffer>(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> B {
+    let dst_len =
+        check_dimensions(src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+    check_source(source, src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+    B::new_with(dst_len, |destination| {
+        if dst_len == 0 {
+            return;
+        }
+        let src_argb = cast_pixels::<Rgba8>(source);
+        with_pixels_mut::<Rgba8, _>(destination, |dst_argb| {
+            crate::scale_pixels_to::<Rgba8, OobReaderTransparent<Rgba8>, _>(
+                &src_argb, dst_argb, src_width, src_height, factor, config, None, None,
+            );
+        });
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A buffer that keeps hold of the allocation it was given back, as a pool would.
+    struct Pooled(Vec<u8>);
+
+    thread_local! {
+        static POOL: std::cell::RefCell<Vec<Vec<u8>>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+
+    impl OutputBuffer for Pooled {
+        fn new_with(len: usize, write: impl FnOnce(&mut [u8])) -> Self {
+            let mut buffer = POOL.with_borrow_mut(|pool| pool.pop()).unwrap_or_default();
+            buffer.resize(len, 0);
+            write(&mut buffer);
+            Pooled(buffer)
+        }
+    }
+
+    impl Drop for Pooled {
+        fn drop(&mut self) {
+            POOL.with_borrow_mut(|pool| pool.push(std::mem::take(&mut self.0)));
+        }
+    }
+
+    #[test]
+    fn every_buffer_holds_the_same_image() {
+        let (width, height) = (45, 40);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width + 3 * (i / width)) % 7 {
+                0..=2 => [255, 255, 255, 255],
+                3 => [30, 60, 200, 128],
+                _ => [0, 0, 0, 255],
+            })
+            .collect();
+        let config = ScalerConfig::default();
+        let expected = crate::scale_rgba_with_config(&source, width, height, 4, &config);
+
+        let scale = |factor| {
+            let vec: Vec<u8> =
+                scale_rgba_to_buffer_with_config(&source, width, height, factor, &config);
+            let boxed: Box<[u8]> =
+                scale_rgba_to_buffer_with_config(&source, width, height, factor, &config);
+            let arc: Arc<[u8]> =
+                scale_rgba_to_buffer_with_config(&source, width, height, factor, &config);
+            let rc: Rc<[u8]> =
+                scale_rgba_to_buffer_with_config(&source, width, height, factor, &config);
+            (vec, boxed, arc, rc)
+        };
+        let (vec, boxed, arc, rc) = scale(4);
+        assert!(vec == expected);
+        assert!(*boxed == *expected);
+        assert!(*arc == *expected);
+        assert!(*rc == *expected);
+
+        // the pooled buffer is handed back and reused for the next image
+        let pooled: Pooled = scale_rgba_to_buffer_with_config(&source, width, height, 4, &config);
+        assert!(pooled.0 == expected);
+        let ptr = pooled.0.as_ptr();
+        drop(pooled);
+        let pooled: Pooled = scale_rgba_to_buffer_with_config(&source, width, height, 3, &config);
+        assert_eq!(pooled.0.as_ptr(), ptr);
+        assert!(pooled.0 == crate::scale_rgba_with_config(&source, width, height, 3, &config));
+    }
+
+    #[test]
+    fn empty_images_give_empty_buffers() {
+        let scaled: Box<[u8]> = scale_rgba_to_buffer(&[], 0, 5, 3);
+        assert!(scaled.is_empty());
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ycbcr_lookup::{dist_ycbcr, weigh_by_alpha};
+use crate::ScalerConfig;
+
+/// A limited set of RGBA colours that a scaled image can be snapped back to, for artwork that
+/// needs xBRZ's smoothed shapes without the new colours blended along its edges.
+///
+/// All fully transparent colours are treated as the same colour, `[0, 0, 0, 0]`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Palette {
+    colors: Vec<[u8; 4]>,
+}
+
+/// How the difference between each pixel and its nearest palette colour is spread out over the image
+/// when snapping to a [`Palette`], so that blended colours are approximated by a pattern of palette colours.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Dither {
+    /// Each pixel is replaced by its nearest colour.
+    #[default]
+    None,
+    /// A 4x4 Bayer matrix, giving a regular cross-hatched pattern that stays put between frames of animation.
+    Ordered,
+    /// Floyd-Steinberg error diffusion, which follows the original colours more closely.
+    FloydSteinberg,
+}
+
+/// Thresholds of a 4x4 Bayer matrix, out of 16.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// Treat every fully transparent pixel as the same colour, whatever its RGB values.
+fn normalize(rgba: [u8; 4]) -> [u8; 4] {
+    match rgba[3] {
+        0 => [0; 4],
+        _ => rgba,
+    }
+}
+
+/// The distance between two colours, using the same YCbCr metric and alpha weighting as the scaler.
+fn distance(a: [u8; 4], b: [u8; 4]) -> f32 {
+    let diff = |c: usize| a[c] as i16 - b[c] as i16;
+    let d = dist_ycbcr(diff(0), diff(1), diff(2)) as f32;
+    weigh_by_alpha(a[3], b[3], d)
+}
+
+impl Palette {
+    /// A palette of the given colours, in order, with any repeated colours removed.
+    pub fn new(colors: impl IntoIterator<Item = [u8; 4]>) -> Self {
+        let mut palette = Self::default();
+        for color in colors.into_iter().map(normalize) {
+            if !palette.colors.contains(&color) {
+                palette.colors.push(color);
+            }
+        }
+        palette
+    }
+
+    /// The colours used in an RGBA image, laid out as for [`scale_rgba`](crate::scale_rgba), from
+    /// most to least common.
+    ///
+    /// If `max_colors` is given and the image has more colours than that, only the most common
+    /// colours are kept; colours that are equally common are kept in the order they first appear.
+    pub fn from_rgba(source: &[u8], max_colors: Option<usize>) -> Self {
+        // the count of each colour, and the order that it first appeared in
+        let mut counts: HashMap<[u8; 4], (usize, usize)> = HashMap::new();
+        for px in source.chunks_exact(4) {
+            let color = normalize(px.try_into().unwrap());
+            let first = counts.len();
+            counts.entry(color).or_insert((0, first)).0 += 1;
+        }
+
+        let mut colors: Vec<_> = counts.into_iter().collect();
+        colors.sort_by_key(|&(_, (count, first))| (usize::MAX - count, first));
+        if let Some(max_colors) = max_colors {
+            colors.truncate(max_colors);
+        }
+        Self {
+            colors: colors.into_iter().map(|(color, _)| color).collect(),
+        }
+    }
+
+    pub fn colors(&self) -> &[[u8; 4]] {
+        &self.colors
+    }
+
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// The colour of the palette closest to `rgba`, or `rgba` itself if the palette is empty.
+    pub fn nearest(&self, rgba: [u8; 4]) -> [u8; 4] {
+        let rgba = normalize(rgba);
+        self.colors
+            .iter()
+            .copied()
+            .min_by(|&a, &b| distance(a, rgba).total_cmp(&distance(b, rgba)))
+            .unwrap_or(rgba)
+    }
+
+    /// Replace every pixel of an RGBA image with the nearest colour of the palette.
+    pub fn apply(&self, image: &mut [u8]) {
+        let mut nearest = self.cached_nearest();
+        for px in image.chunks_exact_mut(4) {
+            let snapped = nearest((&*px).try_into().unwrap());
+            px.copy_from_slice(&snapped);
+        }
+    }
+
+    /// Replace every pixel of an RGBA image, `width` pixels wide, with a colour of the palette,
+    /// dithering the colours in between as chosen by `dither`. Only the colour channels are dithered,
+    /// and pixels that are already one of the colours, including fully transparent pixels, are left as they are.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length of `image` is not a multiple of `width * 4`.
+    pub fn apply_dithered(&self, image: &mut [u8], width: usize, dither: Dither) {
+        if width == 0 {
+            return;
+        }
+        assert_eq!(image.len() % (width * 4), 0);
+
+        match dither {
+            Dither::None => self.apply(image),
+            Dither::Ordered => self.apply_ordered(image, width),
+            Dither::FloydSteinberg => self.apply_floyd_steinberg(image, width),
+        }
+    }
+
+    /// [`Palette::nearest`], remembering each colour that has been looked up.
+    fn cached_nearest(&self) -> impl FnMut([u8; 4]) -> [u8; 4] + '_ {
+        // scaled pixel art has few distinct colours, so most are looked up many times
+        let mut cache: HashMap<[u8; 4], [u8; 4]> = HashMap::new();
+        move |color| *cache.entry(color).or_insert_with(|| self.nearest(color))
+    }
+
+    /// The colours of the palette, and the transparent colour, which are never dithered.
+    fn exact_colors(&self) -> HashSet<[u8; 4]> {
+        self.colors.iter().copied().chain([[0; 4]]).collect()
+    }
+
+    /// The average distance between each colour and the closest other colour of the palette,
+    /// which is how far apart the colours mixed by dithering usually are.
+    fn spacing(&self) -> f32 {
+        let opaque: Vec<_> = self.colors.iter().filter(|c| c[3] > 0).collect();
+        if opaque.len() < 2 {
+            return 0.0;
+        }
+        // the largest difference of any one channel, since every channel is offset by the same amount
+        let rgb_distance =
+            |a: &[u8; 4], b: &[u8; 4]| (0..3).map(|c| a[c].abs_diff(b[c])).max().unwrap() as f32;
+        let total: f32 = opaque
+            .iter()
+            .map(|a| {
+                opaque
+                    .iter()
+                    .filter(|b| a != *b)
+                    .map(|b| rgb_distance(a, b))
+                    .fold(f32::MAX, f32::min)
+            })
+            .sum();
+        total / opaque.len() as f32
+    }
+
+    fn apply_ordered(&self, image: &mut [u8], width: usize) {
+        let spread = self.spacing();
+        let exact = self.exact_colors();
+        let mut nearest = self.cached_nearest();
+
+        for (i, px) in image.chunks_exact_mut(4).enumerate() {
+            if exact.contains(&normalize((&*px).try_into().unwrap())) {
+                px.copy_from_slice(&nearest((&*px).try_into().unwrap()));
+                continue;
+            }
+            let threshold = BAYER_4X4[i / width % 4][i % width % 4];
+            let offset = ((threshold as f32 + 0.5) / 16.0 - 0.5) * spread;
+            let [r, g, b] =
+                [0, 1, 2].map(|c| (px[c] as f32 + offset).round().clamp(0.0, 255.0) as u8);
+            px.copy_from_slice(&nearest([r, g, b, px[3]]));
+        }
+    }
+
+    fn apply_floyd_steinberg(&self, image: &mut [u8], width: usize) {
+        let exact = self.exact_colors();
+        let mut nearest = self.cached_nearest();
+        // the error carried into this row and the next, with a pixel of room on either side
+        let mut errors = vec![[0f32; 3]; width + 2];
+        let mut next_errors = vec![[0f32; 3]; width + 2];
+
+        for row in image.chunks_exact_mut(width * 4) {
+            for (x, px) in row.chunks_exact_mut(4).enumerate() {
+                // flat areas soak up the error without passing it on, so they stay flat
+                if exact.contains(&normalize((&*px).try_into().unwrap())) {
+                    px.copy_from_slice(&nearest((&*px).try_into().unwrap()));
+                    continue;
+                }
+                let wanted = [0, 1, 2].map(|c| (px[c] as f32 + errors[x + 1][c]).clamp(0.0, 255.0));
+                let [r, g, b] = wanted.map(|c| c.round() as u8);
+                let snapped = nearest([r, g, b, px[3]]);
+                px.copy_from_slice(&snapped);
+
+                for c in 0..3 {
+                    let error = wanted[c] - snapped[c] as f32;
+                    errors[x + 2][c] += error * 7.0 / 16.0;
+                    next_errors[x][c] += error * 3.0 / 16.0;
+                    next_errors[x + 1][c] += error * 5.0 / 16.0;
+                    next_errors[x + 2][c] += error / 16.0;
+                }
+            }
+            std::mem::swap(&mut errors, &mut next_errors);
+            next_errors.fill([0.0; 3]);
+        }
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, then snap every pixel
+/// to a colour of `palette`, dithering the colours blended in between as chosen by `dither`.
+///
+/// This is the same as [`scale_rgba_with_config`](crate::scale_rgba_with_config), followed by
+/// [`Palette::apply_dithered`]. To keep to the colours of the source image, pass
+/// [`Palette::from_rgba`] of the `source`.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_with_palette(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    palette: &Palette,
+    dither: Dither,
+) -> Vec<u8> {
+    let mut scaled = crate::scale_rgba_with_config(source, src_width, src_height, factor, config);
+    palette.apply_dithered(&mut scaled, src_width * factor, dither);
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [200, 30, 30, 255];
+    const GREEN: [u8; 4] = [30, 200, 30, 255];
+    const CLEAR: [u8; 4] = [0; 4];
+
+    /// A red diagonal band over green, with a transparent corner.
+    fn test_image(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                match (x + y) % 6 {
+                    _ if x + y < 2 => CLEAR,
+                    0 | 1 => RED,
+                    _ => GREEN,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scaled_image_only_uses_source_colours() {
+        let (width, height) = (10, 8);
+        let source = test_image(width, height);
+        let palette = Palette::from_rgba(&source, None);
+        assert_eq!(palette.len(), 3);
+
+        for dither in [Dither::None, Dither::Ordered, Dither::FloydSteinberg] {
+            for factor in 2..=6 {
+                let scaled = scale_rgba_with_palette(
+                    &source,
+                    width,
+                    height,
+                    factor,
+                    &ScalerConfig::default(),
+                    &palette,
+                    dither,
+                );
+                for px in scaled.chunks_exact(4) {
+                    let px = px.try_into().unwrap();
+                    assert!(
+                        palette.colors().contains(&px),
+                        "{dither:?} x{factor} {px:?}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn max_colors_keeps_the_most_common() {
+        let source = [
+            RED,
+            GREEN,
+            GREEN,
+            [0, 0, 255, 255],
+            GREEN,
+            RED,
+            [9, 9, 9, 0],
+            CLEAR,
+        ];
+        let palette = Palette::from_rgba(source.as_flattened(), Some(3));
+        assert_eq!(palette.colors(), [GREEN, RED, CLEAR]);
+    }
+
+    #[test]
+    fn nearest_colour_is_perceptually_closest() {
+        let palette = Palette::new([RED, GREEN, CLEAR]);
+        assert_eq!(palette.nearest([220, 60, 40, 255]), RED);
+        assert_eq!(palette.nearest([40, 180, 70, 230]), GREEN);
+        assert_eq!(palette.nearest([200, 30, 30, 10]), CLEAR);
+        assert_eq!(Palette::default().nearest(RED), RED);
+    }
+
+    #[test]
+    fn dithering_mixes_colours_in_proportion() {
+        const BLACK: [u8; 4] = [0, 0, 0, 255];
+        const WHITE: [u8; 4] = [255, 255, 255, 255];
+        let palette = Palette::new([BLACK, WHITE]);
+        let (width, height) = (16, 16);
+        let grey = [64, 64, 64, 255].repeat(width * height);
+
+        let white_fraction = |dither| {
+            let mut image = grey.clone();
+            palette.apply_dithered(&mut image, width, dither);
+            let whites = image.chunks_exact(4).filter(|&px| px == WHITE).count();
+            whites as f32 / (width * height) as f32
+        };
+
+        assert_eq!(white_fraction(Dither::None), 0.0);
+        for dither in [Dither::Ordered, Dither::FloydSteinberg] {
+            let fraction = white_fraction(dither);
+            assert!((fraction - 0.25).abs() < 0.05, "{dither:?} {fraction}");
+        }
+    }
+}
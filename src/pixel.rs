@@ -1,20 +1,152 @@
+use std::borrow::Cow;
+use std::f32::consts::PI;
 use std::fmt::{Debug, Formatter};
 use std::mem;
 
-pub(crate) trait Pixel: Debug + Default + Copy + Clone + PartialEq + Eq + Sized {
+use crate::ycbcr_lookup::{to_fixed, weigh_by_alpha, YCbCrLookup};
+
+/// The largest difference between the channels of a colour for it to be considered nearly grey.
+const NEAR_GREY_SPREAD: u8 = 24;
+
+pub(crate) trait Pixel:
+    Debug + Default + Copy + Clone + PartialEq + Eq + Send + Sync + Sized + 'static
+{
     const SIZE: usize = mem::size_of::<Self>();
 
     fn from_rgba(rgba: [u8; 4]) -> Self;
 
     fn alpha(self) -> u8;
     fn to_rgb(self) -> [u8; 3];
-    fn gradient<const M: usize, const N: usize>(front: Self, back: Self) -> Self;
+    /// The same colour with its alpha replaced. Formats without alpha are always opaque, and
+    /// are returned as they are.
+    #[inline(always)]
+    fn with_alpha(self, _alpha: u8) -> Self {
+        self
+    }
+    /// Blend `front` over `back` with a weight of `m / n`, where `0 < m < n`.
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self;
+
+    /// How different two pixels are when detecting edges, where 0 is identical.
+    /// By default this is the YCbCr distance between their colours.
+    #[inline(always)]
+    fn dist(self, other: Self, ycbcr: &YCbCrLookup) -> f32 {
+        ycbcr.dist(self, other)
+    }
+
+    /// [`dist`](Self::dist) as a fixed-point number with
+    /// [`FIXED_SHIFT`](crate::ycbcr_lookup::FIXED_SHIFT) fractional bits, for
+    /// comparing distances with integer arithmetic.
+    #[inline(always)]
+    fn dist_fixed(self, other: Self, ycbcr: &YCbCrLookup) -> u32 {
+        ycbcr.dist_fixed(self, other)
+    }
+
+    /// Whether the colour is so unsaturated that it is nearly grey.
+    #[inline(always)]
+    fn is_near_grey(self) -> bool {
+        let [r, g, b] = self.to_rgb();
+        r.max(g).max(b) - r.min(g).min(b) <= NEAR_GREY_SPREAD
+    }
+}
+
+/// Conversion between the internal pixel types and pixels packed into a byte slice,
+/// which need not be aligned. Multi-byte words are native-endian.
+pub(crate) trait PackedPixel: Pixel {
+    /// The number of bytes taken up by a single packed pixel.
+    const PACKED_SIZE: usize = Self::SIZE;
+
+    fn read(bytes: &[u8]) -> Self;
+    fn write(self, out: &mut Vec<u8>);
+}
+
+/// Packed pixels that are stored in memory as their packed bytes, with an alignment of 1, so that
+/// an image's bytes can be used as its pixels without copying.
+pub(crate) trait BytePixel: PackedPixel {}
+
+impl BytePixel for Rgba8 {}
+impl BytePixel for Argb8 {}
+impl BytePixel for Bgra8 {}
+impl BytePixel for Mask8 {}
+impl BytePixel for Normal8 {}
+
+/// The pixels of an image stored as bytes. With the `forbid_unsafe` feature, they are copied.
+pub(crate) fn cast_pixels<P: BytePixel>(bytes: &[u8]) -> Cow<'_, [P]> {
+    assert!(bytes.len().is_multiple_of(P::SIZE));
+
+    #[cfg(not(feature = "forbid_unsafe"))]
+    {
+        // SAFETY: byte pixels are made up of bytes, with no padding or alignment
+        let (_, pixels, _) = unsafe { bytes.align_to::<P>() };
+        Cow::Borrowed(pixels)
+    }
+
+    #[cfg(feature = "forbid_unsafe")]
+    Cow::Owned(bytes.chunks_exact(P::SIZE).map(P::read).collect())
+}
+
+/// Call `f` with the pixels of an image stored as bytes, which it may change. With the
+/// `forbid_unsafe` feature, they are copied out of the bytes and back again afterwards.
+pub(crate) fn with_pixels_mut<P: BytePixel, R>(
+    bytes: &mut [u8],
+    f: impl FnOnce(&mut [P]) -> R,
+) -> R {
+    assert!(bytes.len().is_multiple_of(P::SIZE));
+
+    #[cfg(not(feature = "forbid_unsafe"))]
+    {
+        // SAFETY: byte pixels are made up of bytes, with no padding or alignment
+        let (_, pixels, _) = unsafe { bytes.align_to_mut::<P>() };
+        f(pixels)
+    }
+
+    #[cfg(feature = "forbid_unsafe")]
+    {
+        let mut pixels: Vec<P> = bytes.chunks_exact(P::SIZE).map(P::read).collect();
+        let result = f(&mut pixels);
+        bytes.copy_from_slice(&pixels_into_bytes(pixels));
+        result
+    }
+}
+
+/// The bytes of an image's pixels, reusing their allocation unless the `forbid_unsafe` feature
+/// is enabled.
+pub(crate) fn pixels_into_bytes<P: BytePixel>(pixels: Vec<P>) -> Vec<u8> {
+    #[cfg(not(feature = "forbid_unsafe"))]
+    {
+        // SAFETY: byte pixels are made up of bytes, so the allocation holds `P::SIZE` bytes for
+        // every pixel, with the same alignment
+        let mut pixels = mem::ManuallyDrop::new(pixels);
+        unsafe {
+            Vec::from_raw_parts(
+                pixels.as_mut_ptr() as *mut u8,
+                pixels.len() * P::SIZE,
+                pixels.capacity() * P::SIZE,
+            )
+        }
+    }
+
+    #[cfg(feature = "forbid_unsafe")]
+    {
+        let mut bytes = Vec::with_capacity(pixels.len() * P::SIZE);
+        for px in pixels {
+            px.write(&mut bytes);
+        }
+        bytes
+    }
 }
 
 #[repr(C)]
 #[derive(Default, Copy, Clone, PartialEq, Eq)]
 pub(crate) struct RGB555(u16);
 
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Rgb565(u16);
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Xrgb8888(u32);
+
 #[repr(C)]
 #[derive(Default, Copy, Clone, PartialEq, Eq)]
 pub(crate) struct Rgb8([u8; 4]);
@@ -27,6 +159,10 @@ pub(crate) const fn u5_to_u8(v: u8) -> u8 {
     (v << 3) | (v >> 2)
 }
 
+pub(crate) const fn u6_to_u8(v: u8) -> u8 {
+    (v << 2) | (v >> 4)
+}
+
 impl RGB555 {
     pub(crate) const fn from_parts(r: u8, g: u8, b: u8) -> Self {
         Self(
@@ -69,6 +205,25 @@ impl From<RGB555> for u16 {
     }
 }
 
+impl Pixel for RGB555 {
+    fn from_rgba(rgba: [u8; 4]) -> Self {
+        Self::from_parts(rgba[0], rgba[1], rgba[2])
+    }
+
+    fn alpha(self) -> u8 {
+        255
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        let (r, g, b) = self.to_parts();
+        [r, g, b]
+    }
+
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
+    }
+}
+
 impl From<Rgb8> for RGB555 {
     fn from(value: Rgb8) -> Self {
         let (r, g, b) = value.to_parts();
@@ -76,6 +231,114 @@ impl From<Rgb8> for RGB555 {
     }
 }
 
+impl Rgb565 {
+    pub(crate) const fn from_parts(r: u8, g: u8, b: u8) -> Self {
+        Self(
+            (((r as u16) << 8) & 0xF800)
+                | (((g as u16) << 3) & 0x07E0)
+                | (((b as u16) >> 3) & 0x001F),
+        )
+    }
+
+    pub(crate) const fn to_parts(self) -> (u8, u8, u8) {
+        (
+            u5_to_u8(((self.0 >> 11) & 0x1F) as u8),
+            u6_to_u8(((self.0 >> 5) & 0x3F) as u8),
+            u5_to_u8((self.0 & 0x1F) as u8),
+        )
+    }
+}
+
+impl Debug for Rgb565 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (r, g, b) = self.to_parts();
+        f.debug_struct("RGB565")
+            .field("repr", &self.0)
+            .field("r", &r)
+            .field("g", &g)
+            .field("b", &b)
+            .finish()
+    }
+}
+
+impl From<u16> for Rgb565 {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Rgb565> for u16 {
+    fn from(value: Rgb565) -> Self {
+        value.0
+    }
+}
+
+impl Pixel for Rgb565 {
+    fn from_rgba(rgba: [u8; 4]) -> Self {
+        Self::from_parts(rgba[0], rgba[1], rgba[2])
+    }
+
+    fn alpha(self) -> u8 {
+        255
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        let (r, g, b) = self.to_parts();
+        [r, g, b]
+    }
+
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
+    }
+}
+
+impl Xrgb8888 {
+    pub(crate) const fn from_parts(r: u8, g: u8, b: u8) -> Self {
+        Self(((r as u32) << 16) | ((g as u32) << 8) | (b as u32))
+    }
+
+    pub(crate) const fn to_parts(self) -> (u8, u8, u8) {
+        ((self.0 >> 16) as u8, (self.0 >> 8) as u8, self.0 as u8)
+    }
+}
+
+impl Debug for Xrgb8888 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:08x}", self.0)
+    }
+}
+
+impl From<u32> for Xrgb8888 {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Xrgb8888> for u32 {
+    fn from(value: Xrgb8888) -> Self {
+        value.0
+    }
+}
+
+impl Pixel for Xrgb8888 {
+    fn from_rgba(rgba: [u8; 4]) -> Self {
+        Self::from_parts(rgba[0], rgba[1], rgba[2])
+    }
+
+    fn alpha(self) -> u8 {
+        255
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        let (r, g, b) = self.to_parts();
+        [r, g, b]
+    }
+
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
+    }
+}
+
 impl Rgb8 {
     pub(crate) const fn from_parts(r: u8, g: u8, b: u8) -> Self {
         Self([0, r, g, b])
@@ -107,10 +370,9 @@ impl From<RGB555> for Rgb8 {
 
 impl From<Argb8> for Rgb8 {
     fn from(value: Argb8) -> Self {
-        // SAFETY: The internal representation of RGB888 and ARGB8888 are compatible
-        // as they use the same memory layout. Note that the "A" value exists in a "don't care"
-        // portion of the RGB888 backing array.
-        unsafe { mem::transmute(value) }
+        // RGB888 and ARGB8888 share the same layout, with the "A" value in a "don't care"
+        // portion of the RGB888 backing array
+        Self(value.0)
     }
 }
 
@@ -127,8 +389,8 @@ impl Pixel for Rgb8 {
         [self.0[1], self.0[2], self.0[3]]
     }
 
-    fn gradient<const M: usize, const N: usize>(front: Self, back: Self) -> Self {
-        todo!()
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
     }
 }
 
@@ -138,11 +400,12 @@ impl Argb8 {
     }
 }
 
-fn gradient_rgba<P: Pixel, const M: usize, const N: usize>(front: P, back: P) -> P {
-    debug_assert!(0 < M && M < N && N <= 1000);
+#[inline(always)]
+fn gradient_rgba<P: Pixel>(front: P, back: P, m: usize, n: usize) -> P {
+    debug_assert!(0 < m && m < n && n <= 1000);
 
-    let weight_front = front.alpha() as usize * M;
-    let weight_back = back.alpha() as usize * (N - M);
+    let weight_front = front.alpha() as usize * m;
+    let weight_back = back.alpha() as usize * (n - m);
     let weight_sum = weight_front + weight_back;
 
     if weight_sum == 0 {
@@ -156,7 +419,7 @@ fn gradient_rgba<P: Pixel, const M: usize, const N: usize>(front: P, back: P) ->
         ((fr as usize * weight_front + br as usize * weight_back) / weight_sum) as u8,
         ((fg as usize * weight_front + bg as usize * weight_back) / weight_sum) as u8,
         ((fb as usize * weight_front + bb as usize * weight_back) / weight_sum) as u8,
-        (weight_sum / N) as u8,
+        (weight_sum / n) as u8,
     ])
 }
 
@@ -170,12 +433,17 @@ impl Pixel for Argb8 {
         self.0[0]
     }
 
+    fn with_alpha(mut self, alpha: u8) -> Self {
+        self.0[0] = alpha;
+        self
+    }
+
     fn to_rgb(self) -> [u8; 3] {
         [self.0[1], self.0[2], self.0[3]]
     }
 
-    fn gradient<const M: usize, const N: usize>(front: Self, back: Self) -> Self {
-        gradient_rgba::<Self, M, N>(front, back)
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
     }
 }
 
@@ -206,11 +474,249 @@ impl Pixel for Rgba8 {
         self.0[3]
     }
 
+    fn with_alpha(mut self, alpha: u8) -> Self {
+        self.0[3] = alpha;
+        self
+    }
+
     fn to_rgb(self) -> [u8; 3] {
         [self.0[0], self.0[1], self.0[2]]
     }
 
-    fn gradient<const M: usize, const N: usize>(front: Self, back: Self) -> Self {
-        gradient_rgba::<Self, M, N>(front, back)
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
+    }
+}
+
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Bgra8([u8; 4]);
+
+impl Debug for Bgra8 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [b, g, r, a] = self.0;
+        write!(f, "{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+impl Pixel for Bgra8 {
+    fn from_rgba(rgba: [u8; 4]) -> Self {
+        Self([rgba[2], rgba[1], rgba[0], rgba[3]])
+    }
+
+    fn alpha(self) -> u8 {
+        self.0[3]
+    }
+
+    fn with_alpha(mut self, alpha: u8) -> Self {
+        self.0[3] = alpha;
+        self
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        [self.0[2], self.0[1], self.0[0]]
+    }
+
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n)
+    }
+}
+
+/// A single 8-bit mask value, such as coverage or collision.
+///
+/// Masks are compared and blended as opaque grey levels, so edges are detected between
+/// differing mask values in the same way as between differing colours.
+#[repr(transparent)]
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Mask8(u8);
+
+impl Debug for Mask8 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02x}", self.0)
+    }
+}
+
+impl Pixel for Mask8 {
+    fn from_rgba(rgba: [u8; 4]) -> Self {
+        Self(rgba[0])
+    }
+
+    fn alpha(self) -> u8 {
+        255
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        [self.0; 3]
+    }
+
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        debug_assert!(0 < m && m < n && n <= 1000);
+        Self(((front.0 as usize * m + back.0 as usize * (n - m)) / n) as u8)
+    }
+}
+
+/// A normal map texel in R, G, B, A order, where R, G and B hold the X, Y and Z components of a
+/// unit vector mapped from [-1, 1] to [0, 255]. Texels are compared by the angle between their
+/// normals rather than by colour, and blended normals are scaled back up to unit length.
+#[repr(C)]
+#[derive(Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Normal8([u8; 4]);
+
+impl Normal8 {
+    fn to_vector(self) -> [f32; 3] {
+        let [x, y, z] = self.to_rgb().map(|c| c as f32 / 127.5 - 1.0);
+        let len = (x * x + y * y + z * z).sqrt();
+        if len == 0.0 {
+            return [0.0; 3];
+        }
+        [x / len, y / len, z / len]
+    }
+
+    /// Scale the normal back up to unit length; a zero vector is left as it is.
+    fn normalize(self) -> Self {
+        let v = self.to_vector();
+        if v == [0.0; 3] {
+            return self;
+        }
+        let [r, g, b] = v.map(|c| ((c + 1.0) * 127.5).round().clamp(0.0, 255.0) as u8);
+        Self([r, g, b, self.0[3]])
+    }
+}
+
+impl Debug for Normal8 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let [r, g, b, a] = self.0;
+        write!(f, "{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+    }
+}
+
+impl Pixel for Normal8 {
+    fn from_rgba(rgba: [u8; 4]) -> Self {
+        Self(rgba)
+    }
+
+    fn alpha(self) -> u8 {
+        self.0[3]
+    }
+
+    fn with_alpha(mut self, alpha: u8) -> Self {
+        self.0[3] = alpha;
+        self
+    }
+
+    fn to_rgb(self) -> [u8; 3] {
+        [self.0[0], self.0[1], self.0[2]]
+    }
+
+    fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+        gradient_rgba(front, back, m, n).normalize()
+    }
+
+    /// The angle between the two normals, with 180 degrees mapped to 255 so that the
+    /// thresholds of [`ScalerConfig`](crate::ScalerConfig) work on the same scale as for colours.
+    fn dist(self, other: Self, _ycbcr: &YCbCrLookup) -> f32 {
+        let [x1, y1, z1] = self.to_vector();
+        let [x2, y2, z2] = other.to_vector();
+        let cos = (x1 * x2 + y1 * y2 + z1 * z2).clamp(-1.0, 1.0);
+        let d = cos.acos() * (255.0 / PI);
+        weigh_by_alpha(self.alpha(), other.alpha(), d)
+    }
+
+    fn dist_fixed(self, other: Self, ycbcr: &YCbCrLookup) -> u32 {
+        to_fixed(self.dist(other, ycbcr))
+    }
+}
+
+// the unused bits are masked off on read so that they can't affect pixel equality
+
+impl PackedPixel for RGB555 {
+    fn read(bytes: &[u8]) -> Self {
+        (u16::from_ne_bytes([bytes[0], bytes[1]]) & 0x7FFF).into()
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&u16::from(self).to_ne_bytes());
+    }
+}
+
+impl PackedPixel for Rgb565 {
+    fn read(bytes: &[u8]) -> Self {
+        u16::from_ne_bytes([bytes[0], bytes[1]]).into()
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&u16::from(self).to_ne_bytes());
+    }
+}
+
+impl PackedPixel for Xrgb8888 {
+    fn read(bytes: &[u8]) -> Self {
+        (u32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) & 0x00FF_FFFF).into()
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&u32::from(self).to_ne_bytes());
+    }
+}
+
+impl PackedPixel for Rgba8 {
+    fn read(bytes: &[u8]) -> Self {
+        Self([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+}
+
+impl PackedPixel for Argb8 {
+    fn read(bytes: &[u8]) -> Self {
+        Self([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+}
+
+impl PackedPixel for Bgra8 {
+    fn read(bytes: &[u8]) -> Self {
+        Self([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+}
+
+impl PackedPixel for Normal8 {
+    fn read(bytes: &[u8]) -> Self {
+        Self([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.0);
+    }
+}
+
+impl PackedPixel for Mask8 {
+    fn read(bytes: &[u8]) -> Self {
+        Self(bytes[0])
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.push(self.0);
+    }
+}
+
+impl PackedPixel for Rgb8 {
+    const PACKED_SIZE: usize = 3;
+
+    fn read(bytes: &[u8]) -> Self {
+        Self::from_parts(bytes[0], bytes[1], bytes[2])
+    }
+
+    fn write(self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_rgb());
     }
 }
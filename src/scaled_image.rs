@@ -0,0 +1,147 @@
+use std::ops::Deref;
+
+use crate::{PixelFormat, ScalerConfig};
+
+/// A scaled image, along with its dimensions and pixel format, so that the dimensions don't need to
+/// be multiplied out again before the image is saved or uploaded.
+///
+/// Dereferences to the pixel data, which is `width * height * format.bytes_per_pixel()` bytes long.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ScaledImage {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    format: PixelFormat,
+}
+
+impl ScaledImage {
+    /// Returns `None` if the length of `data` doesn't match the dimensions and format.
+    pub fn new(data: Vec<u8>, width: usize, height: usize, format: PixelFormat) -> Option<Self> {
+        if data.len() != width * height * format.bytes_per_pixel() {
+            return None;
+        }
+        Some(Self {
+            data,
+            width,
+            height,
+            format,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// The length of each row of pixels, in bytes.
+    pub fn pitch(&self) -> usize {
+        self.width * self.format.bytes_per_pixel()
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Deref for ScaledImage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl AsRef<[u8]> for ScaledImage {
+    fn as_ref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl From<ScaledImage> for Vec<u8> {
+    fn from(image: ScaledImage) -> Self {
+        image.data
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, returning the image along with
+/// its dimensions of `src_width * factor` by `src_height * factor`.
+///
+/// This is the same as [`scale_rgba`](crate::scale_rgba), except that the dimensions and format of
+/// the image are kept with it.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_to_image(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+) -> ScaledImage {
+    scale_rgba_to_image_with_config(
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, returning the image along with
+/// its dimensions, with custom tuning parameters.
+///
+/// This is the same as [`scale_rgba_to_image`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_rgba_to_image_with_config(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> ScaledImage {
+    let data = crate::scale_rgba_with_config(source, src_width, src_height, factor, config);
+    ScaledImage {
+        data,
+        width: src_width * factor,
+        height: src_height * factor,
+        format: PixelFormat::Rgba8,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dimensions_match_the_data() {
+        let (width, height) = (5, 3);
+        let source: Vec<u8> = (0..width * height * 4).map(|i| (i * 37) as u8).collect();
+
+        for factor in 1..=6 {
+            let image = scale_rgba_to_image(&source, width, height, factor);
+            assert_eq!(
+                (image.width(), image.height()),
+                (width * factor, height * factor)
+            );
+            assert_eq!(image.format(), PixelFormat::Rgba8);
+            assert_eq!(image.len(), image.pitch() * image.height());
+            assert_eq!(*image, crate::scale_rgba(&source, width, height, factor));
+        }
+
+        let empty = scale_rgba_to_image(&[], 0, 3, 2);
+        assert_eq!((empty.width(), empty.height(), empty.len()), (0, 6, 0));
+    }
+}
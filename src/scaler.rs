@@ -1,15 +1,108 @@
 use std::ops::Range;
 
+use crate::arena::Scratch;
 use crate::blend::{Blend2x2, BlendType};
-use crate::config::ScalerConfig;
-use crate::kernel::{Kernel4x4, Rotation, RotKernel3x3};
+use crate::blend_map::{for_each_blend, for_each_cached_blend, Visit};
+use crate::config::{BlendWeights, PixelConfig, ScalerConfig};
+use crate::edge_shape::EdgeShape;
+use crate::kernel::{Kernel4x4, RotKernel3x3, Rotation};
 use crate::matrix::OutputMatrix;
 use crate::oob_reader::OobReader;
 use crate::pixel::Pixel;
 use crate::ycbcr_lookup::YCbCrLookup;
 
-fn alpha_grad<P: Pixel, const M: usize, const N: usize>(pix_back: &mut P, pix_front: P) {
-    *pix_back = P::gradient::<M, N>(pix_front, *pix_back);
+/// The length of a buffer holding `rows` rows of `row_len` pixels, with each row starting `stride` pixels
+/// after the last. The final row is not padded out to the full stride.
+pub(crate) const fn strided_len(rows: usize, row_len: usize, stride: usize) -> usize {
+    match rows {
+        0 => 0,
+        _ => (rows - 1) * stride + row_len,
+    }
+}
+
+/// The denominator of blend weights that have been scaled by a blend strength below 1.
+const SCALED_WEIGHT_DENOMINATOR: usize = 256;
+
+/// Blend `pix_front` over `pix_back` with a weight of `m / n`, scaled down by `strength`.
+#[inline(always)]
+fn alpha_grad<P: Pixel>(pix_back: &mut P, pix_front: P, m: usize, n: usize, strength: f32) {
+    // at full strength the weights are kept exactly as they are, to match the original implementation
+    let (m, n) = if strength >= 1.0 {
+        (m, n)
+    } else {
+        let weight = m as f32 * strength / n as f32;
+        let m = (weight * SCALED_WEIGHT_DENOMINATOR as f32).round() as usize;
+        (m, SCALED_WEIGHT_DENOMINATOR)
+    };
+    if m == 0 {
+        return;
+    }
+    *pix_back = match m < n {
+        true => P::gradient(pix_front, *pix_back, m, n),
+        false => pix_front,
+    };
+}
+
+/// A cell of the output block of a pixel, and the weight of `m / n` that a colour is blended into it with.
+/// Cells are given for the bottom right corner of the block, and rotated into place for the other corners.
+///
+/// A weight of 0 leaves the cell as it is, and a weight of 1 or more replaces it with the blended colour.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CellWeight {
+    pub row: u8,
+    pub col: u8,
+    pub m: u16,
+    pub n: u16,
+}
+
+const fn blend(m: u16, n: u16, row: u8, col: u8) -> CellWeight {
+    CellWeight { row, col, m, n }
+}
+
+/// A cell that is replaced by the blended colour.
+const fn set(row: u8, col: u8) -> CellWeight {
+    blend(1, 1, row, col)
+}
+
+/// The cells blended for each kind of edge, at one scale factor.
+#[derive(Debug, Copy, Clone)]
+pub struct BlendTable<'a> {
+    /// An edge at a shallow angle, closer to horizontal than to a diagonal.
+    pub line_shallow: &'a [CellWeight],
+    /// An edge at a steep angle, closer to vertical than to a diagonal.
+    pub line_steep: &'a [CellWeight],
+    /// An edge that is both shallow and steep, such as the end of a line one pixel wide.
+    pub line_steep_and_shallow: &'a [CellWeight],
+    /// A 45 degree diagonal edge.
+    pub line_diagonal: &'a [CellWeight],
+    /// A corner that isn't blended as part of a line, such as where only the corner pixel differs.
+    pub corner: &'a [CellWeight],
+}
+
+impl BlendTable<'static> {
+    /// A table that blends every kind of edge in the same way.
+    const fn uniform(cells: &'static [CellWeight]) -> Self {
+        Self {
+            line_shallow: cells,
+            line_steep: cells,
+            line_steep_and_shallow: cells,
+            line_diagonal: cells,
+            corner: cells,
+        }
+    }
+}
+
+#[inline(always)]
+fn blend_cells<P: Pixel, const N: usize, const R: u8>(
+    out: &mut OutputMatrix<P, N, R>,
+    cells: &[CellWeight],
+    col: P,
+    strength: f32,
+) {
+    for cell in cells {
+        let pix = out.rotated_ref(cell.row as usize, cell.col as usize);
+        alpha_grad(pix, col, cell.m as usize, cell.n as usize, strength);
+    }
 }
 
 fn fill_block<T: Copy>(
@@ -21,510 +114,910 @@ fn fill_block<T: Copy>(
 ) {
     let i_range = (0..(block_height * row_length)).step_by(row_length);
     for i in i_range {
-        for cell in &mut destination[i..i + block_width] {
-            *cell = value;
-        }
+        destination[i..i + block_width].fill(value);
     }
 }
 
-pub(crate) trait Scaler<const SCALE: usize> {
-    fn blend_line_shallow<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, SCALE, R>);
-    fn blend_line_steep<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, SCALE, R>);
-    fn blend_line_steep_and_shallow<P: Pixel, const R: u8>(
-        col: P,
-        out: &mut OutputMatrix<P, SCALE, R>,
-    );
-    fn blend_line_diagonal<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, SCALE, R>);
-    fn blend_corner<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, SCALE, R>);
-
-    fn blend_pixel<P: Pixel, const R: u8>(
-        kernel: RotKernel3x3<'_, P, R>,
-        destination: &mut [P],
-        dest_width: usize,
-        blend_info: Blend2x2,
-        config: &ScalerConfig,
-    ) {
-        // SAFETY: should be initialised by scale_image()
-        debug_assert!(YCbCrLookup::instance_is_initialised());
-        let ycbcr = unsafe { YCbCrLookup::instance_unchecked() };
-        let blend = blend_info.rotate(Rotation::from_u8(R));
+/// How the bottom right corner of a rotated kernel is blended, as seen in its rotation, and the
+/// colour that it's blended with. The corner must be blended in `blend_info`.
+#[inline(always)]
+pub(crate) fn corner_shape<P: Pixel, const R: u8>(
+    kernel: &RotKernel3x3<'_, P, R>,
+    blend_info: Blend2x2,
+    config: &ScalerConfig,
+    ycbcr: &YCbCrLookup,
+) -> (EdgeShape, P) {
+    let blend = blend_info.rotate(Rotation::from_u8(R));
+    debug_assert_ne!(blend.bottom_right, BlendType::None);
+
+    macro_rules! dist {
+        ($x:ident, $y:ident) => {
+            kernel.$x().dist(kernel.$y(), ycbcr)
+        };
+    }
+    // nearly grey colours may have a tolerance of their own, for the shades of anti-aliased text
+    let tolerance = |x: P, y: P| match config.grey_tolerance {
+        Some(grey) if x.is_near_grey() && y.is_near_grey() => grey,
+        _ => config.equal_color_tolerance,
+    };
+    macro_rules! eq {
+        ($x:ident, $y:ident) => {
+            dist!($x, $y) < tolerance(kernel.$x(), kernel.$y()) as f32
+        };
+    }
+    macro_rules! neq {
+        ($x:ident, $y:ident) => {
+            dist!($x, $y) >= tolerance(kernel.$x(), kernel.$y()) as f32
+        };
+    }
 
-        if blend.bottom_right == BlendType::None {
-            return;
+    let do_line_blend = 'a: {
+        if blend.bottom_right == BlendType::Dominant {
+            break 'a true;
         }
 
-        macro_rules! dist {
-            ($x:ident, $y:ident) => {
-                ycbcr.dist(kernel.$x(), kernel.$y())
-            };
+        // make sure there is no second blending in an adjacent rotation for this pixel:
+        // handles insular pixels, mario eyes;
+        // but support double blending for 90-degree corners
+        if blend.top_right != BlendType::None && neq!(e, g) {
+            break 'a false;
         }
-        macro_rules! eq {
-            ($x:ident, $y:ident) => {
-                dist!($x, $y) < config.equal_color_tolerance as f32
-            };
+        if blend.bottom_left != BlendType::None && neq!(e, c) {
+            break 'a false;
         }
-        macro_rules! neq {
-            ($x:ident, $y:ident) => {
-                dist!($x, $y) >= config.equal_color_tolerance as f32
-            };
+
+        // no full blending for L-shapes; blend corner only (handles "mario mushroom eyes")
+        if neq!(e, i) && eq!(g, h) && eq!(h, i) && eq!(i, f) && eq!(f, c) {
+            break 'a false;
         }
 
-        let do_line_blend = 'a: {
-            if blend.bottom_right == BlendType::Dominant {
-                break 'a true;
-            }
+        true
+    };
 
-            // make sure there is no second blending in an adjacent rotation for this pixel:
-            // handles insular pixels, mario eyes;
-            // but support double blending for 90-degree corners
-            if blend.top_right != BlendType::None && neq!(e, g) {
-                break 'a false;
-            }
-            if blend.bottom_left != BlendType::None && neq!(e, c) {
-                break 'a false;
-            }
+    let px = if dist!(e, f) <= dist!(e, h) {
+        kernel.f()
+    } else {
+        kernel.h()
+    };
 
-            // no full blending for L-shapes; blend corner only (handles "mario mushroom eyes")
-            if neq!(e, i) && eq!(g, h) && eq!(h, i) && eq!(i, f) && eq!(f, c) {
-                break 'a false;
+    // dither patterns are made up of many tiny diagonals, which shouldn't be drawn out into lines;
+    // a pixel is taken to be dithered when two neighbouring 2x2 blocks around it are both
+    // checkerboards, which tells dithering apart from a one pixel wide diagonal line
+    let do_line_blend = do_line_blend
+        && !(config.suppress_dither && {
+            let top_left = eq!(a, e) && eq!(b, d) && neq!(a, b);
+            let top_right = eq!(b, f) && eq!(c, e) && neq!(b, c);
+            let bottom_left = eq!(d, h) && eq!(e, g) && neq!(d, e);
+            let bottom_right = eq!(e, i) && eq!(f, h) && neq!(e, f);
+            (top_left || bottom_right) && (top_right || bottom_left)
+        });
+
+    // blending a line over an outline pixel would eat into the outline
+    let do_line_blend = do_line_blend
+        && match &config.preserve_outlines {
+            Some(outline) => {
+                let [r, g, b] = outline.color;
+                let outline_col = P::from_rgba([r, g, b, 255]);
+                let is_outline = |col| ycbcr.dist(col, outline_col) < outline.tolerance as f32;
+                !is_outline(kernel.e()) || is_outline(px)
             }
-
-            true
+            None => true,
         };
 
-        let px = if dist!(e, f) <= dist!(e, h) {
-            kernel.f()
-        } else {
-            kernel.h()
-        };
+    let shape = if do_line_blend {
+        let fg = dist!(f, g);
+        let hc = dist!(h, c);
 
-        let mut out = OutputMatrix::<P, SCALE, R>::new(destination, dest_width);
+        let shallow_line =
+            config.steep_direction_threshold as f32 * fg <= hc && neq!(e, g) && neq!(d, g);
+        let steep_line =
+            config.steep_direction_threshold as f32 * hc <= fg && neq!(e, c) && neq!(b, c);
 
-        if do_line_blend {
-            let fg = dist!(f, g);
-            let hc = dist!(h, c);
+        match (shallow_line, steep_line) {
+            (true, true) => EdgeShape::SteepAndShallow,
+            (true, false) => EdgeShape::Shallow,
+            (false, true) => EdgeShape::Steep,
+            (false, false) => EdgeShape::Diagonal,
+        }
+    } else {
+        EdgeShape::Corner
+    };
+    (shape, px)
+}
 
-            let shallow_line =
-                config.steep_direction_threshold as f32 * fg <= hc && neq!(e, g) && neq!(d, g);
-            let steep_line =
-                config.steep_direction_threshold as f32 * hc <= fg && neq!(e, c) && neq!(b, c);
+pub(crate) trait Scaler<const SCALE: usize> {
+    /// The weights of the original C++ implementation.
+    const CLASSIC: BlendTable<'static>;
+    /// Weights that spread diagonals and corners over more of the output block, for softer edges.
+    const SOFT: BlendTable<'static>;
+
+    /// The table for a set of weights.
+    fn table(weights: BlendWeights) -> BlendTable<'static> {
+        match weights {
+            BlendWeights::Classic => Self::CLASSIC,
+            BlendWeights::Soft => Self::SOFT,
+        }
+    }
 
-            match (shallow_line, steep_line) {
-                (true, true) => Self::blend_line_steep_and_shallow(px, &mut out),
-                (true, false) => Self::blend_line_shallow(px, &mut out),
-                (false, true) => Self::blend_line_steep(px, &mut out),
-                (false, false) => Self::blend_line_diagonal(px, &mut out),
-            }
-        } else {
-            Self::blend_corner(px, &mut out);
+    /// Fill the output block of the centre pixel of `kernel`, then blend each of its corners
+    /// according to `blend_xy`. The block starts at the beginning of `destination`, with each row
+    /// starting `dst_stride` pixels after the last.
+    #[inline(always)]
+    fn blend_block<P: Pixel>(
+        kernel: &Kernel4x4<P>,
+        destination: &mut [P],
+        dst_stride: usize,
+        blend_xy: Blend2x2,
+        config: &ScalerConfig,
+        ycbcr: &YCbCrLookup,
+        table: &BlendTable<'_>,
+    ) {
+        fill_block(destination, dst_stride, kernel.f, SCALE, SCALE);
+
+        // each rotation blends the bottom right corner of its rotated kernel, and usually only one
+        // or two corners of a pixel are blended, so the other rotations aren't built at all
+        let out = destination;
+        if blend_xy.bottom_right != BlendType::None {
+            let rot_0 = RotKernel3x3::<P, { Rotation::None as u8 }>::new(kernel);
+            Self::blend_pixel(rot_0, out, dst_stride, blend_xy, config, ycbcr, table);
+        }
+        if blend_xy.top_right != BlendType::None {
+            let rot_90 = RotKernel3x3::<P, { Rotation::Clockwise90 as u8 }>::new(kernel);
+            Self::blend_pixel(rot_90, out, dst_stride, blend_xy, config, ycbcr, table);
+        }
+        if blend_xy.top_left != BlendType::None {
+            let rot_180 = RotKernel3x3::<P, { Rotation::Clockwise180 as u8 }>::new(kernel);
+            Self::blend_pixel(rot_180, out, dst_stride, blend_xy, config, ycbcr, table);
+        }
+        if blend_xy.bottom_left != BlendType::None {
+            let rot_270 = RotKernel3x3::<P, { Rotation::Clockwise270 as u8 }>::new(kernel);
+            Self::blend_pixel(rot_270, out, dst_stride, blend_xy, config, ycbcr, table);
         }
     }
 
-    fn scale_image<'src, P: Pixel, OOB: OobReader<'src, P>>(
-        source: &'src [P],
+    fn blend_pixel<P: Pixel, const R: u8>(
+        kernel: RotKernel3x3<'_, P, R>,
+        destination: &mut [P],
+        dest_width: usize,
+        blend_info: Blend2x2,
+        config: &ScalerConfig,
+        ycbcr: &YCbCrLookup,
+        table: &BlendTable<'_>,
+    ) {
+        let (shape, px) = corner_shape(&kernel, blend_info, config, ycbcr);
+        let cells = match shape {
+            EdgeShape::SteepAndShallow => table.line_steep_and_shallow,
+            EdgeShape::Shallow => table.line_shallow,
+            EdgeShape::Steep => table.line_steep,
+            EdgeShape::Diagonal => table.line_diagonal,
+            EdgeShape::Corner | EdgeShape::None => table.corner,
+        };
+
+        let mut out = OutputMatrix::<P, SCALE, R>::new(destination, dest_width);
+        blend_cells(&mut out, cells, px, config.blend_strength.clamp(0.0, 1.0));
+    }
+
+    /// Scale the source rows in `y_range`, working through them in square tiles sized according to
+    /// [`ScalerConfig::tile_size_for`]. The `destination` holds only the output rows for `y_range`,
+    /// each starting `dst_stride` pixels after the last.
+    #[allow(clippy::too_many_arguments)]
+    fn scale_tiled<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+        source: &'src OOB::Source,
         destination: &mut [P],
+        dst_stride: usize,
         src_width: usize,
         src_height: usize,
-        config: &ScalerConfig,
+        config: &C,
         y_range: Range<usize>,
+        arena: &mut impl Scratch,
+        blend_map: Option<&[Blend2x2]>,
+        mut on_row: Option<&mut (dyn FnMut() + '_)>,
     ) {
-        let y_first = y_range.start.max(0);
-        let y_last = y_range.end.min(src_height);
-        assert!(y_first < y_last);
-        assert!(src_width > 0);
-        assert!(src_height > 0);
-        YCbCrLookup::initialise();
+        let tile_size = config.base().tile_size_for(src_width);
+        let pre_proc_buf = arena.pre_proc_buf(tile_size);
+        let ycbcr = config.lookup();
+
+        for tile_y in y_range.clone().step_by(tile_size) {
+            let tile_y_last = (tile_y + tile_size).min(y_range.end);
+            let band_start = (tile_y - y_range.start) * SCALE * dst_stride;
+            let band_len = strided_len(
+                (tile_y_last - tile_y) * SCALE,
+                src_width * SCALE,
+                dst_stride,
+            );
+            let dest_rows = &mut destination[band_start..band_start + band_len];
+            for tile_x in (0..src_width).step_by(tile_size) {
+                let tile_x_last = (tile_x + tile_size).min(src_width);
+                // rows are only finished once the last tile in the band has been scaled
+                let tile_on_row = if tile_x_last == src_width {
+                    on_row.as_deref_mut()
+                } else {
+                    None
+                };
+                Self::scale_image::<P, OOB, C>(
+                    source,
+                    dest_rows,
+                    dst_stride,
+                    src_width,
+                    src_height,
+                    config,
+                    tile_x..tile_x_last,
+                    tile_y..tile_y_last,
+                    &mut pre_proc_buf[..tile_x_last - tile_x],
+                    ycbcr,
+                    blend_map,
+                    tile_on_row,
+                );
+            }
+        }
+    }
 
+    #[allow(clippy::too_many_arguments)]
+    fn scale_image<'src, P: Pixel, OOB: OobReader<'src, P>, C: PixelConfig + ?Sized>(
+        source: &'src OOB::Source,
+        destination: &mut [P],
+        dst_stride: usize,
+        src_width: usize,
+        src_height: usize,
+        config: &C,
+        x_range: Range<usize>,
+        y_range: Range<usize>,
+        pre_proc_buf: &mut [Blend2x2],
+        ycbcr: &YCbCrLookup,
+        blend_map: Option<&[Blend2x2]>,
+        on_row: Option<&mut (dyn FnMut() + '_)>,
+    ) {
+        // the destination only holds the output rows for y_range
         let dest_width = src_width * SCALE;
-        let dest_height = src_height * SCALE;
-        assert_eq!(destination.len(), dest_width * dest_height);
-
-        let mut pre_proc_buf = vec![Blend2x2::default(); src_width];
-
-        // initialise preprocessing buffer for first row of current stripe:
-        // detect upper left and right corner blending
-        // this cannot be optimised for adjacent processing stripes; we must not allow for a
-        // memory race condition!
-        {
-            let oob_reader = OOB::new(source, src_width, src_height, y_first as isize - 1);
-            let mut kernel = Kernel4x4::init_row(&oob_reader);
-
-            let Blend2x2 { bottom_right, .. } = kernel.pre_process_corners(config);
-            pre_proc_buf[0].clear();
-            pre_proc_buf[0].top_left = bottom_right;
-
-            for x in 0..src_width {
-                kernel.next_column(&oob_reader, x as isize);
-                let Blend2x2 {
-                    bottom_right,
-                    bottom_left,
-                    ..
-                } = kernel.pre_process_corners(config);
-                pre_proc_buf[x].top_right = bottom_left;
-
-                if x + 1 < src_width {
-                    pre_proc_buf[x + 1].clear();
-                    pre_proc_buf[x + 1].top_left = bottom_right;
+        assert!(dst_stride >= dest_width);
+        assert_eq!(
+            destination.len(),
+            strided_len(
+                (y_range.end.min(src_height) - y_range.start) * SCALE,
+                dest_width,
+                dst_stride
+            )
+        );
+        let y_first = y_range.start;
+
+        let visit = |visit: Visit<'_, P>, x: usize, y: usize| {
+            let out = &mut destination[(y - y_first) * SCALE * dst_stride + x * SCALE..];
+            let (kernel, blend_xy) = match visit {
+                Visit::Pixel(kernel, blend_xy) => (kernel, blend_xy),
+                Visit::Run(colour, len) => {
+                    fill_block(out, dst_stride, colour, len * SCALE, SCALE);
+                    return;
                 }
+            };
+            if !blend_xy.blending_needed() {
+                fill_block(out, dst_stride, kernel.f, SCALE, SCALE);
+                return;
             }
-        }
-
-        for y in y_first..y_last {
-            let row_start = y * SCALE * dest_width;
-            let dest_rows = &mut destination[row_start..];
-
-            let oob_reader = OOB::new(source, src_width, src_height, y as isize);
-            let mut kernel = Kernel4x4::init_row(&oob_reader);
-
-            // corner blending for current (x, y + 1) position
-            let Blend2x2 {
-                bottom_right,
-                top_right,
-                ..
-            } = kernel.pre_process_corners(config);
-            // set 1st known corner for (0, y + 1) and buffer for use on next column
-            let mut blend_xy1 = Blend2x2 {
-                top_left: bottom_right,
-                ..Default::default()
+            let pixel_config = config.at(x as isize, y as isize);
+            let table = match config.blend_table() {
+                Some(table) => *table,
+                None => Self::table(pixel_config.blend_weights),
             };
-            // set 3rd known corner for (0, y)
-            pre_proc_buf[0].top_left = top_right;
-
-            for x in 0..src_width {
-                let out = &mut dest_rows[x * SCALE..];
-                kernel.next_column(&oob_reader, x as isize);
-
-                let mut blend_xy = pre_proc_buf[x];
-                {
-                    let Blend2x2 {
-                        top_left,
-                        top_right,
-                        bottom_left,
-                        bottom_right,
-                    } = kernel.pre_process_corners(config);
-
-                    // all four corners of (x, y) have been determined at this point
-                    blend_xy.bottom_right = top_left;
-                    // set 2nd known corner for (x, y + 1)
-                    blend_xy1.top_right = bottom_left;
-                    pre_proc_buf[x] = blend_xy1;
-
-                    if x + 1 < src_width {
-                        blend_xy1.clear();
-                        // set 1st known corner for (x + 1, y + 1) and buffer for use on next column
-                        blend_xy1.top_left = bottom_right;
-                        // set 3rd known corner for (x + 1, y)
-                        pre_proc_buf[x + 1].bottom_left = top_right;
-                    }
-                }
-
-                fill_block(out, dest_width, kernel.f, SCALE, SCALE);
-
-                if blend_xy.blending_needed() {
-                    let rot_0 = RotKernel3x3::<P, { Rotation::None as u8 }>::new(&kernel);
-                    let rot_90 = RotKernel3x3::<P, { Rotation::Clockwise90 as u8 }>::new(&kernel);
-                    let rot_180 = RotKernel3x3::<P, { Rotation::Clockwise180 as u8 }>::new(&kernel);
-                    let rot_270 = RotKernel3x3::<P, { Rotation::Clockwise270 as u8 }>::new(&kernel);
+            Self::blend_block(
+                kernel,
+                out,
+                dst_stride,
+                blend_xy,
+                pixel_config,
+                ycbcr,
+                &table,
+            );
+        };
 
-                    Self::blend_pixel(rot_0, out, dest_width, blend_xy, config);
-                    Self::blend_pixel(rot_90, out, dest_width, blend_xy, config);
-                    Self::blend_pixel(rot_180, out, dest_width, blend_xy, config);
-                    Self::blend_pixel(rot_270, out, dest_width, blend_xy, config);
-                }
-            }
+        // with a cached blend map, the corners don't need to be preprocessed again
+        match blend_map {
+            Some(blend_map) => for_each_cached_blend::<P, OOB>(
+                source, src_width, src_height, x_range, y_range, blend_map, visit, on_row,
+            ),
+            None => for_each_blend::<P, OOB, C>(
+                source,
+                src_width,
+                src_height,
+                config,
+                x_range,
+                y_range,
+                pre_proc_buf,
+                ycbcr,
+                visit,
+                on_row,
+            ),
         }
     }
 }
 
-macro_rules! blend {
-    ($m:literal / $n:literal, $out:ident [$x:literal, $y:literal], $col:ident) => {
-        alpha_grad::<P, $m, $n>($out.rotated_ref::<$x, $y>(), $col);
-    };
-}
+pub(crate) struct Scaler1x;
 
-macro_rules! set {
-    ($out:ident [$x:literal, $y:literal], $col:ident) => {
-        *$out.rotated_ref::<$x, $y>() = $col;
-    };
+/// At a factor of 1 every blend only touches the corner of a pixel, so that the image is anti-aliased
+/// without any edges being moved.
+impl Scaler<1> for Scaler1x {
+    const CLASSIC: BlendTable<'static> = BlendTable::uniform(&[blend(1, 8, 0, 0)]);
+    const SOFT: BlendTable<'static> = BlendTable::uniform(&[blend(3, 16, 0, 0)]);
 }
 
 pub(crate) struct Scaler2x;
 
 impl Scaler<2> for Scaler2x {
-    fn blend_line_shallow<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 2, R>) {
-        blend!(1/4, out[1, 0], col);
-        blend!(3/4, out[1, 1], col);
-    }
-
-    fn blend_line_steep<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 2, R>) {
-        blend!(1/4, out[0, 1], col);
-        blend!(3/4, out[1, 1], col);
-    }
-
-    fn blend_line_steep_and_shallow<P: Pixel, const R: u8>(
-        col: P,
-        out: &mut OutputMatrix<P, 2, R>,
-    ) {
-        blend!(1/4, out[1, 0], col);
-        blend!(1/4, out[0, 1], col);
-        blend!(5/6, out[1, 1], col);
-    }
-
-    fn blend_line_diagonal<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 2, R>) {
-        blend!(1/2, out[1, 1], col);
-    }
+    const CLASSIC: BlendTable<'static> = BlendTable {
+        line_shallow: &[blend(1, 4, 1, 0), blend(3, 4, 1, 1)],
+        line_steep: &[blend(1, 4, 0, 1), blend(3, 4, 1, 1)],
+        line_steep_and_shallow: &[blend(1, 4, 1, 0), blend(1, 4, 0, 1), blend(5, 6, 1, 1)],
+        line_diagonal: &[blend(1, 2, 1, 1)],
+        corner: &[blend(21, 100, 1, 1)],
+    };
 
-    fn blend_corner<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 2, R>) {
-        blend!(21/100, out[1, 1], col);
-    }
+    const SOFT: BlendTable<'static> = BlendTable {
+        line_diagonal: &[blend(5, 8, 1, 1), blend(1, 8, 1, 0), blend(1, 8, 0, 1)],
+        corner: &[
+            blend(30, 100, 1, 1),
+            blend(5, 100, 1, 0),
+            blend(5, 100, 0, 1),
+        ],
+        ..Self::CLASSIC
+    };
 }
 
 pub(crate) struct Scaler3x;
 
 impl Scaler<3> for Scaler3x {
-    fn blend_line_shallow<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 3, R>) {
-        blend!(1/4, out[2, 0], col);
-        blend!(1/4, out[1, 2], col);
-        blend!(3/4, out[2, 1], col);
-        set!(out[2, 2], col);
-    }
-
-    fn blend_line_steep<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 3, R>) {
-        blend!(1/4, out[0, 2], col);
-        blend!(1/4, out[2, 1], col);
-
-        blend!(3/4, out[1, 2], col);
-        set!(out[2, 2], col);
-    }
-
-    fn blend_line_steep_and_shallow<P: Pixel, const R: u8>(
-        col: P,
-        out: &mut OutputMatrix<P, 3, R>,
-    ) {
-        blend!(1/4, out[2, 0], col);
-        blend!(1/4, out[0, 2], col);
-
-        blend!(3/4, out[2, 1], col);
-        blend!(3/4, out[1, 2], col);
-        set!(out[2, 2], col);
-    }
-
-    fn blend_line_diagonal<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 3, R>) {
-        blend!(1/8, out[1, 2], col);
-        blend!(1/8, out[2, 1], col);
-        blend!(7/8, out[2, 2], col);
-    }
+    const CLASSIC: BlendTable<'static> = BlendTable {
+        line_shallow: &[
+            blend(1, 4, 2, 0),
+            blend(1, 4, 1, 2),
+            blend(3, 4, 2, 1),
+            set(2, 2),
+        ],
+        line_steep: &[
+            blend(1, 4, 0, 2),
+            blend(1, 4, 2, 1),
+            blend(3, 4, 1, 2),
+            set(2, 2),
+        ],
+        line_steep_and_shallow: &[
+            blend(1, 4, 2, 0),
+            blend(1, 4, 0, 2),
+            blend(3, 4, 2, 1),
+            blend(3, 4, 1, 2),
+            set(2, 2),
+        ],
+        line_diagonal: &[blend(1, 8, 1, 2), blend(1, 8, 2, 1), blend(7, 8, 2, 2)],
+        corner: &[blend(45, 100, 2, 2)],
+    };
 
-    fn blend_corner<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 3, R>) {
-        blend!(45/100, out[2, 2], col);
-    }
+    const SOFT: BlendTable<'static> = BlendTable {
+        line_diagonal: &[blend(1, 4, 1, 2), blend(1, 4, 2, 1), blend(7, 8, 2, 2)],
+        corner: &[
+            blend(55, 100, 2, 2),
+            blend(10, 100, 1, 2),
+            blend(10, 100, 2, 1),
+        ],
+        ..Self::CLASSIC
+    };
 }
 
 pub(crate) struct Scaler4x;
 
 impl Scaler<4> for Scaler4x {
-    fn blend_line_shallow<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 4, R>) {
-        blend!(1/4, out[3, 0], col);
-        blend!(1/4, out[2, 2], col);
-
-        blend!(3/4, out[3, 1], col);
-        blend!(3/4, out[2, 3], col);
-
-        set!(out[3, 2], col);
-        set!(out[3, 3], col);
-    }
-
-    fn blend_line_steep<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 4, R>) {
-        blend!(1/4, out[0, 3], col);
-        blend!(1/4, out[2, 2], col);
-
-        blend!(3/4, out[1, 3], col);
-        blend!(3/4, out[3, 2], col);
-
-        set!(out[2, 3], col);
-        set!(out[3, 3], col);
-    }
-
-    fn blend_line_steep_and_shallow<P: Pixel, const R: u8>(
-        col: P,
-        out: &mut OutputMatrix<P, 4, R>,
-    ) {
-        blend!(3/4, out[3, 1], col);
-        blend!(3/4, out[1, 3], col);
-        blend!(1/4, out[3, 0], col);
-        blend!(1/4, out[0, 3], col);
-
-        blend!(1/3, out[2, 2], col);
-
-        set!(out[3, 3], col);
-        set!(out[3, 2], col);
-        set!(out[2, 3], col);
-    }
-
-    fn blend_line_diagonal<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 4, R>) {
-        blend!(1/2, out[3, 2], col);
-        blend!(1/2, out[2, 3], col);
-        set!(out[3, 3], col);
-    }
+    const CLASSIC: BlendTable<'static> = BlendTable {
+        line_shallow: &[
+            blend(1, 4, 3, 0),
+            blend(1, 4, 2, 2),
+            blend(3, 4, 3, 1),
+            blend(3, 4, 2, 3),
+            set(3, 2),
+            set(3, 3),
+        ],
+        line_steep: &[
+            blend(1, 4, 0, 3),
+            blend(1, 4, 2, 2),
+            blend(3, 4, 1, 3),
+            blend(3, 4, 3, 2),
+            set(2, 3),
+            set(3, 3),
+        ],
+        line_steep_and_shallow: &[
+            blend(3, 4, 3, 1),
+            blend(3, 4, 1, 3),
+            blend(1, 4, 3, 0),
+            blend(1, 4, 0, 3),
+            blend(1, 3, 2, 2),
+            set(3, 3),
+            set(3, 2),
+            set(2, 3),
+        ],
+        line_diagonal: &[blend(1, 2, 3, 2), blend(1, 2, 2, 3), set(3, 3)],
+        corner: &[
+            blend(68, 100, 3, 3),
+            blend(9, 100, 3, 2),
+            blend(9, 100, 2, 3),
+        ],
+    };
 
-    fn blend_corner<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 4, R>) {
-        blend!(68/100, out[3, 3], col);
-        blend!(9/100, out[3, 2], col);
-        blend!(9/100, out[2, 3], col);
-    }
+    const SOFT: BlendTable<'static> = BlendTable {
+        line_diagonal: &[
+            blend(1, 8, 3, 1),
+            blend(1, 8, 2, 2),
+            blend(1, 8, 1, 3),
+            blend(1, 2, 3, 2),
+            blend(1, 2, 2, 3),
+            set(3, 3),
+        ],
+        corner: &[
+            blend(75, 100, 3, 3),
+            blend(20, 100, 3, 2),
+            blend(20, 100, 2, 3),
+            blend(4, 100, 2, 2),
+        ],
+        ..Self::CLASSIC
+    };
 }
 
 pub(crate) struct Scaler5x;
 
 impl Scaler<5> for Scaler5x {
-    fn blend_line_shallow<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 5, R>) {
-        blend!(1/4, out[4, 0], col);
-        blend!(1/4, out[3, 2], col);
-        blend!(1/4, out[2, 4], col);
-
-        blend!(3/4, out[4, 1], col);
-        blend!(3/4, out[3, 3], col);
-
-        set!(out[4, 2], col);
-        set!(out[4, 3], col);
-        set!(out[4, 4], col);
-        set!(out[3, 4], col);
-    }
-
-    fn blend_line_steep<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 5, R>) {
-        blend!(1/4, out[0, 4], col);
-        blend!(1/4, out[2, 3], col);
-        blend!(1/4, out[4, 2], col);
-
-        blend!(3/4, out[1, 4], col);
-        blend!(3/4, out[3, 3], col);
-
-        set!(out[2, 4], col);
-        set!(out[3, 4], col);
-        set!(out[4, 4], col);
-        set!(out[4, 3], col);
-    }
+    const CLASSIC: BlendTable<'static> = BlendTable {
+        line_shallow: &[
+            blend(1, 4, 4, 0),
+            blend(1, 4, 3, 2),
+            blend(1, 4, 2, 4),
+            blend(3, 4, 4, 1),
+            blend(3, 4, 3, 3),
+            set(4, 2),
+            set(4, 3),
+            set(4, 4),
+            set(3, 4),
+        ],
+        line_steep: &[
+            blend(1, 4, 0, 4),
+            blend(1, 4, 2, 3),
+            blend(1, 4, 4, 2),
+            blend(3, 4, 1, 4),
+            blend(3, 4, 3, 3),
+            set(2, 4),
+            set(3, 4),
+            set(4, 4),
+            set(4, 3),
+        ],
+        line_steep_and_shallow: &[
+            blend(1, 4, 0, 4),
+            blend(1, 4, 2, 3),
+            blend(3, 4, 1, 4),
+            blend(1, 4, 4, 0),
+            blend(1, 4, 3, 2),
+            blend(3, 4, 4, 1),
+            blend(2, 3, 3, 3),
+            set(2, 4),
+            set(3, 4),
+            set(4, 4),
+            set(4, 2),
+            set(4, 3),
+        ],
+        line_diagonal: &[
+            blend(1, 8, 4, 2),
+            blend(1, 8, 3, 3),
+            blend(1, 8, 2, 4),
+            blend(7, 8, 4, 3),
+            blend(7, 8, 3, 4),
+            set(4, 4),
+        ],
+        corner: &[
+            blend(86, 100, 4, 4),
+            blend(23, 100, 4, 3),
+            blend(23, 100, 3, 4),
+        ],
+    };
 
-    fn blend_line_steep_and_shallow<P: Pixel, const R: u8>(
-        col: P,
-        out: &mut OutputMatrix<P, 5, R>,
-    ) {
-        blend!(1/4, out[0, 4], col);
-        blend!(1/4, out[2, 3], col);
-        blend!(3/4, out[1, 4], col);
+    const SOFT: BlendTable<'static> = BlendTable {
+        line_diagonal: &[
+            blend(1, 4, 4, 2),
+            blend(1, 4, 3, 3),
+            blend(1, 4, 2, 4),
+            blend(7, 8, 4, 3),
+            blend(7, 8, 3, 4),
+            set(4, 4),
+        ],
+        corner: &[
+            blend(92, 100, 4, 4),
+            blend(35, 100, 4, 3),
+            blend(35, 100, 3, 4),
+            blend(8, 100, 3, 3),
+            blend(5, 100, 4, 2),
+            blend(5, 100, 2, 4),
+        ],
+        ..Self::CLASSIC
+    };
+}
 
-        blend!(1/4, out[4, 0], col);
-        blend!(1/4, out[3, 2], col);
-        blend!(3/4, out[4, 1], col);
+pub(crate) struct Scaler6x;
 
-        blend!(2/3, out[3, 3], col);
+impl Scaler<6> for Scaler6x {
+    const CLASSIC: BlendTable<'static> = BlendTable {
+        line_shallow: &[
+            blend(1, 4, 5, 0),
+            blend(1, 4, 4, 2),
+            blend(1, 4, 3, 4),
+            blend(3, 4, 5, 1),
+            blend(3, 4, 4, 3),
+            blend(3, 4, 3, 5),
+            set(5, 2),
+            set(5, 3),
+            set(5, 4),
+            set(5, 5),
+            set(4, 4),
+            set(4, 5),
+        ],
+        line_steep: &[
+            blend(1, 4, 0, 5),
+            blend(1, 4, 2, 4),
+            blend(1, 4, 4, 3),
+            blend(3, 4, 1, 5),
+            blend(3, 4, 3, 4),
+            blend(3, 4, 5, 3),
+            set(2, 5),
+            set(3, 5),
+            set(4, 5),
+            set(5, 5),
+            set(4, 4),
+            set(5, 4),
+        ],
+        line_steep_and_shallow: &[
+            blend(1, 4, 0, 5),
+            blend(1, 4, 2, 4),
+            blend(3, 4, 1, 5),
+            blend(3, 4, 3, 4),
+            blend(1, 4, 5, 0),
+            blend(1, 4, 4, 2),
+            blend(3, 4, 5, 1),
+            blend(3, 4, 4, 3),
+            set(2, 5),
+            set(3, 5),
+            set(4, 5),
+            set(5, 5),
+            set(4, 4),
+            set(5, 4),
+            set(5, 2),
+            set(5, 3),
+        ],
+        line_diagonal: &[
+            blend(1, 2, 5, 3),
+            blend(1, 2, 4, 4),
+            blend(1, 2, 3, 5),
+            set(4, 5),
+            set(5, 5),
+            set(5, 4),
+        ],
+        corner: &[
+            blend(97, 100, 5, 5),
+            blend(42, 100, 4, 5),
+            blend(42, 100, 5, 4),
+            blend(6, 100, 5, 3),
+            blend(6, 100, 3, 5),
+        ],
+    };
 
-        set!(out[2, 4], col);
-        set!(out[3, 4], col);
-        set!(out[4, 4], col);
+    const SOFT: BlendTable<'static> = BlendTable {
+        line_diagonal: &[
+            blend(1, 8, 5, 2),
+            blend(1, 8, 4, 3),
+            blend(1, 8, 3, 4),
+            blend(1, 8, 2, 5),
+            blend(1, 2, 5, 3),
+            blend(1, 2, 4, 4),
+            blend(1, 2, 3, 5),
+            set(4, 5),
+            set(5, 5),
+            set(5, 4),
+        ],
+        corner: &[
+            set(5, 5),
+            blend(55, 100, 4, 5),
+            blend(55, 100, 5, 4),
+            blend(15, 100, 4, 4),
+            blend(12, 100, 5, 3),
+            blend(12, 100, 3, 5),
+        ],
+        ..Self::CLASSIC
+    };
+}
 
-        set!(out[4, 2], col);
-        set!(out[4, 3], col);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pixel that records how it was blended, instead of holding a colour.
+    #[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+    struct Probe {
+        /// Whether the blended colour has been drawn into this pixel.
+        blended: bool,
+        /// The weight it was blended with, or `0 / 0` when it was replaced outright.
+        weight: (usize, usize),
     }
 
-    fn blend_line_diagonal<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 5, R>) {
-        blend!(1/8, out[4, 2], col);
-        blend!(1/8, out[3, 3], col);
-        blend!(1/8, out[2, 4], col);
+    impl Pixel for Probe {
+        fn from_rgba(_rgba: [u8; 4]) -> Self {
+            Self::default()
+        }
 
-        blend!(7/8, out[4, 3], col);
-        blend!(7/8, out[3, 4], col);
+        fn alpha(self) -> u8 {
+            255
+        }
 
-        set!(out[4, 4], col);
-    }
+        fn to_rgb(self) -> [u8; 3] {
+            [0; 3]
+        }
 
-    fn blend_corner<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 5, R>) {
-        blend!(86/100, out[4, 4], col);
-        blend!(23/100, out[4, 3], col);
-        blend!(23/100, out[3, 4], col);
+        fn gradient(front: Self, back: Self, m: usize, n: usize) -> Self {
+            assert!(!back.blended, "a cell was blended twice");
+            Self {
+                blended: front.blended,
+                weight: (m, n),
+            }
+        }
     }
-}
-
-pub(crate) struct Scaler6x;
 
-impl Scaler<6> for Scaler6x {
-    fn blend_line_shallow<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 6, R>) {
-        blend!(1/4, out[5, 0], col);
-        blend!(1/4, out[4, 2], col);
-        blend!(1/4, out[3, 4], col);
-
-        blend!(3/4, out[5, 1], col);
-        blend!(3/4, out[4, 3], col);
-        blend!(3/4, out[3, 5], col);
-
-        set!(out[5, 2], col);
-        set!(out[5, 3], col);
-        set!(out[5, 4], col);
-        set!(out[5, 5], col);
-
-        set!(out[4, 4], col);
-        set!(out[4, 5], col);
+    /// Check that blending `cells` into an unrotated output block blends each cell with the weight
+    /// drawn in `expected`, where `.` is a cell that is left alone and `1` is one that is replaced.
+    fn assert_blends<const SCALE: usize>(cells: &[CellWeight], expected: &[&str; SCALE]) {
+        let mut block = vec![Probe::default(); SCALE * SCALE];
+        let mut out =
+            OutputMatrix::<Probe, SCALE, { Rotation::None as u8 }>::new(&mut block, SCALE);
+        let col = Probe {
+            blended: true,
+            weight: (0, 0),
+        };
+        blend_cells(&mut out, cells, col, 1.0);
+
+        let actual: Vec<Vec<String>> = block
+            .chunks_exact(SCALE)
+            .map(|row| {
+                row.iter()
+                    .map(|p| match (p.blended, p.weight) {
+                        (false, _) => ".".to_string(),
+                        (true, (0, 0)) => "1".to_string(),
+                        (true, (m, n)) => format!("{m}/{n}"),
+                    })
+                    .collect()
+            })
+            .collect();
+        let expected: Vec<Vec<String>> = expected
+            .iter()
+            .map(|row| row.split_whitespace().map(str::to_string).collect())
+            .collect();
+        assert_eq!(actual, expected);
     }
 
-    fn blend_line_steep<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 6, R>) {
-        blend!(1/4, out[0, 5], col);
-        blend!(1/4, out[2, 4], col);
-        blend!(1/4, out[4, 3], col);
-
-        blend!(3/4, out[1, 5], col);
-        blend!(3/4, out[3, 4], col);
-        blend!(3/4, out[5, 3], col);
+    fn check_tables<const SCALE: usize, S: Scaler<SCALE>>() {
+        for table in [S::CLASSIC, S::SOFT] {
+            for cells in [
+                table.line_shallow,
+                table.line_steep,
+                table.line_steep_and_shallow,
+                table.line_diagonal,
+                table.corner,
+            ] {
+                for (i, cell) in cells.iter().enumerate() {
+                    assert!((cell.row as usize) < SCALE && (cell.col as usize) < SCALE);
+                    assert!(0 < cell.m && cell.m <= cell.n && cell.n <= 1000);
+                    // each cell is only blended once
+                    assert!(!cells[..i]
+                        .iter()
+                        .any(|c| (c.row, c.col) == (cell.row, cell.col)));
+                }
+            }
+        }
+    }
 
-        set!(out[2, 5], col);
-        set!(out[3, 5], col);
-        set!(out[4, 5], col);
-        set!(out[5, 5], col);
+    #[test]
+    fn scaler2x_blends_the_right_cells() {
+        let table = Scaler2x::CLASSIC;
+        assert_blends::<2>(table.line_shallow, &[".   .", "1/4 3/4"]);
+        assert_blends::<2>(table.line_steep, &[".   1/4", ".   3/4"]);
+        assert_blends::<2>(table.line_steep_and_shallow, &[".   1/4", "1/4 5/6"]);
+        assert_blends::<2>(table.line_diagonal, &[".   .", ".   1/2"]);
+        assert_blends::<2>(table.corner, &[".      .", ".      21/100"]);
+    }
 
-        set!(out[4, 4], col);
-        set!(out[5, 4], col);
+    #[test]
+    fn scaler3x_blends_the_right_cells() {
+        let table = Scaler3x::CLASSIC;
+        assert_blends::<3>(
+            table.line_shallow,
+            &[".   .   .", ".   .   1/4", "1/4 3/4 1"],
+        );
+        assert_blends::<3>(
+            table.line_steep,
+            &[".   .   1/4", ".   .   3/4", ".   1/4 1"],
+        );
+        assert_blends::<3>(
+            table.line_steep_and_shallow,
+            &[".   .   1/4", ".   .   3/4", "1/4 3/4 1"],
+        );
+        assert_blends::<3>(
+            table.line_diagonal,
+            &[".   .   .", ".   .   1/8", ".   1/8 7/8"],
+        );
+        assert_blends::<3>(
+            table.corner,
+            &[".      .      .", ".      .      .", ".      .      45/100"],
+        );
     }
 
-    fn blend_line_steep_and_shallow<P: Pixel, const R: u8>(
-        col: P,
-        out: &mut OutputMatrix<P, 6, R>,
-    ) {
-        blend!(1/4, out[0, 5], col);
-        blend!(1/4, out[2, 4], col);
-        blend!(3/4, out[1, 5], col);
-        blend!(3/4, out[3, 4], col);
-
-        blend!(1/4, out[5, 0], col);
-        blend!(1/4, out[4, 2], col);
-        blend!(3/4, out[5, 1], col);
-        blend!(3/4, out[4, 3], col);
-
-        set!(out[2, 5], col);
-        set!(out[3, 5], col);
-        set!(out[4, 5], col);
-        set!(out[5, 5], col);
-
-        set!(out[4, 4], col);
-        set!(out[5, 4], col);
-
-        set!(out[5, 2], col);
-        set!(out[5, 3], col);
+    #[test]
+    fn scaler4x_blends_the_right_cells() {
+        let table = Scaler4x::CLASSIC;
+        assert_blends::<4>(
+            table.line_shallow,
+            &[
+                ".   .   .   .",
+                ".   .   .   .",
+                ".   .   1/4 3/4",
+                "1/4 3/4 1   1",
+            ],
+        );
+        assert_blends::<4>(
+            table.line_steep,
+            &[
+                ".   .   .   1/4",
+                ".   .   .   3/4",
+                ".   .   1/4 1",
+                ".   .   3/4 1",
+            ],
+        );
+        assert_blends::<4>(
+            table.line_steep_and_shallow,
+            &[
+                ".   .   .   1/4",
+                ".   .   .   3/4",
+                ".   .   1/3 1",
+                "1/4 3/4 1   1",
+            ],
+        );
+        assert_blends::<4>(
+            table.line_diagonal,
+            &[
+                ".   .   .   .",
+                ".   .   .   .",
+                ".   .   .   1/2",
+                ".   .   1/2 1",
+            ],
+        );
+        assert_blends::<4>(
+            table.corner,
+            &[
+                ".      .      .      .",
+                ".      .      .      .",
+                ".      .      .      9/100",
+                ".      .      9/100  68/100",
+            ],
+        );
     }
 
-    fn blend_line_diagonal<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 6, R>) {
-        blend!(1/2, out[5, 3], col);
-        blend!(1/2, out[4, 4], col);
-        blend!(1/2, out[3, 5], col);
+    #[test]
+    fn scaler5x_blends_the_right_cells() {
+        let table = Scaler5x::CLASSIC;
+        assert_blends::<5>(
+            table.line_shallow,
+            &[
+                ".   .   .   .   .",
+                ".   .   .   .   .",
+                ".   .   .   .   1/4",
+                ".   .   1/4 3/4 1",
+                "1/4 3/4 1   1   1",
+            ],
+        );
+        assert_blends::<5>(
+            table.line_steep,
+            &[
+                ".   .   .   .   1/4",
+                ".   .   .   .   3/4",
+                ".   .   .   1/4 1",
+                ".   .   .   3/4 1",
+                ".   .   1/4 1   1",
+            ],
+        );
+        assert_blends::<5>(
+            table.line_steep_and_shallow,
+            &[
+                ".   .   .   .   1/4",
+                ".   .   .   .   3/4",
+                ".   .   .   1/4 1",
+                ".   .   1/4 2/3 1",
+                "1/4 3/4 1   1   1",
+            ],
+        );
+        assert_blends::<5>(
+            table.line_diagonal,
+            &[
+                ".   .   .   .   .",
+                ".   .   .   .   .",
+                ".   .   .   .   1/8",
+                ".   .   .   1/8 7/8",
+                ".   .   1/8 7/8 1",
+            ],
+        );
+        assert_blends::<5>(
+            table.corner,
+            &[
+                ".      .      .      .      .",
+                ".      .      .      .      .",
+                ".      .      .      .      .",
+                ".      .      .      .      23/100",
+                ".      .      .      23/100 86/100",
+            ],
+        );
+    }
 
-        set!(out[4, 5], col);
-        set!(out[5, 5], col);
-        set!(out[5, 4], col);
+    #[test]
+    fn scaler6x_blends_the_right_cells() {
+        let table = Scaler6x::CLASSIC;
+        assert_blends::<6>(
+            table.line_shallow,
+            &[
+                ".   .   .   .   .   .",
+                ".   .   .   .   .   .",
+                ".   .   .   .   .   .",
+                ".   .   .   .   1/4 3/4",
+                ".   .   1/4 3/4 1   1",
+                "1/4 3/4 1   1   1   1",
+            ],
+        );
+        assert_blends::<6>(
+            table.line_steep,
+            &[
+                ".   .   .   .   .   1/4",
+                ".   .   .   .   .   3/4",
+                ".   .   .   .   1/4 1",
+                ".   .   .   .   3/4 1",
+                ".   .   .   1/4 1   1",
+                ".   .   .   3/4 1   1",
+            ],
+        );
+        assert_blends::<6>(
+            table.line_steep_and_shallow,
+            &[
+                ".   .   .   .   .   1/4",
+                ".   .   .   .   .   3/4",
+                ".   .   .   .   1/4 1",
+                ".   .   .   .   3/4 1",
+                ".   .   1/4 3/4 1   1",
+                "1/4 3/4 1   1   1   1",
+            ],
+        );
+        assert_blends::<6>(
+            table.line_diagonal,
+            &[
+                ".   .   .   .   .   .",
+                ".   .   .   .   .   .",
+                ".   .   .   .   .   .",
+                ".   .   .   .   .   1/2",
+                ".   .   .   .   1/2 1",
+                ".   .   .   1/2 1   1",
+            ],
+        );
+        assert_blends::<6>(
+            table.corner,
+            &[
+                ".      .      .      .      .      .",
+                ".      .      .      .      .      .",
+                ".      .      .      .      .      .",
+                ".      .      .      .      .      6/100",
+                ".      .      .      .      .      42/100",
+                ".      .      .      6/100  42/100 97/100",
+            ],
+        );
     }
 
-    fn blend_corner<P: Pixel, const R: u8>(col: P, out: &mut OutputMatrix<P, 6, R>) {
-        blend!(97/100, out[5, 5], col);
-        blend!(42/100, out[4, 5], col);
-        blend!(42/100, out[5, 4], col);
-        blend!(6/100, out[5, 3], col);
-        blend!(6/100, out[3, 5], col);
+    #[test]
+    fn tables_stay_inside_the_block() {
+        check_tables::<1, Scaler1x>();
+        check_tables::<2, Scaler2x>();
+        check_tables::<3, Scaler3x>();
+        check_tables::<4, Scaler4x>();
+        check_tables::<5, Scaler5x>();
+        check_tables::<6, Scaler6x>();
     }
 }
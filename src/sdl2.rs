@@ -0,0 +1,73 @@
+//! Helpers for scaling [rust-sdl2](https://docs.rs/sdl2) surfaces.
+//!
+//! Enabled by the `sdl2` feature.
+use ::sdl2::pixels::PixelFormatEnum;
+use ::sdl2::surface::{Surface, SurfaceRef};
+
+use crate::PixelFormat;
+
+/// The pixel format of the scaler that stores pixels the same way as an SDL pixel format, if any.
+fn scaler_format(format: PixelFormatEnum) -> Option<PixelFormat> {
+    match format {
+        PixelFormatEnum::RGBA32 => Some(PixelFormat::Rgba8),
+        PixelFormatEnum::ARGB32 => Some(PixelFormat::Argb8),
+        PixelFormatEnum::BGRA32 => Some(PixelFormat::Bgra8),
+        PixelFormatEnum::RGB24 => Some(PixelFormat::Rgb8),
+        PixelFormatEnum::RGB888 => Some(PixelFormat::Xrgb8888),
+        PixelFormatEnum::RGB565 => Some(PixelFormat::Rgb565),
+        PixelFormatEnum::RGB555 => Some(PixelFormat::Rgb555),
+        _ => None,
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an SDL surface by an integer factor, returning a new surface.
+///
+/// Surfaces in the RGBA32, ARGB32, BGRA32, RGB24, RGB888, RGB565 and RGB555 formats are scaled as
+/// they are, and the scaled surface has the same format. Surfaces in any other format, such as
+/// paletted surfaces, are converted to BGRA32 first, and the scaled surface is in BGRA32.
+/// The pitch of the surface is taken into account, so surfaces with padded rows are scaled correctly.
+///
+/// Returns the SDL error if a surface couldn't be created or converted.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_surface(surface: &SurfaceRef, factor: usize) -> Result<Surface<'static>, String> {
+    let converted;
+    let (surface, format) = match scaler_format(surface.pixel_format_enum()) {
+        Some(format) => (surface, format),
+        None => {
+            converted = surface.convert_format(PixelFormatEnum::BGRA32)?;
+            (&*converted, PixelFormat::Bgra8)
+        }
+    };
+
+    let (width, height) = (surface.width() as usize, surface.height() as usize);
+    let pitch = surface.pitch() as usize;
+    let row_len = width * format.bytes_per_pixel();
+    let source = surface.with_lock(|pixels| {
+        let mut source = Vec::with_capacity(row_len * height);
+        for row in pixels.chunks(pitch.max(1)).take(height) {
+            source.extend_from_slice(&row[..row_len]);
+        }
+        source
+    });
+    let scaled = crate::scale(&source, format, width, height, factor);
+
+    let mut out = Surface::new(
+        (width * factor) as u32,
+        (height * factor) as u32,
+        surface.pixel_format_enum(),
+    )?;
+    let out_pitch = out.pitch() as usize;
+    let out_row_len = row_len * factor;
+    out.with_lock_mut(|pixels| {
+        for (dst, src) in pixels
+            .chunks_mut(out_pitch.max(1))
+            .zip(scaled.chunks(out_row_len))
+        {
+            dst[..out_row_len].copy_from_slice(src);
+        }
+    });
+    Ok(out)
+}
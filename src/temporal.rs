@@ -0,0 +1,241 @@
+//! Scaling the frames of an animation with edges that hold steady from one frame to the next.
+use crate::blend::{Blend2x2, BlendType};
+use crate::config::PixelConfig;
+use crate::error::check_source;
+use crate::kernel::{Edge, Kernel4x4};
+use crate::oob_reader::{OobReader, OobReaderTransparent};
+use crate::pixel::{cast_pixels, Rgba8};
+use crate::ScalerConfig;
+
+/// Scales the frames of an animation or video one after another, keeping the edges found in each
+/// frame unless the next frame gives clear evidence against them.
+///
+/// Scaling each frame on its own decides every edge afresh, so anti-aliased and dithered areas,
+/// where the two diagonals through a pixel are nearly as alike as each other, can have their edges
+/// flip back and forth between frames with the slightest change in colour, and shimmer. The
+/// temporal scaler carries the blend map of each frame over to the next, and only moves an edge,
+/// adds one or takes one away when the colour distances favour the change by more than `margin`:
+/// a margin of 0.25 needs the other diagonal to be 25% more alike. A margin of 0 scales each
+/// frame the same as [`scale_rgba_with_config`](crate::scale_rgba_with_config) would.
+///
+/// ```
+/// let (width, height) = (8, 8);
+/// let frames = [[40u8; 8 * 8 * 4], [42u8; 8 * 8 * 4]];
+///
+/// let mut scaler = xbrz::TemporalScaler::new(xbrz::ScalerConfig::default(), 0.25);
+/// for frame in &frames {
+///     let scaled = scaler.scale_frame(frame, width, height, 3);
+///     # assert_eq!(scaled.len(), width * 3 * height * 3 * 4);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TemporalScaler {
+    config: ScalerConfig,
+    margin: f64,
+    /// The blend map of the last frame scaled, and its width and height.
+    previous: Option<(Vec<Blend2x2>, usize, usize)>,
+}
+
+impl TemporalScaler {
+    pub fn new(config: ScalerConfig, margin: f64) -> Self {
+        Self {
+            config,
+            margin,
+            previous: None,
+        }
+    }
+
+    /// Use the xBRZ algorithm to scale up the next frame by an integer factor, leaning towards
+    /// the edges of the frame before.
+    ///
+    /// A frame with different dimensions to the one before is scaled on its own, as the first
+    /// frame is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+    /// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn scale_frame(
+        &mut self,
+        source: &[u8],
+        src_width: usize,
+        src_height: usize,
+        factor: usize,
+    ) -> Vec<u8> {
+        check_source(source, src_width, src_height, factor, 4).unwrap_or_else(|e| panic!("{e}"));
+
+        let blend_map = match self.previous.take() {
+            Some((previous, width, height)) if (width, height) == (src_width, src_height) => {
+                held_blend_map(
+                    source,
+                    src_width,
+                    src_height,
+                    &self.config,
+                    &previous,
+                    self.margin,
+                )
+            }
+            _ => crate::compute_blend_map(source, src_width, src_height, &self.config),
+        };
+        let scaled = crate::scale_rgba_with_blend_map(
+            source,
+            src_width,
+            src_height,
+            factor,
+            &self.config,
+            &blend_map,
+        );
+        self.previous = Some((blend_map, src_width, src_height));
+        scaled
+    }
+
+    /// Forget the edges of the last frame, so that the next frame is scaled on its own, such as
+    /// after a cut to a new scene.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+}
+
+/// The blend map of an image, with each edge leaning towards the edge at the same place in the
+/// `previous` blend map.
+fn held_blend_map(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    config: &ScalerConfig,
+    previous: &[Blend2x2],
+    margin: f64,
+) -> Vec<Blend2x2> {
+    let pixels = cast_pixels::<Rgba8>(source);
+    let (width, height) = (src_width as isize, src_height as isize);
+    let index = |x: isize, y: isize| {
+        ((0..width).contains(&x) && (0..height).contains(&y)).then(|| (y * width + x) as usize)
+    };
+    let corner = |x, y, pick: fn(&Blend2x2) -> BlendType| match index(x, y) {
+        Some(i) => pick(&previous[i]),
+        None => BlendType::None,
+    };
+
+    let ycbcr = config.lookup();
+    let mut blend_map = vec![Blend2x2::default(); src_width * src_height];
+    let mut set = |x, y, corner: fn(&mut Blend2x2) -> &mut BlendType, blend| {
+        if let Some(i) = index(x, y) {
+            *corner(&mut blend_map[i]) = blend;
+        }
+    };
+    // each kernel decides the corners around the point between its central 2x2 pixels, so the
+    // kernels run from the one above and to the left of the image
+    for y in -1..height {
+        let oob_reader = OobReaderTransparent::new(&pixels, src_width, src_height, y);
+        let mut kernel = Kernel4x4::init_row(&oob_reader, -1);
+        for x in -1..width {
+            kernel.next_column(&oob_reader, x);
+            if kernel.is_transparent() {
+                continue;
+            }
+
+            // the edge here in the previous frame, from the corners that it blended
+            let either = |a, b| if a == BlendType::None { b } else { a };
+            let (jg, fk) = if x == -1 {
+                // the first column keeps the corner to its left in its top left corner
+                (BlendType::None, corner(0, y, |b| b.top_left))
+            } else {
+                (
+                    either(
+                        corner(x, y, |b| b.bottom_right),
+                        corner(x + 1, y + 1, |b| b.top_left),
+                    ),
+                    either(
+                        corner(x, y + 1, |b| b.top_right),
+                        corner(x + 1, y, |b| b.bottom_left),
+                    ),
+                )
+            };
+            let held = match (jg, fk) {
+                (BlendType::None, BlendType::None) => Edge::None,
+                (BlendType::None, fk) => Edge::AlongFK(fk),
+                (jg, _) => Edge::AlongJG(jg),
+            };
+
+            let blend = kernel.pre_process_corners_held(config, ycbcr, held, margin);
+            if x == -1 {
+                // as in the reference implementation, which the default output matches
+                set(0, y, |b| &mut b.top_left, blend.top_right);
+                continue;
+            }
+            set(x, y, |b| &mut b.bottom_right, blend.top_left);
+            set(x + 1, y, |b| &mut b.bottom_left, blend.top_right);
+            set(x, y + 1, |b| &mut b.top_right, blend.bottom_left);
+            set(x + 1, y + 1, |b| &mut b.top_left, blend.bottom_right);
+        }
+    }
+    blend_map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image of unique, opaque colours, as in a noisy or dithered area.
+    fn noise(width: usize, height: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..width * height * 4)
+            .map(|i| match i % 4 {
+                3 => 255,
+                _ => {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    (state >> 16) as u8
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn no_margin_scales_each_frame_on_its_own() {
+        let config = ScalerConfig::default();
+        let mut scaler = TemporalScaler::new(config.clone(), 0.0);
+        for seed in 0..4 {
+            let frame = noise(9, 7, seed);
+            assert_eq!(
+                scaler.scale_frame(&frame, 9, 7, 2),
+                crate::scale_rgba_with_config(&frame, 9, 7, 2, &config)
+            );
+            assert_eq!(
+                scaler.previous.as_ref().unwrap().0,
+                crate::compute_blend_map(&frame, 9, 7, &config)
+            );
+        }
+    }
+
+    #[test]
+    fn edges_hold_through_small_changes() {
+        let config = ScalerConfig::default();
+        let first = noise(8, 8, 2);
+        // the same image with every colour nudged a little, by enough to flip some decisions with
+        // either size of colour lookup table and with fixed point distances
+        let second: Vec<u8> = first
+            .iter()
+            .enumerate()
+            .map(|(i, &c)| match i % 4 {
+                3 => c,
+                _ => c.saturating_add((i % 7) as u8 * 2),
+            })
+            .collect();
+        let second_alone = crate::compute_blend_map(&second, 8, 8, &config);
+
+        let mut scaler = TemporalScaler::new(config.clone(), 4.0);
+        scaler.scale_frame(&first, 8, 8, 2);
+        let first_map = scaler.previous.clone().unwrap().0;
+        assert_ne!(first_map, second_alone);
+
+        scaler.scale_frame(&second, 8, 8, 2);
+        assert_eq!(scaler.previous.as_ref().unwrap().0, first_map);
+
+        // a frame of a different size starts afresh
+        scaler.scale_frame(&first[..8 * 4 * 4], 8, 4, 2);
+        assert_eq!(
+            scaler.previous.as_ref().unwrap().0,
+            crate::compute_blend_map(&first[..8 * 4 * 4], 8, 4, &config)
+        );
+    }
+}
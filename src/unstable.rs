@@ -0,0 +1,305 @@
+//! The building blocks of the scaler, for experimenting with other corner detectors and blend tables
+//! without forking the crate.
+//!
+//! xBRZ scales each source pixel in two steps. First the corners of every source pixel are classified
+//! by looking at the 4x4 [`Kernel4x4`] around them, giving a [`Blend2x2`] for each pixel. Then the block
+//! of output pixels for each source pixel is filled with its colour, and each corner marked for blending
+//! is blended with its neighbours by [`blend_pixel`], using the cells of a [`BlendTable`].
+//!
+//! A different corner detector can be tried by building a blend map of its own and passing it to
+//! [`scale_rgba_with_blend_map`](crate::scale_rgba_with_blend_map), and different blend tables by
+//! passing them to [`scale_rgba_with_table`], or by calling [`blend_pixel`] for each source pixel.
+//!
+//! This module is only available with the `unstable` feature, and may change in any release.
+use crate::blend::Blend2x2;
+use crate::config::{BlendWeights, ScalerConfig, TableConfig};
+use crate::kernel;
+use crate::oob_reader::{OobReader, OobReaderTransparent};
+use crate::pixel::{cast_pixels, with_pixels_mut, Pixel, Rgba8};
+pub use crate::scaler::{BlendTable, CellWeight};
+use crate::scaler::{Scaler, Scaler1x, Scaler2x, Scaler3x, Scaler4x, Scaler5x, Scaler6x};
+use crate::ycbcr_lookup::YCbCrLookup;
+
+/// The 4x4 neighbourhood of a source pixel of an RGBA image, with logical positions:
+/// ```text
+/// -----------------
+/// | A | B | C | D |
+/// -----------------
+/// | E | F | G | H |
+/// -----------------
+/// | I | J | K | L |
+/// -----------------
+/// | M | N | O | P |
+/// -----------------
+/// ```
+/// F is the centre pixel.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct Kernel4x4 {
+    pub a: [u8; 4],
+    pub b: [u8; 4],
+    pub c: [u8; 4],
+    pub d: [u8; 4],
+    pub e: [u8; 4],
+    pub f: [u8; 4],
+    pub g: [u8; 4],
+    pub h: [u8; 4],
+    pub i: [u8; 4],
+    pub j: [u8; 4],
+    pub k: [u8; 4],
+    pub l: [u8; 4],
+    pub m: [u8; 4],
+    pub n: [u8; 4],
+    pub o: [u8; 4],
+    pub p: [u8; 4],
+}
+
+impl Kernel4x4 {
+    /// The kernel with the pixel at `(x, y)` of an RGBA image as its centre, F. Pixels beyond the
+    /// edges of the image are transparent, as they are when the image is scaled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` slice length is not exactly equal to `width * height * 4`, or if
+    /// `(x, y)` is outside of the image.
+    pub fn read(source: &[u8], width: usize, height: usize, x: usize, y: usize) -> Self {
+        assert_eq!(source.len(), width * height * 4);
+        assert!(x < width && y < height);
+        let pixels = cast_pixels::<Rgba8>(source);
+        let oob_reader = OobReaderTransparent::new(&pixels, width, height, y as isize);
+        let mut kernel = kernel::Kernel4x4::init_row(&oob_reader, x as isize);
+        kernel.next_column(&oob_reader, x as isize);
+        Self::from_pixels(&kernel)
+    }
+
+    /// The blending that xBRZ decides for the corners of the central 2x2 pixels: F, G, J and K.
+    ///
+    /// Each returned corner is the corner of the central 2x2 pixels as a whole, so the
+    /// `bottom_right` corner is the corner shared by F, G, J and K that is the bottom right corner
+    /// of F, the bottom left of G, the top right of J and the top left of K. A blend map for a whole
+    /// image gathers the four corners of each pixel from four neighbouring kernels.
+    pub fn pre_process_corners(&self, config: &ScalerConfig) -> Blend2x2 {
+        self.to_pixels()
+            .pre_process_corners(config, YCbCrLookup::instance(config.ycbcr_matrix))
+    }
+
+    fn from_pixels(kernel: &kernel::Kernel4x4<Rgba8>) -> Self {
+        let rgba = |px: Rgba8| {
+            let [r, g, b] = px.to_rgb();
+            [r, g, b, px.alpha()]
+        };
+        Self {
+            a: rgba(kernel.a),
+            b: rgba(kernel.b),
+            c: rgba(kernel.c),
+            d: rgba(kernel.d),
+            e: rgba(kernel.e),
+            f: rgba(kernel.f),
+            g: rgba(kernel.g),
+            h: rgba(kernel.h),
+            i: rgba(kernel.i),
+            j: rgba(kernel.j),
+            k: rgba(kernel.k),
+            l: rgba(kernel.l),
+            m: rgba(kernel.m),
+            n: rgba(kernel.n),
+            o: rgba(kernel.o),
+            p: rgba(kernel.p),
+        }
+    }
+
+    fn to_pixels(self) -> kernel::Kernel4x4<Rgba8> {
+        kernel::Kernel4x4 {
+            a: Rgba8::from_rgba(self.a),
+            b: Rgba8::from_rgba(self.b),
+            c: Rgba8::from_rgba(self.c),
+            d: Rgba8::from_rgba(self.d),
+            e: Rgba8::from_rgba(self.e),
+            f: Rgba8::from_rgba(self.f),
+            g: Rgba8::from_rgba(self.g),
+            h: Rgba8::from_rgba(self.h),
+            i: Rgba8::from_rgba(self.i),
+            j: Rgba8::from_rgba(self.j),
+            k: Rgba8::from_rgba(self.k),
+            l: Rgba8::from_rgba(self.l),
+            m: Rgba8::from_rgba(self.m),
+            n: Rgba8::from_rgba(self.n),
+            o: Rgba8::from_rgba(self.o),
+            p: Rgba8::from_rgba(self.p),
+        }
+    }
+}
+
+/// The blend table used by the scaler for `factor` and a set of weights.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn blend_table(factor: usize, weights: BlendWeights) -> BlendTable<'static> {
+    match factor {
+        1 => Scaler1x::table(weights),
+        2 => Scaler2x::table(weights),
+        3 => Scaler3x::table(weights),
+        4 => Scaler4x::table(weights),
+        5 => Scaler5x::table(weights),
+        6 => Scaler6x::table(weights),
+        _ => panic!("factor must be between 1 and 6, got {factor}"),
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image by an integer factor, blending edges with the cells
+/// of `table` instead of the built in tables.
+///
+/// This is the same as [`scale_rgba_with_config`](crate::scale_rgba_with_config) in every other way:
+/// the corners are detected and the image is traversed, tiled and split across threads as usual.
+/// The `blend_weights` of `config` are ignored. At a factor of 1, the table is only used when
+/// `antialias_at_1x` is set.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`, if
+/// `factor` is not one of 1, 2, 3, 4, 5 or 6, or if a cell of `table` lies outside of a `factor` by
+/// `factor` block.
+pub fn scale_rgba_with_table(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+    table: &BlendTable<'_>,
+) -> Vec<u8> {
+    check_table(table, factor);
+    let config = TableConfig { config, table };
+    crate::scale_bytes::<Rgba8, _>(source, src_width, src_height, factor, &config, None, None)
+}
+
+/// Panic if a cell of `table` lies outside of a `factor` by `factor` block.
+fn check_table(table: &BlendTable<'_>, factor: usize) {
+    let cells = [
+        table.line_shallow,
+        table.line_steep,
+        table.line_steep_and_shallow,
+        table.line_diagonal,
+        table.corner,
+    ];
+    assert!(
+        cells
+            .iter()
+            .flat_map(|cells| cells.iter())
+            .all(|cell| (cell.row as usize) < factor && (cell.col as usize) < factor),
+        "blend table has cells outside of a {factor}x{factor} block"
+    );
+}
+
+/// Scale the centre pixel F of `kernel` into `block`, a `factor` by `factor` RGBA image, by filling
+/// it with F and blending each corner marked in `blend` with the cells of `table`.
+///
+/// `blend` holds the corners of F itself, as found in a blend map, rather than those returned by
+/// [`Kernel4x4::pre_process_corners`]. Every other setting, such as the blend strength, is taken
+/// from `config`, except for its `blend_weights`, which are replaced by `table`.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6, if the `block` slice length is not exactly
+/// equal to `factor * factor * 4`, or if a cell of `table` lies outside of a `factor` by `factor` block.
+pub fn blend_pixel(
+    kernel: &Kernel4x4,
+    blend: Blend2x2,
+    factor: usize,
+    config: &ScalerConfig,
+    table: &BlendTable<'_>,
+    block: &mut [u8],
+) {
+    assert_eq!(block.len(), factor * factor * 4);
+    check_table(table, factor);
+
+    let kernel = kernel.to_pixels();
+    let ycbcr = YCbCrLookup::instance(config.ycbcr_matrix);
+    with_pixels_mut::<Rgba8, _>(block, |block| match factor {
+        1 => Scaler1x::blend_block(&kernel, block, 1, blend, config, ycbcr, table),
+        2 => Scaler2x::blend_block(&kernel, block, 2, blend, config, ycbcr, table),
+        3 => Scaler3x::blend_block(&kernel, block, 3, blend, config, ycbcr, table),
+        4 => Scaler4x::blend_block(&kernel, block, 4, blend, config, ycbcr, table),
+        5 => Scaler5x::blend_block(&kernel, block, 5, blend, config, ycbcr, table),
+        6 => Scaler6x::blend_block(&kernel, block, 6, blend, config, ycbcr, table),
+        _ => panic!("factor must be between 1 and 6, got {factor}"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_blend_map;
+
+    #[test]
+    fn blending_each_pixel_matches_scaling() {
+        let (width, height, factor) = (9, 7, 3);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width + i / width) % 4 {
+                0 => [200, 40, 40, 255],
+                1 => [0, 0, 0, 0],
+                _ => [255, 255, 255, 255],
+            })
+            .collect();
+        let config = ScalerConfig::default();
+        let blend_map = compute_blend_map(&source, width, height, &config);
+        let table = blend_table(factor, config.blend_weights);
+
+        let mut scaled = vec![0; width * height * factor * factor * 4];
+        let mut block = vec![0; factor * factor * 4];
+        for y in 0..height {
+            for x in 0..width {
+                let kernel = Kernel4x4::read(&source, width, height, x, y);
+                assert_eq!(kernel.f, source[(y * width + x) * 4..][..4]);
+                blend_pixel(
+                    &kernel,
+                    blend_map[y * width + x],
+                    factor,
+                    &config,
+                    &table,
+                    &mut block,
+                );
+                for (row, block_row) in block.chunks_exact(factor * 4).enumerate() {
+                    let start = ((y * factor + row) * width * factor + x * factor) * 4;
+                    scaled[start..start + factor * 4].copy_from_slice(block_row);
+                }
+            }
+        }
+
+        let expected = crate::scale_rgba_with_config(&source, width, height, factor, &config);
+        assert_eq!(scaled, expected);
+    }
+
+    #[test]
+    fn custom_tables_replace_the_built_in_ones() {
+        let (width, height, factor) = (12, 10, 3);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| match (i % width) * 2 < i / width + 4 {
+                true => [200, 40, 40, 255],
+                false => [255, 255, 255, 255],
+            })
+            .collect();
+        let config = ScalerConfig::default();
+
+        let classic = blend_table(factor, BlendWeights::Classic);
+        let scaled = crate::scale_rgba_with_config(&source, width, height, factor, &config);
+        assert_eq!(
+            scale_rgba_with_table(&source, width, height, factor, &config, &classic),
+            scaled
+        );
+
+        // a table that never blends gives nearest neighbour scaling
+        let none = BlendTable {
+            line_shallow: &[],
+            line_steep: &[],
+            line_steep_and_shallow: &[],
+            line_diagonal: &[],
+            corner: &[],
+        };
+        let nearest = scale_rgba_with_table(&source, width, height, factor, &config, &none);
+        assert_ne!(nearest, scaled);
+        for (i, px) in nearest.chunks_exact(4).enumerate() {
+            let (x, y) = (i % (width * factor) / factor, i / (width * factor) / factor);
+            assert_eq!(px, &source[(y * width + x) * 4..][..4]);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Which version of the algorithm this build runs, for recording where scaled images came from.
+use std::fmt;
+
+/// How colour distances are compared when deciding which way edges run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DistanceMode {
+    /// Distances are summed and compared as 32-bit floats, as in the original xBRZ.
+    Float,
+    /// Distances are rounded to the nearest 1/128 and compared as integers, with the
+    /// `fixed_point` feature.
+    FixedPoint,
+}
+
+/// How finely the lookup table from RGB differences to YCbCr distances is indexed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LutPrecision {
+    /// Differences are indexed by their top 5 bits per channel, as in the original xBRZ.
+    Small,
+    /// Differences are indexed by all 8 bits per channel, with the `large_lut` feature.
+    Large,
+}
+
+/// The version of the algorithm run by this build of the crate, as given by [`version_info`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct VersionInfo {
+    /// The version of xBRZ that the algorithm is ported from.
+    pub algorithm: &'static str,
+    /// The version of this crate.
+    pub crate_version: &'static str,
+    pub distance: DistanceMode,
+    pub lut: LutPrecision,
+}
+
+impl fmt::Display for VersionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let distance = match self.distance {
+            DistanceMode::Float => "float",
+            DistanceMode::FixedPoint => "fixed-point",
+        };
+        let lut = match self.lut {
+            LutPrecision::Small => "5-bit",
+            LutPrecision::Large => "8-bit",
+        };
+        write!(
+            f,
+            "xBRZ {} (xbrz-rs {}, {distance} distances, {lut} LUT)",
+            self.algorithm, self.crate_version
+        )
+    }
+}
+
+/// The version of the algorithm run by this build of the crate, and the features that change its
+/// output.
+///
+/// For a given source image, factor and [`ScalerConfig`](crate::ScalerConfig), every build with
+/// the same `VersionInfo` gives exactly the same scaled image at every factor, on every platform
+/// and whether or not the `rayon` feature splits the image between threads. A build that differs
+/// in any field may scale some pixels differently, so asset pipelines can store this alongside
+/// scaled images, or hash its [`Display`](fmt::Display) form into their cache keys, and scale the
+/// images again when it changes. Features that only change how fast images are scaled, such as
+/// `static_lut`, are not included.
+pub const fn version_info() -> VersionInfo {
+    VersionInfo {
+        algorithm: "1.8",
+        crate_version: env!("CARGO_PKG_VERSION"),
+        distance: if cfg!(feature = "fixed_point") {
+            DistanceMode::FixedPoint
+        } else {
+            DistanceMode::Float
+        },
+        lut: if cfg!(feature = "large_lut") {
+            LutPrecision::Large
+        } else {
+            LutPrecision::Small
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_info_follows_the_features() {
+        let info = version_info();
+        assert_eq!(info.algorithm, "1.8");
+        assert_eq!(info.crate_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            info.distance == DistanceMode::FixedPoint,
+            cfg!(feature = "fixed_point")
+        );
+        assert_eq!(info.lut == LutPrecision::Large, cfg!(feature = "large_lut"));
+
+        let shown = info.to_string();
+        assert!(shown.starts_with("xBRZ 1.8 (xbrz-rs "), "{shown}");
+        let with_lut = |lut| VersionInfo { lut, ..info }.to_string();
+        assert_ne!(with_lut(LutPrecision::Small), with_lut(LutPrecision::Large));
+    }
+
+    /// Output that changes without the algorithm version changing breaks the promise made by
+    /// [`version_info`]. If this fails because of an intended change to the output, bump
+    /// `algorithm` along with the hash.
+    #[test]
+    fn output_is_pinned_to_the_algorithm_version() {
+        let (width, height) = (19, 13);
+        let source: Vec<u8> = (0..width * height)
+            .flat_map(|i| {
+                let (x, y) = (i % width, i / width);
+                match (x + 2 * y) % 7 {
+                    _ if x * 3 == y * 4 => [250, 250, 250, 255],
+                    0 | 1 => [40, 90, 200, 255],
+                    2 => [200, 40, 40, 255],
+                    3 => [30, 160, 60, 128],
+                    4 => [0, 0, 0, 255],
+                    _ => [0, 0, 0, 0],
+                }
+            })
+            .collect();
+        // FNV-1a over the image scaled by every factor
+        let hash = (1..=6)
+            .flat_map(|factor| crate::scale_rgba(&source, width, height, factor))
+            .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+                (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+            });
+
+        let info = version_info();
+        if (info.distance, info.lut) == (DistanceMode::Float, LutPrecision::Small) {
+            assert_eq!(
+                (info.algorithm, hash),
+                ("1.8", 0xa0d9_b6e8_6bc6_7bb0),
+                "{hash:#018x}"
+            );
+        }
+    }
+}
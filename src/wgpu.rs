@@ -0,0 +1,114 @@
+//! Helpers for uploading scaled images as [wgpu](https://docs.rs/wgpu) textures.
+//!
+//! Enabled by the `wgpu` feature.
+use ::wgpu::{
+    Device, Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Queue, Texture, TextureAspect,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    COPY_BYTES_PER_ROW_ALIGNMENT,
+};
+
+use crate::{ScaleArena, ScalerConfig};
+
+/// Use the xBRZ algorithm to scale up an RGBA image by an integer factor, and upload the result
+/// as a new wgpu [`Texture`].
+///
+/// The texture is in the `Rgba8UnormSrgb` format, and can be bound in shaders and copied into.
+/// The scaled image is written with each row padded out to wgpu's row alignment, so that it can be
+/// copied to the GPU as it is, without wgpu repacking it first.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `src_width` or `src_height` is 0, as textures can't be empty, or if `factor` is not one of
+/// 1, 2, 3, 4, 5 or 6. A scaled image larger than the device allows is reported as a wgpu
+/// validation error.
+pub fn scale_to_texture(
+    device: &Device,
+    queue: &Queue,
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+) -> Texture {
+    scale_to_texture_with_config(
+        device,
+        queue,
+        source,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an RGBA image by an integer factor, with custom tuning
+/// parameters, and upload the result as a new wgpu [`Texture`].
+///
+/// This is the same as [`scale_to_texture`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height * 4`,
+/// if `src_width` or `src_height` is 0, or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_to_texture_with_config(
+    device: &Device,
+    queue: &Queue,
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Texture {
+    assert!(
+        src_width > 0 && src_height > 0,
+        "can't make a texture from an empty image"
+    );
+    let (dst_width, dst_height) = (src_width * factor, src_height * factor);
+    let alignment = COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+    let stride = (dst_width * 4).next_multiple_of(alignment);
+
+    let mut padded = vec![0; stride * dst_height];
+    crate::scale_rgba_into_with_stride(
+        source,
+        src_width,
+        src_height,
+        factor,
+        config,
+        &mut padded,
+        stride,
+        &mut ScaleArena::with_max_width(src_width),
+    );
+
+    let size = Extent3d {
+        width: dst_width as u32,
+        height: dst_height as u32,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&TextureDescriptor {
+        label: Some("xbrz scaled texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        &padded,
+        ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(stride as u32),
+            rows_per_image: Some(dst_height as u32),
+        },
+        size,
+    );
+    texture
+}
@@ -0,0 +1,180 @@
+use crate::oob_reader::OobReaderDuplicate;
+use crate::pixel::Xrgb8888;
+use crate::{scale_pixels, ScalerConfig};
+
+/// Where a scaled image goes in a window that it doesn't exactly fill.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum WindowAlign {
+    /// In the middle of the window, with an equal margin on each side.
+    #[default]
+    Center,
+    /// In the top left corner of the window.
+    TopLeft,
+}
+
+/// How a scaled image is placed in a window buffer, for [`scale_into_window_buffer_with_placement`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct WindowPlacement {
+    pub align: WindowAlign,
+    /// The 0RGB colour to fill the rest of the window with, or `None` to leave it as it is.
+    pub letterbox: Option<u32>,
+}
+
+impl Default for WindowPlacement {
+    /// Centred, with black bars.
+    fn default() -> Self {
+        Self {
+            align: WindowAlign::Center,
+            letterbox: Some(0),
+        }
+    }
+}
+
+/// Use the xBRZ algorithm to scale up an image of 0RGB pixels by an integer factor, drawing it
+/// centred in the buffer of a window, with black bars around it.
+///
+/// Both the `source` and the `window` buffer hold one `u32` per pixel, with the red, green and blue
+/// channels in the low 24 bits, as used by minifb and softbuffer. `window_pitch` is the number of
+/// pixels from the start of one row of the window to the start of the next, which is the width of
+/// the window for both of those crates. A scaled image larger than the window is cropped.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height`,
+/// if `factor` is not one of 1, 2, 3, 4, 5 or 6, or if `window_pitch` is 0.
+pub fn scale_into_window_buffer(
+    source: &[u32],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    window: &mut [u32],
+    window_pitch: usize,
+) {
+    scale_into_window_buffer_with_placement(
+        source,
+        src_width,
+        src_height,
+        factor,
+        window,
+        window_pitch,
+        &WindowPlacement::default(),
+    )
+}
+
+/// Use the xBRZ algorithm to scale up an image of 0RGB pixels by an integer factor, drawing it
+/// in the buffer of a window as given by `placement`.
+///
+/// This is the same as [`scale_into_window_buffer`], except that the scaled image can be aligned
+/// to the top left of the window, and the rest of the window can be filled with another colour,
+/// or left alone.
+///
+/// # Panics
+///
+/// Panics if the `source` slice length is not exactly equal to `src_width * src_height`,
+/// if `factor` is not one of 1, 2, 3, 4, 5 or 6, or if `window_pitch` is 0.
+pub fn scale_into_window_buffer_with_placement(
+    source: &[u32],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    window: &mut [u32],
+    window_pitch: usize,
+    placement: &WindowPlacement,
+) {
+    assert_eq!(source.len(), src_width * src_height);
+    assert_ne!(window_pitch, 0, "window pitch must not be 0");
+
+    let pixels: Vec<Xrgb8888> = source.iter().map(|&p| (p & 0x00FF_FFFF).into()).collect();
+    let scaled = scale_pixels::<Xrgb8888, OobReaderDuplicate<Xrgb8888>, _>(
+        &pixels,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+        None,
+        None,
+    );
+
+    let (dst_width, dst_height) = (src_width * factor, src_height * factor);
+    let window_height = window.len() / window_pitch;
+    // the margin before the image in the window, or the part of the image cropped off if it doesn't fit
+    let (x, crop_x, y, crop_y) = match placement.align {
+        WindowAlign::Center => (
+            window_pitch.saturating_sub(dst_width) / 2,
+            dst_width.saturating_sub(window_pitch) / 2,
+            window_height.saturating_sub(dst_height) / 2,
+            dst_height.saturating_sub(window_height) / 2,
+        ),
+        WindowAlign::TopLeft => (0, 0, 0, 0),
+    };
+    let visible_width = (dst_width - crop_x).min(window_pitch - x);
+    let visible_height = (dst_height - crop_y).min(window_height - y);
+
+    for (row, window_row) in window.chunks_exact_mut(window_pitch).enumerate() {
+        let Some(src_row) = row.checked_sub(y).filter(|&r| r < visible_height) else {
+            if let Some(color) = placement.letterbox {
+                window_row.fill(color);
+            }
+            continue;
+        };
+        let start = (src_row + crop_y) * dst_width + crop_x;
+        let (before, rest) = window_row.split_at_mut(x);
+        let (image, after) = rest.split_at_mut(visible_width);
+        for (dst, &src) in image.iter_mut().zip(&scaled[start..start + visible_width]) {
+            *dst = src.into();
+        }
+        if let Some(color) = placement.letterbox {
+            before.fill(color);
+            after.fill(color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled_image_is_letterboxed_and_cropped() {
+        let (width, height): (usize, usize) = (3, 2);
+        let source: Vec<u32> = (0..(width * height) as u32)
+            .map(|i| i * 0x0012_3456)
+            .collect();
+        let scaled: Vec<u32> = crate::scale(
+            &source
+                .iter()
+                .flat_map(|p| p.to_ne_bytes())
+                .collect::<Vec<_>>(),
+            crate::PixelFormat::Xrgb8888,
+            width,
+            height,
+            2,
+        )
+        .chunks_exact(4)
+        .map(|p| u32::from_ne_bytes(p.try_into().unwrap()))
+        .collect();
+
+        // a 6x4 image in the middle of a 10x7 window, with an odd margin left over at the bottom
+        let mut window = vec![0xFFFF_FFFF; 10 * 7];
+        scale_into_window_buffer(&source, width, height, 2, &mut window, 10);
+        for (i, &px) in window.iter().enumerate() {
+            let (x, y) = (i % 10, i / 10);
+            let expected = match (x.checked_sub(2), y.checked_sub(1)) {
+                (Some(sx), Some(sy)) if sx < 6 && sy < 4 => scaled[sy * 6 + sx],
+                _ => 0,
+            };
+            assert_eq!(px, expected, "({x}, {y})");
+        }
+
+        // the middle of the image in a smaller window, leaving the pixels past the last whole row alone
+        let mut window = vec![0xFFFF_FFFF; 4 * 2 + 1];
+        let placement = WindowPlacement {
+            align: WindowAlign::Center,
+            letterbox: None,
+        };
+        let (w, h, f) = (width, height, 2);
+        scale_into_window_buffer_with_placement(&source, w, h, f, &mut window, 4, &placement);
+        let expected: Vec<u32> = [&scaled[7..11], &scaled[13..17], &[0xFFFF_FFFF]].concat();
+        assert_eq!(window, expected);
+    }
+}
@@ -1,134 +1,274 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::OnceLock;
+
 use bytemuck::must_cast;
-use parking_lot::Once;
 
 use crate::pixel::Pixel;
 
-pub(crate) enum YCbCrLookup {
-    IDiff555(Box<[f32]>),
-    IDiff888(Box<[f32]>),
-}
+mod distance;
 
-// SAFETY: Only written to once by the closure in instance(), which is mediated by a parking_lot::Once.
-static mut LOOKUP_INSTANCE: Option<YCbCrLookup> = None;
-static LOOKUP_LOCK: Once = Once::new();
+pub(crate) use distance::{dist_ycbcr, to_fixed, FIXED_SHIFT};
+use distance::{dist_ycbcr_with, small_table_dist, REC_2020, REC_601, REC_709};
 
-#[inline]
-fn dist_ycbcr(r_diff: i16, g_diff: i16, b_diff: i16) -> f64 {
-    let r_diff = r_diff as f64;
-    let g_diff = g_diff as f64;
-    let b_diff = b_diff as f64;
+// the small Rec.2020 tables, as generated by the build script
+#[cfg(feature = "static_lut")]
+include!(concat!(env!("OUT_DIR"), "/small_lut.rs"));
 
-    // using Rec.2020 RGB -> YCbCr conversion
-    const K_B: f64 = 0.0593;
-    const K_R: f64 = 0.2627;
-    const K_G: f64 = 1.0 - K_B - K_R;
+/// The YCbCr matrices that colour distances can be measured in. They differ in how much red, green
+/// and blue count towards the brightness of a colour, and so in which colours are found to be alike.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum YCbCrMatrix {
+    /// ITU-R BT.601, the matrix of standard definition video, as output by older consoles.
+    Rec601,
+    /// ITU-R BT.709, the matrix of HD video and sRGB desktop content.
+    Rec709,
+    /// ITU-R BT.2020, the matrix of UHD video, which is what this crate has always used.
+    #[default]
+    Rec2020,
+}
+
+impl YCbCrMatrix {
+    fn luma_weights(self) -> [f64; 2] {
+        match self {
+            YCbCrMatrix::Rec601 => REC_601,
+            YCbCrMatrix::Rec709 => REC_709,
+            YCbCrMatrix::Rec2020 => REC_2020,
+        }
+    }
+}
+
+/// A table of the perceptual distances between colours, which the scaler uses to decide which
+/// pixels are the same colour and where edges run.
+///
+/// The scaling functions share one table for each [`YCbCrMatrix`] for the whole process, built the
+/// first time it is needed. A table built with [`YCbCrLookup::with_matrix`], or one of the Rec.2020
+/// tables built with [`YCbCrLookup::new_small`], [`YCbCrLookup::new_large`] or their fixed-point
+/// versions, can instead be passed explicitly to [`scale_rgba_pure`](crate::scale_rgba_pure).
+pub struct YCbCrLookup {
+    table: Table,
+    matrix: YCbCrMatrix,
+}
 
-    const SCALE_B: f64 = 0.5 / (1.0 - K_B);
-    const SCALE_R: f64 = 0.5 / (1.0 - K_R);
+/// The tables are borrowed when they are embedded in the binary by the `static_lut` feature.
+enum Table {
+    IDiff555(Cow<'static, [f32]>),
+    IDiff888(Cow<'static, [f32]>),
+    Fixed555(Cow<'static, [u16]>),
+    Fixed888(Cow<'static, [u16]>),
+}
 
-    let y = K_R * r_diff + K_G * g_diff + K_B * b_diff;
-    let c_b = SCALE_B * (b_diff - y);
-    let c_r = SCALE_R * (r_diff - y);
+impl fmt::Debug for YCbCrLookup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits_per_channel = match self.table {
+            Table::IDiff555(_) | Table::Fixed555(_) => 5,
+            Table::IDiff888(_) | Table::Fixed888(_) => 8,
+        };
+        f.debug_struct("YCbCrLookup")
+            .field("matrix", &self.matrix)
+            .field("bits_per_channel", &bits_per_channel)
+            .field("fixed_point", &self.is_fixed_point())
+            .finish_non_exhaustive()
+    }
+}
 
-    (y * y + c_b * c_b + c_r * c_r).sqrt()
+/// Convert a fixed-point distance back to a float.
+#[inline]
+fn from_fixed(d: u32) -> f32 {
+    d as f32 / (1 << FIXED_SHIFT) as f32
 }
 
 impl YCbCrLookup {
+    /// The lookup table used for scaling with `matrix`, chosen by the `large_lut` and `fixed_point`
+    /// features. Each matrix has a table of its own, built the first time it is needed, which can
+    /// be shared by any number of threads after that.
     #[inline]
-    pub(crate) fn instance() -> &'static Self {
-        Self::initialise();
+    pub(crate) fn instance(matrix: YCbCrMatrix) -> &'static Self {
+        static LOOKUPS: [OnceLock<YCbCrLookup>; 3] = [const { OnceLock::new() }; 3];
+        LOOKUPS[matrix as usize].get_or_init(|| Self::with_matrix(matrix))
+    }
 
-        unsafe { Self::instance_unchecked() }
+    /// Build the table that the scaling functions use for configs with the given
+    /// [`ycbcr_matrix`](crate::ScalerConfig::ycbcr_matrix). Its size and precision are chosen by
+    /// the `large_lut` and `fixed_point` features, as for [`YCbCrLookup::new_small`] and the other
+    /// constructors.
+    pub fn with_matrix(matrix: YCbCrMatrix) -> Self {
+        Self::build(
+            matrix,
+            cfg!(feature = "large_lut"),
+            cfg!(feature = "fixed_point"),
+        )
     }
 
-    #[inline]
-    pub(crate) fn initialise() {
-        LOOKUP_LOCK.call_once(|| unsafe {
-            #[cfg(feature = "large_lut")]
-            {
-                LOOKUP_INSTANCE = Some(Self::new_large());
+    /// Build a Rec.2020 table with 5 bits per channel of precision, 128 KiB in size.
+    /// This is the table used by the scaling functions unless the `large_lut` feature is enabled.
+    ///
+    /// With the `static_lut` feature, the table is embedded in the binary instead, so this takes
+    /// no time at all.
+    pub fn new_small() -> Self {
+        Self::build(YCbCrMatrix::Rec2020, false, false)
+    }
+
+    /// Build a Rec.2020 table with 8 bits per channel of precision, 64 MiB in size.
+    /// This is the table used by the scaling functions when the `large_lut` feature is enabled.
+    pub fn new_large() -> Self {
+        Self::build(YCbCrMatrix::Rec2020, true, false)
+    }
+
+    /// Build a Rec.2020 table with 5 bits per channel of precision that holds 16-bit fixed-point
+    /// distances, 64 KiB in size. This is the table used by the scaling functions when the
+    /// `fixed_point` feature is enabled.
+    ///
+    /// Distances are rounded to the nearest 1/128, and edges are found by comparing them with
+    /// integer arithmetic rather than floats. The scaled images differ from those of the float
+    /// tables only where two directions are within rounding of each other.
+    pub fn new_small_fixed() -> Self {
+        Self::build(YCbCrMatrix::Rec2020, false, true)
+    }
+
+    /// Build a Rec.2020 table with 8 bits per channel of precision that holds 16-bit fixed-point
+    /// distances, 32 MiB in size. This is the table used by the scaling functions when both the
+    /// `large_lut` and `fixed_point` features are enabled.
+    pub fn new_large_fixed() -> Self {
+        Self::build(YCbCrMatrix::Rec2020, true, true)
+    }
+
+    fn build(matrix: YCbCrMatrix, large: bool, fixed_point: bool) -> Self {
+        let weights = matrix.luma_weights();
+        let float = |d: f64| d as f32;
+        let fixed = |d: f64| to_fixed(d as f32) as u16;
+        let table = match (large, fixed_point) {
+            #[cfg(feature = "static_lut")]
+            (false, false) if matrix == YCbCrMatrix::Rec2020 => {
+                Table::IDiff555(Cow::Borrowed(&SMALL_LUT))
             }
-            #[cfg(not(feature = "large_lut"))]
-            {
-                LOOKUP_INSTANCE = Some(Self::new_small());
+            #[cfg(feature = "static_lut")]
+            (false, true) if matrix == YCbCrMatrix::Rec2020 => {
+                Table::Fixed555(Cow::Borrowed(&SMALL_FIXED_LUT))
             }
-        });
+            (false, false) => Table::IDiff555(small_table(weights, float)),
+            (true, false) => Table::IDiff888(large_table(weights, float)),
+            (false, true) => Table::Fixed555(small_table(weights, fixed)),
+            (true, true) => Table::Fixed888(large_table(weights, fixed)),
+        };
+        Self { table, matrix }
     }
 
-    #[inline]
-    pub(crate) unsafe fn instance_unchecked() -> &'static Self {
-        unsafe { LOOKUP_INSTANCE.as_ref().unwrap_unchecked() }
+    /// The matrix that the distances in the table are measured in.
+    pub fn matrix(&self) -> YCbCrMatrix {
+        self.matrix
     }
 
-    pub(crate) fn instance_is_initialised() -> bool {
-        unsafe { LOOKUP_INSTANCE.is_some() }
+    /// Whether distances are held in fixed point, so that edges are found with integer arithmetic.
+    #[inline]
+    pub(crate) fn is_fixed_point(&self) -> bool {
+        matches!(self.table, Table::Fixed555(_) | Table::Fixed888(_))
     }
 
-    pub(crate) fn new_small() -> Self {
-        let mut lookup = Vec::with_capacity(0x8000);
-
-        for i in 0..0x8000 {
-            let r_diff = must_cast::<_, i8>((((i >> 10) & 0x1F) << 3) as u8) as i16 * 2;
-            let g_diff = must_cast::<_, i8>((((i >> 5) & 0x1F) << 3) as u8) as i16 * 2;
-            let b_diff = must_cast::<_, i8>(((i & 0x1F) << 3) as u8) as i16 * 2;
+    #[inline]
+    pub(crate) fn dist_rgb(&self, rgb1: [u8; 3], rgb2: [u8; 3]) -> f32 {
+        let (index_555, index_888) = indices(rgb1, rgb2);
+        match &self.table {
+            Table::IDiff555(lookup) => lookup[index_555],
+            Table::IDiff888(lookup) => lookup[index_888],
+            Table::Fixed555(lookup) => from_fixed(lookup[index_555] as u32),
+            Table::Fixed888(lookup) => from_fixed(lookup[index_888] as u32),
+        }
+    }
 
-            lookup.push(dist_ycbcr(r_diff, g_diff, b_diff) as f32);
+    /// [`dist_rgb`](Self::dist_rgb) as a fixed-point number with [`FIXED_SHIFT`] fractional bits.
+    #[inline]
+    pub(crate) fn dist_rgb_fixed(&self, rgb1: [u8; 3], rgb2: [u8; 3]) -> u32 {
+        let (index_555, index_888) = indices(rgb1, rgb2);
+        match &self.table {
+            Table::IDiff555(lookup) => to_fixed(lookup[index_555]),
+            Table::IDiff888(lookup) => to_fixed(lookup[index_888]),
+            Table::Fixed555(lookup) => lookup[index_555] as u32,
+            Table::Fixed888(lookup) => lookup[index_888] as u32,
         }
+    }
+
+    #[inline]
+    pub(crate) fn dist<P: Pixel>(&self, pix1: P, pix2: P) -> f32 {
+        let d = self.dist_rgb(pix1.to_rgb(), pix2.to_rgb());
+        weigh_by_alpha(pix1.alpha(), pix2.alpha(), d)
+    }
 
-        Self::IDiff555(lookup.into_boxed_slice())
+    /// [`dist`](Self::dist) as a fixed-point number with [`FIXED_SHIFT`] fractional bits.
+    #[inline]
+    pub(crate) fn dist_fixed<P: Pixel>(&self, pix1: P, pix2: P) -> u32 {
+        let d = self.dist_rgb_fixed(pix1.to_rgb(), pix2.to_rgb());
+        weigh_by_alpha_fixed(pix1.alpha(), pix2.alpha(), d)
     }
+}
+
+/// The indices of the distance between two colours in the 5 and 8 bit per channel tables.
+#[inline]
+fn indices(rgb1: [u8; 3], rgb2: [u8; 3]) -> (usize, usize) {
+    let [r1, g1, b1] = rgb1;
+    let [r2, g2, b2] = rgb2;
+    let r_part: u8 = must_cast((((r1 as i16) - (r2 as i16)) / 2) as i8);
+    let g_part: u8 = must_cast((((g1 as i16) - (g2 as i16)) / 2) as i8);
+    let b_part: u8 = must_cast((((b1 as i16) - (b2 as i16)) / 2) as i8);
 
-    pub(crate) fn new_large() -> Self {
-        let mut lookup = Vec::with_capacity(0x100_0000);
+    let index_555 = (((r_part as usize) >> 3) << 10)
+        | (((g_part as usize) >> 3) << 5)
+        | ((b_part as usize) >> 3);
+    let index_888 = ((r_part as usize) << 16) | ((g_part as usize) << 8) | (b_part as usize);
+    (index_555, index_888)
+}
+
+/// Build a table with 5 bits per channel for the matrix with the given luma weights, indexed by
+/// the halved differences between two colours.
+fn small_table<T: Clone>(weights: [f64; 2], convert: impl Fn(f64) -> T) -> Cow<'static, [T]> {
+    (0..0x8000)
+        .map(|i| convert(small_table_dist(weights, i)))
+        .collect()
+}
 
-        for i in 0..0x100_0000 {
+/// Build a table with 8 bits per channel for the matrix with the given luma weights, indexed by
+/// the halved differences between two colours.
+fn large_table<T: Clone>(weights: [f64; 2], convert: impl Fn(f64) -> T) -> Cow<'static, [T]> {
+    (0..0x100_0000)
+        .map(|i| {
             let r_diff = must_cast::<_, i8>(((i >> 16) & 0xFF) as u8) as i16 * 2;
             let g_diff = must_cast::<_, i8>(((i >> 8) & 0xFF) as u8) as i16 * 2;
             let b_diff = must_cast::<_, i8>((i & 0xFF) as u8) as i16 * 2;
 
-            lookup.push(dist_ycbcr(r_diff, g_diff, b_diff) as f32);
-        }
-
-        Self::IDiff888(lookup.into_boxed_slice())
-    }
+            convert(dist_ycbcr_with(weights, r_diff, g_diff, b_diff))
+        })
+        .collect()
+}
 
-    #[inline]
-    pub(crate) fn dist_rgb(&self, rgb1: [u8; 3], rgb2: [u8; 3]) -> f32 {
-        let [r1, g1, b1] = rgb1;
-        let [r2, g2, b2] = rgb2;
-        let r_part: u8 = must_cast((((r1 as i16) - (r2 as i16)) / 2) as i8);
-        let g_part: u8 = must_cast((((g1 as i16) - (g2 as i16)) / 2) as i8);
-        let b_part: u8 = must_cast((((b1 as i16) - (b2 as i16)) / 2) as i8);
+/// Combine the distance `d` between the colours of two pixels with the difference in their alpha,
+/// so that the colour of a mostly transparent pixel counts for little.
+#[inline]
+pub(crate) fn weigh_by_alpha(alpha1: u8, alpha2: u8, d: f32) -> f32 {
+    let a1 = alpha1 as f32 / u8::MAX as f32;
+    let a2 = alpha2 as f32 / u8::MAX as f32;
 
-        match self {
-            YCbCrLookup::IDiff555(lookup) => {
-                lookup[(((r_part as usize) >> 3) << 10)
-                    | (((g_part as usize) >> 3) << 5)
-                    | ((b_part as usize) >> 3)]
-            }
-            YCbCrLookup::IDiff888(lookup) => {
-                lookup[((r_part as usize) << 16) | ((g_part as usize) << 8) | (b_part as usize)]
-            }
-        }
+    if a1 < a2 {
+        a1 * d + 255.0 * (a2 - a1)
+    } else {
+        a2 * d + 255.0 * (a1 - a2)
     }
+}
 
-    pub(crate) fn dist<P: Pixel>(&self, pix1: P, pix2: P) -> f32 {
-        let a1 = pix1.alpha() as f32 / u8::MAX as f32;
-        let a2 = pix2.alpha() as f32 / u8::MAX as f32;
-
-        let d = self.dist_rgb(pix1.to_rgb(), pix2.to_rgb());
-        if a1 < a2 {
-            a1 * d + 255.0 * (a2 - a1)
-        } else {
-            a2 * d + 255.0 * (a1 - a2)
-        }
-    }
+/// [`weigh_by_alpha`] for fixed-point distances. Since the alphas are out of 255, the difference in
+/// alpha counts for exactly that many whole steps of distance.
+#[inline]
+pub(crate) fn weigh_by_alpha_fixed(alpha1: u8, alpha2: u8, d: u32) -> u32 {
+    let (low, high) = (alpha1.min(alpha2) as u32, alpha1.max(alpha2) as u32);
+    (low * d + 127) / 255 + ((high - low) << FIXED_SHIFT)
 }
 
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
+
     use crate::pixel::Rgb8;
-    use crate::ycbcr_lookup::{dist_ycbcr, YCbCrLookup};
+    use crate::ycbcr_lookup::*;
 
     fn test_lut(lut: &YCbCrLookup, rgb1: (u8, u8, u8), rgb2: (u8, u8, u8)) {
         let (r1, g1, b1) = rgb1;
@@ -142,31 +282,96 @@ mod test {
         assert_eq!(dist, lut_dist)
     }
 
+    fn colour_pairs(step: usize) -> impl Iterator<Item = ([u8; 3], [u8; 3])> {
+        let colours = move || {
+            (0..=0xFFu8).step_by(step).flat_map(move |r| {
+                (0..=0xFFu8)
+                    .step_by(step)
+                    .flat_map(move |g| (0..=0xFFu8).step_by(step).map(move |b| [r, g, b]))
+            })
+        };
+        colours().flat_map(move |rgb1| colours().map(move |rgb2| (rgb1, rgb2)))
+    }
+
     fn test_whole_lut(lut: &YCbCrLookup) {
-        for r1 in (0..=0xFF).step_by(16) {
-            for g1 in (0..=0xFF).step_by(16) {
-                for b1 in (0..=0xFF).step_by(16) {
-                    for r2 in (0..=0xFF).step_by(16) {
-                        for g2 in (0..=0xFF).step_by(16) {
-                            for b2 in (0..=0xFF).step_by(16) {
-                                test_lut(lut, (r1, g1, b1), (r2, g2, b2))
-                            }
-                        }
-                    }
-                }
-            }
+        for ([r1, g1, b1], [r2, g2, b2]) in colour_pairs(16) {
+            test_lut(lut, (r1, g1, b1), (r2, g2, b2))
         }
     }
 
     #[test]
+    #[cfg_attr(miri, ignore = "the large LUT takes far too long to build under Miri")]
     fn test_large_lut() {
-        let lookup = YCbCrLookup::new_large();
-        test_whole_lut(&lookup);
+        test_whole_lut(&YCbCrLookup::new_large());
     }
 
     #[test]
     fn test_small_lut() {
-        let lookup = YCbCrLookup::new_small();
-        test_whole_lut(&lookup);
+        test_whole_lut(&YCbCrLookup::new_small());
+    }
+
+    #[test]
+    fn each_matrix_has_its_own_lut() {
+        for matrix in [
+            YCbCrMatrix::Rec601,
+            YCbCrMatrix::Rec709,
+            YCbCrMatrix::Rec2020,
+        ] {
+            let shared = YCbCrLookup::instance(matrix);
+            assert_eq!(shared.matrix(), matrix);
+            assert!(std::ptr::eq(shared, YCbCrLookup::instance(matrix)));
+
+            let lut = YCbCrLookup::build(matrix, false, false);
+            for ([r1, g1, b1], [r2, g2, b2]) in colour_pairs(48) {
+                let dist = dist_ycbcr_with(
+                    matrix.luma_weights(),
+                    r1 as i16 - r2 as i16,
+                    g1 as i16 - g2 as i16,
+                    b1 as i16 - b2 as i16,
+                );
+                assert_eq!(lut.dist_rgb([r1, g1, b1], [r2, g2, b2]), dist as f32);
+            }
+        }
+
+        // the matrices weigh green differently, so pure green is a different distance from black
+        let green = |matrix| YCbCrLookup::instance(matrix).dist_rgb([0, 255, 0], [0, 0, 0]);
+        assert!(green(YCbCrMatrix::Rec601) < green(YCbCrMatrix::Rec709));
+        assert_ne!(green(YCbCrMatrix::Rec709), green(YCbCrMatrix::Rec2020));
+    }
+
+    #[cfg(feature = "static_lut")]
+    #[test]
+    fn embedded_luts_match_computed_luts() {
+        assert_eq!(SMALL_LUT[..], *small_table(REC_2020, |d| d as f32));
+        assert_eq!(
+            SMALL_FIXED_LUT[..],
+            *small_table(REC_2020, |d| to_fixed(d as f32) as u16)
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore = "the large LUT takes far too long to build under Miri")]
+    fn fixed_point_luts_round_the_float_luts() {
+        for (float, fixed) in [
+            (YCbCrLookup::new_small(), YCbCrLookup::new_small_fixed()),
+            (YCbCrLookup::new_large(), YCbCrLookup::new_large_fixed()),
+        ] {
+            assert!(fixed.is_fixed_point() && !float.is_fixed_point());
+            for (rgb1, rgb2) in colour_pairs(24) {
+                let d = float.dist_rgb(rgb1, rgb2);
+                assert_eq!(fixed.dist_rgb_fixed(rgb1, rgb2), to_fixed(d));
+                assert_eq!(float.dist_rgb_fixed(rgb1, rgb2), to_fixed(d));
+                assert!((fixed.dist_rgb(rgb1, rgb2) - d).abs() <= 0.5 / (1 << FIXED_SHIFT) as f32);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn fixed_point_alpha_weighting_rounds_float(alpha1: u8, alpha2: u8, d in 0u32..45000) {
+            let float = weigh_by_alpha(alpha1, alpha2, d as f32 / (1 << FIXED_SHIFT) as f32);
+            let fixed = weigh_by_alpha_fixed(alpha1, alpha2, d);
+            prop_assert!((fixed as f32 - float * (1 << FIXED_SHIFT) as f32).abs() <= 0.51);
+        }
     }
 }
@@ -0,0 +1,54 @@
+//! The colour distances held by the lookup tables. This file is also compiled into the build
+//! script, which embeds the small table when the `static_lut` feature is enabled, so it uses
+//! nothing from the rest of the crate.
+
+/// The number of fractional bits in a fixed-point distance.
+pub(crate) const FIXED_SHIFT: u32 = 7;
+
+/// Convert a distance to fixed point, rounding to the nearest step. Every distance in the tables is
+/// under 342, so they all fit in 16 bits.
+#[inline]
+pub(crate) fn to_fixed(d: f32) -> u32 {
+    (d * (1 << FIXED_SHIFT) as f32).round() as u32
+}
+
+/// The weights of red and blue in luma, `K_R` and `K_B`, in the YCbCr matrices of ITU-R BT.601,
+/// BT.709 and BT.2020.
+pub(crate) const REC_601: [f64; 2] = [0.299, 0.114];
+pub(crate) const REC_709: [f64; 2] = [0.2126, 0.0722];
+pub(crate) const REC_2020: [f64; 2] = [0.2627, 0.0593];
+
+/// The distance between two colours with the Rec.2020 matrix, given the differences between them.
+#[inline]
+pub(crate) fn dist_ycbcr(r_diff: i16, g_diff: i16, b_diff: i16) -> f64 {
+    dist_ycbcr_with(REC_2020, r_diff, g_diff, b_diff)
+}
+
+/// The distance between two colours with the matrix of the given luma weights, given the
+/// differences between them.
+#[inline]
+pub(crate) fn dist_ycbcr_with([k_r, k_b]: [f64; 2], r_diff: i16, g_diff: i16, b_diff: i16) -> f64 {
+    let r_diff = r_diff as f64;
+    let g_diff = g_diff as f64;
+    let b_diff = b_diff as f64;
+
+    let k_g = 1.0 - k_b - k_r;
+    let scale_b = 0.5 / (1.0 - k_b);
+    let scale_r = 0.5 / (1.0 - k_r);
+
+    let y = k_r * r_diff + k_g * g_diff + k_b * b_diff;
+    let c_b = scale_b * (b_diff - y);
+    let c_r = scale_r * (r_diff - y);
+
+    (y * y + c_b * c_b + c_r * c_r).sqrt()
+}
+
+/// The distance held at index `i` of the table with 5 bits per channel for the matrix with the
+/// given luma weights. The table is indexed by the halved differences between two colours.
+pub(crate) fn small_table_dist(weights: [f64; 2], i: usize) -> f64 {
+    let r_diff = ((((i >> 10) & 0x1F) << 3) as u8 as i8) as i16 * 2;
+    let g_diff = ((((i >> 5) & 0x1F) << 3) as u8 as i8) as i16 * 2;
+    let b_diff = (((i & 0x1F) << 3) as u8 as i8) as i16 * 2;
+
+    dist_ycbcr_with(weights, r_diff, g_diff, b_diff)
+}
@@ -0,0 +1,207 @@
+use crate::oob_reader::OobReaderDuplicate;
+use crate::pixel::{Pixel, Rgb8};
+use crate::{scale_pixels, ScalerConfig};
+
+/// How the two chroma planes of a 4:2:0 frame are laid out after the Y plane.
+#[derive(Copy, Clone)]
+enum ChromaLayout {
+    /// A U plane followed by a V plane, as in I420.
+    Planar,
+    /// A single plane of interleaved U and V samples, as in NV12.
+    Interleaved,
+}
+
+impl ChromaLayout {
+    /// The offsets of the U and V samples for chroma index `ci`, relative to the end of the Y plane.
+    fn offsets(self, ci: usize, chroma_len: usize) -> (usize, usize) {
+        match self {
+            ChromaLayout::Planar => (ci, chroma_len + ci),
+            ChromaLayout::Interleaved => (ci * 2, ci * 2 + 1),
+        }
+    }
+}
+
+/// The dimensions of the chroma planes of a 4:2:0 frame; odd sizes are rounded up.
+fn chroma_size(width: usize, height: usize) -> (usize, usize) {
+    (width.div_ceil(2), height.div_ceil(2))
+}
+
+fn frame_len(width: usize, height: usize) -> usize {
+    let (cw, ch) = chroma_size(width, height);
+    width * height + 2 * cw * ch
+}
+
+/// Use the xBRZ algorithm to scale up a planar YUV 4:2:0 video frame by an integer factor.
+///
+/// The `source` holds an I420 frame: the Y plane of `src_width` by `src_height` samples, followed by the U plane and
+/// then the V plane, each subsampled by 2 in both directions and rounded up. Samples are interpreted as
+/// BT.601 limited range, as produced by most video decoders.
+///
+/// A newly allocated I420 frame is returned, with dimensions `src_width * factor` by `src_height * factor`.
+/// Frames are treated as opaque, so their edges are extended outwards when scaling rather than faded out.
+///
+/// # Panics
+///
+/// Panics if the `source` slice is not exactly the length of an I420 frame of the given size,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_yuv420(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<u8> {
+    scale_yuv(source, src_width, src_height, factor, ChromaLayout::Planar)
+}
+
+/// Use the xBRZ algorithm to scale up a semi-planar NV12 video frame by an integer factor.
+///
+/// This is the same as [`scale_yuv420`], except that the U and V samples of `source` and of the returned frame
+/// are interleaved in a single plane following the Y plane, in U, V order.
+///
+/// # Panics
+///
+/// Panics if the `source` slice is not exactly the length of an NV12 frame of the given size,
+/// or if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_nv12(source: &[u8], src_width: usize, src_height: usize, factor: usize) -> Vec<u8> {
+    scale_yuv(
+        source,
+        src_width,
+        src_height,
+        factor,
+        ChromaLayout::Interleaved,
+    )
+}
+
+fn scale_yuv(
+    source: &[u8],
+    src_width: usize,
+    src_height: usize,
+    factor: usize,
+    layout: ChromaLayout,
+) -> Vec<u8> {
+    assert_eq!(source.len(), frame_len(src_width, src_height));
+
+    let pixels = yuv_to_pixels(source, src_width, src_height, layout);
+    let scaled = scale_pixels::<Rgb8, OobReaderDuplicate<Rgb8>, _>(
+        &pixels,
+        src_width,
+        src_height,
+        factor,
+        &ScalerConfig::default(),
+        None,
+        None,
+    );
+    pixels_to_yuv(&scaled, src_width * factor, src_height * factor, layout)
+}
+
+#[inline]
+fn clamp_u8(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+fn yuv_to_pixels(frame: &[u8], width: usize, height: usize, layout: ChromaLayout) -> Vec<Rgb8> {
+    let (cw, ch) = chroma_size(width, height);
+    let (y_plane, chroma) = frame.split_at(width * height);
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let (u, v) = layout.offsets((y / 2) * cw + x / 2, cw * ch);
+            let luma = 1.164 * (y_plane[y * width + x] as f32 - 16.0);
+            let (u, v) = (chroma[u] as f32 - 128.0, chroma[v] as f32 - 128.0);
+
+            pixels.push(Rgb8::from_rgba([
+                clamp_u8(luma + 1.596 * v),
+                clamp_u8(luma - 0.392 * u - 0.813 * v),
+                clamp_u8(luma + 2.017 * u),
+                u8::MAX,
+            ]));
+        }
+    }
+    pixels
+}
+
+fn pixels_to_yuv(pixels: &[Rgb8], width: usize, height: usize, layout: ChromaLayout) -> Vec<u8> {
+    let (cw, ch) = chroma_size(width, height);
+    let mut frame = vec![0u8; frame_len(width, height)];
+    let (y_plane, chroma) = frame.split_at_mut(width * height);
+
+    // chroma is averaged over each 2x2 block of pixels
+    let mut u_sum = vec![0f32; cw * ch];
+    let mut v_sum = vec![0f32; cw * ch];
+    let mut samples = vec![0u32; cw * ch];
+
+    for (i, px) in pixels.iter().enumerate() {
+        let [r, g, b] = px.to_rgb().map(f32::from);
+        y_plane[i] = clamp_u8(16.0 + 0.257 * r + 0.504 * g + 0.098 * b);
+
+        let (x, y) = (i % width, i / width);
+        let ci = (y / 2) * cw + x / 2;
+        u_sum[ci] += -0.148 * r - 0.291 * g + 0.439 * b;
+        v_sum[ci] += 0.439 * r - 0.368 * g - 0.071 * b;
+        samples[ci] += 1;
+    }
+
+    for ci in 0..cw * ch {
+        let n = samples[ci].max(1) as f32;
+        let (u, v) = layout.offsets(ci, cw * ch);
+        chroma[u] = clamp_u8(128.0 + u_sum[ci] / n);
+        chroma[v] = clamp_u8(128.0 + v_sum[ci] / n);
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An I420 frame with a bright diagonal stripe over a dark background.
+    fn test_frame(width: usize, height: usize) -> Vec<u8> {
+        let (cw, ch) = chroma_size(width, height);
+        let luma = (0..width * height).map(|i| match (i % width + i / width) % 7 < 2 {
+            true => 200,
+            false => 40,
+        });
+        let u = (0..cw * ch).map(|i| 90 + (i % 3) as u8 * 30);
+        let v = (0..cw * ch).map(|i| 160 - (i % 5) as u8 * 10);
+        luma.chain(u).chain(v).collect()
+    }
+
+    fn to_nv12(i420: &[u8], width: usize, height: usize) -> Vec<u8> {
+        let (cw, ch) = chroma_size(width, height);
+        let (y_plane, chroma) = i420.split_at(width * height);
+        let (u_plane, v_plane) = chroma.split_at(cw * ch);
+        let uv = u_plane.iter().zip(v_plane).flat_map(|(&u, &v)| [u, v]);
+        y_plane.iter().copied().chain(uv).collect()
+    }
+
+    #[test]
+    fn flat_frame_keeps_its_colour() {
+        let (width, height) = (5, 3);
+        let (cw, ch) = chroma_size(width, height);
+        let mut frame = vec![120; width * height];
+        frame.extend(vec![100; cw * ch]);
+        frame.extend(vec![170; cw * ch]);
+
+        let scaled = scale_yuv420(&frame, width, height, 3);
+        assert_eq!(frame_len(15, 9), scaled.len());
+        let (scaled_y, scaled_chroma) = scaled.split_at(15 * 9);
+        let (scaled_u, scaled_v) = scaled_chroma.split_at(8 * 5);
+        assert!(scaled_y.iter().all(|&s| s.abs_diff(120) <= 1));
+        assert!(scaled_u.iter().all(|&s| s.abs_diff(100) <= 1));
+        assert!(scaled_v.iter().all(|&s| s.abs_diff(170) <= 1));
+    }
+
+    #[test]
+    fn nv12_matches_i420() {
+        let (width, height) = (9, 7);
+        let i420 = test_frame(width, height);
+        let nv12 = to_nv12(&i420, width, height);
+
+        for factor in 2..=6 {
+            let scaled_i420 = scale_yuv420(&i420, width, height, factor);
+            let scaled_nv12 = scale_nv12(&nv12, width, height, factor);
+            assert_eq!(
+                to_nv12(&scaled_i420, width * factor, height * factor),
+                scaled_nv12,
+                "x{factor}"
+            );
+        }
+    }
+}
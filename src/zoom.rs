@@ -0,0 +1,209 @@
+//! A cache of an image scaled to each zoom level, for viewers and editors with zoom controls.
+use crate::blend::Blend2x2;
+use crate::error::check_source;
+use crate::{PixelFormat, ScaledImage, ScalerConfig};
+
+/// An image along with the levels it has been scaled to so far, which are scaled the first time
+/// they are asked for and kept until they no longer fit in a memory budget.
+///
+/// Map viewers and sprite editors scale the same image again every time the zoom changes. The
+/// cache keeps the levels that were used most recently, and when the scaled levels take up more
+/// than `budget` bytes, drops the one used longest ago. The blending of the image is decided once
+/// with [`compute_blend_map`](crate::compute_blend_map) and shared by every level, so even a level
+/// that was dropped is quicker to scale again.
+///
+/// ```
+/// let (width, height) = (16, 16);
+/// let source = vec![255u8; width * height * 4];
+///
+/// // room for the 4x level, but not for the 3x level as well
+/// let budget = 64 * 64 * 4;
+/// let config = xbrz::ScalerConfig::default();
+/// let mut cache = xbrz::ZoomCache::new(source, width, height, config, budget);
+/// assert_eq!(cache.get(3).width(), 48);
+/// assert_eq!(cache.get(4).width(), 64);
+/// assert_eq!(cache.cached_factors().collect::<Vec<_>>(), [4]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ZoomCache {
+    source: Vec<u8>,
+    width: usize,
+    height: usize,
+    config: ScalerConfig,
+    budget: usize,
+    blend_map: Option<Vec<Blend2x2>>,
+    /// The scaled levels and their factors, from the least recently used to the most.
+    levels: Vec<(usize, ScaledImage)>,
+}
+
+impl ZoomCache {
+    /// Creates a cache of the `source` image, a flat RGBA image as for
+    /// [`scale_rgba`](crate::scale_rgba), with nothing scaled yet.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` length is not exactly equal to `width * height * 4`.
+    pub fn new(
+        source: Vec<u8>,
+        width: usize,
+        height: usize,
+        config: ScalerConfig,
+        budget: usize,
+    ) -> Self {
+        check_source(&source, width, height, 1, 4).unwrap_or_else(|e| panic!("{e}"));
+        Self {
+            source,
+            width,
+            height,
+            config,
+            budget,
+            blend_map: None,
+            levels: Vec::new(),
+        }
+    }
+
+    /// The image scaled by `factor`, scaling it now if it isn't already in the cache.
+    ///
+    /// The level asked for is always kept, even if on its own it takes up more than the budget.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+    pub fn get(&mut self, factor: usize) -> &ScaledImage {
+        match self.levels.iter().position(|&(f, _)| f == factor) {
+            Some(i) => {
+                let level = self.levels.remove(i);
+                self.levels.push(level);
+            }
+            None => {
+                let level = self.scale(factor);
+                self.levels.push((factor, level));
+                self.evict();
+            }
+        }
+        &self
+            .levels
+            .last()
+            .expect("the level was just moved to the end")
+            .1
+    }
+
+    /// The factors of the levels in the cache, from the least recently used to the most.
+    pub fn cached_factors(&self) -> impl Iterator<Item = usize> + '_ {
+        self.levels.iter().map(|&(factor, _)| factor)
+    }
+
+    /// The number of bytes taken up by the scaled levels in the cache.
+    pub fn cached_bytes(&self) -> usize {
+        self.levels.iter().map(|(_, level)| level.len()).sum()
+    }
+
+    /// Change the memory budget, dropping levels until the cache fits in it.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.evict();
+    }
+
+    /// Drop every scaled level, such as after the image has been edited.
+    pub fn clear(&mut self) {
+        self.levels.clear();
+    }
+
+    /// Replace the image with an edited version of the same size, dropping every scaled level.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `source` slice length is not exactly equal to `width * height * 4`.
+    pub fn update(&mut self, source: &[u8]) {
+        check_source(source, self.width, self.height, 1, 4).unwrap_or_else(|e| panic!("{e}"));
+        self.source.copy_from_slice(source);
+        self.blend_map = None;
+        self.levels.clear();
+    }
+
+    fn scale(&mut self, factor: usize) -> ScaledImage {
+        check_source(&self.source, self.width, self.height, factor, 4)
+            .unwrap_or_else(|e| panic!("{e}"));
+        let (source, width, height, config) = (&self.source, self.width, self.height, &self.config);
+        let blend_map = self
+            .blend_map
+            .get_or_insert_with(|| crate::compute_blend_map(source, width, height, config));
+        let data =
+            crate::scale_rgba_with_blend_map(source, width, height, factor, config, blend_map);
+        ScaledImage::new(data, width * factor, height * factor, PixelFormat::Rgba8)
+            .expect("scaled image has the scaled dimensions")
+    }
+
+    /// Drop the least recently used levels until the cache fits in its budget, keeping at least
+    /// the most recently used.
+    fn evict(&mut self) {
+        while self.levels.len() > 1 && self.cached_bytes() > self.budget {
+            self.levels.remove(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: usize, height: usize) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| match (2 * (i % width) + i / width) % 5 {
+                0 | 1 => [255, 255, 255, 255],
+                2 => [200, 40, 40, 255],
+                _ => [0, 0, 0, 0],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn levels_match_scaling_by_their_factor() {
+        let (width, height) = (9, 7);
+        let source = image(width, height);
+        let config = ScalerConfig::preset(crate::Preset::Smooth);
+        let mut cache = ZoomCache::new(source.clone(), width, height, config.clone(), !0);
+        for factor in [3, 6, 2, 3] {
+            let level = cache.get(factor);
+            assert_eq!(level.width(), width * factor);
+            assert_eq!(
+                **level,
+                crate::scale_rgba_with_config(&source, width, height, factor, &config)
+            );
+        }
+        assert_eq!(cache.cached_factors().collect::<Vec<_>>(), [6, 2, 3]);
+    }
+
+    #[test]
+    fn least_recently_used_levels_are_dropped() {
+        let (width, height) = (4, 4);
+        let level_bytes = |factor: usize| width * height * factor * factor * 4;
+        let budget = level_bytes(2) + level_bytes(3) + level_bytes(4);
+        let mut cache = ZoomCache::new(
+            image(width, height),
+            width,
+            height,
+            Default::default(),
+            budget,
+        );
+
+        cache.get(2);
+        cache.get(3);
+        cache.get(4);
+        assert_eq!(cache.cached_bytes(), budget);
+        cache.get(2);
+        // the 5x level only fits once both the 3x and 4x levels are gone
+        cache.get(5);
+        assert_eq!(cache.cached_factors().collect::<Vec<_>>(), [2, 5]);
+
+        // a level larger than the whole budget is still kept until the next is asked for
+        cache.set_budget(level_bytes(2));
+        assert_eq!(cache.cached_factors().collect::<Vec<_>>(), [5]);
+        cache.get(2);
+        assert_eq!(cache.cached_factors().collect::<Vec<_>>(), [2]);
+
+        cache.update(&vec![0; width * height * 4]);
+        assert_eq!(cache.cached_bytes(), 0);
+        assert!(cache.get(2).iter().all(|&c| c == 0));
+    }
+}
@@ -0,0 +1,703 @@
+use std::fmt;
+use std::io::{self, Read};
+
+use flate2::read::ZlibDecoder;
+use image::{imageops, Rgba, RgbaImage};
+use xbrz::ScalerConfig;
+
+const HEADER_MAGIC: u16 = 0xA5E0;
+const FRAME_MAGIC: u16 = 0xF1FA;
+
+const CHUNK_OLD_PALETTE: u16 = 0x0004;
+const CHUNK_LAYER: u16 = 0x2004;
+const CHUNK_CEL: u16 = 0x2005;
+const CHUNK_PALETTE: u16 = 0x2019;
+
+const CEL_RAW: u16 = 0;
+const CEL_LINKED: u16 = 1;
+const CEL_COMPRESSED: u16 = 2;
+
+const LAYER_VISIBLE: u16 = 1;
+const LAYER_BACKGROUND: u16 = 8;
+const LAYER_IMAGE: u16 = 0;
+
+/// Set in the header when the opacity of each layer should be used.
+const FLAG_LAYER_OPACITY: u32 = 1;
+
+#[derive(Debug)]
+pub enum AsepriteError {
+    Io(io::Error),
+    /// The file ended in the middle of a header or chunk.
+    UnexpectedEof,
+    /// The file isn't valid, or uses a feature that isn't supported.
+    Format(String),
+}
+
+impl fmt::Display for AsepriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsepriteError::Io(e) => write!(f, "{e}"),
+            AsepriteError::UnexpectedEof => write!(f, "unexpected end of file"),
+            AsepriteError::Format(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// The parts of an Aseprite sprite needed to render its frames.
+pub struct Sprite {
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<Frame>,
+    layers: Vec<Layer>,
+}
+
+pub struct Frame {
+    /// How long the frame is shown for, in milliseconds.
+    pub duration: u16,
+    cels: Vec<Cel>,
+}
+
+struct Layer {
+    /// Whether the layer and all of its parent groups are visible.
+    visible: bool,
+    opacity: u8,
+    /// Group layers have no cels of their own, and tilemap layers aren't supported.
+    is_image: bool,
+    is_background: bool,
+    /// How deeply the layer is nested in groups.
+    level: u16,
+}
+
+#[derive(Clone)]
+struct Cel {
+    layer: usize,
+    x: i32,
+    y: i32,
+    opacity: u8,
+    image: RgbaImage,
+}
+
+/// Little-endian reads from the bytes of a file, as used throughout the Aseprite format.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], AsepriteError> {
+        if len > self.data.len() {
+            return Err(AsepriteError::UnexpectedEof);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, AsepriteError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, AsepriteError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn i16(&mut self) -> Result<i16, AsepriteError> {
+        Ok(i16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, AsepriteError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, AsepriteError> {
+        let len = self.u16()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+}
+
+fn format_error(message: impl Into<String>) -> AsepriteError {
+    AsepriteError::Format(message.into())
+}
+
+/// How the pixels of a cel are stored, from the colour depth in the header.
+#[derive(Copy, Clone)]
+enum ColorDepth {
+    Rgba,
+    Grayscale,
+    Indexed { transparent: u8 },
+}
+
+impl ColorDepth {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorDepth::Rgba => 4,
+            ColorDepth::Grayscale => 2,
+            ColorDepth::Indexed { .. } => 1,
+        }
+    }
+}
+
+impl Sprite {
+    /// Parse the contents of an `.ase` or `.aseprite` file.
+    ///
+    /// Only cels with image data are read; tilemap cels, blend modes other than normal and
+    /// the z-index of cels are not supported, so cels are drawn in layer order.
+    pub fn parse(data: &[u8]) -> Result<Self, AsepriteError> {
+        let mut reader = Reader { data };
+        let mut header = Reader {
+            data: reader.bytes(128)?,
+        };
+        header.u32()?;
+        if header.u16()? != HEADER_MAGIC {
+            return Err(format_error("not an Aseprite file"));
+        }
+        let frame_count = header.u16()?;
+        let width = header.u16()? as u32;
+        let height = header.u16()? as u32;
+        let depth = header.u16()?;
+        let flags = header.u32()?;
+        header.bytes(10)?;
+        let transparent = header.u8()?;
+        let depth = match depth {
+            32 => ColorDepth::Rgba,
+            16 => ColorDepth::Grayscale,
+            8 => ColorDepth::Indexed { transparent },
+            _ => return Err(format_error(format!("unknown colour depth {depth}"))),
+        };
+
+        let mut sprite = Sprite {
+            width,
+            height,
+            frames: Vec::with_capacity(frame_count as usize),
+            layers: Vec::new(),
+        };
+        let mut palette = vec![Rgba([0, 0, 0, 0]); 256];
+
+        for _ in 0..frame_count {
+            let frame_len = reader.u32()? as usize;
+            let mut frame_reader = Reader {
+                data: reader.bytes(frame_len.saturating_sub(4))?,
+            };
+            if frame_reader.u16()? != FRAME_MAGIC {
+                return Err(format_error("invalid frame header"));
+            }
+            let old_chunk_count = frame_reader.u16()?;
+            let duration = frame_reader.u16()?;
+            frame_reader.bytes(2)?;
+            let chunk_count = match frame_reader.u32()? {
+                0 => old_chunk_count as u32,
+                count => count,
+            };
+
+            let mut frame = Frame {
+                duration,
+                cels: Vec::new(),
+            };
+            for _ in 0..chunk_count {
+                let chunk_len = frame_reader.u32()? as usize;
+                let mut chunk = Reader {
+                    data: frame_reader.bytes(chunk_len.saturating_sub(4))?,
+                };
+                match chunk.u16()? {
+                    CHUNK_OLD_PALETTE => read_old_palette(&mut chunk, &mut palette)?,
+                    CHUNK_PALETTE => read_palette(&mut chunk, &mut palette)?,
+                    CHUNK_LAYER => {
+                        let layer = read_layer(&mut chunk, flags, &sprite.layers)?;
+                        sprite.layers.push(layer);
+                    }
+                    CHUNK_CEL => {
+                        let cel = read_cel(&mut chunk, depth, &palette, &sprite)?;
+                        frame.cels.extend(cel);
+                    }
+                    _ => {}
+                }
+            }
+            sprite.frames.push(frame);
+        }
+
+        Ok(sprite)
+    }
+
+    /// Scale every cel of each frame by `factor`, with its offset multiplied to match,
+    /// and draw the visible layers of each frame onto a transparent canvas.
+    pub fn scale_frames(&self, factor: u32, config: &ScalerConfig) -> Vec<RgbaImage> {
+        self.frames
+            .iter()
+            .map(|frame| {
+                let mut canvas = RgbaImage::new(self.width * factor, self.height * factor);
+                let mut cels: Vec<&Cel> = frame.cels.iter().collect();
+                cels.sort_by_key(|cel| cel.layer);
+
+                for cel in cels {
+                    let layer = &self.layers[cel.layer];
+                    if !layer.visible || !layer.is_image {
+                        continue;
+                    }
+                    let scaled = xbrz::scale_rgba_with_config(
+                        &cel.image,
+                        cel.image.width() as usize,
+                        cel.image.height() as usize,
+                        factor as usize,
+                        config,
+                    );
+                    let scaled = RgbaImage::from_raw(
+                        cel.image.width() * factor,
+                        cel.image.height() * factor,
+                        scaled,
+                    )
+                    .expect("scaled image has the right size");
+
+                    let opacity = (cel.opacity as u32 * layer.opacity as u32 / 255) as u8;
+                    draw(
+                        &mut canvas,
+                        &scaled,
+                        cel.x as i64 * factor as i64,
+                        cel.y as i64 * factor as i64,
+                        opacity,
+                    );
+                }
+                canvas
+            })
+            .collect()
+    }
+}
+
+/// Lay out `frames` left to right in a single image.
+pub fn sheet(frames: &[RgbaImage]) -> RgbaImage {
+    let width = frames.iter().map(RgbaImage::width).sum();
+    let height = frames.iter().map(RgbaImage::height).max().unwrap_or(0);
+    let mut sheet = RgbaImage::new(width, height);
+    let mut x = 0;
+    for frame in frames {
+        imageops::replace(&mut sheet, frame, x, 0);
+        x += frame.width() as i64;
+    }
+    sheet
+}
+
+fn read_layer(
+    chunk: &mut Reader,
+    header_flags: u32,
+    previous: &[Layer],
+) -> Result<Layer, AsepriteError> {
+    let flags = chunk.u16()?;
+    let kind = chunk.u16()?;
+    let level = chunk.u16()?;
+    chunk.bytes(6)?;
+    let opacity = chunk.u8()?;
+
+    // layers are listed in tree order, so the parent group is the nearest earlier layer one level up
+    let parent_visible = match level {
+        0 => true,
+        _ => previous
+            .iter()
+            .rev()
+            .find(|layer| layer.level == level - 1)
+            .is_none_or(|parent| parent.visible),
+    };
+
+    Ok(Layer {
+        visible: flags & LAYER_VISIBLE != 0 && parent_visible,
+        opacity: match header_flags & FLAG_LAYER_OPACITY {
+            0 => u8::MAX,
+            _ => opacity,
+        },
+        is_image: kind == LAYER_IMAGE,
+        is_background: flags & LAYER_BACKGROUND != 0,
+        level,
+    })
+}
+
+/// Read a cel, returning `None` for cels that can't be drawn, such as those of tilemap layers.
+fn read_cel(
+    chunk: &mut Reader,
+    depth: ColorDepth,
+    palette: &[Rgba<u8>],
+    sprite: &Sprite,
+) -> Result<Option<Cel>, AsepriteError> {
+    let layer = chunk.u16()? as usize;
+    let x = chunk.i16()? as i32;
+    let y = chunk.i16()? as i32;
+    let opacity = chunk.u8()?;
+    let kind = chunk.u16()?;
+    chunk.bytes(7)?;
+
+    let Some(layer_info) = sprite.layers.get(layer) else {
+        return Err(format_error(format!("cel refers to missing layer {layer}")));
+    };
+
+    let image = match kind {
+        CEL_RAW | CEL_COMPRESSED => {
+            let width = chunk.u16()? as u32;
+            let height = chunk.u16()? as u32;
+            let len = (width * height) as usize * depth.bytes_per_pixel();
+            let pixels = match kind {
+                CEL_RAW => chunk.bytes(len)?.to_vec(),
+                _ => {
+                    let mut pixels = Vec::with_capacity(len);
+                    ZlibDecoder::new(chunk.data)
+                        .take(len as u64)
+                        .read_to_end(&mut pixels)
+                        .map_err(AsepriteError::Io)?;
+                    pixels
+                }
+            };
+            if pixels.len() != len {
+                return Err(AsepriteError::UnexpectedEof);
+            }
+            to_rgba(
+                &pixels,
+                width,
+                height,
+                depth,
+                palette,
+                layer_info.is_background,
+            )
+        }
+        CEL_LINKED => {
+            let frame = chunk.u16()? as usize;
+            let linked = sprite
+                .frames
+                .get(frame)
+                .and_then(|frame| frame.cels.iter().find(|cel| cel.layer == layer));
+            match linked {
+                Some(cel) => cel.image.clone(),
+                None => return Err(format_error(format!("linked cel in missing frame {frame}"))),
+            }
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some(Cel {
+        layer,
+        x,
+        y,
+        opacity,
+        image,
+    }))
+}
+
+fn to_rgba(
+    pixels: &[u8],
+    width: u32,
+    height: u32,
+    depth: ColorDepth,
+    palette: &[Rgba<u8>],
+    opaque: bool,
+) -> RgbaImage {
+    let rgba = pixels
+        .chunks_exact(depth.bytes_per_pixel())
+        .flat_map(|px| match depth {
+            ColorDepth::Rgba => [px[0], px[1], px[2], px[3]],
+            ColorDepth::Grayscale => [px[0], px[0], px[0], px[1]],
+            // the transparent index is only transparent on layers other than the background
+            ColorDepth::Indexed { transparent } if px[0] == transparent && !opaque => [0; 4],
+            ColorDepth::Indexed { .. } => palette[px[0] as usize].0,
+        })
+        .collect();
+    RgbaImage::from_raw(width, height, rgba).expect("cel has the right number of pixels")
+}
+
+fn read_palette(chunk: &mut Reader, palette: &mut [Rgba<u8>]) -> Result<(), AsepriteError> {
+    chunk.u32()?;
+    let first = chunk.u32()? as usize;
+    let last = chunk.u32()? as usize;
+    chunk.bytes(8)?;
+    for i in first..=last {
+        let flags = chunk.u16()?;
+        let colour = Rgba([chunk.u8()?, chunk.u8()?, chunk.u8()?, chunk.u8()?]);
+        if flags & 1 != 0 {
+            chunk.string()?;
+        }
+        if let Some(entry) = palette.get_mut(i) {
+            *entry = colour;
+        }
+    }
+    Ok(())
+}
+
+/// Palettes written by versions of Aseprite before 1.2, as packets of colours after skipped entries.
+fn read_old_palette(chunk: &mut Reader, palette: &mut [Rgba<u8>]) -> Result<(), AsepriteError> {
+    let packets = chunk.u16()?;
+    let mut i = 0;
+    for _ in 0..packets {
+        i += chunk.u8()? as usize;
+        let count = match chunk.u8()? {
+            0 => 256,
+            count => count as usize,
+        };
+        for _ in 0..count {
+            let colour = Rgba([chunk.u8()?, chunk.u8()?, chunk.u8()?, u8::MAX]);
+            if let Some(entry) = palette.get_mut(i) {
+                *entry = colour;
+            }
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+/// Draw `image` over `canvas` with its top left corner at `(x, y)`, faded by `opacity`.
+fn draw(canvas: &mut RgbaImage, image: &RgbaImage, x: i64, y: i64, opacity: u8) {
+    for (ix, iy, src) in image.enumerate_pixels() {
+        let (cx, cy) = (x + ix as i64, y + iy as i64);
+        if cx < 0 || cy < 0 || cx >= canvas.width() as i64 || cy >= canvas.height() as i64 {
+            continue;
+        }
+        let dst = canvas.get_pixel_mut(cx as u32, cy as u32);
+
+        let src_alpha = src[3] as f32 * opacity as f32 / (255.0 * 255.0);
+        let dst_alpha = dst[3] as f32 / 255.0 * (1.0 - src_alpha);
+        let alpha = src_alpha + dst_alpha;
+        if alpha <= 0.0 {
+            continue;
+        }
+        for c in 0..3 {
+            dst[c] =
+                ((src[c] as f32 * src_alpha + dst[c] as f32 * dst_alpha) / alpha).round() as u8;
+        }
+        dst[3] = (alpha * 255.0).round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    use super::*;
+
+    /// The type and contents of a chunk.
+    type Chunk = (u16, Vec<u8>);
+
+    /// Builds the bytes of a sprite, one frame of chunks at a time.
+    struct Builder {
+        width: u16,
+        height: u16,
+        depth: u16,
+        flags: u32,
+        transparent: u8,
+        frames: Vec<(u16, Vec<Chunk>)>,
+    }
+
+    impl Builder {
+        fn new(width: u16, height: u16, depth: u16) -> Self {
+            Self {
+                width,
+                height,
+                depth,
+                flags: FLAG_LAYER_OPACITY,
+                transparent: 0,
+                frames: Vec::new(),
+            }
+        }
+
+        fn frame(mut self, duration: u16, chunks: Vec<Chunk>) -> Self {
+            self.frames.push((duration, chunks));
+            self
+        }
+
+        fn build(&self) -> Vec<u8> {
+            let mut frames = Vec::new();
+            for (duration, chunks) in &self.frames {
+                let mut body = Vec::new();
+                for (kind, data) in chunks {
+                    body.extend((data.len() as u32 + 6).to_le_bytes());
+                    body.extend(kind.to_le_bytes());
+                    body.extend(data);
+                }
+                frames.extend((body.len() as u32 + 16).to_le_bytes());
+                frames.extend(FRAME_MAGIC.to_le_bytes());
+                frames.extend((chunks.len() as u16).to_le_bytes());
+                frames.extend(duration.to_le_bytes());
+                frames.extend([0; 2]);
+                frames.extend((chunks.len() as u32).to_le_bytes());
+                frames.extend(body);
+            }
+
+            let mut file = Vec::new();
+            file.extend((128 + frames.len() as u32).to_le_bytes());
+            file.extend(HEADER_MAGIC.to_le_bytes());
+            file.extend((self.frames.len() as u16).to_le_bytes());
+            file.extend(self.width.to_le_bytes());
+            file.extend(self.height.to_le_bytes());
+            file.extend(self.depth.to_le_bytes());
+            file.extend(self.flags.to_le_bytes());
+            file.extend([0; 10]);
+            file.push(self.transparent);
+            file.resize(128, 0);
+            file.extend(frames);
+            file
+        }
+    }
+
+    fn layer(flags: u16, kind: u16, level: u16, opacity: u8) -> Chunk {
+        let mut data = Vec::new();
+        for field in [flags, kind, level, 0, 0, 0] {
+            data.extend(field.to_le_bytes());
+        }
+        data.push(opacity);
+        data.extend([0; 3]);
+        data.extend(5u16.to_le_bytes());
+        data.extend(b"layer");
+        (CHUNK_LAYER, data)
+    }
+
+    fn cel_header(layer: u16, x: i16, y: i16, opacity: u8, kind: u16) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend(layer.to_le_bytes());
+        data.extend(x.to_le_bytes());
+        data.extend(y.to_le_bytes());
+        data.push(opacity);
+        data.extend(kind.to_le_bytes());
+        data.extend([0; 7]);
+        data
+    }
+
+    fn raw_cel(layer: u16, x: i16, y: i16, width: u16, pixels: &[u8], px: usize) -> Chunk {
+        let mut data = cel_header(layer, x, y, 255, CEL_RAW);
+        data.extend(width.to_le_bytes());
+        data.extend(((pixels.len() / px) as u16 / width).to_le_bytes());
+        data.extend(pixels);
+        (CHUNK_CEL, data)
+    }
+
+    fn compressed_cel(layer: u16, x: i16, y: i16, width: u16, pixels: &[u8]) -> Chunk {
+        let mut data = cel_header(layer, x, y, 255, CEL_COMPRESSED);
+        data.extend(width.to_le_bytes());
+        data.extend(((pixels.len() / 4) as u16 / width).to_le_bytes());
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(pixels).unwrap();
+        data.extend(encoder.finish().unwrap());
+        (CHUNK_CEL, data)
+    }
+
+    fn linked_cel(layer: u16, frame: u16) -> Chunk {
+        let mut data = cel_header(layer, 0, 0, 255, CEL_LINKED);
+        data.extend(frame.to_le_bytes());
+        (CHUNK_CEL, data)
+    }
+
+    const RED: [u8; 4] = [200, 40, 40, 255];
+    const BLUE: [u8; 4] = [40, 90, 200, 255];
+
+    /// A 4x2 RGBA sprite: a red background, a blue 2x1 cel over it, and a hidden layer.
+    fn layered() -> Builder {
+        Builder::new(4, 2, 32).frame(
+            100,
+            vec![
+                layer(LAYER_VISIBLE | LAYER_BACKGROUND, LAYER_IMAGE, 0, 255),
+                layer(LAYER_VISIBLE, LAYER_IMAGE, 0, 255),
+                layer(0, LAYER_IMAGE, 0, 255),
+                raw_cel(0, 0, 0, 4, &RED.repeat(8), 4),
+                compressed_cel(1, 1, 1, 2, &BLUE.repeat(2)),
+                raw_cel(2, 0, 0, 4, &[0, 255, 0, 255].repeat(8), 4),
+            ],
+        )
+    }
+
+    #[test]
+    fn visible_layers_are_drawn_in_order() {
+        let sprite = Sprite::parse(&layered().build()).unwrap();
+        assert_eq!((sprite.width, sprite.height), (4, 2));
+        assert_eq!(sprite.frames.len(), 1);
+        assert_eq!(sprite.frames[0].duration, 100);
+        assert_eq!(sprite.layers.len(), 3);
+        assert_eq!(sprite.frames[0].cels.len(), 3);
+
+        let frames = sprite.scale_frames(1, &ScalerConfig::default());
+        let expected: Vec<u8> = [RED, RED, RED, RED, RED, BLUE, BLUE, RED].concat();
+        assert_eq!(frames[0].as_raw(), &expected);
+
+        let scaled = sprite.scale_frames(3, &ScalerConfig::default());
+        assert_eq!(scaled[0].dimensions(), (12, 6));
+    }
+
+    #[test]
+    fn compressed_cels_match_raw_cels() {
+        let pixels: Vec<u8> = (0..6 * 4).map(|i| (i * 10) as u8).collect();
+        let parse = |cel| {
+            let file = Builder::new(3, 2, 32)
+                .frame(10, vec![layer(LAYER_VISIBLE, LAYER_IMAGE, 0, 255), cel])
+                .build();
+            Sprite::parse(&file).unwrap().frames[0].cels[0]
+                .image
+                .clone()
+        };
+        let raw = parse(raw_cel(0, 0, 0, 3, &pixels, 4));
+        assert_eq!(raw.as_raw(), &pixels);
+        assert_eq!(parse(compressed_cel(0, 0, 0, 3, &pixels)), raw);
+    }
+
+    #[test]
+    fn indexed_cels_use_the_palette_and_linked_cels_repeat() {
+        let mut palette = Vec::new();
+        palette.extend(0u32.to_le_bytes());
+        palette.extend(0u32.to_le_bytes());
+        palette.extend(2u32.to_le_bytes());
+        palette.extend([0; 8]);
+        for colour in [[0, 0, 0, 0], RED, BLUE] {
+            palette.extend(0u16.to_le_bytes());
+            palette.extend(colour);
+        }
+
+        let file = Builder::new(3, 1, 8)
+            .frame(
+                50,
+                vec![
+                    (CHUNK_PALETTE, palette),
+                    // a hidden group hides the layer inside it
+                    layer(0, 1, 0, 255),
+                    layer(LAYER_VISIBLE, LAYER_IMAGE, 1, 255),
+                    layer(LAYER_VISIBLE, LAYER_IMAGE, 0, 255),
+                    raw_cel(1, 0, 0, 3, &[1, 1, 1], 1),
+                    raw_cel(2, 0, 0, 3, &[1, 0, 2], 1),
+                ],
+            )
+            .frame(60, vec![linked_cel(2, 0)])
+            .build();
+        let sprite = Sprite::parse(&file).unwrap();
+        assert!(!sprite.layers[1].visible);
+        assert_eq!(sprite.frames[1].duration, 60);
+
+        let frames = sprite.scale_frames(1, &ScalerConfig::default());
+        let expected = [RED, [0; 4], BLUE].concat();
+        assert_eq!(frames[0].as_raw(), &expected);
+        assert_eq!(frames[1], frames[0]);
+        assert_eq!(sheet(&frames).dimensions(), (6, 1));
+    }
+
+    #[test]
+    fn malformed_files_are_errors() {
+        let file = layered().build();
+        for len in 0..file.len() {
+            assert!(
+                Sprite::parse(&file[..len]).is_err(),
+                "truncated to {len} bytes"
+            );
+        }
+
+        let mut bad_magic = file.clone();
+        bad_magic[4] = 0;
+        assert!(matches!(
+            Sprite::parse(&bad_magic),
+            Err(AsepriteError::Format(_))
+        ));
+
+        let mut builder = layered();
+        builder.depth = 24;
+        assert!(matches!(
+            Sprite::parse(&builder.build()),
+            Err(AsepriteError::Format(_))
+        ));
+
+        let missing_layer = Builder::new(2, 1, 32)
+            .frame(10, vec![raw_cel(0, 0, 0, 2, &RED.repeat(2), 4)])
+            .build();
+        assert!(matches!(
+            Sprite::parse(&missing_layer),
+            Err(AsepriteError::Format(_))
+        ));
+    }
+}
@@ -0,0 +1,289 @@
+//! A simple encoder for the BC1 and BC3 block compressed texture formats, which fits the colours
+//! of each block to a line between the darkest and brightest of them.
+use image::RgbaImage;
+
+/// Block compressed formats that textures can be written in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlockFormat {
+    /// 8 bytes per block of 4x4 pixels, with 1-bit alpha: pixels with alpha below 128 become fully
+    /// transparent and the rest fully opaque.
+    Bc1,
+    /// 16 bytes per block of 4x4 pixels: BC1 colour with its own block of interpolated alpha.
+    Bc3,
+}
+
+impl BlockFormat {
+    pub fn block_bytes(self) -> usize {
+        match self {
+            BlockFormat::Bc1 => 8,
+            BlockFormat::Bc3 => 16,
+        }
+    }
+}
+
+/// Compress an image block by block, left to right then top to bottom. Blocks over the right or
+/// bottom edge repeat the last column or row of pixels.
+pub fn encode(image: &RgbaImage, format: BlockFormat) -> Vec<u8> {
+    let (width, height) = image.dimensions();
+    let (blocks_x, blocks_y) = (width.div_ceil(4), height.div_ceil(4));
+    let mut out = Vec::with_capacity((blocks_x * blocks_y) as usize * format.block_bytes());
+    for block_y in 0..blocks_y {
+        for block_x in 0..blocks_x {
+            let block: [[u8; 4]; 16] = std::array::from_fn(|i| {
+                let x = (block_x * 4 + i as u32 % 4).min(width - 1);
+                let y = (block_y * 4 + i as u32 / 4).min(height - 1);
+                image.get_pixel(x, y).0
+            });
+            match format {
+                BlockFormat::Bc1 => encode_color(&block, true, &mut out),
+                BlockFormat::Bc3 => {
+                    encode_alpha(&block, &mut out);
+                    encode_color(&block, false, &mut out);
+                }
+            }
+        }
+    }
+    out
+}
+
+fn to_565([r, g, b, _]: [u8; 4]) -> u16 {
+    (u16::from(r) >> 3) << 11 | (u16::from(g) >> 2) << 5 | u16::from(b) >> 3
+}
+
+fn from_565(color: u16) -> [i32; 3] {
+    let (r, g, b) = (color >> 11, (color >> 5) & 0x3f, color & 0x1f);
+    [
+        i32::from(r << 3 | r >> 2),
+        i32::from(g << 2 | g >> 4),
+        i32::from(b << 3 | b >> 2),
+    ]
+}
+
+fn distance(a: [i32; 3], [r, g, b, _]: [u8; 4]) -> i32 {
+    let d = [
+        a[0] - i32::from(r),
+        a[1] - i32::from(g),
+        a[2] - i32::from(b),
+    ];
+    d[0] * d[0] + d[1] * d[1] + d[2] * d[2]
+}
+
+/// Encode the colour half of a block: two RGB565 endpoints and a 2-bit index per pixel. With
+/// `punch_through`, blocks with transparent pixels use the three colour mode of BC1, where the
+/// fourth index is transparent black.
+fn encode_color(block: &[[u8; 4]; 16], punch_through: bool, out: &mut Vec<u8>) {
+    let transparent = |pixel: &[u8; 4]| punch_through && pixel[3] < 128;
+    let luma = |[r, g, b, _]: [u8; 4]| u32::from(r) * 2 + u32::from(g) * 5 + u32::from(b);
+    let opaque = block.iter().filter(|pixel| !transparent(pixel));
+    let (Some(&dark), Some(&bright)) = (
+        opaque.clone().min_by_key(|&&pixel| luma(pixel)),
+        opaque.max_by_key(|&&pixel| luma(pixel)),
+    ) else {
+        // fully transparent: both endpoints black, in three colour mode, and every index 3
+        out.extend_from_slice(&[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        return;
+    };
+
+    let has_transparency = block.iter().any(transparent);
+    let (mut c0, mut c1) = (to_565(bright), to_565(dark));
+    // four colour mode needs c0 > c1 and three colour mode c0 <= c1
+    if has_transparency != (c0 <= c1) {
+        (c0, c1) = (c1, c0);
+    }
+    if !has_transparency && c0 == c1 {
+        // a solid block, which is still only four colour mode if the endpoints differ
+        c1 = c0.saturating_sub(1);
+        if c0 == c1 {
+            c0 = 1;
+        }
+    }
+
+    let (e0, e1) = (from_565(c0), from_565(c1));
+    let mix = |a: i32, b: i32, wa: i32, wb: i32| (a * wa + b * wb) / (wa + wb);
+    let palette: Vec<[i32; 3]> = if has_transparency {
+        vec![e0, e1, std::array::from_fn(|i| mix(e0[i], e1[i], 1, 1))]
+    } else {
+        vec![
+            e0,
+            e1,
+            std::array::from_fn(|i| mix(e0[i], e1[i], 2, 1)),
+            std::array::from_fn(|i| mix(e0[i], e1[i], 1, 2)),
+        ]
+    };
+
+    let mut indices = 0u32;
+    for (i, pixel) in block.iter().enumerate() {
+        let index = if transparent(pixel) {
+            3
+        } else {
+            (0..palette.len())
+                .min_by_key(|&index| distance(palette[index], *pixel))
+                .unwrap()
+        };
+        indices |= (index as u32) << (i * 2);
+    }
+    out.extend_from_slice(&c0.to_le_bytes());
+    out.extend_from_slice(&c1.to_le_bytes());
+    out.extend_from_slice(&indices.to_le_bytes());
+}
+
+/// Encode the alpha half of a BC3 block: two alpha endpoints and a 3-bit index per pixel, into
+/// the eight alpha levels between them.
+fn encode_alpha(block: &[[u8; 4]; 16], out: &mut Vec<u8>) {
+    let max = block.iter().map(|pixel| pixel[3]).max().unwrap();
+    let min = block.iter().map(|pixel| pixel[3]).min().unwrap();
+    // a0 > a1 gives eight levels; a0 == a1 gives a0 for every index in use here
+    let (a0, a1) = (max, min);
+    let levels: [i32; 8] = std::array::from_fn(|index| {
+        let (a0, a1) = (i32::from(a0), i32::from(a1));
+        match index {
+            0 => a0,
+            1 => a1,
+            _ => (a0 * (8 - index as i32) + a1 * (index as i32 - 1)) / 7,
+        }
+    });
+
+    let mut indices = 0u64;
+    for (i, pixel) in block.iter().enumerate() {
+        let index = (0..8)
+            .min_by_key(|&index| (levels[index] - i32::from(pixel[3])).abs())
+            .unwrap();
+        indices |= (index as u64) << (i * 3);
+    }
+    out.push(a0);
+    out.push(a1);
+    out.extend_from_slice(&indices.to_le_bytes()[..6]);
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// Decode the colour half of a block, following the BC1 rules for its endpoints.
+    fn decode_color(bytes: &[u8], opaque: bool) -> [[u8; 4]; 16] {
+        let c0 = u16::from_le_bytes([bytes[0], bytes[1]]);
+        let c1 = u16::from_le_bytes([bytes[2], bytes[3]]);
+        let indices = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let (e0, e1) = (from_565(c0), from_565(c1));
+        let mix = |wa: i32, wb: i32| -> [u8; 4] {
+            let [r, g, b] = std::array::from_fn(|i| ((e0[i] * wa + e1[i] * wb) / (wa + wb)) as u8);
+            [r, g, b, 255]
+        };
+        let palette = if c0 > c1 || opaque {
+            [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+        } else {
+            [mix(1, 0), mix(0, 1), mix(1, 1), [0; 4]]
+        };
+        std::array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+    }
+
+    /// Decode the alpha half of a BC3 block.
+    fn decode_alpha(bytes: &[u8]) -> [u8; 16] {
+        let (a0, a1) = (i32::from(bytes[0]), i32::from(bytes[1]));
+        let mut index_bytes = [0; 8];
+        index_bytes[..6].copy_from_slice(&bytes[2..8]);
+        let indices = u64::from_le_bytes(index_bytes);
+        let level = |index: i32| match index {
+            0 => a0,
+            1 => a1,
+            _ if a0 > a1 => (a0 * (8 - index) + a1 * (index - 1)) / 7,
+            6 => 0,
+            7 => 255,
+            _ => (a0 * (6 - index) + a1 * (index - 1)) / 5,
+        };
+        std::array::from_fn(|i| level((indices >> (i * 3)) as i32 & 7) as u8)
+    }
+
+    fn decode(data: &[u8], width: u32, height: u32, format: BlockFormat) -> RgbaImage {
+        let blocks_x = width.div_ceil(4);
+        RgbaImage::from_fn(width, height, |x, y| {
+            let block = ((y / 4) * blocks_x + x / 4) as usize * format.block_bytes();
+            let i = (y % 4 * 4 + x % 4) as usize;
+            let bytes = &data[block..block + format.block_bytes()];
+            Rgba(match format {
+                BlockFormat::Bc1 => decode_color(bytes, false)[i],
+                BlockFormat::Bc3 => {
+                    let [r, g, b, _] = decode_color(&bytes[8..], true)[i];
+                    [r, g, b, decode_alpha(bytes)[i]]
+                }
+            })
+        })
+    }
+
+    /// An image whose colours vary along a line within each block, as BC1 stores them, with a
+    /// ramp of alpha and a transparent corner. It is not a whole number of blocks wide or high.
+    fn gradient() -> RgbaImage {
+        RgbaImage::from_fn(10, 7, |x, y| {
+            let t = ((x + y) * 8) as u8;
+            let alpha = if x < 2 && y < 2 {
+                0
+            } else {
+                255 - (y * 20) as u8
+            };
+            Rgba([t, 255 - t, t / 2 + 40, alpha])
+        })
+    }
+
+    /// Check that every pixel decoded within `tolerance` of the image, and its alpha, after
+    /// `alpha` has been applied, within `alpha_tolerance`.
+    fn assert_close(
+        decoded: &RgbaImage,
+        image: &RgbaImage,
+        alpha: impl Fn(u8) -> u8,
+        alpha_tolerance: u8,
+        tolerance: u8,
+    ) {
+        for ((x, y, got), want) in decoded.enumerate_pixels().zip(image.pixels()) {
+            let want_alpha = alpha(want[3]);
+            assert!(
+                got[3].abs_diff(want_alpha) <= alpha_tolerance,
+                "({x}, {y}): alpha {} for {want_alpha}",
+                got[3]
+            );
+            if want_alpha == 0 && got[3] == 0 {
+                continue;
+            }
+            for c in 0..3 {
+                assert!(
+                    got[c].abs_diff(want[c]) <= tolerance,
+                    "({x}, {y}): {got:?} for {want:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bc1_round_trips_within_tolerance() {
+        let image = gradient();
+        let data = encode(&image, BlockFormat::Bc1);
+        assert_eq!(data.len(), 3 * 2 * 8);
+        let decoded = decode(&data, 10, 7, BlockFormat::Bc1);
+        assert_close(&decoded, &image, |a| if a < 128 { 0 } else { 255 }, 0, 12);
+    }
+
+    #[test]
+    fn bc3_round_trips_within_tolerance() {
+        let image = gradient();
+        let data = encode(&image, BlockFormat::Bc3);
+        assert_eq!(data.len(), 3 * 2 * 16);
+        let decoded = decode(&data, 10, 7, BlockFormat::Bc3);
+        // the alpha of the block with the transparent corner spans every level
+        assert_close(&decoded, &image, |a| a, 255 / 14, 12);
+    }
+
+    #[test]
+    fn solid_and_transparent_blocks() {
+        let solid = RgbaImage::from_pixel(4, 4, Rgba([90, 160, 30, 255]));
+        let decoded = decode(&encode(&solid, BlockFormat::Bc1), 4, 4, BlockFormat::Bc1);
+        // within a step of RGB565
+        assert_close(&decoded, &solid, |a| a, 0, 8);
+
+        let clear = RgbaImage::from_pixel(4, 4, Rgba([90, 160, 30, 0]));
+        let data = encode(&clear, BlockFormat::Bc1);
+        assert_eq!(data, [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        let decoded = decode(&data, 4, 4, BlockFormat::Bc1);
+        assert!(decoded.pixels().all(|px| px.0 == [0; 4]));
+    }
+}
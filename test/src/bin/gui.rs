@@ -0,0 +1,190 @@
+//! A window that scales images dropped onto it, for pixel artists who would rather not use the
+//! command line.
+use std::path::{Path, PathBuf};
+
+use eframe::egui;
+use image::RgbaImage;
+use xbrz::egui::ComparisonView;
+use xbrz::{Preset, ScaleError, ScalerConfig};
+
+const PRESETS: [Preset; 5] = [
+    Preset::Faithful,
+    Preset::Sharp,
+    Preset::Smooth,
+    Preset::Text,
+    Preset::CrispText,
+];
+
+fn main() -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_title("xBRZ")
+            .with_inner_size([960.0, 640.0])
+            .with_drag_and_drop(true),
+        ..Default::default()
+    };
+    eframe::run_native("xbrz-gui", options, Box::new(|_| Ok(Box::<Gui>::default())))
+}
+
+/// A dropped image, and a comparison of it with its scaled copy for the current settings.
+struct Loaded {
+    path: PathBuf,
+    source: RgbaImage,
+    view: ComparisonView,
+}
+
+struct Gui {
+    factor: usize,
+    preset: Preset,
+    image: Option<Loaded>,
+    /// The result of the last load or save, shown at the bottom of the window.
+    status: String,
+}
+
+impl Default for Gui {
+    fn default() -> Self {
+        Self {
+            factor: 4,
+            preset: Preset::Faithful,
+            image: None,
+            status: "Drop an image onto the window to scale it".to_owned(),
+        }
+    }
+}
+
+impl Gui {
+    fn open(&mut self, ctx: &egui::Context, path: PathBuf) {
+        let source = match image::open(&path) {
+            Ok(source) => source.into_rgba8(),
+            Err(e) => {
+                self.status = format!("Image read error: {e}");
+                return;
+            }
+        };
+        if let Err(e) = check_size(&source, self.factor) {
+            self.status = format!("Scaling error: {e}");
+            return;
+        }
+        let view = ComparisonView::with_config(
+            ctx,
+            &source,
+            source.width() as usize,
+            source.height() as usize,
+            self.factor,
+            &ScalerConfig::preset(self.preset),
+        );
+        self.status = format!("Opened {}", path.display());
+        self.image = Some(Loaded { path, source, view });
+    }
+
+    /// Scale the image again after the factor or preset changed, keeping the last scaled image if
+    /// it can't be.
+    fn rescale(&mut self) {
+        let Some(image) = &mut self.image else {
+            return;
+        };
+        match check_size(&image.source, self.factor) {
+            Ok(()) => image
+                .view
+                .set_scaling(self.factor, &ScalerConfig::preset(self.preset)),
+            Err(e) => self.status = format!("Scaling error: {e}"),
+        }
+    }
+
+    fn save(&mut self) {
+        let Some(image) = &self.image else {
+            return;
+        };
+        let factor = image.view.factor();
+        let path = scaled_path(&image.path, factor);
+        self.status = match scale(&image.source, factor, self.preset) {
+            Ok(scaled) => match scaled.save(&path) {
+                Ok(()) => format!("Saved scaled image at {}", path.display()),
+                Err(e) => format!("Error saving new image: {e}"),
+            },
+            Err(e) => format!("Scaling error: {e}"),
+        };
+    }
+}
+
+impl eframe::App for Gui {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let dropped = ctx.input(|i| i.raw.dropped_files.first().and_then(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            self.open(ctx, path);
+        }
+
+        egui::TopBottomPanel::top("settings").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let factor = ui.add(egui::Slider::new(&mut self.factor, 2..=6).text("Factor"));
+                let mut preset_changed = false;
+                egui::ComboBox::from_label("Preset")
+                    .selected_text(format!("{:?}", self.preset))
+                    .show_ui(ui, |ui| {
+                        for preset in PRESETS {
+                            preset_changed |= ui
+                                .selectable_value(&mut self.preset, preset, format!("{preset:?}"))
+                                .changed();
+                        }
+                    });
+                if factor.changed() || preset_changed {
+                    self.rescale();
+                }
+                if ui
+                    .add_enabled(self.image.is_some(), egui::Button::new("Save"))
+                    .clicked()
+                {
+                    self.save();
+                }
+            });
+        });
+        egui::TopBottomPanel::bottom("status").show(ctx, |ui| ui.label(&self.status));
+
+        egui::CentralPanel::default().show(ctx, |ui| match &mut self.image {
+            Some(image) => {
+                image.view.show(ui).on_hover_text(
+                    "Drag to pan, scroll to zoom and double click to fit the image in view",
+                );
+            }
+            None => {
+                ui.centered_and_justified(|ui| ui.heading("Drop an image here"));
+            }
+        });
+    }
+}
+
+/// Check that the image isn't too large to scale by `factor`, as the comparison view expects.
+fn check_size(source: &RgbaImage, factor: usize) -> Result<(), ScaleError> {
+    let (width, height) = (source.width() as usize, source.height() as usize);
+    let (max_width, max_height) = xbrz::max_supported_dimensions(factor);
+    if width > max_width || height > max_height {
+        return Err(ScaleError::TooLarge {
+            width,
+            height,
+            factor,
+        });
+    }
+    Ok(())
+}
+
+fn scale(source: &RgbaImage, factor: usize, preset: Preset) -> Result<RgbaImage, ScaleError> {
+    let scaled = xbrz::try_scale_rgba_with_config(
+        source,
+        source.width() as usize,
+        source.height() as usize,
+        factor,
+        &ScalerConfig::preset(preset),
+    )?;
+    let (width, height) = (
+        source.width() * factor as u32,
+        source.height() * factor as u32,
+    );
+    Ok(RgbaImage::from_raw(width, height, scaled).expect("scaled image has the scaled dimensions"))
+}
+
+/// Where the scaled copy of `path` is saved: next to it, with the factor added to its name,
+/// as `sprite.x4.png`.
+fn scaled_path(path: &Path, factor: usize) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}.x{factor}.png"))
+}
@@ -0,0 +1,499 @@
+//! An HTTP service that scales PNG images, for asset backends that want scaling behind a URL.
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use image::{ImageFormat, ImageReader, Limits, RgbaImage};
+use xbrz::ScaleFactor;
+use xbrz_test::profile::Profile;
+
+/// How long a client may take to send its whole request before the connection is dropped.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Largest request line and headers accepted, in bytes.
+const MAX_HEADER_BYTES: u64 = 8 << 10;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1:8000")]
+    bind: String,
+
+    /// Load scaling settings from a TOML or JSON profile
+    #[arg(long)]
+    profile: Option<PathBuf>,
+
+    /// Most requests handled at once; any more are turned away with 503 Service Unavailable
+    #[arg(long, default_value_t = 4)]
+    max_concurrent: usize,
+
+    /// Largest request body accepted, in bytes
+    #[arg(long, default_value_t = 8 << 20)]
+    max_body_bytes: usize,
+
+    /// Largest image accepted, in source pixels
+    #[arg(long, default_value_t = 4 << 20)]
+    max_pixels: u64,
+}
+
+/// The limits on what each request may ask for.
+struct RequestLimits {
+    max_body_bytes: usize,
+    max_pixels: u64,
+}
+
+/// Counts a request as in progress for as long as it is held.
+struct Active(Arc<AtomicUsize>);
+
+impl Drop for Active {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A response to a request that can't be scaled.
+struct Rejection {
+    status: &'static str,
+    message: String,
+}
+
+fn reject(status: &'static str, message: impl Into<String>) -> Rejection {
+    Rejection {
+        status,
+        message: message.into(),
+    }
+}
+
+/// Count another request as in progress, unless `max_concurrent` already are.
+fn admit(active: &Arc<AtomicUsize>, max_concurrent: usize) -> Option<Active> {
+    if active.fetch_add(1, Ordering::SeqCst) >= max_concurrent {
+        active.fetch_sub(1, Ordering::SeqCst);
+        return None;
+    }
+    Some(Active(active.clone()))
+}
+
+/// A connection that gives up on a request once its deadline has passed, so that a client can't
+/// hold on to it by trickling the request in a byte at a time.
+struct DeadlineStream {
+    stream: TcpStream,
+    deadline: Instant,
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(remaining))?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn main() {
+    let args = Args::parse();
+    let profile = match &args.profile {
+        Some(path) => match Profile::load(path) {
+            Ok(profile) => profile,
+            Err(e) => {
+                eprintln!("Profile read error: {e}");
+                return;
+            }
+        },
+        None => Profile::default(),
+    };
+    let limits = Arc::new(RequestLimits {
+        max_body_bytes: args.max_body_bytes,
+        max_pixels: args.max_pixels,
+    });
+    let profile = Arc::new(profile);
+
+    let listener = match TcpListener::bind(&args.bind) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Server error: {e}");
+            return;
+        }
+    };
+    eprintln!(
+        "Scaling PNG images posted to http://{}/scale?factor=N (press Ctrl+C to stop)",
+        args.bind
+    );
+
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Connection error: {e}");
+                continue;
+            }
+        };
+        let Some(active) = admit(&active, args.max_concurrent) else {
+            let busy = reject("503 Service Unavailable", "too many requests in progress");
+            if let Err(e) = respond_error(&mut stream, &busy) {
+                eprintln!("Request error: {e}");
+            }
+            continue;
+        };
+
+        let (profile, limits) = (profile.clone(), limits.clone());
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &profile, &limits) {
+                eprintln!("Request error: {e}");
+            }
+            drop(active);
+        });
+    }
+}
+
+fn handle(stream: TcpStream, profile: &Profile, limits: &RequestLimits) -> io::Result<()> {
+    let mut stream = DeadlineStream {
+        stream,
+        deadline: Instant::now() + REQUEST_TIMEOUT,
+    };
+    answer(&mut stream, profile, limits)
+}
+
+/// Read a request from `stream` and write the response to it.
+fn answer(
+    stream: &mut (impl Read + Write),
+    profile: &Profile,
+    limits: &RequestLimits,
+) -> io::Result<()> {
+    match scale_request(stream, profile, limits)? {
+        Ok(png) => respond(stream, "200 OK", "image/png", &png),
+        Err(rejection) => respond_error(stream, &rejection),
+    }
+}
+
+/// Read a request and scale the image it holds, returning the scaled image as a PNG.
+fn scale_request(
+    stream: &mut (impl Read + Write),
+    profile: &Profile,
+    limits: &RequestLimits,
+) -> io::Result<Result<Vec<u8>, Rejection>> {
+    let mut reader = BufReader::new(stream);
+    let mut head = (&mut reader).take(MAX_HEADER_BYTES);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    let (method, target) = match request_line.split(' ').collect::<Vec<_>>()[..] {
+        [method, target, _] => (method, target),
+        _ if head.limit() == 0 => return Ok(Err(headers_too_large())),
+        _ => return Ok(Err(reject("400 Bad Request", "malformed request line"))),
+    };
+
+    let mut content_length = None;
+    let mut expect_continue = false;
+    let mut header = String::new();
+    while head.read_line(&mut header)? > 2 {
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse::<usize>().ok();
+            } else if name.eq_ignore_ascii_case("expect") {
+                expect_continue = value.eq_ignore_ascii_case("100-continue");
+            }
+        }
+        header.clear();
+    }
+    if head.limit() == 0 {
+        return Ok(Err(headers_too_large()));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    if path != "/scale" {
+        return Ok(Err(reject("404 Not Found", "not found")));
+    }
+    if method != "POST" {
+        return Ok(Err(reject(
+            "405 Method Not Allowed",
+            "only POST is allowed",
+        )));
+    }
+    let factor = match query_value(query, "factor") {
+        Some(value) => match value.parse().ok().and_then(ScaleFactor::new) {
+            Some(factor) => factor.get(),
+            None => return Ok(Err(reject("400 Bad Request", "factor must be 1 to 6"))),
+        },
+        None => profile.scale.map_or(2, ScaleFactor::get),
+    };
+    let Some(length) = content_length else {
+        return Ok(Err(reject(
+            "411 Length Required",
+            "Content-Length is needed",
+        )));
+    };
+    if length > limits.max_body_bytes {
+        return Ok(Err(reject(
+            "413 Payload Too Large",
+            format!("the body may be at most {} bytes", limits.max_body_bytes),
+        )));
+    }
+
+    // clients waiting for permission to send a large body are only answered once it's accepted
+    if expect_continue {
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    let image = match decode(&body, limits) {
+        Ok(image) => image,
+        Err(rejection) => return Ok(Err(rejection)),
+    };
+    let scaled = xbrz::scale_rgba_with_config(
+        &image,
+        image.width() as usize,
+        image.height() as usize,
+        factor,
+        &profile.config,
+    );
+    let (width, height) = (
+        image.width() * factor as u32,
+        image.height() * factor as u32,
+    );
+    let scaled =
+        RgbaImage::from_raw(width, height, scaled).expect("scaled image has the right size");
+
+    let mut png = Vec::new();
+    scaled
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(io::Error::other)?;
+    Ok(Ok(png))
+}
+
+fn headers_too_large() -> Rejection {
+    reject(
+        "431 Request Header Fields Too Large",
+        format!("the request line and headers may be at most {MAX_HEADER_BYTES} bytes"),
+    )
+}
+
+/// The value of the parameter `key` in a query string.
+fn query_value<'a>(query: &'a str, key: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find_map(|(k, value)| (k == key).then_some(value))
+}
+
+/// Decode a PNG, refusing any image larger than the limit before its pixels are decoded.
+fn decode(body: &[u8], limits: &RequestLimits) -> Result<RgbaImage, Rejection> {
+    let reader = || {
+        let mut reader = ImageReader::with_format(Cursor::new(body), ImageFormat::Png);
+        let mut decoder_limits = Limits::default();
+        // enough for the decoded image as 16-bit RGBA
+        decoder_limits.max_alloc = Some(limits.max_pixels * 8);
+        reader.limits(decoder_limits);
+        reader
+    };
+    let too_large = || {
+        reject(
+            "413 Payload Too Large",
+            format!("the image may be at most {} pixels", limits.max_pixels),
+        )
+    };
+    let (width, height) = reader()
+        .into_dimensions()
+        .map_err(|e| reject("400 Bad Request", format!("not a PNG: {e}")))?;
+    if width as u64 * height as u64 > limits.max_pixels {
+        return Err(too_large());
+    }
+
+    match reader().decode() {
+        Ok(image) => Ok(image.into_rgba8()),
+        Err(image::ImageError::Limits(_)) => Err(too_large()),
+        Err(e) => Err(reject("400 Bad Request", format!("not a PNG: {e}"))),
+    }
+}
+
+fn respond_error(stream: &mut impl Write, rejection: &Rejection) -> io::Result<()> {
+    let body = format!("{}\n", rejection.message);
+    respond(stream, rejection.status, "text/plain", body.as_bytes())
+}
+
+fn respond(
+    stream: &mut impl Write,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A connection that reads a request from memory and collects the response.
+    struct MemoryStream {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const LIMITS: RequestLimits = RequestLimits {
+        max_body_bytes: 1 << 10,
+        max_pixels: 64,
+    };
+
+    /// Send a request and return the response status line and body.
+    fn request(head: &str, body: &[u8]) -> (String, Vec<u8>) {
+        let mut stream = MemoryStream {
+            request: Cursor::new([head.as_bytes(), b"\r\n", body].concat()),
+            response: Vec::new(),
+        };
+        answer(&mut stream, &Profile::default(), &LIMITS).unwrap();
+
+        let response = stream.response;
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("response has headers");
+        let head = String::from_utf8(response[..end].to_vec()).unwrap();
+        let status = head.lines().next().unwrap().to_string();
+        (status, response[end + 4..].to_vec())
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([x as u8 * 20, y as u8 * 20, 200, 255])
+        });
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn scales_posted_images() {
+        let body = png(3, 2);
+        let head = format!(
+            "POST /scale?factor=3 HTTP/1.1\r\nContent-Length: {}\r\n",
+            body.len()
+        );
+        let (status, response) = request(&head, &body);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+
+        let scaled = image::load_from_memory_with_format(&response, ImageFormat::Png).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (9, 6));
+    }
+
+    #[test]
+    fn rejects_requests_it_cannot_answer() {
+        let cases = [
+            ("GET /other HTTP/1.1\r\n", "404 Not Found"),
+            ("GET /scale HTTP/1.1\r\n", "405 Method Not Allowed"),
+            ("POST /scale HTTP/1.1\r\n", "411 Length Required"),
+            (
+                "POST /scale HTTP/1.1\r\nContent-Length: 1025\r\n",
+                "413 Payload Too Large",
+            ),
+            (
+                "POST /scale?factor=7 HTTP/1.1\r\nContent-Length: 0\r\n",
+                "400 Bad Request",
+            ),
+            ("nonsense\r\n", "400 Bad Request"),
+        ];
+        for (head, expected) in cases {
+            let (status, body) = request(head, b"");
+            assert_eq!(status, format!("HTTP/1.1 {expected}"), "{head:?}");
+            assert!(!body.is_empty());
+        }
+    }
+
+    #[test]
+    fn rejects_images_over_the_pixel_limit() {
+        let body = png(9, 8);
+        let head = format!("POST /scale HTTP/1.1\r\nContent-Length: {}\r\n", body.len());
+        let (status, message) = request(&head, &body);
+        assert_eq!(status, "HTTP/1.1 413 Payload Too Large");
+        assert!(String::from_utf8(message).unwrap().contains("64 pixels"));
+    }
+
+    #[test]
+    fn rejects_oversized_headers() {
+        let filler = format!("X-Filler: {}\r\n", "a".repeat(MAX_HEADER_BYTES as usize));
+        let (status, _) = request(&format!("POST /scale HTTP/1.1\r\n{filler}"), b"");
+        assert_eq!(status, "HTTP/1.1 431 Request Header Fields Too Large");
+
+        let endless = "a".repeat(MAX_HEADER_BYTES as usize * 2);
+        let (status, _) = request(&endless, b"");
+        assert_eq!(status, "HTTP/1.1 431 Request Header Fields Too Large");
+    }
+
+    #[test]
+    fn turns_away_requests_over_the_concurrency_limit() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let first = admit(&active, 1).expect("first request is admitted");
+        assert!(admit(&active, 1).is_none());
+        drop(first);
+        assert!(admit(&active, 1).is_some());
+
+        let mut response = Vec::new();
+        let busy = reject("503 Service Unavailable", "too many requests in progress");
+        respond_error(&mut response, &busy).unwrap();
+        assert!(response.starts_with(b"HTTP/1.1 503 Service Unavailable\r\n"));
+    }
+
+    #[test]
+    fn slow_requests_time_out_at_the_deadline() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut stream = DeadlineStream {
+            stream,
+            deadline: Instant::now() + Duration::from_millis(50),
+        };
+        let error = stream.read(&mut [0; 16]).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+        let error = stream.read(&mut [0; 16]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
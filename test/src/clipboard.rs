@@ -0,0 +1,31 @@
+use std::borrow::Cow;
+
+use arboard::{Clipboard, ImageData};
+use image::RgbaImage;
+
+use crate::error::{CliError, ErrorKind};
+
+/// Read the image on the system clipboard.
+pub fn read() -> Result<RgbaImage, CliError> {
+    let image = Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_image())
+        .map_err(|e| CliError::new(ErrorKind::Input, format!("Clipboard read error: {e}")))?;
+    let (width, height) = (image.width as u32, image.height as u32);
+    Ok(RgbaImage::from_raw(width, height, image.bytes.into_owned())
+        .expect("clipboard images are RGBA of their size"))
+}
+
+/// Replace what's on the system clipboard with an RGBA image.
+///
+/// On Linux, the clipboard is served by the program that set it, so the image is handed over to
+/// the clipboard manager, if one is running, to stay on the clipboard after the CLI exits.
+pub fn write(rgba: &[u8], width: u32, height: u32) -> Result<(), CliError> {
+    let image = ImageData {
+        width: width as usize,
+        height: height as usize,
+        bytes: Cow::Borrowed(rgba),
+    };
+    Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_image(image))
+        .map_err(|e| CliError::new(ErrorKind::Output, format!("Clipboard write error: {e}")))
+}
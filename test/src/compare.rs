@@ -0,0 +1,298 @@
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use xbrz::ScalerConfig;
+
+use crate::hqx;
+
+/// Scaling algorithms that can be shown side by side.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Algorithm {
+    /// xBRZ, with the settings from --profile
+    Xbrz,
+    /// Nearest neighbour
+    Nearest,
+    /// Bilinear interpolation
+    Bilinear,
+    /// xBRZ mixed with a bicubic upscale, with `softness` the share of the bicubic image
+    XbrzHybrid { softness: f32 },
+    /// hq2x, hq3x or hq4x, depending on the factor
+    Hqx,
+}
+
+/// The softness of `hybrid` when none is given.
+const DEFAULT_SOFTNESS: f32 = 0.3;
+
+impl std::str::FromStr for Algorithm {
+    type Err = String;
+
+    /// An algorithm name, with the softness of the hybrid optionally given as `hybrid:SOFTNESS`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "xbrz" => Ok(Algorithm::Xbrz),
+            None if s == "nearest" => Ok(Algorithm::Nearest),
+            None if s == "bilinear" => Ok(Algorithm::Bilinear),
+            None if s == "hqx" => Ok(Algorithm::Hqx),
+            None if s == "hybrid" => Ok(Algorithm::XbrzHybrid {
+                softness: DEFAULT_SOFTNESS,
+            }),
+            Some(("hybrid", softness)) => match softness.parse() {
+                Ok(softness @ 0.0..=1.0) => Ok(Algorithm::XbrzHybrid { softness }),
+                _ => Err(format!(
+                    "expected a softness from 0 to 1, got \"{softness}\""
+                )),
+            },
+            _ => Err(format!(
+                "expected xbrz, nearest, bilinear, hqx or hybrid[:SOFTNESS], got \"{s}\""
+            )),
+        }
+    }
+}
+
+impl Algorithm {
+    fn name(self) -> String {
+        match self {
+            Algorithm::Xbrz => "xbrz".to_string(),
+            Algorithm::Nearest => "nearest".to_string(),
+            Algorithm::Bilinear => "bilinear".to_string(),
+            Algorithm::XbrzHybrid { softness } => format!("hybrid {}", (softness * 100.0).round()),
+            Algorithm::Hqx => "hqx".to_string(),
+        }
+    }
+
+    /// Whether the algorithm can scale by `factor`, which is any factor but for hqx.
+    pub fn supports(self, factor: u32) -> bool {
+        self != Algorithm::Hqx || hqx::FACTORS.contains(&factor)
+    }
+
+    fn scale(self, image: &RgbaImage, factor: u32, config: &ScalerConfig) -> RgbaImage {
+        let (width, height) = (image.width() * factor, image.height() * factor);
+        let algorithm = match self {
+            Algorithm::Xbrz => xbrz::Algorithm::Xbrz,
+            Algorithm::XbrzHybrid { softness } => xbrz::Algorithm::XbrzHybrid { softness },
+            Algorithm::Nearest => {
+                return imageops::resize(image, width, height, FilterType::Nearest)
+            }
+            Algorithm::Bilinear => {
+                return imageops::resize(image, width, height, FilterType::Triangle)
+            }
+            Algorithm::Hqx => return hqx::scale(image, factor),
+        };
+        let scaled = xbrz::scale_rgba_with_algorithm(
+            image,
+            image.width() as usize,
+            image.height() as usize,
+            factor as usize,
+            config,
+            algorithm,
+        );
+        RgbaImage::from_raw(width, height, scaled).expect("scaled image has the right size")
+    }
+}
+
+const BACKGROUND: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const LABEL_COLOUR: Rgba<u8> = Rgba([255, 255, 255, 255]);
+/// Space around each image, and between each label and its image.
+const PADDING: u32 = 8;
+/// Each pixel of the label font is drawn as a square of this size.
+const FONT_SCALE: u32 = 2;
+const GLYPH_WIDTH: u32 = 3;
+const GLYPH_HEIGHT: u32 = 5;
+const LABEL_HEIGHT: u32 = GLYPH_HEIGHT * FONT_SCALE + PADDING;
+
+/// Render a grid with one row per algorithm and one column per factor, each image labelled with
+/// the algorithm and factor used. Every image is given a cell large enough for the largest factor.
+pub fn contact_sheet(
+    image: &RgbaImage,
+    factors: &[u32],
+    algorithms: &[Algorithm],
+    config: &ScalerConfig,
+) -> RgbaImage {
+    let max_factor = factors.iter().copied().max().unwrap_or(1);
+    let labels: Vec<Vec<String>> = algorithms
+        .iter()
+        .map(|algorithm| {
+            factors
+                .iter()
+                .map(|factor| format!("{} x{factor}", algorithm.name()))
+                .collect()
+        })
+        .collect();
+    let label_width = labels
+        .iter()
+        .flatten()
+        .map(|label| text_width(label))
+        .max()
+        .unwrap_or(0);
+
+    let cell_width = (image.width() * max_factor).max(label_width) + PADDING;
+    let cell_height = LABEL_HEIGHT + image.height() * max_factor + PADDING;
+    let mut sheet = RgbaImage::from_pixel(
+        cell_width * factors.len() as u32 + PADDING,
+        cell_height * algorithms.len() as u32 + PADDING,
+        BACKGROUND,
+    );
+
+    for (row, (&algorithm, labels)) in algorithms.iter().zip(&labels).enumerate() {
+        for (column, (&factor, label)) in factors.iter().zip(labels).enumerate() {
+            let x = PADDING + column as u32 * cell_width;
+            let y = PADDING + row as u32 * cell_height;
+            draw_text(&mut sheet, label, x, y);
+
+            let scaled = algorithm.scale(image, factor, config);
+            imageops::overlay(&mut sheet, &scaled, x.into(), (y + LABEL_HEIGHT).into());
+        }
+    }
+
+    sheet
+}
+
+/// Glyphs for the characters used in labels, as rows of 3 pixels from top to bottom,
+/// with the leftmost pixel in the highest bit.
+const FONT: [(char, [u8; 5]); 38] = [
+    ('a', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('b', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('c', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('d', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('e', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('f', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('g', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('h', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('i', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('j', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('k', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('l', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('m', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('n', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('o', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('p', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('r', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('s', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('t', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('u', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('v', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('w', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('x', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b110, 0b001, 0b010, 0b100, 0b111]),
+    ('3', [0b110, 0b001, 0b010, 0b001, 0b110]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b110, 0b001, 0b110]),
+    ('6', [0b011, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b010, 0b010, 0b010]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b110]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+];
+
+/// The width of a label in pixels, with one font pixel between characters.
+fn text_width(text: &str) -> u32 {
+    let chars = text.chars().count() as u32;
+    (chars * (GLYPH_WIDTH + 1)).saturating_sub(1) * FONT_SCALE
+}
+
+/// Draw `text` with its top left corner at `(x, y)`. Characters missing from the font are left blank.
+fn draw_text(image: &mut RgbaImage, text: &str, x: u32, y: u32) {
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, rows)) = FONT
+            .iter()
+            .find(|(glyph, _)| *glyph == c.to_ascii_lowercase())
+        else {
+            continue;
+        };
+        let glyph_x = x + i as u32 * (GLYPH_WIDTH + 1) * FONT_SCALE;
+
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                for dy in 0..FONT_SCALE {
+                    for dx in 0..FONT_SCALE {
+                        let px = glyph_x + column * FONT_SCALE + dx;
+                        let py = y + row as u32 * FONT_SCALE + dy;
+                        if px < image.width() && py < image.height() {
+                            image.put_pixel(px, py, LABEL_COLOUR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn algorithms_parse_by_name() {
+        assert_eq!("xbrz".parse(), Ok(Algorithm::Xbrz));
+        assert_eq!("nearest".parse(), Ok(Algorithm::Nearest));
+        assert_eq!("bilinear".parse(), Ok(Algorithm::Bilinear));
+        assert_eq!(
+            "hybrid".parse(),
+            Ok(Algorithm::XbrzHybrid {
+                softness: DEFAULT_SOFTNESS
+            })
+        );
+        assert_eq!(
+            "hybrid:0.5".parse(),
+            Ok(Algorithm::XbrzHybrid { softness: 0.5 })
+        );
+        assert!("hybrid:1.5".parse::<Algorithm>().is_err());
+        assert!("bicubic".parse::<Algorithm>().is_err());
+
+        assert_eq!("hqx".parse(), Ok(Algorithm::Hqx));
+        assert!("hq5x".parse::<Algorithm>().is_err());
+        assert!(Algorithm::Hqx.supports(4) && !Algorithm::Hqx.supports(5));
+        assert!(Algorithm::Nearest.supports(5));
+    }
+
+    #[test]
+    fn contact_sheet_has_a_labelled_cell_per_combination() {
+        let red = Rgba([200, 40, 40, 255]);
+        let image = RgbaImage::from_pixel(3, 2, red);
+        let factors = [2, 4];
+        let algorithms = [Algorithm::Nearest, Algorithm::XbrzHybrid { softness: 0.5 }];
+        let sheet = contact_sheet(&image, &factors, &algorithms, &ScalerConfig::default());
+
+        // cells are sized for the widest label and the largest factor
+        let cell_width = text_width("hybrid 50 x4") + PADDING;
+        assert!(cell_width > 3 * 4 + PADDING);
+        let cell_height = LABEL_HEIGHT + 2 * 4 + PADDING;
+        assert_eq!(sheet.width(), 2 * cell_width + PADDING);
+        assert_eq!(sheet.height(), 2 * cell_height + PADDING);
+
+        for (row, algorithm) in algorithms.iter().enumerate() {
+            for (column, &factor) in factors.iter().enumerate() {
+                let x = PADDING + column as u32 * cell_width;
+                let y = PADDING + row as u32 * cell_height;
+                // the label starts with a letter, whose top row has at least one pixel set
+                let label_row = (x..x + GLYPH_WIDTH * FONT_SCALE).map(|x| sheet.get_pixel(x, y));
+                assert!(label_row.into_iter().any(|&px| px == LABEL_COLOUR));
+
+                // the scaled image sits below the label, with the background around it
+                let image_y = y + LABEL_HEIGHT;
+                let centre = sheet.get_pixel(x + 3 * factor / 2, image_y + factor);
+                assert_eq!(*centre, red, "{algorithm:?} x{factor}");
+                assert_eq!(*sheet.get_pixel(x + 3 * factor, image_y), BACKGROUND);
+                assert_eq!(*sheet.get_pixel(x, image_y + 2 * factor), BACKGROUND);
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_characters_are_left_blank() {
+        let mut image = RgbaImage::from_pixel(40, 10, BACKGROUND);
+        draw_text(&mut image, "?!", 0, 0);
+        assert!(image.pixels().all(|&px| px == BACKGROUND));
+        draw_text(&mut image, "X", 0, 0);
+        assert_eq!(*image.get_pixel(0, 0), LABEL_COLOUR);
+        assert_eq!(text_width("ab"), 7 * FONT_SCALE);
+    }
+}
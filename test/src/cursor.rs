@@ -0,0 +1,431 @@
+use std::fmt;
+use std::io::Cursor;
+
+use image::codecs::ico::IcoDecoder;
+use image::{DynamicImage, ImageError, RgbaImage};
+use xbrz::ScalerConfig;
+
+/// The resource type in the header of an icon or cursor file.
+const TYPE_ICON: u16 = 1;
+const TYPE_CURSOR: u16 = 2;
+
+/// Each directory entry is 16 bytes, after the 6 byte header.
+const HEADER_LEN: usize = 6;
+const ENTRY_LEN: usize = 16;
+
+/// The size of a `BITMAPINFOHEADER`, which starts each bitmap in the file.
+const BITMAP_HEADER_LEN: u32 = 40;
+
+#[derive(Debug)]
+pub enum CursorError {
+    Image(ImageError),
+    /// The file isn't a valid cursor, or is cut short.
+    Format(String),
+    /// A scaled frame is larger than the 256x256 pixels a cursor file can describe.
+    TooLarge(u32, u32),
+}
+
+impl fmt::Display for CursorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CursorError::Image(e) => write!(f, "{e}"),
+            CursorError::Format(e) => write!(f, "{e}"),
+            CursorError::TooLarge(width, height) => write!(
+                f,
+                "cursor files can't hold {width}x{height} images, only up to 256x256"
+            ),
+        }
+    }
+}
+
+fn format_error(message: &str) -> CursorError {
+    CursorError::Format(message.to_owned())
+}
+
+/// Scale a .cur, .ico or animated .ani cursor file, returning a file of the same kind with every
+/// image scaled by `factor` and the hotspots of cursors moved to match.
+pub fn scale_cursor(
+    bytes: &[u8],
+    factor: u32,
+    config: &ScalerConfig,
+) -> Result<Vec<u8>, CursorError> {
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"ACON") {
+        scale_ani(bytes, factor, config)
+    } else {
+        scale_cur(bytes, factor, config)
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, CursorError> {
+    let bytes = bytes
+        .get(offset..offset + 2)
+        .ok_or_else(|| format_error("unexpected end of file"))?;
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, CursorError> {
+    let bytes = bytes
+        .get(offset..offset + 4)
+        .ok_or_else(|| format_error("unexpected end of file"))?;
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// Scale each image of an icon or cursor file, saving them all as 32-bit bitmaps, which every
+/// version of Windows can load as cursors.
+fn scale_cur(bytes: &[u8], factor: u32, config: &ScalerConfig) -> Result<Vec<u8>, CursorError> {
+    let kind = u16_at(bytes, 2)?;
+    if u16_at(bytes, 0)? != 0 || !matches!(kind, TYPE_ICON | TYPE_CURSOR) {
+        return Err(format_error("not an icon or cursor file"));
+    }
+    let count = u16_at(bytes, 4)? as usize;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut images = Vec::with_capacity(count);
+    for i in 0..count {
+        let entry = bytes
+            .get(HEADER_LEN + i * ENTRY_LEN..HEADER_LEN + (i + 1) * ENTRY_LEN)
+            .ok_or_else(|| format_error("unexpected end of file"))?;
+        let (len, offset) = (u32_at(entry, 8)? as usize, u32_at(entry, 12)? as usize);
+        let data = bytes
+            .get(offset..offset + len)
+            .ok_or_else(|| format_error("image data is outside the file"))?;
+
+        let image = scale(&decode_entry(entry, data)?, factor, config);
+        if image.width() > 256 || image.height() > 256 {
+            return Err(CursorError::TooLarge(image.width(), image.height()));
+        }
+        // cursors store their hotspot where icons store their planes and bit depth
+        let (planes_or_x, bits_or_y) = match kind {
+            TYPE_CURSOR => (
+                u16_at(entry, 4)? * factor as u16,
+                u16_at(entry, 6)? * factor as u16,
+            ),
+            _ => (1, 32),
+        };
+        entries.push((planes_or_x, bits_or_y));
+        images.push(encode_bitmap(&image));
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&kind.to_le_bytes());
+    out.extend_from_slice(&(count as u16).to_le_bytes());
+    let mut offset = HEADER_LEN + count * ENTRY_LEN;
+    for ((planes_or_x, bits_or_y), (width, height, data)) in entries.iter().zip(&images) {
+        // sizes of 256 are stored as 0
+        out.push(*width as u8);
+        out.push(*height as u8);
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&planes_or_x.to_le_bytes());
+        out.extend_from_slice(&bits_or_y.to_le_bytes());
+        out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        out.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += data.len();
+    }
+    for (_, _, data) in &images {
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+/// Decode the image of one directory entry, by wrapping it in an icon file of its own, as the
+/// icon decoder only decodes the largest image of a file.
+fn decode_entry(entry: &[u8], data: &[u8]) -> Result<RgbaImage, CursorError> {
+    let mut ico = Vec::with_capacity(HEADER_LEN + ENTRY_LEN + data.len());
+    ico.extend_from_slice(&[0, 0]);
+    ico.extend_from_slice(&TYPE_ICON.to_le_bytes());
+    ico.extend_from_slice(&1u16.to_le_bytes());
+    // the size and colour count, then 1 plane and an unknown bit depth in place of any hotspot
+    ico.extend_from_slice(&entry[..4]);
+    ico.extend_from_slice(&[1, 0, 0, 0]);
+    ico.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    ico.extend_from_slice(&((HEADER_LEN + ENTRY_LEN) as u32).to_le_bytes());
+    ico.extend_from_slice(data);
+
+    let decoder = IcoDecoder::new(Cursor::new(ico)).map_err(CursorError::Image)?;
+    let image = DynamicImage::from_decoder(decoder).map_err(CursorError::Image)?;
+    Ok(image.into_rgba8())
+}
+
+fn scale(image: &RgbaImage, factor: u32, config: &ScalerConfig) -> RgbaImage {
+    let pixels = xbrz::scale_rgba_with_config(
+        image,
+        image.width() as usize,
+        image.height() as usize,
+        factor as usize,
+        config,
+    );
+    RgbaImage::from_raw(image.width() * factor, image.height() * factor, pixels)
+        .expect("scaled image has the scaled dimensions")
+}
+
+/// Encode an image as the bitmap of an icon or cursor entry: a `BITMAPINFOHEADER` giving twice its
+/// height, its pixels as BGRA from the bottom row up, then a 1-bit mask, set where it's transparent,
+/// for programs that ignore the alpha channel.
+fn encode_bitmap(image: &RgbaImage) -> (u32, u32, Vec<u8>) {
+    let (width, height) = image.dimensions();
+    // each row of the mask is padded to a multiple of 4 bytes
+    let mask_stride = width.div_ceil(32) as usize * 4;
+    let pixels_len = (width * height * 4) as usize;
+
+    let mut data = Vec::with_capacity(BITMAP_HEADER_LEN as usize + pixels_len);
+    data.extend_from_slice(&BITMAP_HEADER_LEN.to_le_bytes());
+    data.extend_from_slice(&(width as i32).to_le_bytes());
+    data.extend_from_slice(&(height as i32 * 2).to_le_bytes());
+    data.extend_from_slice(&1u16.to_le_bytes());
+    data.extend_from_slice(&32u16.to_le_bytes());
+    // no compression, then the image size, resolution and palette, all of which can be left as 0
+    data.extend_from_slice(&[0; 24]);
+
+    for row in image.rows().rev() {
+        for pixel in row {
+            let [r, g, b, a] = pixel.0;
+            data.extend_from_slice(&[b, g, r, a]);
+        }
+    }
+    for row in image.rows().rev() {
+        let mut mask = vec![0u8; mask_stride];
+        for (x, pixel) in row.enumerate() {
+            if pixel.0[3] == 0 {
+                mask[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        data.extend_from_slice(&mask);
+    }
+    (width, height, data)
+}
+
+/// Scale an animated cursor: a RIFF file holding an `anih` header and a `LIST` of `fram` chunks,
+/// each an icon or cursor file. Every other chunk, such as the frame rates and sequence, is copied
+/// as it is.
+fn scale_ani(bytes: &[u8], factor: u32, config: &ScalerConfig) -> Result<Vec<u8>, CursorError> {
+    let len = u32_at(bytes, 4)? as usize;
+    let body = bytes
+        .get(12..8 + len)
+        .ok_or_else(|| format_error("unexpected end of file"))?;
+
+    let mut out = b"RIFF\0\0\0\0ACON".to_vec();
+    for (id, data) in chunks(body)? {
+        match id {
+            b"anih" => {
+                // the width and height, which are usually 0 as the frames give their own sizes
+                let mut header = data.to_vec();
+                for offset in [12, 16] {
+                    if let Some(size) = header.get_mut(offset..offset + 4) {
+                        let scaled = u32::from_le_bytes(size.try_into().unwrap()) * factor;
+                        size.copy_from_slice(&scaled.to_le_bytes());
+                    }
+                }
+                push_chunk(&mut out, id, &header);
+            }
+            b"LIST" if data.starts_with(b"fram") => {
+                let mut frames = b"fram".to_vec();
+                for (id, frame) in chunks(&data[4..])? {
+                    let frame = match id {
+                        b"icon" => scale_cur(frame, factor, config)?,
+                        _ => frame.to_vec(),
+                    };
+                    push_chunk(&mut frames, id, &frame);
+                }
+                push_chunk(&mut out, id, &frames);
+            }
+            _ => push_chunk(&mut out, id, data),
+        }
+    }
+    let len = out.len() as u32 - 8;
+    out[4..8].copy_from_slice(&len.to_le_bytes());
+    Ok(out)
+}
+
+/// The id and data of a chunk of a RIFF file.
+type Chunk<'a> = (&'a [u8; 4], &'a [u8]);
+
+/// Split the body of a RIFF file or list into its chunks.
+fn chunks(mut body: &[u8]) -> Result<Vec<Chunk<'_>>, CursorError> {
+    let mut chunks = Vec::new();
+    while body.len() >= 8 {
+        let id: &[u8; 4] = body[..4].try_into().unwrap();
+        let len = u32_at(body, 4)? as usize;
+        let data = body
+            .get(8..8 + len)
+            .ok_or_else(|| format_error("unexpected end of file"))?;
+        chunks.push((id, data));
+        // chunks are padded to an even length
+        body = body.get(8 + len + len % 2..).unwrap_or_default();
+    }
+    Ok(chunks)
+}
+
+fn push_chunk(out: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(id);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn arrow(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| match (x + y) % 3 {
+            0 => Rgba([0, 0, 0, 0]),
+            1 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        })
+    }
+
+    /// A cursor file holding `images`, each with its hotspot.
+    fn cursor_file(images: &[(RgbaImage, u16, u16)]) -> Vec<u8> {
+        let mut file = vec![0, 0];
+        file.extend_from_slice(&TYPE_CURSOR.to_le_bytes());
+        file.extend_from_slice(&(images.len() as u16).to_le_bytes());
+        let bitmaps: Vec<_> = images
+            .iter()
+            .map(|(image, ..)| encode_bitmap(image))
+            .collect();
+        let mut offset = HEADER_LEN + images.len() * ENTRY_LEN;
+        for ((_, x, y), (width, height, data)) in images.iter().zip(&bitmaps) {
+            file.extend_from_slice(&[*width as u8, *height as u8, 0, 0]);
+            file.extend_from_slice(&x.to_le_bytes());
+            file.extend_from_slice(&y.to_le_bytes());
+            file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            file.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += data.len();
+        }
+        for (_, _, data) in &bitmaps {
+            file.extend_from_slice(data);
+        }
+        file
+    }
+
+    fn entry(file: &[u8], i: usize) -> &[u8] {
+        &file[HEADER_LEN + i * ENTRY_LEN..][..ENTRY_LEN]
+    }
+
+    #[test]
+    fn cursor_hotspots_move_with_the_image() {
+        let config = ScalerConfig::default();
+        let images = [(arrow(8, 4), 3, 1), (arrow(16, 16), 15, 0)];
+        let scaled = scale_cursor(&cursor_file(&images), 3, &config).unwrap();
+
+        assert_eq!(scaled[..6], [0, 0, 2, 0, 2, 0]);
+        let mut offset = HEADER_LEN + 2 * ENTRY_LEN;
+        for (i, (image, x, y)) in images.iter().enumerate() {
+            let entry = entry(&scaled, i);
+            let (width, height) = (image.width() * 3, image.height() * 3);
+            assert_eq!(entry[..4], [width as u8, height as u8, 0, 0]);
+            assert_eq!(u16_at(entry, 4).unwrap(), x * 3);
+            assert_eq!(u16_at(entry, 6).unwrap(), y * 3);
+            let len = u32_at(entry, 8).unwrap() as usize;
+            assert_eq!(u32_at(entry, 12).unwrap() as usize, offset);
+
+            // a 32-bit bitmap of twice the height, then the pixels and a padded 1-bit mask
+            let data = &scaled[offset..offset + len];
+            assert_eq!(u32_at(data, 0).unwrap(), BITMAP_HEADER_LEN);
+            assert_eq!(u32_at(data, 4).unwrap(), width);
+            assert_eq!(u32_at(data, 8).unwrap(), height * 2);
+            assert_eq!(u16_at(data, 14).unwrap(), 32);
+            let mask_len = (width.div_ceil(32) * 4 * height) as usize;
+            assert_eq!(len, 40 + (width * height * 4) as usize + mask_len);
+            assert_eq!(decode_entry(entry, data).unwrap(), scale(image, 3, &config));
+            offset += len;
+        }
+        assert_eq!(scaled.len(), offset);
+    }
+
+    #[test]
+    fn icons_are_saved_as_32_bit_images() {
+        let mut file = cursor_file(&[(arrow(64, 64), 10, 20)]);
+        file[2..4].copy_from_slice(&TYPE_ICON.to_le_bytes());
+        let scaled = scale_cursor(&file, 4, &ScalerConfig::default()).unwrap();
+
+        // 256 pixels is stored as 0, and the planes and bit depth take the place of the hotspot
+        let entry = entry(&scaled, 0);
+        assert_eq!(entry[..4], [0, 0, 0, 0]);
+        assert_eq!(
+            (u16_at(entry, 4).unwrap(), u16_at(entry, 6).unwrap()),
+            (1, 32)
+        );
+
+        assert!(matches!(
+            scale_cursor(&file, 5, &ScalerConfig::default()),
+            Err(CursorError::TooLarge(320, 320))
+        ));
+    }
+
+    #[test]
+    fn malformed_cursors_are_errors() {
+        let file = cursor_file(&[(arrow(4, 4), 0, 0)]);
+        let config = ScalerConfig::default();
+        for len in [0, 5, HEADER_LEN + 8, file.len() - 1] {
+            assert!(
+                matches!(
+                    scale_cursor(&file[..len], 2, &config),
+                    Err(CursorError::Format(_))
+                ),
+                "{len} bytes"
+            );
+        }
+        let mut wrong_kind = file.clone();
+        wrong_kind[2] = 3;
+        assert!(matches!(
+            scale_cursor(&wrong_kind, 2, &config),
+            Err(CursorError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn animated_cursors_keep_their_chunks() {
+        let frame = cursor_file(&[(arrow(8, 8), 2, 5)]);
+        let mut anih = vec![0; 36];
+        anih[0..4].copy_from_slice(&36u32.to_le_bytes());
+        anih[12..16].copy_from_slice(&8u32.to_le_bytes());
+        anih[16..20].copy_from_slice(&8u32.to_le_bytes());
+        // an odd length, so that the chunk is padded
+        let name = b"arrow".to_vec();
+
+        let mut frames = b"fram".to_vec();
+        push_chunk(&mut frames, b"icon", &frame);
+        push_chunk(&mut frames, b"icon", &frame);
+        let mut info = b"INFO".to_vec();
+        push_chunk(&mut info, b"INAM", &name);
+        let mut ani = b"RIFF\0\0\0\0ACON".to_vec();
+        push_chunk(&mut ani, b"LIST", &info);
+        push_chunk(&mut ani, b"anih", &anih);
+        push_chunk(&mut ani, b"rate", &[10, 0, 0, 0, 20, 0, 0, 0]);
+        push_chunk(&mut ani, b"LIST", &frames);
+        let len = ani.len() as u32 - 8;
+        ani[4..8].copy_from_slice(&len.to_le_bytes());
+
+        let config = ScalerConfig::default();
+        let scaled = scale_cursor(&ani, 2, &config).unwrap();
+        assert_eq!(scaled[..4], *b"RIFF");
+        assert_eq!(u32_at(&scaled, 4).unwrap() as usize, scaled.len() - 8);
+        assert_eq!(scaled[8..12], *b"ACON");
+
+        let riff = chunks(&scaled[12..]).unwrap();
+        let ids: Vec<_> = riff.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, [b"LIST", b"anih", b"rate", b"LIST"]);
+        assert_eq!(riff[0].1, info);
+        assert_eq!(u32_at(riff[1].1, 12).unwrap(), 16);
+        assert_eq!(u32_at(riff[1].1, 16).unwrap(), 16);
+        assert_eq!(riff[1].1[20..], anih[20..]);
+        assert_eq!(riff[2].1, [10, 0, 0, 0, 20, 0, 0, 0]);
+
+        let (frame_list, expected) = (riff[3].1, scale_cursor(&frame, 2, &config).unwrap());
+        assert_eq!(frame_list[..4], *b"fram");
+        let frames = chunks(&frame_list[4..]).unwrap();
+        assert_eq!(frames.len(), 2);
+        for (id, frame) in frames {
+            assert_eq!(id, b"icon");
+            assert_eq!(frame, expected);
+            assert_eq!(u16_at(entry(frame, 0), 4).unwrap(), 4);
+            assert_eq!(u16_at(entry(frame, 0), 6).unwrap(), 10);
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::fmt;
+use std::process::ExitCode;
+
+use serde::Serialize;
+
+/// What went wrong, which decides the exit code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Anything else, such as a server or watcher that stopped. Exits with 1.
+    Other,
+    /// The arguments couldn't be parsed. Exits with 2, as clap does.
+    Usage,
+    /// An input image, profile or manifest couldn't be read. Exits with 3.
+    Input,
+    /// The image couldn't be scaled, such as when the scaled image would be too large. Exits with 4.
+    Scale,
+    /// An output file couldn't be written. Exits with 5.
+    Output,
+    /// Some of the jobs in a manifest failed, as listed in the report. Exits with 6.
+    Jobs,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Usage => 2,
+            ErrorKind::Input => 3,
+            ErrorKind::Scale => 4,
+            ErrorKind::Output => 5,
+            ErrorKind::Jobs => 6,
+        }
+    }
+}
+
+/// An error that stops the CLI, printed to stderr before it exits.
+#[derive(Debug, Serialize)]
+pub struct CliError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl CliError {
+    pub fn new(kind: ErrorKind, message: impl fmt::Display) -> Self {
+        Self {
+            kind,
+            message: message.to_string(),
+        }
+    }
+
+    /// Print the error, either as text or as a line of JSON along with its kind and exit code,
+    /// and return the exit code.
+    pub fn report(&self, json: bool) -> ExitCode {
+        let code = self.kind.exit_code();
+        if json {
+            #[derive(Serialize)]
+            struct Report<'a> {
+                error: &'a CliError,
+                exit_code: u8,
+            }
+            let report = Report {
+                error: self,
+                exit_code: code,
+            };
+            eprintln!(
+                "{}",
+                serde_json::to_string(&report).expect("errors can be serialized")
+            );
+        } else {
+            eprintln!("{}", self.message);
+        }
+        ExitCode::from(code)
+    }
+}
+
+impl From<xbrz::ScaleError> for CliError {
+    fn from(e: xbrz::ScaleError) -> Self {
+        CliError::new(ErrorKind::Scale, format!("Scaling error: {e}"))
+    }
+}
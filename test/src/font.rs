@@ -0,0 +1,520 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use image::{imageops, ImageError, RgbaImage};
+use serde_json::Value;
+use xbrz::ScalerConfig;
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Image(ImageError),
+    /// The descriptor is missing a value, or uses a feature that isn't supported.
+    Format(String),
+    /// A scaled page couldn't be saved.
+    Save(ImageError),
+    /// The scaled descriptor couldn't be written.
+    Write(io::Error),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io(e) => write!(f, "{e}"),
+            FontError::Json(e) => write!(f, "{e}"),
+            FontError::Image(e) => write!(f, "{e}"),
+            FontError::Format(e) => write!(f, "{e}"),
+            FontError::Save(e) => write!(f, "{e}"),
+            FontError::Write(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// The values of each kind of line or object in a descriptor that are measured in pixels.
+fn scaled_keys(tag: &str) -> &'static [&'static str] {
+    match tag {
+        "info" => &["size", "padding", "spacing", "outline"],
+        "common" => &["lineHeight", "base", "scaleW", "scaleH"],
+        "char" | "chars" => &[
+            "x", "y", "width", "height", "xoffset", "yoffset", "xadvance",
+        ],
+        "kerning" | "kernings" => &["amount"],
+        _ => &[],
+    }
+}
+
+/// Glyphs drawn across all channels; fonts with glyphs packed into single channels can't be scaled.
+fn is_all_channels(chnl: i64) -> bool {
+    chnl == 0 || chnl == 15
+}
+
+/// Where a glyph is drawn in the page textures.
+struct Glyph {
+    page: usize,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Scale the pages of the BMFont at `input` one glyph at a time, and save them next to `output`
+/// along with a descriptor with every size, offset, advance and kerning amount multiplied by `factor`.
+/// Returns the paths of the scaled pages.
+///
+/// Descriptors may be in the text format, or the JSON format when the file has a `.json` extension.
+pub fn scale_font(
+    input: &Path,
+    output: &Path,
+    factor: u32,
+    config: &ScalerConfig,
+) -> Result<Vec<PathBuf>, FontError> {
+    let text = fs::read_to_string(input).map_err(FontError::Io)?;
+    let is_json = input
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+    let mut json = None;
+    let (pages, glyphs) = match is_json {
+        true => {
+            let value = serde_json::from_str(&text).map_err(FontError::Json)?;
+            let layout = read_json(&value)?;
+            json = Some(value);
+            layout
+        }
+        false => read_text(&text)?,
+    };
+
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let mut scaled_pages = Vec::with_capacity(pages.len());
+    let mut scaled_paths = Vec::with_capacity(pages.len());
+    for (i, page) in pages.iter().enumerate() {
+        let image = image::open(input.with_file_name(page))
+            .map_err(FontError::Image)?
+            .into_rgba8();
+        let page_glyphs = glyphs.iter().filter(|glyph| glyph.page == i);
+        let scaled = scale_glyphs(&image, page_glyphs, factor, config);
+
+        let name = format!("{stem}_{i}.png");
+        let path = output.with_file_name(&name);
+        scaled.save(&path).map_err(FontError::Save)?;
+        scaled_pages.push(name);
+        scaled_paths.push(path);
+    }
+
+    let descriptor = match json {
+        Some(mut value) => {
+            rewrite_json(&mut value, factor, &scaled_pages);
+            serde_json::to_string_pretty(&value).map_err(FontError::Json)?
+        }
+        None => rewrite_text(&text, factor, &scaled_pages),
+    };
+    fs::write(output, descriptor).map_err(FontError::Write)?;
+    Ok(scaled_paths)
+}
+
+/// Scale each glyph on its own, so that glyphs packed next to each other don't blend together.
+/// Anything outside of the glyphs is left transparent.
+fn scale_glyphs<'a>(
+    image: &RgbaImage,
+    glyphs: impl Iterator<Item = &'a Glyph>,
+    factor: u32,
+    config: &ScalerConfig,
+) -> RgbaImage {
+    let mut scaled = RgbaImage::new(image.width() * factor, image.height() * factor);
+    for glyph in glyphs {
+        let width = glyph.width.min(image.width().saturating_sub(glyph.x));
+        let height = glyph.height.min(image.height().saturating_sub(glyph.y));
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let pixels = imageops::crop_imm(image, glyph.x, glyph.y, width, height).to_image();
+        let pixels = xbrz::scale_rgba_with_config(
+            &pixels,
+            width as usize,
+            height as usize,
+            factor as usize,
+            config,
+        );
+        let pixels = RgbaImage::from_raw(width * factor, height * factor, pixels)
+            .expect("scaled image has the right size");
+        imageops::replace(
+            &mut scaled,
+            &pixels,
+            (glyph.x * factor).into(),
+            (glyph.y * factor).into(),
+        );
+    }
+    scaled
+}
+
+/// Multiply a value, or each of a list of comma separated values, leaving anything else unchanged.
+fn scale_values(value: &str, factor: u32) -> String {
+    value
+        .split(',')
+        .map(|n| match n.parse::<i64>() {
+            Ok(n) => (n * factor as i64).to_string(),
+            Err(_) => n.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// The `key=value` pairs after the tag of a line of a text descriptor, with the position of each
+/// value in the line. Quoted values keep their quotes.
+fn text_fields(line: &str) -> Vec<(&str, usize, usize)> {
+    let mut fields = Vec::new();
+    let bytes = line.as_bytes();
+    let mut i = line.find(' ').unwrap_or(line.len());
+
+    while i < bytes.len() {
+        if bytes[i] == b' ' {
+            i += 1;
+            continue;
+        }
+        let key_start = i;
+        while i < bytes.len() && bytes[i] != b'=' && bytes[i] != b' ' {
+            i += 1;
+        }
+        if i == bytes.len() || bytes[i] != b'=' {
+            continue;
+        }
+        let key = &line[key_start..i];
+        i += 1;
+
+        let value_start = i;
+        if bytes.get(i) == Some(&b'"') {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b'"' {
+                i += 1;
+            }
+            i = (i + 1).min(bytes.len());
+        } else {
+            while i < bytes.len() && bytes[i] != b' ' {
+                i += 1;
+            }
+        }
+        fields.push((key, value_start, i));
+    }
+    fields
+}
+
+fn text_tag(line: &str) -> &str {
+    line.split(' ').next().unwrap_or_default()
+}
+
+fn number_field(line: &str, fields: &[(&str, usize, usize)], key: &str) -> Result<i64, FontError> {
+    fields
+        .iter()
+        .find(|(k, ..)| *k == key)
+        .and_then(|&(_, start, end)| line[start..end].parse().ok())
+        .ok_or_else(|| FontError::Format(format!("missing or invalid {key} in \"{line}\"")))
+}
+
+fn read_text(text: &str) -> Result<(Vec<String>, Vec<Glyph>), FontError> {
+    let mut pages = Vec::new();
+    let mut glyphs = Vec::new();
+
+    for line in text.lines() {
+        let fields = text_fields(line);
+        match text_tag(line) {
+            "page" => {
+                let id = number_field(line, &fields, "id")? as usize;
+                let file = fields
+                    .iter()
+                    .find(|(key, ..)| *key == "file")
+                    .map(|&(_, start, end)| line[start..end].trim_matches('"').to_string())
+                    .ok_or_else(|| FontError::Format(format!("missing file in \"{line}\"")))?;
+                if pages.len() <= id {
+                    pages.resize(id + 1, String::new());
+                }
+                pages[id] = file;
+            }
+            "char" => {
+                let chnl = number_field(line, &fields, "chnl").unwrap_or(15);
+                if !is_all_channels(chnl) {
+                    return Err(FontError::Format(
+                        "glyphs packed into separate channels aren't supported".into(),
+                    ));
+                }
+                glyphs.push(Glyph {
+                    page: number_field(line, &fields, "page").unwrap_or(0) as usize,
+                    x: number_field(line, &fields, "x")? as u32,
+                    y: number_field(line, &fields, "y")? as u32,
+                    width: number_field(line, &fields, "width")? as u32,
+                    height: number_field(line, &fields, "height")? as u32,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok((pages, glyphs))
+}
+
+/// Copy a text descriptor, scaling the values listed in [`scaled_keys`] and pointing each page at
+/// its scaled texture. The spacing between values and any other lines are kept as they are.
+fn rewrite_text(text: &str, factor: u32, pages: &[String]) -> String {
+    let mut rewritten = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let tag = text_tag(line.trim_end());
+        let keys = scaled_keys(tag);
+        let fields = text_fields(line.trim_end());
+        let page = fields
+            .iter()
+            .find(|(key, ..)| *key == "id")
+            .and_then(|&(_, start, end)| line[start..end].parse::<usize>().ok());
+
+        let mut copied = 0;
+        for (key, start, end) in fields {
+            let value = match key {
+                "file" if tag == "page" => match page.and_then(|id| pages.get(id)) {
+                    Some(file) => format!("\"{file}\""),
+                    None => continue,
+                },
+                key if keys.contains(&key) => scale_values(&line[start..end], factor),
+                _ => continue,
+            };
+            rewritten.push_str(&line[copied..start]);
+            rewritten.push_str(&value);
+            copied = end;
+        }
+        rewritten.push_str(&line[copied..]);
+    }
+    rewritten
+}
+
+fn json_number(glyph: &Value, key: &str) -> Result<u32, FontError> {
+    glyph[key]
+        .as_u64()
+        .map(|n| n as u32)
+        .ok_or_else(|| FontError::Format(format!("missing or invalid {key} in {glyph}")))
+}
+
+fn read_json(value: &Value) -> Result<(Vec<String>, Vec<Glyph>), FontError> {
+    let pages = value["pages"]
+        .as_array()
+        .ok_or_else(|| FontError::Format("descriptor has no pages".into()))?
+        .iter()
+        .map(|page| page.as_str().unwrap_or_default().to_string())
+        .collect();
+
+    let chars = value["chars"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let mut glyphs = Vec::with_capacity(chars.len());
+    for glyph in chars {
+        if !is_all_channels(glyph["chnl"].as_i64().unwrap_or(15)) {
+            return Err(FontError::Format(
+                "glyphs packed into separate channels aren't supported".into(),
+            ));
+        }
+        glyphs.push(Glyph {
+            page: glyph["page"].as_u64().unwrap_or(0) as usize,
+            x: json_number(glyph, "x")?,
+            y: json_number(glyph, "y")?,
+            width: json_number(glyph, "width")?,
+            height: json_number(glyph, "height")?,
+        });
+    }
+    Ok((pages, glyphs))
+}
+
+/// Multiply a number, or each number of an array, in place.
+fn scale_json_value(value: &mut Value, factor: u32) {
+    match value {
+        Value::Number(n) => {
+            if let Some(n) = n.as_i64() {
+                *value = (n * factor as i64).into();
+            } else if let Some(n) = n.as_f64() {
+                *value = (n * factor as f64).into();
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| scale_json_value(value, factor)),
+        _ => {}
+    }
+}
+
+/// Scale the values listed in [`scaled_keys`] of a JSON descriptor in place, and point it at the scaled pages.
+fn rewrite_json(value: &mut Value, factor: u32, pages: &[String]) {
+    for section in ["info", "common", "chars", "kernings"] {
+        let keys = scaled_keys(section);
+        let Some(section) = value.get_mut(section) else {
+            continue;
+        };
+        let objects = match section {
+            Value::Array(objects) => objects.iter_mut().collect(),
+            object => vec![object],
+        };
+        for object in objects {
+            for (key, value) in object.as_object_mut().into_iter().flatten() {
+                if keys.contains(&key.as_str()) {
+                    scale_json_value(value, factor);
+                }
+            }
+        }
+    }
+    value["pages"] = pages
+        .iter()
+        .map(|page| Value::from(page.as_str()))
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    const DESCRIPTOR: &str = "info face=\"Pixel Font\" size=8 bold=0 padding=0,1,0,1 spacing=1,1\n\
+        common lineHeight=10 base=8 scaleW=16 scaleH=8 pages=1\n\
+        page id=0 file=\"font_0.png\"\n\
+        chars count=2\n\
+        char id=65   x=0 y=0 width=5 height=7 xoffset=0 yoffset=1 xadvance=6 page=0 chnl=15\n\
+        char id=66   x=6 y=0 width=5 height=7 xoffset=-1 yoffset=1 xadvance=6 page=0 chnl=15\n\
+        kerning first=65 second=66 amount=-1\n";
+
+    /// A page with two glyphs drawn right next to each other, in different colours.
+    fn page() -> RgbaImage {
+        RgbaImage::from_fn(16, 8, |x, y| match (x, y) {
+            (0..=4, 0..=6) if (x + y) % 2 == 0 => Rgba([255, 255, 255, 255]),
+            (6..=10, 0..=6) if x == 6 || y == 3 => Rgba([200, 40, 40, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        })
+    }
+
+    fn glyph_positions(glyphs: &[Glyph]) -> Vec<(usize, u32, u32, u32, u32)> {
+        glyphs
+            .iter()
+            .map(|g| (g.page, g.x, g.y, g.width, g.height))
+            .collect()
+    }
+
+    #[test]
+    fn text_descriptors_are_scaled_in_place() {
+        let (pages, glyphs) = read_text(DESCRIPTOR).unwrap();
+        assert_eq!(pages, ["font_0.png"]);
+        assert_eq!(glyph_positions(&glyphs), [(0, 0, 0, 5, 7), (0, 6, 0, 5, 7)]);
+
+        let scaled = rewrite_text(DESCRIPTOR, 3, &["big_0.png".to_string()]);
+        assert_eq!(
+            scaled,
+            "info face=\"Pixel Font\" size=24 bold=0 padding=0,3,0,3 spacing=3,3\n\
+            common lineHeight=30 base=24 scaleW=48 scaleH=24 pages=1\n\
+            page id=0 file=\"big_0.png\"\n\
+            chars count=2\n\
+            char id=65   x=0 y=0 width=15 height=21 xoffset=0 yoffset=3 xadvance=18 page=0 chnl=15\n\
+            char id=66   x=18 y=0 width=15 height=21 xoffset=-3 yoffset=3 xadvance=18 page=0 chnl=15\n\
+            kerning first=65 second=66 amount=-3\n"
+        );
+        let (_, glyphs) = read_text(&scaled).unwrap();
+        assert_eq!(
+            glyph_positions(&glyphs),
+            [(0, 0, 0, 15, 21), (0, 18, 0, 15, 21)]
+        );
+    }
+
+    #[test]
+    fn json_descriptors_are_scaled_in_place() {
+        let mut value: Value = serde_json::from_str(
+            r#"{
+                "pages": ["font_0.png"],
+                "info": {"face": "Pixel Font", "size": 8, "padding": [0, 1, 0, 1]},
+                "common": {"lineHeight": 10, "base": 8, "scaleW": 16, "scaleH": 8},
+                "chars": [
+                    {"id": 65, "x": 0, "y": 0, "width": 5, "height": 7, "xadvance": 6, "page": 0},
+                    {"id": 66, "x": 6, "y": 0, "width": 5, "height": 7, "xoffset": -1, "chnl": 15}
+                ],
+                "kernings": [{"first": 65, "second": 66, "amount": -1}]
+            }"#,
+        )
+        .unwrap();
+        let (pages, glyphs) = read_json(&value).unwrap();
+        assert_eq!(pages, ["font_0.png"]);
+        assert_eq!(glyph_positions(&glyphs), [(0, 0, 0, 5, 7), (0, 6, 0, 5, 7)]);
+
+        rewrite_json(&mut value, 2, &["big_0.png".to_string()]);
+        assert_eq!(value["pages"], serde_json::json!(["big_0.png"]));
+        assert_eq!(value["info"]["size"], 16);
+        assert_eq!(value["info"]["padding"], serde_json::json!([0, 2, 0, 2]));
+        assert_eq!(value["info"]["face"], "Pixel Font");
+        assert_eq!(value["common"]["scaleW"], 32);
+        assert_eq!(value["chars"][0]["id"], 65);
+        assert_eq!(value["chars"][0]["xadvance"], 12);
+        assert_eq!(value["chars"][1]["xoffset"], -2);
+        assert_eq!(value["kernings"][0]["amount"], -2);
+        let (_, glyphs) = read_json(&value).unwrap();
+        assert_eq!(
+            glyph_positions(&glyphs),
+            [(0, 0, 0, 10, 14), (0, 12, 0, 10, 14)]
+        );
+    }
+
+    #[test]
+    fn single_channel_glyphs_are_errors() {
+        let packed = DESCRIPTOR.replace("chnl=15", "chnl=4");
+        assert!(matches!(read_text(&packed), Err(FontError::Format(_))));
+        let missing = DESCRIPTOR.replace(" width=5", "");
+        assert!(matches!(read_text(&missing), Err(FontError::Format(_))));
+    }
+
+    #[test]
+    fn glyphs_are_scaled_on_their_own() {
+        let page = page();
+        let config = ScalerConfig::default();
+        let (_, glyphs) = read_text(DESCRIPTOR).unwrap();
+        let scaled = scale_glyphs(&page, glyphs.iter(), 4, &config);
+        assert_eq!(scaled.dimensions(), (64, 32));
+
+        for glyph in &glyphs {
+            let pixels =
+                imageops::crop_imm(&page, glyph.x, glyph.y, glyph.width, glyph.height).to_image();
+            let expected = xbrz::scale_rgba_with_config(
+                &pixels,
+                glyph.width as usize,
+                glyph.height as usize,
+                4,
+                &config,
+            );
+            let actual = imageops::crop_imm(
+                &scaled,
+                glyph.x * 4,
+                glyph.y * 4,
+                glyph.width * 4,
+                glyph.height * 4,
+            )
+            .to_image();
+            assert!(actual.into_raw() == expected, "glyph at {}", glyph.x);
+        }
+        // the gap between the glyphs and the rows below them stay empty
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            if (20..24).contains(&x) || x >= 44 || y >= 28 {
+                assert_eq!(pixel.0, [0; 4], "({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn fonts_are_saved_with_their_scaled_pages() {
+        let dir = std::env::temp_dir().join(format!("xbrz-font-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        page().save(dir.join("font_0.png")).unwrap();
+        fs::write(dir.join("font.fnt"), DESCRIPTOR).unwrap();
+
+        let config = ScalerConfig::default();
+        let output = dir.join("big.fnt");
+        let paths = scale_font(&dir.join("font.fnt"), &output, 2, &config).unwrap();
+        assert_eq!(paths, [dir.join("big_0.png")]);
+
+        let descriptor = fs::read_to_string(&output).unwrap();
+        assert_eq!(
+            descriptor,
+            rewrite_text(DESCRIPTOR, 2, &["big_0.png".into()])
+        );
+        let (_, glyphs) = read_text(DESCRIPTOR).unwrap();
+        let expected = scale_glyphs(&page(), glyphs.iter(), 2, &config);
+        assert_eq!(image::open(&paths[0]).unwrap().into_rgba8(), expected);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
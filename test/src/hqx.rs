@@ -0,0 +1,273 @@
+use image::{Rgba, RgbaImage};
+
+/// The factors that [`scale`] supports, as hq2x, hq3x and hq4x.
+pub const FACTORS: [u32; 3] = [2, 3, 4];
+
+/// Largest differences in luma, blue and red chroma, and alpha between pixels that are treated as
+/// the same colour, on the 0 to 255 scale. The colour thresholds are those of the original hqx.
+const THRESHOLDS: [i32; 4] = [48, 7, 6, 48];
+
+/// Scale up an image with hqx, for comparing against xBRZ.
+///
+/// Like the original, each pixel is compared with its eight neighbours in YUV, and each corner of
+/// the scaled pixel is blended with the neighbours that touch it. A corner where the two edge
+/// neighbours match each other but not the pixel itself lies on a diagonal edge, and is drawn
+/// mostly or wholly in their colour. The blends follow the rules of the reference implementation
+/// rather than its lookup tables, so the output is close to it but not identical. Pixels beyond
+/// the image are taken from its edges.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of [`FACTORS`].
+pub fn scale(image: &RgbaImage, factor: u32) -> RgbaImage {
+    assert!(FACTORS.contains(&factor), "hqx can't scale by {factor}");
+    let (width, height) = image.dimensions();
+    let mut scaled = RgbaImage::new(width * factor, height * factor);
+
+    for y in 0..height {
+        for x in 0..width {
+            let at = |dx: i32, dy: i32| {
+                let x = x.saturating_add_signed(dx).min(width - 1);
+                let y = y.saturating_add_signed(dy).min(height - 1);
+                *image.get_pixel(x, y)
+            };
+            // the neighbours around each corner, clockwise from the top left
+            let corners = [(-1, -1), (1, -1), (1, 1), (-1, 1)].map(|(dx, dy)| Corner {
+                centre: at(0, 0),
+                vertical: at(0, dy),
+                horizontal: at(dx, 0),
+                diagonal: at(dx, dy),
+            });
+
+            for sy in 0..factor {
+                for sx in 0..factor {
+                    let colour = cell_pixel(&corners, factor, sx, sy);
+                    scaled.put_pixel(x * factor + sx, y * factor + sy, colour);
+                }
+            }
+        }
+    }
+    scaled
+}
+
+/// A pixel and the three neighbours that touch one of its corners.
+struct Corner {
+    centre: Rgba<u8>,
+    /// The neighbour above or below
+    vertical: Rgba<u8>,
+    /// The neighbour to the left or right
+    horizontal: Rgba<u8>,
+    diagonal: Rgba<u8>,
+}
+
+impl Corner {
+    /// Whether the corner is cut by an edge running diagonally past it, and if so, whether the
+    /// diagonal neighbour is on the far side of it too, making the pixel a convex corner of its area.
+    fn diagonal_edge(&self) -> Option<bool> {
+        let edge = differ(self.centre, self.vertical)
+            && differ(self.centre, self.horizontal)
+            && !differ(self.vertical, self.horizontal);
+        edge.then(|| differ(self.centre, self.diagonal))
+    }
+
+    /// The pixel `depth_x` across and `depth_y` down from the corner, in the quarter of a cell
+    /// scaled by `factor` that the corner belongs to.
+    fn pixel(&self, factor: u32, depth_x: u32, depth_y: u32) -> Rgba<u8> {
+        let Corner {
+            centre: c,
+            vertical: v,
+            horizontal: h,
+            diagonal: d,
+        } = *self;
+        // the edge neighbour nearest to pixels along the sides of the corner
+        let side = if depth_y == 0 { v } else { h };
+
+        match (self.diagonal_edge(), depth_x + depth_y) {
+            (Some(true), 0) => match factor {
+                2 => blend(&[(c, 4), (v, 6), (h, 6)]),
+                3 => blend(&[(c, 2), (v, 7), (h, 7)]),
+                _ => blend(&[(v, 1), (h, 1)]),
+            },
+            (Some(false), 0) if factor == 4 => blend(&[(c, 2), (v, 1), (h, 1)]),
+            (Some(false), 0) => blend(&[(c, 6), (v, 1), (h, 1)]),
+            (Some(true), 1) => blend(&[(c, 1), (side, 1)]),
+            (Some(false), 1) => blend(&[(c, 3), (side, 1)]),
+            (Some(true), _) => blend(&[(c, 14), (v, 1), (h, 1)]),
+            (None, 0) => match (differ(c, v), differ(c, h)) {
+                (false, false) => blend(&[(c, 2), (v, 1), (h, 1)]),
+                (true, true) => blend(&[(c, 3), (d, 1)]),
+                (true, false) | (false, true) => {
+                    let similar = if differ(c, v) { h } else { v };
+                    if differ(c, d) {
+                        blend(&[(c, 3), (similar, 1)])
+                    } else {
+                        blend(&[(c, 2), (d, 1), (similar, 1)])
+                    }
+                }
+            },
+            (None, 1) if !differ(c, side) => blend(&[(c, 3), (side, 1)]),
+            _ => c,
+        }
+    }
+}
+
+/// The colour of the pixel at `(sx, sy)` in a cell scaled by `factor`, given the neighbours of
+/// each of its corners clockwise from the top left.
+fn cell_pixel(corners: &[Corner; 4], factor: u32, sx: u32, sy: u32) -> Rgba<u8> {
+    // which half of the cell the pixel is in along each axis, or `None` for the middle row or column
+    let half = |s: u32| match s * 2 + 1 {
+        twice if twice < factor => Some(false),
+        twice if twice > factor => Some(true),
+        _ => None,
+    };
+    let depth = |s: u32| s.min(factor - 1 - s);
+
+    match (half(sx), half(sy)) {
+        (Some(right), Some(bottom)) => {
+            let corner = match (right, bottom) {
+                (false, false) => &corners[0],
+                (true, false) => &corners[1],
+                (true, true) => &corners[2],
+                (false, true) => &corners[3],
+            };
+            corner.pixel(factor, depth(sx), depth(sy))
+        }
+        (None, None) => corners[0].centre,
+        // the middle of a side, between the two corners along it
+        (None, Some(bottom)) => {
+            let pair = if bottom { [2, 3] } else { [0, 1] };
+            side_pixel(&corners[pair[0]], &corners[pair[1]], true)
+        }
+        (Some(right), None) => {
+            let pair = if right { [1, 2] } else { [3, 0] };
+            side_pixel(&corners[pair[0]], &corners[pair[1]], false)
+        }
+    }
+}
+
+/// The pixel in the middle of the side of a cell shared by two corners, which is only blended with
+/// the neighbour on that side if it matches, or if a diagonal edge rounds off either corner.
+fn side_pixel(a: &Corner, b: &Corner, vertical: bool) -> Rgba<u8> {
+    let c = a.centre;
+    let neighbour = if vertical { a.vertical } else { a.horizontal };
+    if !differ(c, neighbour) {
+        blend(&[(c, 3), (neighbour, 1)])
+    } else if a.diagonal_edge() == Some(true) || b.diagonal_edge() == Some(true) {
+        blend(&[(c, 7), (neighbour, 1)])
+    } else {
+        c
+    }
+}
+
+/// Whether two pixels are far enough apart in YUV or alpha to be treated as different colours.
+fn differ(a: Rgba<u8>, b: Rgba<u8>) -> bool {
+    let yuva = |Rgba([r, g, b, a]): Rgba<u8>| {
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+        [
+            (299 * r + 587 * g + 114 * b) / 1000,
+            (-169 * r - 331 * g + 500 * b) / 1000,
+            (500 * r - 419 * g - 81 * b) / 1000,
+            a as i32,
+        ]
+    };
+    let (a, b) = (yuva(a), yuva(b));
+    (0..4).any(|i| (a[i] - b[i]).abs() > THRESHOLDS[i])
+}
+
+/// A weighted average of pixels, with the colours weighted by alpha as well so that transparent
+/// pixels don't darken their neighbours.
+fn blend(pixels: &[(Rgba<u8>, u32)]) -> Rgba<u8> {
+    let total: u32 = pixels.iter().map(|&(_, weight)| weight).sum();
+    let alpha: u32 = pixels
+        .iter()
+        .map(|&(px, weight)| px[3] as u32 * weight)
+        .sum();
+    if alpha == 0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let channel = |i: usize| {
+        let sum: u32 = pixels
+            .iter()
+            .map(|&(px, weight)| px[i] as u32 * px[3] as u32 * weight)
+            .sum();
+        ((sum + alpha / 2) / alpha) as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        ((alpha + total / 2) / total) as u8,
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+    #[test]
+    fn flat_images_stay_flat() {
+        let image = RgbaImage::from_pixel(3, 2, Rgba([90, 140, 30, 200]));
+        for factor in FACTORS {
+            let scaled = scale(&image, factor);
+            assert_eq!(scaled.dimensions(), (3 * factor, 2 * factor));
+            assert!(scaled.pixels().all(|px| px == image.get_pixel(0, 0)));
+        }
+    }
+
+    #[test]
+    fn isolated_pixels_are_rounded_off() {
+        let mut image = RgbaImage::from_pixel(3, 3, WHITE);
+        image.put_pixel(1, 1, BLACK);
+
+        let hq4x = scale(&image, 4);
+        // the corners of the dot take the colour around it, and its middle keeps its own
+        for (x, y) in [(4, 4), (7, 4), (7, 7), (4, 7)] {
+            assert_eq!(*hq4x.get_pixel(x, y), WHITE, "({x}, {y})");
+        }
+        for (x, y) in [(5, 5), (6, 5), (6, 6), (5, 6)] {
+            assert!(hq4x.get_pixel(x, y)[0] < 64, "({x}, {y})");
+        }
+
+        let hq3x = scale(&image, 3);
+        assert_eq!(*hq3x.get_pixel(4, 4), BLACK);
+        assert!(hq3x.get_pixel(3, 3)[0] > 200);
+        assert!(hq3x.get_pixel(4, 3)[0] > 0);
+    }
+
+    #[test]
+    fn straight_edges_stay_sharp() {
+        // black on the left, white on the right
+        let image = RgbaImage::from_fn(4, 4, |x, _| if x < 2 { BLACK } else { WHITE });
+        for factor in FACTORS {
+            let scaled = scale(&image, factor);
+            for y in 0..4 * factor {
+                for x in 0..4 * factor {
+                    let expected = if x < 2 * factor { BLACK } else { WHITE };
+                    assert_eq!(*scaled.get_pixel(x, y), expected, "x{factor} ({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transparent_neighbours_only_lower_the_alpha() {
+        let mut image = RgbaImage::from_pixel(3, 3, Rgba([0, 0, 0, 0]));
+        image.put_pixel(1, 1, Rgba([200, 40, 40, 255]));
+
+        let scaled = scale(&image, 2);
+        for px in scaled.pixels().filter(|px| px[3] > 0) {
+            assert_eq!(px.0[..3], [200, 40, 40], "{px:?}");
+        }
+        assert!(scaled.pixels().any(|px| px[3] > 0 && px[3] < 255));
+    }
+
+    #[test]
+    #[should_panic(expected = "hqx can't scale by 5")]
+    fn other_factors_are_rejected() {
+        scale(&RgbaImage::new(1, 1), 5);
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+use image::codecs::ico::{IcoEncoder, IcoFrame};
+use image::imageops::{self, FilterType};
+use image::{ExtendedColorType, ImageError, ImageFormat, RgbaImage};
+use xbrz::ScalerConfig;
+
+/// Icon container formats that can be written, chosen by file extension.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IconFormat {
+    /// Windows icon
+    Ico,
+    /// Apple icon image
+    Icns,
+}
+
+impl IconFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?;
+        if ext.eq_ignore_ascii_case("ico") {
+            Some(IconFormat::Ico)
+        } else if ext.eq_ignore_ascii_case("icns") {
+            Some(IconFormat::Icns)
+        } else {
+            None
+        }
+    }
+
+    /// The sizes generated when none are given.
+    pub fn default_sizes(self) -> &'static [u32] {
+        match self {
+            IconFormat::Ico => &[32, 48, 64, 128, 256],
+            // ICNS has no PNG entry for 48x48 icons
+            IconFormat::Icns => &[32, 64, 128, 256],
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum IconError {
+    Io(io::Error),
+    Image(ImageError),
+    /// The size can't be stored in the chosen format.
+    UnsupportedSize(IconFormat, u32),
+}
+
+impl fmt::Display for IconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IconError::Io(e) => write!(f, "{e}"),
+            IconError::Image(e) => write!(f, "{e}"),
+            IconError::UnsupportedSize(format, size) => {
+                let name = match format {
+                    IconFormat::Ico => "ICO",
+                    IconFormat::Icns => "ICNS",
+                };
+                write!(f, "{name} files can't hold {size}x{size} icons")
+            }
+        }
+    }
+}
+
+/// The ICNS element type holding a PNG of each supported size.
+fn icns_type(size: u32) -> Option<&'static [u8; 4]> {
+    Some(match size {
+        16 => b"icp4",
+        32 => b"icp5",
+        64 => b"icp6",
+        128 => b"ic07",
+        256 => b"ic08",
+        512 => b"ic09",
+        1024 => b"ic10",
+        _ => return None,
+    })
+}
+
+/// Write an icon file at `path` holding a copy of `image` at each of `sizes`.
+pub fn write_icon(
+    image: &RgbaImage,
+    sizes: &[u32],
+    format: IconFormat,
+    config: &ScalerConfig,
+    path: &Path,
+) -> Result<(), IconError> {
+    let unsupported = sizes.iter().find(|&&size| match format {
+        IconFormat::Ico => !(1..=256).contains(&size),
+        IconFormat::Icns => icns_type(size).is_none(),
+    });
+    if let Some(&size) = unsupported {
+        return Err(IconError::UnsupportedSize(format, size));
+    }
+
+    let images: Vec<RgbaImage> = sizes
+        .iter()
+        .map(|&size| render(image, size, config))
+        .collect();
+    let bytes = match format {
+        IconFormat::Ico => encode_ico(&images),
+        IconFormat::Icns => encode_icns(&images),
+    }
+    .map_err(IconError::Image)?;
+    fs::write(path, bytes).map_err(IconError::Io)
+}
+
+/// Render `image` as a square icon of `size` pixels. The image is scaled with xBRZ by the smallest
+/// factor that reaches `size`, in several passes if it needs more than 6x, then resampled down to
+/// the exact size if the factor doesn't land on it. Images that aren't square are centred on a
+/// transparent background first.
+fn render(image: &RgbaImage, size: u32, config: &ScalerConfig) -> RgbaImage {
+    let side = image.width().max(image.height());
+    let mut scaled = RgbaImage::new(side, side);
+    imageops::overlay(
+        &mut scaled,
+        image,
+        ((side - image.width()) / 2).into(),
+        ((side - image.height()) / 2).into(),
+    );
+
+    while scaled.width() < size {
+        let side = scaled.width();
+        let factor = size.div_ceil(side).min(6);
+        let pixels = xbrz::scale_rgba_with_config(
+            &scaled,
+            side as usize,
+            side as usize,
+            factor as usize,
+            config,
+        );
+        scaled = RgbaImage::from_raw(side * factor, side * factor, pixels)
+            .expect("scaled image has the right size");
+    }
+
+    if scaled.width() == size {
+        scaled
+    } else {
+        imageops::resize(&scaled, size, size, FilterType::CatmullRom)
+    }
+}
+
+fn encode_png(image: &RgbaImage) -> Result<Vec<u8>, ImageError> {
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)?;
+    Ok(png)
+}
+
+fn encode_ico(images: &[RgbaImage]) -> Result<Vec<u8>, ImageError> {
+    let frames = images
+        .iter()
+        .map(|image| {
+            IcoFrame::as_png(
+                image,
+                image.width(),
+                image.height(),
+                ExtendedColorType::Rgba8,
+            )
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut bytes = Vec::new();
+    IcoEncoder::new(&mut bytes).encode_images(&frames)?;
+    Ok(bytes)
+}
+
+/// An ICNS file is a header followed by elements, each made of a type, a big-endian length that
+/// includes its own 8-byte header, and the data; here every element is a PNG.
+fn encode_icns(images: &[RgbaImage]) -> Result<Vec<u8>, ImageError> {
+    let mut elements = Vec::new();
+    for image in images {
+        let png = encode_png(image)?;
+        let kind = icns_type(image.width()).expect("sizes are checked before rendering");
+        elements.extend_from_slice(kind);
+        elements.extend_from_slice(&(png.len() as u32 + 8).to_be_bytes());
+        elements.extend_from_slice(&png);
+    }
+
+    let mut bytes = Vec::with_capacity(elements.len() + 8);
+    bytes.extend_from_slice(b"icns");
+    bytes.extend_from_slice(&(elements.len() as u32 + 8).to_be_bytes());
+    bytes.extend_from_slice(&elements);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    fn icon(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            Rgba([x as u8, y as u8, (x ^ y) as u8, 255 - (x / 2) as u8])
+        })
+    }
+
+    fn decode_png(png: &[u8]) -> RgbaImage {
+        assert!(png.starts_with(PNG_SIGNATURE));
+        image::load_from_memory_with_format(png, ImageFormat::Png)
+            .unwrap()
+            .into_rgba8()
+    }
+
+    #[test]
+    fn ico_directory_points_at_each_image() {
+        let images = [icon(16), icon(48), icon(256)];
+        let bytes = encode_ico(&images).unwrap();
+        assert_eq!(bytes[..6], [0, 0, 1, 0, 3, 0]);
+
+        let mut offset = 6 + 3 * 16;
+        for (i, image) in images.iter().enumerate() {
+            let entry = &bytes[6 + i * 16..][..16];
+            // sizes of 256 are stored as 0
+            let size = (image.width() % 256) as u8;
+            assert_eq!(entry[..4], [size, size, 0, 0]);
+            // the planes may be 0 or 1, and the bit depth is that of the PNG
+            assert!(u16_at(entry, 4) <= 1);
+            assert_eq!(u16_at(entry, 6), 32);
+            let len = u32_at(entry, 8);
+            assert_eq!(u32_at(entry, 12), offset);
+            assert_eq!(decode_png(&bytes[offset..offset + len]), *image);
+            offset += len;
+        }
+        assert_eq!(bytes.len(), offset);
+    }
+
+    #[test]
+    fn icns_elements_hold_their_own_length() {
+        let images = [icon(32), icon(64), icon(256)];
+        let bytes = encode_icns(&images).unwrap();
+        assert_eq!(bytes[..4], *b"icns");
+        let total = u32::from_be_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        assert_eq!(total, bytes.len());
+
+        let mut offset = 8;
+        for (image, kind) in images.iter().zip([b"icp5", b"icp6", b"ic08"]) {
+            assert_eq!(&bytes[offset..offset + 4], kind);
+            let len = u32::from_be_bytes(bytes[offset + 4..offset + 8].try_into().unwrap());
+            let element = &bytes[offset..offset + len as usize];
+            assert_eq!(decode_png(&element[8..]), *image);
+            offset += element.len();
+        }
+        assert_eq!(offset, bytes.len());
+    }
+
+    #[test]
+    fn icons_are_rendered_square_at_each_size() {
+        let image = RgbaImage::from_pixel(4, 2, Rgba([200, 40, 40, 255]));
+        let config = ScalerConfig::default();
+        for size in [4, 12, 30, 48, 256] {
+            let icon = render(&image, size, &config);
+            assert_eq!(icon.dimensions(), (size, size));
+            // the image is centred, leaving the top and bottom rows transparent
+            assert_eq!(icon.get_pixel(size / 2, 0).0[3], 0, "{size}");
+            assert_eq!(icon.get_pixel(size / 2, size / 2).0, [200, 40, 40, 255]);
+        }
+    }
+
+    #[test]
+    fn unsupported_sizes_are_errors() {
+        let path = std::env::temp_dir().join("xbrz-icon-never-written.ico");
+        let config = ScalerConfig::default();
+        for (format, size) in [
+            (IconFormat::Ico, 0),
+            (IconFormat::Ico, 512),
+            (IconFormat::Icns, 48),
+        ] {
+            assert!(matches!(
+                write_icon(&icon(8), &[32, size], format, &config, &path),
+                Err(IconError::UnsupportedSize(f, s)) if f == format && s == size
+            ));
+        }
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,3 @@
+//! Code shared by the binaries of the test crate.
+
+pub mod profile;
@@ -1,57 +1,982 @@
-use clap::Parser;
-use clio::{InputPath, OutputPath};
-use image::RgbaImage;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use image::{DynamicImage, ImageFormat, ImageReader, RgbaImage};
+use xbrz::ScaleFactor;
+use xbrz_test::profile::Profile;
+
+use crate::aseprite::{AsepriteError, Sprite};
+use crate::compare::Algorithm;
+use crate::cursor::CursorError;
+use crate::error::{CliError, ErrorKind};
+use crate::font::FontError;
+use crate::icon::IconFormat;
+use crate::manifest::Manifest;
+use crate::metadata::Metadata;
+use crate::pipe::{FrameSize, PipeFormat};
+use crate::png_output::PngCompression;
+use crate::texture::{Compression, Container, Mips};
+use crate::tileset::TilesetError;
+use crate::watch::WatchError;
+
+mod aseprite;
+#[cfg(feature = "bcn")]
+mod bcn;
+mod clipboard;
+mod compare;
+mod cursor;
+mod error;
+mod font;
+mod hqx;
+mod icon;
+mod manifest;
+mod metadata;
+mod pipe;
+mod png_output;
+mod serve;
+mod texture;
+mod tileset;
+mod watch;
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 struct Args {
-    #[arg()]
-    input: InputPath,
+    #[command(subcommand)]
+    command: Option<Command>,
 
+    /// The image to scale, or - to read it from stdin
+    #[arg(required_unless_present_any = ["pipe", "clipboard"])]
+    input: Option<PathBuf>,
+
+    /// Where to save the scaled image, or - to write it to stdout
     #[arg(short, long, default_value = "output.png")]
-    output: OutputPath,
+    output: PathBuf,
+
+    /// Format to save the scaled image in [default: chosen by the extension of --output, or PNG on stdout]
+    #[arg(long, value_enum, conflicts_with_all = ["pipe", "raw", "hash"])]
+    output_format: Option<OutputFormat>,
+
+    /// How the smaller mip levels of DDS and KTX2 textures are made
+    #[arg(long, value_enum, default_value_t = Mips::None)]
+    mips: Mips,
+
+    /// How the pixels of DDS and KTX2 textures are stored
+    #[arg(long, value_enum, default_value_t = Compression::None)]
+    texture_compression: Compression,
+
+    /// Scaling factor [default: 2, or the factor saved in --profile]
+    #[arg(short, long, visible_alias = "factor", value_parser = 2..=6)]
+    scale: Option<i64>,
+
+    /// Load scaling settings from a TOML or JSON profile
+    #[arg(long, global = true)]
+    profile: Option<PathBuf>,
+
+    /// Save the scaling settings in use to a TOML or JSON profile
+    #[arg(long)]
+    save_profile: Option<PathBuf>,
+
+    /// Scale the image on the clipboard and copy the scaled image back to it, instead of reading
+    /// and saving files
+    #[arg(long, conflicts_with_all = ["input", "output", "output_format", "pipe", "raw"])]
+    clipboard: bool,
+
+    /// Read raw video frames from stdin and write the scaled frames to stdout
+    #[arg(long, conflicts_with_all = ["input", "output"])]
+    pipe: bool,
+
+    /// Frame format used with --pipe
+    #[arg(long, value_enum, default_value_t = PipeFormat::Y4m, requires = "pipe")]
+    pipe_format: PipeFormat,
+
+    /// Frame size (WIDTHxHEIGHT) of rawvideo input
+    #[arg(long, requires = "pipe")]
+    size: Option<FrameSize>,
+
+    /// Pick the scale factor that best fits the image within WIDTHxHEIGHT, keeping its aspect ratio,
+    /// and scale the result down to fit if no factor fits exactly. Sizes such as 1080p or 4k are also accepted
+    #[arg(long, value_name = "WIDTHxHEIGHT", conflicts_with_all = ["scale", "pipe"])]
+    fit: Option<FrameSize>,
+
+    /// Scale the image to cover all of WIDTHxHEIGHT, keeping its aspect ratio, and crop the
+    /// centre of the result to exactly that size. Sizes such as 1080p or 4k are also accepted
+    #[arg(long, value_name = "WIDTHxHEIGHT", conflicts_with_all = ["scale", "pipe", "fit"])]
+    cover: Option<FrameSize>,
+
+    /// Read the input as a raw RGBA file of WIDTHxHEIGHT pixels and write the output as raw RGBA,
+    /// scaling through memory maps so that images larger than the available memory can be scaled
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        conflicts_with_all = ["pipe", "fit", "cover", "hash", "progress", "palette"]
+    )]
+    raw: Option<FrameSize>,
+
+    /// Don't copy colour profiles, EXIF data or text from the input to the output
+    #[arg(long, conflicts_with = "pipe")]
+    strip_metadata: bool,
+
+    /// How hard to compress PNG output
+    #[arg(long, value_enum, default_value_t = PngCompression::Default, conflicts_with = "pipe")]
+    png_compression: PngCompression,
+
+    /// Store PNG output with no more than 256 colours as indices into a palette
+    #[arg(long, conflicts_with = "pipe")]
+    png_palette: bool,
+
+    /// Run oxipng, which must be installed, over PNG output to shrink it further
+    #[arg(long, conflicts_with = "pipe")]
+    oxipng: bool,
+
+    /// Print a BLAKE3 hash of the scaled image instead of saving it, to check whether it has changed
+    #[arg(long, conflicts_with_all = ["pipe", "output"])]
+    hash: bool,
+
+    /// Show the progress of scaling on stderr
+    #[arg(short, long, conflicts_with = "pipe")]
+    progress: bool,
+
+    /// Snap the scaled image to the colours of the input, keeping at most MAX_COLORS of the most common
+    #[arg(long, value_name = "MAX_COLORS", num_args = 0..=1, conflicts_with = "pipe")]
+    palette: Option<Option<usize>>,
+
+    /// Dithering used when snapping to the colours of the input with --palette
+    #[arg(long, value_enum, default_value_t = DitherArg::None, requires = "palette")]
+    dither: DitherArg,
+
+    /// Don't print messages about what was done, only errors and requested output
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Print errors to stderr as a line of JSON giving the kind of error, its message and the
+    /// exit code: 1 for other errors, 2 for invalid arguments, 3 for unreadable input, 4 for images
+    /// that can't be scaled, 5 for unwritable output and 6 for failed manifest jobs
+    #[arg(long, global = true)]
+    json_errors: bool,
+}
+
+/// Formats that scaled images can be saved in, all of which keep their alpha channel.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+enum OutputFormat {
+    Png,
+    Webp,
+    Bmp,
+    Tga,
+    Tiff,
+    Qoi,
+    Dds,
+    Ktx2,
+}
+
+/// How the scaled image is written out: as an image, or as a texture with a mip chain.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Encoding {
+    Image(ImageFormat),
+    Texture(Container),
+}
+
+impl From<OutputFormat> for Encoding {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Png => Encoding::Image(ImageFormat::Png),
+            OutputFormat::Webp => Encoding::Image(ImageFormat::WebP),
+            OutputFormat::Bmp => Encoding::Image(ImageFormat::Bmp),
+            OutputFormat::Tga => Encoding::Image(ImageFormat::Tga),
+            OutputFormat::Tiff => Encoding::Image(ImageFormat::Tiff),
+            OutputFormat::Qoi => Encoding::Image(ImageFormat::Qoi),
+            OutputFormat::Dds => Encoding::Texture(Container::Dds),
+            OutputFormat::Ktx2 => Encoding::Texture(Container::Ktx2),
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+enum DitherArg {
+    None,
+    Ordered,
+    FloydSteinberg,
+}
+
+impl From<DitherArg> for xbrz::Dither {
+    fn from(dither: DitherArg) -> Self {
+        match dither {
+            DitherArg::None => xbrz::Dither::None,
+            DitherArg::Ordered => xbrz::Dither::Ordered,
+            DitherArg::FloydSteinberg => xbrz::Dither::FloydSteinberg,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Render a labelled grid of an image scaled with several algorithms and factors
+    Compare {
+        input: PathBuf,
+
+        #[arg(short, long, default_value = "compare.png")]
+        output: PathBuf,
+
+        /// Scaling factors to compare, separated by commas
+        #[arg(long, value_delimiter = ',', default_value = "2,3,4", value_parser = 1..=6)]
+        factors: Vec<i64>,
+
+        /// Scaling algorithms to compare, separated by commas: xbrz, nearest, bilinear, hqx for
+        /// factors 2 to 4, or hybrid[:SOFTNESS] for xBRZ mixed with bicubic, with SOFTNESS from 0
+        /// to 1
+        #[arg(long, value_delimiter = ',', default_value = "xbrz,nearest")]
+        algorithms: Vec<Algorithm>,
+    },
+    /// Serve a local page for tuning the scaling settings with a live preview of part of an image
+    Serve {
+        input: PathBuf,
+
+        /// Port to listen on, on 127.0.0.1
+        #[arg(long, default_value_t = 8000)]
+        port: u16,
+    },
+    /// Generate an .ico or .icns file holding several sizes of a small icon
+    Icon {
+        input: PathBuf,
+
+        /// The icon file to write, its format chosen by the extension
+        #[arg(short, long, default_value = "icon.ico")]
+        output: PathBuf,
+
+        /// Icon sizes in pixels, separated by commas [default: 32,48,64,128,256, without 48 for .icns]
+        #[arg(long, value_delimiter = ',')]
+        sizes: Vec<u32>,
+    },
+    /// Scale a Windows cursor, still (.cur) or animated (.ani), moving its hotspot to match
+    Cursor {
+        input: PathBuf,
+
+        /// The cursor file to write [default: the name of the input with the factor added, such as arrow.x2.cur]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Scaling factor [default: 2, or the factor saved in --profile]
+        #[arg(short, long, visible_alias = "factor", value_parser = 2..=6)]
+        scale: Option<i64>,
+    },
+    /// Scale each cel of an Aseprite file and save its frames as PNGs
+    Aseprite {
+        input: PathBuf,
+
+        /// Output path; frames are saved with their index added to the file name, unless --sheet is given
+        #[arg(short, long, default_value = "output.png")]
+        output: PathBuf,
+
+        /// Scaling factor [default: 2, or the factor saved in --profile]
+        #[arg(short, long, visible_alias = "factor", value_parser = 2..=6)]
+        scale: Option<i64>,
+
+        /// Save all frames side by side in a single image
+        #[arg(long)]
+        sheet: bool,
+    },
+    /// Scale the pages of a BMFont (.fnt or .json) one glyph at a time and update the font to match
+    Font {
+        input: PathBuf,
+
+        /// The font descriptor to write; each scaled page is saved next to it, named after it and the page id
+        #[arg(short, long, default_value = "output.fnt")]
+        output: PathBuf,
+
+        /// Scaling factor [default: 2, or the factor saved in --profile]
+        #[arg(short, long, visible_alias = "factor", value_parser = 2..=6)]
+        scale: Option<i64>,
+    },
+    /// Scale the image of a Tiled tileset (.tsx) one tile at a time and update the tileset to match
+    Tileset {
+        input: PathBuf,
+
+        /// The tileset to write; the scaled image is saved next to it with the same name and a .png extension
+        #[arg(short, long, default_value = "output.tsx")]
+        output: PathBuf,
+
+        /// Scaling factor [default: 2, or the factor saved in --profile]
+        #[arg(short, long, visible_alias = "factor", value_parser = 2..=6)]
+        scale: Option<i64>,
+    },
+    /// Scale the images listed in a JSON manifest in parallel, and print a JSON report of the results
+    Run {
+        /// The manifest, with a "jobs" list of objects each giving an "input" and "output" path
+        /// and optionally a "factor" and "config"; a "factor" and "config" at the top level apply
+        /// to every job that doesn't give its own. Paths are relative to the manifest
+        manifest: PathBuf,
+
+        /// Write the report to this file instead of stdout
+        #[arg(long)]
+        report: Option<PathBuf>,
+
+        /// Images scaled at once [default: the number of CPUs]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Scale every image in a directory, then keep watching it and scale images again as they change
+    Watch {
+        /// The directory of images to scale, including its subdirectories
+        input: PathBuf,
+
+        /// The directory to save scaled images in, at the same paths as in the input directory
+        #[arg(long)]
+        out_dir: PathBuf,
+
+        /// Scaling factor [default: 2, or the factor saved in --profile]
+        #[arg(short, long, visible_alias = "factor", value_parser = 2..=6)]
+        scale: Option<i64>,
+    },
+}
+
+/// Whether a path given on the command line is `-`, standing for stdin or stdout.
+fn is_std(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Read all of the input file, or of stdin if the input is `-`.
+fn read_input(input: &Path) -> Result<Vec<u8>, CliError> {
+    let read_error = |e| CliError::new(ErrorKind::Input, format!("Image read error: {e}"));
+    if is_std(input) {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).map_err(read_error)?;
+        Ok(bytes)
+    } else {
+        fs::read(input).map_err(read_error)
+    }
+}
+
+/// Decode an image read by [`read_input`], in the format given by its first few bytes, or by
+/// the extension of the input file for formats such as TGA that can't be told apart that way.
+fn decode_input(input: &Path, bytes: &[u8]) -> Result<DynamicImage, CliError> {
+    let read_error = |e| CliError::new(ErrorKind::Input, format!("Image read error: {e}"));
+    let mut reader = ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .expect("reading from memory can't fail");
+    if reader.format().is_none() && !is_std(input) {
+        reader.set_format(ImageFormat::from_path(input).map_err(read_error)?);
+    }
+    reader.decode().map_err(read_error)
+}
+
+fn open_rgba(input: &Path) -> Result<RgbaImage, CliError> {
+    let bytes = read_input(input)?;
+    decode_input(input, &bytes).map(|img| img.into_rgba8())
+}
+
+/// The size with the aspect ratio of `width` by `height` that fits within `target`, touching
+/// it on two sides, or with `cover`, that covers `target`, spilling over on two sides.
+fn fit_to(width: u32, height: u32, target: &FrameSize, cover: bool) -> (u32, u32) {
+    let (width, height) = (width as u64, height as u64);
+    let (target_width, target_height) = (target.width as u64, target.height as u64);
+    let (fit_width, fit_height) = if (target_width * height <= target_height * width) != cover {
+        (target_width, (height * target_width + width / 2) / width)
+    } else {
+        ((width * target_height + height / 2) / height, target_height)
+    };
+    (fit_width.max(1) as u32, fit_height.max(1) as u32)
+}
+
+/// The smallest factor that scales `width` by `height` up to cover all of `target`, and whether
+/// the result then needs to be resized to exactly cover it.
+fn cover_factor(width: u32, height: u32, target: &FrameSize) -> (usize, bool) {
+    let covers = |factor: usize| {
+        width as usize * factor >= target.width && height as usize * factor >= target.height
+    };
+    let factor = (1..=ScaleFactor::MAX.get())
+        .find(|&factor| covers(factor))
+        .unwrap_or(ScaleFactor::MAX.get());
+    let (scaled_width, scaled_height) = (width * factor as u32, height * factor as u32);
+    (
+        factor,
+        fit_to(width, height, target, true) != (scaled_width, scaled_height),
+    )
+}
 
-    #[arg(short, long, default_value_t = 2, value_parser = 2..=6)]
-    scale: i64,
+fn main() -> ExitCode {
+    let args = match Args::try_parse() {
+        Ok(args) => args,
+        // the arguments couldn't be parsed, so --json-errors is looked for on its own
+        Err(e) if e.use_stderr() && std::env::args().any(|arg| arg == "--json-errors") => {
+            let message = e.render().to_string();
+            return CliError::new(ErrorKind::Usage, message.trim_end()).report(true);
+        }
+        Err(e) => e.exit(),
+    };
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => e.report(args.json_errors),
+    }
 }
 
-fn main() {
-    let args = Args::parse();
+fn run(args: &Args) -> Result<(), CliError> {
+    // messages about what was done, which --quiet leaves out
+    let status = |message: fmt::Arguments| {
+        if !args.quiet {
+            println!("{message}");
+        }
+    };
+
+    let mut profile = match &args.profile {
+        Some(path) => Profile::load(path)
+            .map_err(|e| CliError::new(ErrorKind::Input, format!("Profile read error: {e}")))?,
+        None => Profile::default(),
+    };
+    if let Some(scale) = args.scale {
+        profile.scale = ScaleFactor::new(scale as usize);
+    }
+    let scale = profile.scale.map_or(2, ScaleFactor::get);
+
+    if let Some(path) = &args.save_profile {
+        profile
+            .save(path)
+            .map_err(|e| CliError::new(ErrorKind::Output, format!("Profile write error: {e}")))?;
+    }
+
+    match &args.command {
+        Some(Command::Compare {
+            input,
+            output,
+            factors,
+            algorithms,
+        }) => {
+            let in_image = open_rgba(input)?;
+            let factors: Vec<u32> = factors.iter().map(|&f| f as u32).collect();
+            if let Some(factor) = factors
+                .iter()
+                .find(|&&f| !algorithms.iter().all(|a| a.supports(f)))
+            {
+                return Err(CliError::new(
+                    ErrorKind::Scale,
+                    format!("hqx can only scale by 2, 3 or 4, not {factor}"),
+                ));
+            }
+            let sheet = compare::contact_sheet(&in_image, &factors, algorithms, &profile.config);
+            sheet.save(output).map_err(|e| {
+                CliError::new(ErrorKind::Output, format!("Error saving comparison: {e}"))
+            })?;
+            status(format_args!("Saved comparison at {}", output.display()));
+            return Ok(());
+        }
+        Some(Command::Serve { input, port }) => {
+            let in_image = open_rgba(input)?;
+            return serve::serve(&in_image, *port, &profile)
+                .map_err(|e| CliError::new(ErrorKind::Other, format!("Server error: {e}")));
+        }
+        Some(Command::Icon {
+            input,
+            output,
+            sizes,
+        }) => {
+            let path = output;
+            let Some(format) = IconFormat::from_path(path) else {
+                return Err(CliError::new(
+                    ErrorKind::Output,
+                    "Icon files must end in .ico or .icns",
+                ));
+            };
+            let in_image = open_rgba(input)?;
+            let sizes = match sizes.is_empty() {
+                true => format.default_sizes(),
+                false => sizes,
+            };
+            icon::write_icon(&in_image, sizes, format, &profile.config, path)
+                .map_err(|e| CliError::new(ErrorKind::Output, format!("Error saving icon: {e}")))?;
+            status(format_args!("Saved icon at {}", path.display()));
+            return Ok(());
+        }
+        Some(Command::Cursor {
+            input,
+            output,
+            scale: cursor_scale,
+        }) => {
+            let factor = cursor_scale.map_or(scale, |s| s as usize) as u32;
+            let cursor = cursor::scale_cursor(&read_input(input)?, factor, &profile.config)
+                .map_err(|e| {
+                    let kind = match e {
+                        CursorError::TooLarge(..) => ErrorKind::Scale,
+                        _ => ErrorKind::Input,
+                    };
+                    CliError::new(kind, format!("Cursor error: {e}"))
+                })?;
+            let output = output.clone().unwrap_or_else(|| {
+                let path = input;
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let ext = path.extension().unwrap_or("cur".as_ref()).to_string_lossy();
+                path.with_file_name(format!("{stem}.x{factor}.{ext}"))
+            });
+            fs::write(&output, cursor).map_err(|e| {
+                CliError::new(ErrorKind::Output, format!("Error saving cursor: {e}"))
+            })?;
+            status(format_args!("Saved cursor at {}", output.display()));
+            return Ok(());
+        }
+        Some(Command::Aseprite {
+            input,
+            output,
+            scale: frame_scale,
+            sheet,
+        }) => {
+            let sprite = fs::read(input)
+                .map_err(AsepriteError::Io)
+                .and_then(|data| Sprite::parse(&data))
+                .map_err(|e| {
+                    CliError::new(ErrorKind::Input, format!("Aseprite read error: {e}"))
+                })?;
+            let factor = frame_scale.map_or(scale, |s| s as usize) as u32;
+            let frames = sprite.scale_frames(factor, &profile.config);
+
+            if *sheet {
+                aseprite::sheet(&frames).save(output).map_err(|e| {
+                    CliError::new(ErrorKind::Output, format!("Error saving sheet: {e}"))
+                })?;
+                status(format_args!(
+                    "Saved sheet of {} frames at {}",
+                    frames.len(),
+                    output.display()
+                ));
+                return Ok(());
+            }
+            let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+            let extension = output
+                .extension()
+                .unwrap_or("png".as_ref())
+                .to_string_lossy();
+            for (i, (frame, info)) in frames.iter().zip(&sprite.frames).enumerate() {
+                let path = output.with_file_name(format!("{stem}_{i}.{extension}"));
+                frame.save(&path).map_err(|e| {
+                    CliError::new(ErrorKind::Output, format!("Error saving frame: {e}"))
+                })?;
+                status(format_args!(
+                    "Saved frame {i} ({} ms) at {}",
+                    info.duration,
+                    path.display()
+                ));
+            }
+            return Ok(());
+        }
+        Some(Command::Font {
+            input,
+            output,
+            scale: font_scale,
+        }) => {
+            let factor = font_scale.map_or(scale, |s| s as usize) as u32;
+            let pages = font::scale_font(input, output, factor, &profile.config).map_err(|e| {
+                let kind = match e {
+                    FontError::Save(_) | FontError::Write(_) => ErrorKind::Output,
+                    _ => ErrorKind::Input,
+                };
+                CliError::new(kind, format!("Font error: {e}"))
+            })?;
+            status(format_args!(
+                "Saved font at {} with {} pages",
+                output.display(),
+                pages.len()
+            ));
+            return Ok(());
+        }
+        Some(Command::Tileset {
+            input,
+            output,
+            scale: tileset_scale,
+        }) => {
+            let factor = tileset_scale.map_or(scale, |s| s as usize) as u32;
+            let image =
+                tileset::scale_tileset(input, output, factor, &profile.config).map_err(|e| {
+                    let kind = match e {
+                        TilesetError::Save(_) | TilesetError::Write(_) => ErrorKind::Output,
+                        _ => ErrorKind::Input,
+                    };
+                    CliError::new(kind, format!("Tileset error: {e}"))
+                })?;
+            status(format_args!(
+                "Saved tileset at {} with image {}",
+                output.display(),
+                image.display()
+            ));
+            return Ok(());
+        }
+        Some(Command::Run {
+            manifest,
+            report,
+            jobs,
+        }) => {
+            let manifest = Manifest::load(manifest).map_err(|e| {
+                CliError::new(ErrorKind::Input, format!("Manifest read error: {e}"))
+            })?;
+            let threads =
+                jobs.unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+            let results = manifest.run(&profile, threads);
+            let json = serde_json::to_string_pretty(&results).expect("reports can be serialized");
+            match report {
+                Some(path) => {
+                    fs::write(path, json).map_err(|e| {
+                        CliError::new(ErrorKind::Output, format!("Error saving report: {e}"))
+                    })?;
+                    status(format_args!(
+                        "Scaled {} images, {} failed; saved report at {}",
+                        results.succeeded,
+                        results.failed,
+                        path.display()
+                    ));
+                }
+                None => println!("{json}"),
+            }
+            if results.failed > 0 {
+                return Err(CliError::new(
+                    ErrorKind::Jobs,
+                    format!(
+                        "{} of {} jobs failed",
+                        results.failed,
+                        results.failed + results.succeeded
+                    ),
+                ));
+            }
+            return Ok(());
+        }
+        Some(Command::Watch {
+            input,
+            out_dir,
+            scale: watch_scale,
+        }) => {
+            let factor = watch_scale.map_or(scale, |s| s as usize) as u32;
+            return watch::watch(input, out_dir, factor, &profile.config, args.quiet).map_err(
+                |e| {
+                    let kind = match e {
+                        WatchError::Input(_) => ErrorKind::Input,
+                        WatchError::Output(_) => ErrorKind::Output,
+                        WatchError::Notify(_) => ErrorKind::Other,
+                    };
+                    CliError::new(kind, format!("Watch error: {e}"))
+                },
+            );
+        }
+        None => {}
+    }
+
+    if args.pipe {
+        let mut stdin = io::stdin().lock();
+        let mut stdout = io::BufWriter::new(io::stdout().lock());
+        let frames = pipe::scale_stream(
+            &mut stdin,
+            &mut stdout,
+            args.pipe_format,
+            args.size,
+            scale,
+            &profile.config,
+        )
+        .map_err(|e| CliError::new(ErrorKind::Other, format!("Pipe error: {e}")))?;
+        // stdout holds the frames, so this goes to stderr
+        if !args.quiet {
+            eprintln!("Scaled {frames} frames");
+        }
+        return Ok(());
+    }
+
+    if let (Some(size), Some(input)) = (args.raw, &args.input) {
+        if is_std(input) || is_std(&args.output) {
+            return Err(CliError::new(
+                ErrorKind::Other,
+                "Raw images are mapped into memory, so they can't be read from stdin or written to stdout",
+            ));
+        }
+        let output = &args.output;
+        xbrz::scale_rgba_file(
+            input,
+            output,
+            size.width,
+            size.height,
+            scale,
+            &profile.config,
+        )
+        .map_err(|e| {
+            let kind = match e.kind() {
+                io::ErrorKind::InvalidInput => ErrorKind::Scale,
+                _ => ErrorKind::Other,
+            };
+            CliError::new(kind, format!("Raw image error: {e}"))
+        })?;
+        status(format_args!("Saved raw image at {}", output.display()));
+        return Ok(());
+    }
 
-    let in_image = match image::open(args.input.path().path()) {
-        Ok(img) => img,
-        Err(e) => {
-            eprintln!("Image read error: {e}");
-            return;
+    // the image, and the file it was decoded from to copy metadata from, which images on the
+    // clipboard don't have
+    let (in_image, in_bytes) = match &args.input {
+        Some(input) => {
+            let in_bytes = read_input(input)?;
+            (decode_input(input, &in_bytes)?, in_bytes)
         }
+        None => (DynamicImage::from(clipboard::read()?), Vec::new()),
     };
 
     let width = in_image.width();
     let height = in_image.height();
-    let factor = args.scale as u32;
+    // the size to resize the scaled image to, and the size to then crop its centre to
+    let (factor, resize, crop) = match (args.fit, args.cover) {
+        (Some(target), _) => {
+            let (factor, needs_downscale) =
+                xbrz::best_factor(width as usize, height as usize, target.width, target.height);
+            let resize = needs_downscale.then(|| fit_to(width, height, &target, false));
+            (factor as u32, resize, None)
+        }
+        (None, Some(target)) => {
+            let (factor, needs_resize) = cover_factor(width, height, &target);
+            let resize = needs_resize.then(|| fit_to(width, height, &target, true));
+            let crop = (target.width as u32, target.height as u32);
+            (factor as u32, resize, Some(crop))
+        }
+        (None, None) => (scale as u32, None, None),
+    };
 
     let rgba = RgbaImage::from(in_image);
-    let out_rgba = xbrz::scale_rgba(&rgba, width as usize, height as usize, factor as usize);
+    let mut out_rgba = if args.progress {
+        // scaling with progress panics on images too large to scale, so they are caught first
+        let (max_width, max_height) = xbrz::max_supported_dimensions(factor as usize);
+        if width as usize > max_width || height as usize > max_height {
+            return Err(xbrz::ScaleError::TooLarge {
+                width: width as usize,
+                height: height as usize,
+                factor: factor as usize,
+            }
+            .into());
+        }
+        let mut last_percent = None;
+        let scaled = xbrz::scale_rgba_with_progress(
+            &rgba,
+            width as usize,
+            height as usize,
+            factor as usize,
+            &profile.config,
+            |done, total| {
+                let percent = done * 100 / total;
+                if last_percent != Some(percent) {
+                    last_percent = Some(percent);
+                    eprint!("\rScaling... {percent:3}%");
+                }
+            },
+        );
+        eprintln!();
+        scaled
+    } else {
+        xbrz::try_scale_rgba_with_config(
+            &rgba,
+            width as usize,
+            height as usize,
+            factor as usize,
+            &profile.config,
+        )?
+    };
+
+    let (mut out_width, mut out_height) = (width * factor, height * factor);
+    if let Some((fit_width, fit_height)) = resize {
+        let scaled = RgbaImage::from_raw(out_width, out_height, out_rgba)
+            .expect("scaled image has the scaled dimensions");
+        out_rgba = image::imageops::resize(
+            &scaled,
+            fit_width,
+            fit_height,
+            image::imageops::FilterType::CatmullRom,
+        )
+        .into_raw();
+        (out_width, out_height) = (fit_width, fit_height);
+    }
+    if let Some((crop_width, crop_height)) = crop {
+        let (crop_width, crop_height) = (crop_width.min(out_width), crop_height.min(out_height));
+        let scaled = RgbaImage::from_raw(out_width, out_height, out_rgba)
+            .expect("scaled image has the scaled dimensions");
+        out_rgba = image::imageops::crop_imm(
+            &scaled,
+            (out_width - crop_width) / 2,
+            (out_height - crop_height) / 2,
+            crop_width,
+            crop_height,
+        )
+        .to_image()
+        .into_raw();
+        (out_width, out_height) = (crop_width, crop_height);
+    }
 
-    let out_width = width * factor;
-    let out_height = height * factor;
+    if let Some(max_colors) = args.palette {
+        xbrz::Palette::from_rgba(&rgba, max_colors).apply_dithered(
+            &mut out_rgba,
+            out_width as usize,
+            args.dither.into(),
+        );
+    }
 
-    match image::save_buffer(
-        args.output.path().path(),
-        &out_rgba,
-        out_width,
-        out_height,
-        image::ExtendedColorType::Rgba8,
-    ) {
-        Ok(()) => {}
-        Err(e) => {
-            eprintln!("Error saving new image: {e}");
-            return;
+    if args.hash {
+        let hash = xbrz::digest_rgba(&out_rgba, out_width as usize, out_height as usize);
+        println!("{}", hash.map(|b| format!("{b:02x}")).concat());
+        return Ok(());
+    }
+
+    if args.clipboard {
+        clipboard::write(&out_rgba, out_width, out_height)?;
+        status(format_args!(
+            "Copied the {out_width}x{out_height} scaled image to the clipboard"
+        ));
+        return Ok(());
+    }
+
+    let metadata = match args.strip_metadata {
+        true => Metadata::default(),
+        false => Metadata::parse(&in_bytes).unwrap_or_else(|e| {
+            eprintln!("Metadata read error: {e}");
+            Metadata::default()
+        }),
+    };
+    let out_path = &args.output;
+    let save_error = |e: &dyn fmt::Display| {
+        CliError::new(ErrorKind::Output, format!("Error saving new image: {e}"))
+    };
+    let encoding = match args.output_format {
+        Some(format) => format.into(),
+        None if is_std(out_path) => Encoding::Image(ImageFormat::Png),
+        None => match Container::from_path(out_path) {
+            Some(container) => Encoding::Texture(container),
+            None => Encoding::Image(ImageFormat::from_path(out_path).map_err(|e| save_error(&e))?),
+        },
+    };
+    let png_output = encoding == Encoding::Image(ImageFormat::Png);
+    if !metadata.is_empty() && !png_output {
+        eprintln!("Metadata is only kept in PNG output; use --strip-metadata to silence this");
+    }
+    let encoded = match encoding {
+        Encoding::Texture(container) => {
+            // smaller levels can only be scaled from the source if the scaled image is as it was
+            let source = (resize.is_none() && crop.is_none() && args.palette.is_none())
+                .then_some((&rgba, factor));
+            let top = RgbaImage::from_raw(out_width, out_height, out_rgba)
+                .expect("scaled image has the output dimensions");
+            let levels = texture::mip_chain(top, args.mips, source, &profile.config);
+            texture::encode(&levels, container, args.texture_compression)
+        }
+        Encoding::Image(ImageFormat::Png) => png_output::encode_png(
+            &out_rgba,
+            out_width,
+            out_height,
+            args.png_compression,
+            args.png_palette,
+        )
+        .map(|mut png| {
+            metadata.insert_into_png(&mut png);
+            png
+        })
+        .map_err(|e| save_error(&e))?,
+        Encoding::Image(out_format) => {
+            let mut encoded = Cursor::new(Vec::new());
+            image::write_buffer_with_format(
+                &mut encoded,
+                &out_rgba,
+                out_width,
+                out_height,
+                image::ExtendedColorType::Rgba8,
+                out_format,
+            )
+            .map_err(|e| save_error(&e))?;
+            encoded.into_inner()
+        }
+    };
+
+    if is_std(out_path) {
+        if args.oxipng {
+            eprintln!("oxipng is only run over files, not stdout");
         }
+        let mut stdout = io::stdout().lock();
+        return stdout
+            .write_all(&encoded)
+            .and_then(|()| stdout.flush())
+            .map_err(|e| save_error(&e));
     }
+    fs::write(out_path, encoded).map_err(|e| save_error(&e))?;
+    if args.oxipng && png_output {
+        // oxipng keeps all metadata unless told otherwise
+        match std::process::Command::new("oxipng")
+            .arg("--quiet")
+            .arg(out_path)
+            .status()
+        {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("oxipng failed: {status}"),
+            Err(e) => eprintln!("Couldn't run oxipng: {e}"),
+        }
+    }
+
+    status(format_args!("Saved scaled image at {}", out_path.display()));
+    Ok(())
+}
 
-    println!(
-        "Saved scaled image at {}",
-        args.output.path().path().display()
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compare_args(extra: &[&str]) -> Result<Command, clap::Error> {
+        let args = ["xbrz-cli", "compare", "images/yoshi.png"];
+        let args = Args::try_parse_from(args.iter().chain(extra))?;
+        Ok(args.command.expect("compare is a subcommand"))
+    }
+
+    #[test]
+    fn compare_takes_lists_of_factors_and_algorithms() {
+        let Command::Compare {
+            factors,
+            algorithms,
+            ..
+        } = compare_args(&[
+            "--factors",
+            "2,5",
+            "--algorithms",
+            "xbrz,hybrid:0.2,nearest",
+        ])
+        .unwrap()
+        else {
+            panic!("expected the compare command");
+        };
+        assert_eq!(factors, [2, 5]);
+        assert_eq!(
+            algorithms,
+            [
+                Algorithm::Xbrz,
+                Algorithm::XbrzHybrid { softness: 0.2 },
+                Algorithm::Nearest
+            ]
+        );
+
+        let Command::Compare {
+            factors,
+            algorithms,
+            ..
+        } = compare_args(&[]).unwrap()
+        else {
+            panic!("expected the compare command");
+        };
+        assert_eq!(factors, [2, 3, 4]);
+        assert_eq!(algorithms, [Algorithm::Xbrz, Algorithm::Nearest]);
+    }
+
+    #[test]
+    fn compare_rejects_unknown_factors_and_algorithms() {
+        assert!(compare_args(&["--factors", "2,7"]).is_err());
+        assert!(compare_args(&["--algorithms", "xbrz,hq5x"]).is_err());
+
+        let Command::Compare { algorithms, .. } =
+            compare_args(&["--algorithms", "xbrz,hqx,nearest"]).unwrap()
+        else {
+            panic!("expected the compare command");
+        };
+        assert_eq!(
+            algorithms,
+            [Algorithm::Xbrz, Algorithm::Hqx, Algorithm::Nearest]
+        );
+    }
 }
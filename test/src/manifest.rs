@@ -0,0 +1,289 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use xbrz::{ScaleFactor, ScalerConfig};
+use xbrz_test::profile::Profile;
+
+use crate::error::ErrorKind;
+use crate::watch::scale_file;
+
+/// A list of images to scale, read from a JSON file.
+///
+/// `factor` and `config` apply to every job that doesn't give its own, and default to the
+/// settings of the `--profile`, if any.
+#[derive(Debug)]
+pub struct Manifest {
+    factor: Option<ScaleFactor>,
+    config: Option<ScalerConfig>,
+    jobs: Vec<Job>,
+}
+
+/// A manifest as it is written, with each job read on its own afterwards so that any errors in
+/// it can say which job they are in.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ManifestFile {
+    #[serde(default)]
+    factor: Option<ScaleFactor>,
+    #[serde(default)]
+    config: Option<ScalerConfig>,
+    jobs: Vec<Value>,
+}
+
+/// An image to scale. Relative paths are relative to the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Job {
+    input: PathBuf,
+    output: PathBuf,
+    #[serde(default)]
+    factor: Option<ScaleFactor>,
+    #[serde(default)]
+    config: Option<ScalerConfig>,
+}
+
+/// What happened to each job, in the same order as the manifest, written out as JSON.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub succeeded: usize,
+    pub failed: usize,
+    results: Vec<JobResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct JobResult {
+    input: PathBuf,
+    output: PathBuf,
+    factor: usize,
+    /// Why the job failed, or `None` if the image was scaled and saved.
+    error: Option<String>,
+    /// What kind of error the job failed with, as in the exit codes of the CLI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<ErrorKind>,
+    millis: u128,
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// The job at this index in the `jobs` list is malformed.
+    Job(usize, serde_json::Error),
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestError::Io(e) => write!(f, "{e}"),
+            ManifestError::Json(e) => write!(f, "{e}"),
+            ManifestError::Job(i, e) => write!(f, "jobs[{i}]: {e}"),
+        }
+    }
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let text = fs::read_to_string(path).map_err(ManifestError::Io)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&text, dir)
+    }
+
+    /// Read a manifest, with the paths of its jobs relative to `dir`.
+    fn parse(text: &str, dir: &Path) -> Result<Self, ManifestError> {
+        let file: ManifestFile = serde_json::from_str(text).map_err(ManifestError::Json)?;
+        let jobs = (file.jobs.into_iter().enumerate())
+            .map(|(i, job)| {
+                let mut job = Job::deserialize(job).map_err(|e| ManifestError::Job(i, e))?;
+                job.input = dir.join(&job.input);
+                job.output = dir.join(&job.output);
+                Ok(job)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            factor: file.factor,
+            config: file.config,
+            jobs,
+        })
+    }
+
+    /// Scale every job on `threads` threads, carrying on past any that fail.
+    pub fn run(&self, profile: &Profile, threads: usize) -> Report {
+        let results: Vec<OnceLock<JobResult>> = self.jobs.iter().map(|_| OnceLock::new()).collect();
+        // each thread takes the next job that hasn't been started until there are none left
+        let next = AtomicUsize::new(0);
+        thread::scope(|scope| {
+            for _ in 0..threads.clamp(1, self.jobs.len().max(1)) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(job) = self.jobs.get(i) else {
+                        break;
+                    };
+                    let _ = results[i].set(self.run_job(job, profile));
+                });
+            }
+        });
+
+        let results: Vec<JobResult> = results
+            .into_iter()
+            .map(|result| result.into_inner().expect("every job is run"))
+            .collect();
+        let failed = results.iter().filter(|r| r.error.is_some()).count();
+        Report {
+            succeeded: results.len() - failed,
+            failed,
+            results,
+        }
+    }
+
+    fn run_job(&self, job: &Job, profile: &Profile) -> JobResult {
+        let factor = job
+            .factor
+            .or(self.factor)
+            .or(profile.scale)
+            .map_or(2, ScaleFactor::get);
+        let config = job
+            .config
+            .as_ref()
+            .or(self.config.as_ref())
+            .unwrap_or(&profile.config);
+
+        let start = Instant::now();
+        let error = scale_file(&job.input, &job.output, factor as u32, config).err();
+        JobResult {
+            input: job.input.clone(),
+            output: job.output.clone(),
+            factor,
+            error_kind: error.as_ref().map(|e| e.kind),
+            error: error.map(|e| e.message),
+            millis: start.elapsed().as_millis(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::RgbaImage;
+
+    use super::*;
+
+    #[test]
+    fn jobs_are_read_relative_to_the_manifest() {
+        let manifest = Manifest::parse(
+            r#"{
+                "factor": 3,
+                "config": { "equal_color_tolerance": 20.0 },
+                "jobs": [
+                    { "input": "a.png", "output": "out/a.png" },
+                    { "input": "/abs/b.png", "output": "b.png", "factor": 2, "config": {} }
+                ]
+            }"#,
+            Path::new("assets"),
+        )
+        .unwrap();
+        assert_eq!(manifest.factor.map(ScaleFactor::get), Some(3));
+        assert_eq!(manifest.config.unwrap().equal_color_tolerance, 20.0);
+
+        let [a, b] = &manifest.jobs[..] else {
+            panic!("expected two jobs");
+        };
+        assert_eq!(a.input, Path::new("assets/a.png"));
+        assert_eq!(a.output, Path::new("assets/out/a.png"));
+        assert_eq!((a.factor, a.config.is_none()), (None, true));
+        assert_eq!(b.input, Path::new("/abs/b.png"));
+        assert_eq!(b.factor.map(ScaleFactor::get), Some(2));
+        assert!(b.config.is_some());
+    }
+
+    #[test]
+    fn errors_name_the_malformed_job() {
+        let parse = |jobs: &str| {
+            let text =
+                format!(r#"{{ "jobs": [{{ "input": "a.png", "output": "b.png" }}, {jobs}] }}"#);
+            Manifest::parse(&text, Path::new(""))
+                .unwrap_err()
+                .to_string()
+        };
+
+        let error = parse(r#"{ "input": "c.png", "output": "d.png", "fator": 2 }"#);
+        assert!(
+            error.starts_with("jobs[1]: unknown field `fator`"),
+            "{error}"
+        );
+        let error = parse(r#"{ "input": "c.png" }"#);
+        assert!(
+            error.starts_with("jobs[1]: missing field `output`"),
+            "{error}"
+        );
+        let error = parse(r#"{ "input": "c.png", "output": "d.png", "factor": 7 }"#);
+        assert!(error.starts_with("jobs[1]: "), "{error}");
+        let error = parse(r#""c.png""#);
+        assert!(error.starts_with("jobs[1]: "), "{error}");
+
+        // errors outside of the jobs are reported by where they are in the file
+        let error = Manifest::parse(r#"{ "jobs": [], "threads": 2 }"#, Path::new(""));
+        assert!(matches!(error, Err(ManifestError::Json(_))));
+        let error = Manifest::parse(r#"{ "jobs": [ "#, Path::new(""));
+        assert!(matches!(error, Err(ManifestError::Json(_))));
+    }
+
+    #[test]
+    fn jobs_take_the_factor_from_the_job_then_the_manifest_then_the_profile() {
+        let dir = std::env::temp_dir().join(format!("xbrz-manifest-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(3, 2, image::Rgba([200, 40, 40, 255]))
+            .save(dir.join("a.png"))
+            .unwrap();
+        let profile = Profile {
+            scale: ScaleFactor::new(4),
+            config: ScalerConfig::default(),
+        };
+
+        let manifest = Manifest::parse(
+            r#"{
+                "factor": 3,
+                "jobs": [
+                    { "input": "a.png", "output": "out/manifest.png" },
+                    { "input": "a.png", "output": "out/job.png", "factor": 5 },
+                    { "input": "missing.png", "output": "out/missing.png" }
+                ]
+            }"#,
+            &dir,
+        )
+        .unwrap();
+        let report = manifest.run(&profile, 2);
+        assert_eq!((report.succeeded, report.failed), (2, 1));
+        let factors: Vec<usize> = report.results.iter().map(|r| r.factor).collect();
+        assert_eq!(factors, [3, 5, 3]);
+        assert_eq!(report.results[2].error_kind, Some(ErrorKind::Input));
+        assert!(report.results[..2].iter().all(|r| r.error.is_none()));
+        assert_eq!(
+            image::image_dimensions(dir.join("out/manifest.png")).unwrap(),
+            (9, 6)
+        );
+        assert_eq!(
+            image::image_dimensions(dir.join("out/job.png")).unwrap(),
+            (15, 10)
+        );
+
+        let manifest = Manifest::parse(
+            r#"{ "jobs": [{ "input": "a.png", "output": "out/profile.png" }] }"#,
+            &dir,
+        )
+        .unwrap();
+        let report = manifest.run(&profile, 1);
+        assert_eq!(report.results[0].factor, 4);
+        assert_eq!(
+            image::image_dimensions(dir.join("out/profile.png")).unwrap(),
+            (12, 8)
+        );
+    }
+}
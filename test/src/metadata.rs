@@ -0,0 +1,233 @@
+use std::io::{self, Cursor, Write};
+
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use image_webp::WebPDecoder;
+use png::chunk::ChunkType;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// PNG chunks that describe the image rather than its pixels, and so still apply once it's scaled.
+/// `pHYs` is left out, as the physical size of a pixel changes with the scale.
+const KEPT_CHUNKS: [[u8; 4]; 9] = [
+    *b"iCCP", *b"sRGB", *b"gAMA", *b"cHRM", *b"cICP", *b"eXIf", *b"tEXt", *b"zTXt", *b"iTXt",
+];
+
+/// Colour profiles, EXIF data and text carried over from an input image to the scaled output,
+/// stored as the PNG chunks they are written out as.
+#[derive(Debug, Default)]
+pub struct Metadata {
+    chunks: Vec<(ChunkType, Vec<u8>)>,
+}
+
+impl Metadata {
+    /// Read the metadata of an encoded PNG or WebP image. Images in any other format have none.
+    pub fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.starts_with(&PNG_SIGNATURE) {
+            Ok(Self::from_png(&bytes[PNG_SIGNATURE.len()..]))
+        } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+            Self::from_webp(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Copy the kept chunks as they are, without decompressing or checking them.
+    fn from_png(mut bytes: &[u8]) -> Self {
+        let mut chunks = Vec::new();
+        // each chunk is a 4 byte length, a 4 byte type, its data and a 4 byte CRC
+        while bytes.len() >= 12 {
+            let len = u32::from_be_bytes(bytes[..4].try_into().unwrap()) as usize;
+            let kind: [u8; 4] = bytes[4..8].try_into().unwrap();
+            let Some(data) = bytes.get(8..8 + len) else {
+                break;
+            };
+            if KEPT_CHUNKS.contains(&kind) {
+                chunks.push((ChunkType(kind), data.to_vec()));
+            }
+            if &kind == b"IEND" {
+                break;
+            }
+            bytes = bytes.get(12 + len..).unwrap_or_default();
+        }
+        Self { chunks }
+    }
+
+    fn from_webp(bytes: &[u8]) -> Result<Self, image_webp::DecodingError> {
+        let mut decoder = WebPDecoder::new(Cursor::new(bytes))?;
+        let mut chunks = Vec::new();
+        if let Some(profile) = decoder.icc_profile()? {
+            // profile name, its null terminator, and the compression method, which is always zlib
+            let mut data = b"ICC Profile\0\0".to_vec();
+            let mut encoder = ZlibEncoder::new(&mut data, Compression::default());
+            encoder
+                .write_all(&profile)
+                .and_then(|()| encoder.finish().map(drop))?;
+            chunks.push((ChunkType(*b"iCCP"), data));
+        }
+        if let Some(exif) = decoder.exif_metadata()? {
+            chunks.push((ChunkType(*b"eXIf"), exif));
+        }
+        if let Some(xmp) = decoder.xmp_metadata()? {
+            // an uncompressed iTXt chunk with no language or translated keyword
+            let mut data = b"XML:com.adobe.xmp\0\0\0\0\0".to_vec();
+            data.extend_from_slice(&xmp);
+            chunks.push((ChunkType(*b"iTXt"), data));
+        }
+        Ok(Self { chunks })
+    }
+
+    /// Add the metadata to an encoded PNG file, just after its header, where colour profiles
+    /// need to be for them to apply to the palette of an indexed image.
+    pub fn insert_into_png(&self, png: &mut Vec<u8>) {
+        // the signature, then the IHDR chunk with its 13 bytes of data
+        let header_end = PNG_SIGNATURE.len() + 12 + 13;
+        let mut chunks = Vec::new();
+        for (ChunkType(kind), data) in &self.chunks {
+            let mut crc = Crc::new();
+            crc.update(kind);
+            crc.update(data);
+            chunks.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            chunks.extend_from_slice(kind);
+            chunks.extend_from_slice(data);
+            chunks.extend_from_slice(&crc.sum().to_be_bytes());
+        }
+        png.splice(header_end..header_end, chunks);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image_webp::{ColorType, WebPEncoder};
+    use png::{PixelDimensions, ScaledFloat, Unit};
+
+    use super::*;
+
+    const PIXELS: [u8; 3 * 4] = [200, 40, 40, 255, 30, 160, 60, 128, 40, 90, 200, 0];
+
+    /// A 3x1 RGBA PNG, with the chunks added by `setup`.
+    fn png(setup: impl FnOnce(&mut png::Encoder<&mut Vec<u8>>)) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 3, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        setup(&mut encoder);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&PIXELS).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    fn webp(setup: impl FnOnce(&mut WebPEncoder<&mut Vec<u8>>)) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = WebPEncoder::new(&mut bytes);
+        setup(&mut encoder);
+        encoder.encode(&PIXELS, 3, 1, ColorType::Rgba8).unwrap();
+        bytes
+    }
+
+    fn kinds(metadata: &Metadata) -> Vec<&[u8; 4]> {
+        metadata
+            .chunks
+            .iter()
+            .map(|(ChunkType(kind), _)| kind)
+            .collect()
+    }
+
+    /// Decode a PNG, checking that its pixels are intact.
+    fn decode(png: &[u8]) -> png::Info<'static> {
+        let mut reader = png::Decoder::new(png).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, PIXELS);
+        reader.finish().unwrap();
+        reader.info().clone()
+    }
+
+    #[test]
+    fn png_metadata_is_copied_without_the_physical_size() {
+        let source = png(|encoder| {
+            encoder.set_source_gamma(ScaledFloat::new(0.5));
+            encoder.set_pixel_dims(Some(PixelDimensions {
+                xppu: 2835,
+                yppu: 2835,
+                unit: Unit::Meter,
+            }));
+            encoder
+                .add_text_chunk("Title".into(), "Sprite".into())
+                .unwrap();
+            encoder
+                .add_itxt_chunk("Comment".into(), "scaled ×2".into())
+                .unwrap();
+        });
+        let metadata = Metadata::parse(&source).unwrap();
+        assert_eq!(kinds(&metadata), [b"gAMA", b"tEXt", b"iTXt"]);
+
+        let mut scaled = png(|_| {});
+        metadata.insert_into_png(&mut scaled);
+        let info = decode(&scaled);
+        assert_eq!(info.source_gamma, Some(ScaledFloat::new(0.5)));
+        assert!(info.pixel_dims.is_none());
+        assert_eq!(info.uncompressed_latin1_text[0].keyword, "Title");
+        assert_eq!(info.uncompressed_latin1_text[0].text, "Sprite");
+        assert_eq!(info.utf8_text[0].get_text().unwrap(), "scaled ×2");
+    }
+
+    #[test]
+    fn webp_metadata_becomes_png_chunks() {
+        let profile = b"not really an ICC profile, but enough to compress".to_vec();
+        let exif = b"Exif\0\0MM\0*\0\0\0\x08\0\0".to_vec();
+        let xmp = b"<x:xmpmeta xmlns:x='adobe:ns:meta/'/>".to_vec();
+        let source = webp(|encoder| {
+            encoder.set_icc_profile(profile.clone());
+            encoder.set_exif_metadata(exif.clone());
+            encoder.set_xmp_metadata(xmp.clone());
+        });
+        let metadata = Metadata::parse(&source).unwrap();
+        assert_eq!(kinds(&metadata), [b"iCCP", b"eXIf", b"iTXt"]);
+
+        let mut scaled = png(|_| {});
+        metadata.insert_into_png(&mut scaled);
+        let info = decode(&scaled);
+        assert_eq!(info.icc_profile.as_deref(), Some(&profile[..]));
+        assert_eq!(info.utf8_text[0].keyword, "XML:com.adobe.xmp");
+        assert_eq!(info.utf8_text[0].get_text().unwrap().as_bytes(), xmp);
+
+        // the chunks are read back the same from the scaled PNG, EXIF data included
+        let copied = Metadata::parse(&scaled).unwrap();
+        assert_eq!(copied.chunks, metadata.chunks);
+        assert_eq!(copied.chunks[1].1, exif);
+    }
+
+    #[test]
+    fn images_without_metadata_have_none() {
+        let plain = png(|_| {});
+        assert!(Metadata::parse(&plain).unwrap().is_empty());
+        assert!(Metadata::parse(&webp(|_| {})).unwrap().is_empty());
+        assert!(Metadata::parse(b"BM not a PNG or WebP").unwrap().is_empty());
+
+        let mut scaled = plain.clone();
+        Metadata::default().insert_into_png(&mut scaled);
+        assert_eq!(scaled, plain);
+    }
+
+    #[test]
+    fn truncated_chunks_are_left_out() {
+        let source = png(|encoder| {
+            encoder.set_source_gamma(ScaledFloat::new(0.5));
+            encoder
+                .add_text_chunk("Title".into(), "Sprite".into())
+                .unwrap();
+        });
+        let text = source.windows(4).position(|w| w == b"tEXt").unwrap();
+        // cut off partway through the data of the text chunk
+        let metadata = Metadata::parse(&source[..text + 8]).unwrap();
+        assert_eq!(kinds(&metadata), [b"gAMA"]);
+
+        let source = webp(|encoder| encoder.set_exif_metadata(b"Exif\0\0".to_vec()));
+        assert!(Metadata::parse(&source[..source.len() / 2]).is_err());
+    }
+}
@@ -0,0 +1,564 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+
+use clap::ValueEnum;
+use xbrz::{PixelFormat, ScalerConfig};
+
+/// Container format for raw video frames read from stdin and written to stdout.
+#[derive(ValueEnum, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PipeFormat {
+    /// YUV4MPEG2 stream, as produced by `ffmpeg -f yuv4mpegpipe`
+    #[default]
+    Y4m,
+    /// Headerless RGBA frames, as produced by `ffmpeg -f rawvideo -pix_fmt rgba`
+    Rawvideo,
+}
+
+/// Frame dimensions given on the command line as `WIDTHxHEIGHT`, or as the name of a common
+/// display size such as `1080p` or `4k`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameSize {
+    pub width: usize,
+    pub height: usize,
+}
+
+/// Display sizes that can be given by name instead of as `WIDTHxHEIGHT`.
+const NAMED_SIZES: [(&str, usize, usize); 6] = [
+    ("720p", 1280, 720),
+    ("1080p", 1920, 1080),
+    ("1440p", 2560, 1440),
+    ("2160p", 3840, 2160),
+    ("4k", 3840, 2160),
+    ("8k", 7680, 4320),
+];
+
+impl std::str::FromStr for FrameSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(&(_, width, height)) = NAMED_SIZES
+            .iter()
+            .find(|(name, _, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self { width, height });
+        }
+        let (w, h) = s.split_once(['x', 'X']).ok_or_else(|| {
+            format!("expected WIDTHxHEIGHT or a size such as 1080p or 4k, got \"{s}\"")
+        })?;
+        let width = w.parse().map_err(|e| format!("invalid width: {e}"))?;
+        let height = h.parse().map_err(|e| format!("invalid height: {e}"))?;
+        Ok(Self { width, height })
+    }
+}
+
+#[derive(Debug)]
+pub enum PipeError {
+    Io(io::Error),
+    Header(String),
+    UnsupportedColorspace(String),
+    MissingSize,
+}
+
+impl Display for PipeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipeError::Io(e) => write!(f, "{e}"),
+            PipeError::Header(msg) => write!(f, "invalid y4m header: {msg}"),
+            PipeError::UnsupportedColorspace(c) => write!(f, "unsupported y4m colorspace: {c}"),
+            PipeError::MissingSize => write!(f, "--size is required for rawvideo input"),
+        }
+    }
+}
+
+impl From<io::Error> for PipeError {
+    fn from(value: io::Error) -> Self {
+        PipeError::Io(value)
+    }
+}
+
+/// Scale every frame of a video stream read from `input`, writing the scaled stream to `output`.
+///
+/// Returns the number of frames processed.
+pub fn scale_stream(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    format: PipeFormat,
+    size: Option<FrameSize>,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Result<usize, PipeError> {
+    match format {
+        PipeFormat::Y4m => scale_y4m(input, output, factor, config),
+        PipeFormat::Rawvideo => {
+            let size = size.ok_or(PipeError::MissingSize)?;
+            scale_rawvideo(input, output, size, factor, config)
+        }
+    }
+}
+
+fn scale_rawvideo(
+    input: &mut impl Read,
+    output: &mut impl Write,
+    size: FrameSize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Result<usize, PipeError> {
+    let mut frame = vec![0u8; size.width * size.height * 4];
+    let mut count = 0;
+
+    while read_frame(input, &mut frame)? {
+        let scaled = scale_frame(&frame, size.width, size.height, factor, config);
+        output.write_all(&scaled)?;
+        count += 1;
+    }
+
+    output.flush()?;
+    Ok(count)
+}
+
+/// Scale one RGBA frame. Fully opaque frames are scaled as RGB, so that their edges are extended
+/// outwards like the rest of the picture rather than faded out to transparent.
+fn scale_frame(
+    rgba: &[u8],
+    width: usize,
+    height: usize,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Vec<u8> {
+    if rgba.chunks_exact(4).any(|px| px[3] != 255) {
+        return xbrz::scale_rgba_with_config(rgba, width, height, factor, config);
+    }
+
+    let rgb: Vec<u8> = rgba
+        .chunks_exact(4)
+        .flat_map(|px| [px[0], px[1], px[2]])
+        .collect();
+    xbrz::scale_with_config(&rgb, PixelFormat::Rgb8, width, height, factor, config)
+        .chunks_exact(3)
+        .flat_map(|px| [px[0], px[1], px[2], 255])
+        .collect()
+}
+
+/// Fill `buf` with the next frame, returning `false` on a clean end of stream.
+fn read_frame(input: &mut impl Read, buf: &mut [u8]) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match input.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+/// Chroma layout of a y4m stream, as given by its `C` header parameter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Colorspace {
+    /// horizontal chroma subsampling divisor
+    chroma_x: usize,
+    /// vertical chroma subsampling divisor
+    chroma_y: usize,
+    has_chroma: bool,
+    has_alpha: bool,
+}
+
+impl Colorspace {
+    fn parse(tag: &str) -> Result<Self, PipeError> {
+        let (chroma_x, chroma_y, has_chroma, has_alpha) = match tag {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => (2, 2, true, false),
+            "422" => (2, 1, true, false),
+            "444" => (1, 1, true, false),
+            "444alpha" => (1, 1, true, true),
+            "mono" => (1, 1, false, false),
+            _ => return Err(PipeError::UnsupportedColorspace(tag.to_string())),
+        };
+        Ok(Self {
+            chroma_x,
+            chroma_y,
+            has_chroma,
+            has_alpha,
+        })
+    }
+
+    fn chroma_size(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.has_chroma {
+            (
+                width.div_ceil(self.chroma_x),
+                height.div_ceil(self.chroma_y),
+            )
+        } else {
+            (0, 0)
+        }
+    }
+
+    fn frame_len(&self, width: usize, height: usize) -> usize {
+        let (cw, ch) = self.chroma_size(width, height);
+        let alpha = if self.has_alpha { width * height } else { 0 };
+        width * height + 2 * cw * ch + alpha
+    }
+}
+
+struct Y4mHeader {
+    width: usize,
+    height: usize,
+    colorspace: Colorspace,
+    /// all parameters other than width and height, passed through verbatim
+    params: Vec<String>,
+}
+
+impl Y4mHeader {
+    fn read(input: &mut impl BufRead) -> Result<Option<Self>, PipeError> {
+        let mut line = Vec::new();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8(line).map_err(|_| PipeError::Header("not ASCII".into()))?;
+        let mut tokens = line.trim_end_matches('\n').split(' ');
+
+        if tokens.next() != Some("YUV4MPEG2") {
+            return Err(PipeError::Header("missing YUV4MPEG2 signature".into()));
+        }
+
+        let mut width = None;
+        let mut height = None;
+        let mut colorspace = Colorspace::parse("420jpeg")?;
+        let mut params = Vec::new();
+        for token in tokens.filter(|t| !t.is_empty()) {
+            let (tag, value) = token.split_at(token.chars().next().map_or(0, char::len_utf8));
+            match tag {
+                "W" => width = value.parse().ok(),
+                "H" => height = value.parse().ok(),
+                "C" => {
+                    colorspace = Colorspace::parse(value)?;
+                    params.push(token.to_string());
+                }
+                _ => params.push(token.to_string()),
+            }
+        }
+
+        Ok(Some(Self {
+            width: width.ok_or_else(|| PipeError::Header("missing width".into()))?,
+            height: height.ok_or_else(|| PipeError::Header("missing height".into()))?,
+            colorspace,
+            params,
+        }))
+    }
+
+    fn write(&self, output: &mut impl Write) -> io::Result<()> {
+        write!(output, "YUV4MPEG2 W{} H{}", self.width, self.height)?;
+        for param in &self.params {
+            write!(output, " {param}")?;
+        }
+        writeln!(output)
+    }
+}
+
+fn scale_y4m(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Result<usize, PipeError> {
+    let Some(header) = Y4mHeader::read(input)? else {
+        return Ok(0);
+    };
+    let (width, height, colorspace) = (header.width, header.height, header.colorspace);
+    let out_header = Y4mHeader {
+        width: width * factor,
+        height: height * factor,
+        ..header
+    };
+    out_header.write(output)?;
+
+    let mut frame = vec![0u8; colorspace.frame_len(width, height)];
+    let mut line = Vec::new();
+    let mut count = 0;
+
+    loop {
+        line.clear();
+        if input.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        if !line.starts_with(b"FRAME") {
+            return Err(PipeError::Header("missing FRAME marker".into()));
+        }
+        if !read_frame(input, &mut frame)? {
+            return Err(PipeError::Io(ErrorKind::UnexpectedEof.into()));
+        }
+
+        let rgba = yuv_to_rgba(&frame, width, height, colorspace);
+        let scaled = scale_frame(&rgba, width, height, factor, config);
+
+        output.write_all(&line)?;
+        output.write_all(&rgba_to_yuv(
+            &scaled,
+            out_header.width,
+            out_header.height,
+            colorspace,
+        ))?;
+        count += 1;
+    }
+
+    output.flush()?;
+    Ok(count)
+}
+
+#[inline]
+fn clamp_u8(v: f32) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+// BT.601 limited range, which is what ffmpeg assumes for y4m streams without further tags
+
+fn yuv_to_rgba(frame: &[u8], width: usize, height: usize, cs: Colorspace) -> Vec<u8> {
+    let (cw, ch) = cs.chroma_size(width, height);
+    let (y_plane, rest) = frame.split_at(width * height);
+    let (u_plane, rest) = rest.split_at(cw * ch);
+    let (v_plane, a_plane) = rest.split_at(cw * ch);
+
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for y in 0..height {
+        for x in 0..width {
+            let luma = 1.164 * (y_plane[y * width + x] as f32 - 16.0);
+            let (u, v) = if cs.has_chroma {
+                let ci = (y / cs.chroma_y) * cw + x / cs.chroma_x;
+                (u_plane[ci] as f32 - 128.0, v_plane[ci] as f32 - 128.0)
+            } else {
+                (0.0, 0.0)
+            };
+            let alpha = if cs.has_alpha {
+                a_plane[y * width + x]
+            } else {
+                u8::MAX
+            };
+
+            rgba.extend_from_slice(&[
+                clamp_u8(luma + 1.596 * v),
+                clamp_u8(luma - 0.392 * u - 0.813 * v),
+                clamp_u8(luma + 2.017 * u),
+                alpha,
+            ]);
+        }
+    }
+    rgba
+}
+
+fn rgba_to_yuv(rgba: &[u8], width: usize, height: usize, cs: Colorspace) -> Vec<u8> {
+    let (cw, ch) = cs.chroma_size(width, height);
+    let mut frame = vec![0u8; cs.frame_len(width, height)];
+    let (y_plane, rest) = frame.split_at_mut(width * height);
+    let (u_plane, rest) = rest.split_at_mut(cw * ch);
+    let (v_plane, a_plane) = rest.split_at_mut(cw * ch);
+
+    let mut u_sum = vec![0f32; cw * ch];
+    let mut v_sum = vec![0f32; cw * ch];
+    let mut samples = vec![0u32; cw * ch];
+
+    for (i, px) in rgba.chunks_exact(4).enumerate() {
+        let (r, g, b, a) = (px[0] as f32, px[1] as f32, px[2] as f32, px[3]);
+        y_plane[i] = clamp_u8(16.0 + 0.257 * r + 0.504 * g + 0.098 * b);
+        if cs.has_alpha {
+            a_plane[i] = a;
+        }
+        if cs.has_chroma {
+            let (x, y) = (i % width, i / width);
+            let ci = (y / cs.chroma_y) * cw + x / cs.chroma_x;
+            u_sum[ci] += -0.148 * r - 0.291 * g + 0.439 * b;
+            v_sum[ci] += 0.439 * r - 0.368 * g - 0.071 * b;
+            samples[ci] += 1;
+        }
+    }
+
+    for ci in 0..cw * ch {
+        let n = samples[ci].max(1) as f32;
+        u_plane[ci] = clamp_u8(128.0 + u_sum[ci] / n);
+        v_plane[ci] = clamp_u8(128.0 + v_sum[ci] / n);
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_sizes_parse_by_name_or_dimensions() {
+        let size = |width, height| Ok(FrameSize { width, height });
+        assert_eq!("320x240".parse(), size(320, 240));
+        assert_eq!("64X48".parse(), size(64, 48));
+        assert_eq!("1080p".parse(), size(1920, 1080));
+        assert_eq!("4K".parse(), size(3840, 2160));
+        assert!("320".parse::<FrameSize>().is_err());
+        assert!("320xtall".parse::<FrameSize>().is_err());
+    }
+
+    #[test]
+    fn y4m_header_keeps_unknown_parameters() {
+        let mut input: &[u8] = b"YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C444 XYSCSS=444\n";
+        let header = Y4mHeader::read(&mut input).unwrap().unwrap();
+        assert_eq!((header.width, header.height), (4, 2));
+        assert_eq!(header.colorspace, Colorspace::parse("444").unwrap());
+        assert_eq!(
+            header.params,
+            ["F30000:1001", "Ip", "A1:1", "C444", "XYSCSS=444"]
+        );
+
+        let mut written = Vec::new();
+        header.write(&mut written).unwrap();
+        assert_eq!(
+            written,
+            b"YUV4MPEG2 W4 H2 F30000:1001 Ip A1:1 C444 XYSCSS=444\n"
+        );
+    }
+
+    #[test]
+    fn y4m_header_errors_instead_of_panicking() {
+        let read = |mut header: &[u8]| Y4mHeader::read(&mut header).map(|h| h.is_some());
+        assert!(matches!(read(b""), Ok(false)));
+        assert!(matches!(
+            read(b"YUV4MPEG W4 H2\n"),
+            Err(PipeError::Header(_))
+        ));
+        assert!(matches!(read(b"YUV4MPEG2 W4\n"), Err(PipeError::Header(_))));
+        assert!(matches!(
+            read(b"YUV4MPEG2 W4 H2 C411\n"),
+            Err(PipeError::UnsupportedColorspace(_))
+        ));
+        // a parameter starting with a multi-byte character is passed through like any other
+        assert!(matches!(
+            read("YUV4MPEG2 W4 H2 \u{e9}t\u{e9}\n".as_bytes()),
+            Ok(true)
+        ));
+    }
+
+    #[test]
+    fn y4m_stream_is_scaled_frame_by_frame() {
+        let frame_len = Colorspace::parse("420").unwrap().frame_len(4, 2);
+        let mut input = b"YUV4MPEG2 W4 H2 F25:1 C420\n".to_vec();
+        for _ in 0..2 {
+            input.extend_from_slice(b"FRAME\n");
+            input.extend(std::iter::repeat_n(128, frame_len));
+        }
+
+        let mut output = Vec::new();
+        let config = ScalerConfig::default();
+        let count = scale_stream(
+            &mut &input[..],
+            &mut output,
+            PipeFormat::Y4m,
+            None,
+            3,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(count, 2);
+
+        let header = b"YUV4MPEG2 W12 H6 F25:1 C420\n";
+        assert!(output.starts_with(header));
+        let scaled_len = Colorspace::parse("420").unwrap().frame_len(12, 6);
+        assert_eq!(
+            output.len(),
+            header.len() + 2 * (b"FRAME\n".len() + scaled_len)
+        );
+        assert!(output[header.len()..].starts_with(b"FRAME\n"));
+    }
+
+    #[test]
+    fn rawvideo_needs_a_size() {
+        let config = ScalerConfig::default();
+        let mut output = Vec::new();
+        let scale = |size, output: &mut Vec<u8>| {
+            let input = [255u8; 2 * 2 * 4 * 3];
+            scale_stream(
+                &mut &input[..],
+                output,
+                PipeFormat::Rawvideo,
+                size,
+                2,
+                &config,
+            )
+        };
+        assert!(matches!(
+            scale(None, &mut output),
+            Err(PipeError::MissingSize)
+        ));
+        let size = FrameSize {
+            width: 2,
+            height: 2,
+        };
+        assert_eq!(scale(Some(size), &mut output).unwrap(), 3);
+        // an opaque white frame stays opaque white right out to its edges
+        assert_eq!(output, vec![255; 4 * 4 * 4 * 3]);
+    }
+
+    #[test]
+    fn opaque_frames_keep_opaque_edges() {
+        let config = ScalerConfig::default();
+        let frame: Vec<u8> = (0..4 * 4)
+            .flat_map(|i| {
+                if (i % 4 + i / 4) % 2 == 0 {
+                    [220, 30, 30, 255]
+                } else {
+                    [20, 20, 200, 255]
+                }
+            })
+            .collect();
+        let scaled = scale_frame(&frame, 4, 4, 3, &config);
+        assert_eq!(scaled.len(), 12 * 12 * 4);
+        assert!(scaled.chunks_exact(4).all(|px| px[3] == 255));
+
+        let mut translucent = frame.clone();
+        translucent[3] = 128;
+        assert_eq!(
+            scale_frame(&translucent, 4, 4, 3, &config),
+            xbrz::scale_rgba_with_config(&translucent, 4, 4, 3, &config)
+        );
+    }
+
+    #[test]
+    fn yuv_round_trips_through_rgba() {
+        let (width, height) = (6, 4);
+        // flat 2x2 blocks, so that subsampled chroma loses nothing
+        let colours = [
+            [200, 40, 40, 255],
+            [30, 160, 60, 128],
+            [40, 90, 200, 0],
+            [250, 250, 250, 255],
+            [16, 16, 16, 255],
+            [128, 128, 128, 64],
+        ];
+        let rgba: Vec<u8> = (0..width * height)
+            .flat_map(|i| colours[(i % width / 2 + i / width / 2 * 3) % colours.len()])
+            .collect();
+        let grey: Vec<u8> = rgba
+            .chunks_exact(4)
+            .flat_map(|px| [px[1], px[1], px[1], 255])
+            .collect();
+
+        for (tag, source) in [
+            ("420", &rgba),
+            ("444", &rgba),
+            ("mono", &grey),
+            ("444alpha", &rgba),
+        ] {
+            let cs = Colorspace::parse(tag).unwrap();
+            let yuv = rgba_to_yuv(source, width, height, cs);
+            assert_eq!(yuv.len(), cs.frame_len(width, height), "{tag}");
+            let back = yuv_to_rgba(&yuv, width, height, cs);
+            for (i, (a, b)) in source.chunks_exact(4).zip(back.chunks_exact(4)).enumerate() {
+                for c in 0..3 {
+                    assert!(
+                        a[c].abs_diff(b[c]) <= 3,
+                        "{tag}: pixel {i} {a:?} became {b:?}"
+                    );
+                }
+                let alpha = if cs.has_alpha { a[3] } else { 255 };
+                assert_eq!(b[3], alpha, "{tag}: pixel {i}");
+            }
+        }
+    }
+}
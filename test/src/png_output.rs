@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use clap::ValueEnum;
+
+/// How hard the PNG encoder tries to shrink the output.
+#[derive(ValueEnum, Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum PngCompression {
+    /// Quickest to write, but the largest files
+    Fast,
+    /// The same as other image tools
+    #[default]
+    Default,
+    /// Smallest files, but the slowest to write
+    Best,
+}
+
+impl From<PngCompression> for png::Compression {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => png::Compression::Fast,
+            PngCompression::Default => png::Compression::Default,
+            PngCompression::Best => png::Compression::Best,
+        }
+    }
+}
+
+/// Encode an RGBA image as PNG. With `indexed`, an image of no more than 256 colours, as is common
+/// for scaled pixel art, is stored as indices into a palette, using as few bits as it can.
+pub fn encode_png(
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+    compression: PngCompression,
+    indexed: bool,
+) -> Result<Vec<u8>, png::EncodingError> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_compression(compression.into());
+    encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+
+    let data = match indexed.then(|| Palette::of(rgba)).flatten() {
+        Some(palette) => {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_depth(palette.bit_depth());
+            encoder.set_palette(palette.rgb());
+            if let Some(trns) = palette.trns() {
+                encoder.set_trns(trns);
+            }
+            palette.index(rgba, width as usize)
+        }
+        None => {
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            rgba.to_vec()
+        }
+    };
+
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// The colours of an image with no more than 256 of them, with the translucent colours first,
+/// so that the `tRNS` chunk giving their alpha can leave out the opaque ones.
+struct Palette {
+    colors: Vec<[u8; 4]>,
+    indices: HashMap<[u8; 4], u8>,
+}
+
+impl Palette {
+    fn of(rgba: &[u8]) -> Option<Self> {
+        let mut colors = Vec::new();
+        let mut seen = HashSet::new();
+        for px in rgba.chunks_exact(4) {
+            let px: [u8; 4] = px.try_into().unwrap();
+            if seen.insert(px) {
+                if colors.len() == 256 {
+                    return None;
+                }
+                colors.push(px);
+            }
+        }
+        colors.sort_by_key(|px| px[3] == 255);
+        let indices = (colors.iter().enumerate())
+            .map(|(i, &px)| (px, i as u8))
+            .collect();
+        Some(Self { colors, indices })
+    }
+
+    fn bits(&self) -> usize {
+        match self.colors.len() {
+            0..=2 => 1,
+            3..=4 => 2,
+            5..=16 => 4,
+            _ => 8,
+        }
+    }
+
+    fn bit_depth(&self) -> png::BitDepth {
+        match self.bits() {
+            1 => png::BitDepth::One,
+            2 => png::BitDepth::Two,
+            4 => png::BitDepth::Four,
+            _ => png::BitDepth::Eight,
+        }
+    }
+
+    fn rgb(&self) -> Vec<u8> {
+        self.colors
+            .iter()
+            .flat_map(|px| [px[0], px[1], px[2]])
+            .collect()
+    }
+
+    fn trns(&self) -> Option<Vec<u8>> {
+        let trns: Vec<u8> = (self.colors.iter())
+            .map(|px| px[3])
+            .take_while(|&a| a != 255)
+            .collect();
+        (!trns.is_empty()).then_some(trns)
+    }
+
+    /// The rows of the image as indices, packed into bytes with the leftmost pixel in the highest bits.
+    fn index(&self, rgba: &[u8], width: usize) -> Vec<u8> {
+        let bits = self.bits();
+        let per_byte = 8 / bits;
+        let mut out = Vec::with_capacity(rgba.len() / 4 / per_byte + rgba.len() / 4 / width.max(1));
+        for row in rgba.chunks_exact(width * 4) {
+            for pixels in row.chunks(4 * per_byte) {
+                let mut byte = 0;
+                for (i, px) in pixels.chunks_exact(4).enumerate() {
+                    let index = self.indices[<&[u8; 4]>::try_from(px).unwrap()];
+                    byte |= index << (8 - bits * (i + 1));
+                }
+                out.push(byte);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An image of `width` by 3 pixels using `colors` colours, the first of them translucent.
+    fn image(width: usize, colors: usize) -> Vec<u8> {
+        (0..width * 3)
+            .flat_map(|i| {
+                let c = i % colors;
+                let alpha = if c == 0 { 100 } else { 255 };
+                [c as u8, (c * 7 % 256) as u8, (c / 256 * 50) as u8, alpha]
+            })
+            .collect()
+    }
+
+    /// The colour type and bit depth stored in a PNG, and its pixels as RGBA.
+    fn decode(png: &[u8]) -> (png::ColorType, png::BitDepth, Vec<u8>) {
+        let reader = png::Decoder::new(png).read_info().unwrap();
+        let (color_type, bit_depth) = (reader.info().color_type, reader.info().bit_depth);
+        let rgba = image::load_from_memory_with_format(png, image::ImageFormat::Png)
+            .unwrap()
+            .into_rgba8()
+            .into_raw();
+        (color_type, bit_depth, rgba)
+    }
+
+    #[test]
+    fn indexed_images_use_as_few_bits_as_they_can() {
+        use png::BitDepth::*;
+
+        // an odd width, so that rows end partway through a byte
+        let width = 5;
+        let cases: [(usize, _); 5] = [(1, One), (2, One), (4, Two), (16, Four), (200, Eight)];
+        for compression in [
+            PngCompression::Fast,
+            PngCompression::Default,
+            PngCompression::Best,
+        ] {
+            for (colors, depth) in cases {
+                let width = width.max(colors.div_ceil(3));
+                let rgba = image(width, colors);
+                let png = encode_png(&rgba, width as u32, 3, compression, true).unwrap();
+                let (color_type, bit_depth, decoded) = decode(&png);
+                let case = format!("{colors} colours, {compression:?}");
+                assert_eq!(color_type, png::ColorType::Indexed, "{case}");
+                assert_eq!(bit_depth, depth, "{case}");
+                assert_eq!(decoded, rgba, "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn other_images_are_stored_as_rgba() {
+        for compression in [
+            PngCompression::Fast,
+            PngCompression::Default,
+            PngCompression::Best,
+        ] {
+            // too many colours for a palette
+            let rgba = image(100, 300);
+            let png = encode_png(&rgba, 100, 3, compression, true).unwrap();
+            assert_eq!(
+                decode(&png),
+                (png::ColorType::Rgba, png::BitDepth::Eight, rgba)
+            );
+
+            // few enough colours, but no palette asked for
+            let rgba = image(5, 4);
+            let png = encode_png(&rgba, 5, 3, compression, false).unwrap();
+            assert_eq!(
+                decode(&png),
+                (png::ColorType::Rgba, png::BitDepth::Eight, rgba)
+            );
+        }
+    }
+
+    #[test]
+    fn only_translucent_colours_are_given_alpha() {
+        let palette = Palette::of(&image(5, 4)).unwrap();
+        // the translucent colour is sorted to the front, and the opaque ones left out of tRNS
+        assert_eq!(palette.colors[0][3], 100);
+        assert_eq!(palette.trns(), Some(vec![100]));
+
+        let opaque: Vec<u8> = image(5, 4)
+            .chunks_exact(4)
+            .flat_map(|px| [px[0], px[1], px[2], 255])
+            .collect();
+        assert_eq!(Palette::of(&opaque).unwrap().trns(), None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use xbrz::{ScaleFactor, ScalerConfig};
+
+/// Scaling settings saved to or loaded from a TOML or JSON file.
+///
+/// Unknown settings are refused rather than ignored, so that a misspelt setting isn't silently lost.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<ScaleFactor>,
+    #[serde(default)]
+    pub config: ScalerConfig,
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(std::io::Error),
+    Toml(String),
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::Io(e) => write!(f, "{e}"),
+            ProfileError::Toml(e) => write!(f, "{e}"),
+            ProfileError::Json(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+impl Profile {
+    /// Reads a profile, as JSON if the file has a `.json` extension and as TOML otherwise.
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        let text = fs::read_to_string(path).map_err(ProfileError::Io)?;
+        if is_json(path) {
+            serde_json::from_str(&text).map_err(ProfileError::Json)
+        } else {
+            toml::from_str(&text).map_err(|e| ProfileError::Toml(e.to_string()))
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ProfileError> {
+        let text = if is_json(path) {
+            serde_json::to_string_pretty(self).map_err(ProfileError::Json)?
+        } else {
+            toml::to_string_pretty(self).map_err(|e| ProfileError::Toml(e.to_string()))?
+        };
+        fs::write(path, text).map_err(ProfileError::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use xbrz::{AlphaMode, OutlineConfig};
+
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xbrz-profile-{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn profiles_round_trip_from_json_to_toml() {
+        let profile = Profile {
+            scale: ScaleFactor::new(3),
+            config: ScalerConfig {
+                preserve_outlines: Some(OutlineConfig {
+                    color: [20, 10, 40],
+                    tolerance: 12.5,
+                }),
+                alpha_mode: AlphaMode::Threshold(96),
+                equal_color_tolerance: 24.0,
+                ..ScalerConfig::default()
+            },
+        };
+
+        let json = temp_path("round-trip.json");
+        profile.save(&json).unwrap();
+        let from_json = Profile::load(&json).unwrap();
+        let toml = temp_path("round-trip.toml");
+        from_json.save(&toml).unwrap();
+        let from_toml = Profile::load(&toml).unwrap();
+
+        assert!(fs::read_to_string(&json)
+            .unwrap()
+            .trim_start()
+            .starts_with('{'));
+        assert!(toml::from_str::<toml::Table>(&fs::read_to_string(&toml).unwrap()).is_ok());
+        for loaded in [&from_json, &from_toml] {
+            assert_eq!(loaded.scale, profile.scale);
+            assert_eq!(loaded.config, profile.config);
+        }
+    }
+
+    #[test]
+    fn unknown_settings_are_refused() {
+        let path = temp_path("unknown.toml");
+        fs::write(&path, "scale = 2\nsharpness = 3\n").unwrap();
+        let error = Profile::load(&path).unwrap_err();
+        assert!(matches!(error, ProfileError::Toml(_)));
+        assert!(error.to_string().contains("sharpness"), "{error}");
+
+        let path = temp_path("unknown.json");
+        fs::write(&path, r#"{"scale": 2, "sharpness": 3}"#).unwrap();
+        let error = Profile::load(&path).unwrap_err();
+        assert!(matches!(error, ProfileError::Json(_)));
+        assert!(error.to_string().contains("sharpness"), "{error}");
+
+        let missing = Profile::load(&temp_path("missing.toml")).unwrap_err();
+        assert!(matches!(missing, ProfileError::Io(_)));
+    }
+}
@@ -0,0 +1,382 @@
+use std::io::{self, BufRead, BufReader, Cursor, Read, Write};
+use std::net::TcpListener;
+
+use image::{imageops, ImageFormat, RgbaImage};
+use xbrz::{AlphaMode, BlendWeights, OutlineConfig, ScaleFactor, ScalerConfig, YCbCrMatrix};
+use xbrz_test::profile::Profile;
+
+/// Extra source pixels scaled around the requested region, so that the edges of the region
+/// are blended the same as in the whole image.
+const REGION_MARGIN: u32 = 4;
+/// The largest region previewed by default, in source pixels.
+const DEFAULT_REGION_SIZE: u32 = 128;
+
+const PAGE: &str = include_str!("serve.html");
+
+/// Serve a page on `127.0.0.1:port` for tuning the scaler settings on `image`, starting from `profile`.
+///
+/// Requests are handled one at a time until the process is stopped.
+pub fn serve(image: &RgbaImage, port: u16, profile: &Profile) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Serving preview at http://127.0.0.1:{port}/ (press Ctrl+C to stop)");
+
+    for stream in listener.incoming() {
+        if let Err(e) = handle(&mut stream?, image, profile) {
+            eprintln!("Request error: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// Read a request from `stream` and write the response to it.
+fn handle(
+    stream: &mut (impl Read + Write),
+    image: &RgbaImage,
+    profile: &Profile,
+) -> io::Result<()> {
+    let mut request_line = String::new();
+    let mut reader = BufReader::new(&mut *stream);
+    reader.read_line(&mut request_line)?;
+    // the headers aren't needed, but are read so the client isn't cut off mid-request
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let target = match request_line.split(' ').collect::<Vec<_>>()[..] {
+        ["GET", target, _] => target,
+        _ => return respond(stream, "405 Method Not Allowed", "text/plain", b""),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let params = Params::parse(query, image, profile);
+
+    match path {
+        "/" => {
+            let page = PAGE
+                .replace("{{width}}", &image.width().to_string())
+                .replace("{{height}}", &image.height().to_string())
+                .replace("{{query}}", &params.to_query());
+            respond(stream, "200 OK", "text/html", page.as_bytes())
+        }
+        "/preview" => {
+            let mut png = Vec::new();
+            preview(image, &params)
+                .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                .map_err(io::Error::other)?;
+            respond(stream, "200 OK", "image/png", &png)
+        }
+        "/profile" => {
+            let profile = Profile {
+                scale: ScaleFactor::new(params.factor as usize),
+                config: params.config,
+            };
+            let text = toml::to_string_pretty(&profile).map_err(io::Error::other)?;
+            respond(stream, "200 OK", "text/plain", text.as_bytes())
+        }
+        _ => respond(stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+fn respond(
+    stream: &mut impl Write,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// The settings and source region of a preview, taken from the query string.
+struct Params {
+    factor: u32,
+    config: ScalerConfig,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Params {
+    /// Reads each known parameter, keeping the value from `profile` for any that are missing or invalid.
+    fn parse(query: &str, image: &RgbaImage, profile: &Profile) -> Self {
+        let mut params = Self {
+            factor: profile.scale.map_or(2, ScaleFactor::get) as u32,
+            config: profile.config.clone(),
+            x: 0,
+            y: 0,
+            width: image.width().min(DEFAULT_REGION_SIZE),
+            height: image.height().min(DEFAULT_REGION_SIZE),
+        };
+
+        for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+            let config = &mut params.config;
+            match key {
+                "factor" => set(&mut params.factor, value),
+                "x" => set(&mut params.x, value),
+                "y" => set(&mut params.y, value),
+                "width" => set(&mut params.width, value),
+                "height" => set(&mut params.height, value),
+                "equal_color_tolerance" => set(&mut config.equal_color_tolerance, value),
+                "center_direction_bias" => set(&mut config.center_direction_bias, value),
+                "dominant_direction_threshold" => {
+                    set(&mut config.dominant_direction_threshold, value)
+                }
+                "steep_direction_threshold" => set(&mut config.steep_direction_threshold, value),
+                "suppress_dither" => set(&mut config.suppress_dither, value),
+                "preserve_thin_lines" => set(&mut config.preserve_thin_lines, value),
+                "blend_strength" => set(&mut config.blend_strength, value),
+                "antialias_at_1x" => set(&mut config.antialias_at_1x, value),
+                "blend_weights" => match value {
+                    "classic" => config.blend_weights = BlendWeights::Classic,
+                    "soft" => config.blend_weights = BlendWeights::Soft,
+                    _ => {}
+                },
+                "alpha_mode" => match value {
+                    "blend" => config.alpha_mode = AlphaMode::Blend,
+                    "max" => config.alpha_mode = AlphaMode::Max,
+                    _ => {
+                        if let Ok(threshold) = value.parse() {
+                            config.alpha_mode = AlphaMode::Threshold(threshold);
+                        }
+                    }
+                },
+                "ycbcr_matrix" => match value {
+                    "601" => config.ycbcr_matrix = YCbCrMatrix::Rec601,
+                    "709" => config.ycbcr_matrix = YCbCrMatrix::Rec709,
+                    "2020" => config.ycbcr_matrix = YCbCrMatrix::Rec2020,
+                    _ => {}
+                },
+                "preserve_outlines" => match value {
+                    "true" => {
+                        config.preserve_outlines =
+                            config.preserve_outlines.or(Some(OutlineConfig::default()))
+                    }
+                    "false" => config.preserve_outlines = None,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        params.factor = params.factor.clamp(1, 6);
+        params.x = params.x.min(image.width().saturating_sub(1));
+        params.y = params.y.min(image.height().saturating_sub(1));
+        params.width = params.width.clamp(1, image.width() - params.x);
+        params.height = params.height.clamp(1, image.height() - params.y);
+        params
+    }
+
+    fn to_query(&self) -> String {
+        let config = &self.config;
+        format!(
+            "factor={}&x={}&y={}&width={}&height={}&equal_color_tolerance={}&center_direction_bias={}\
+             &dominant_direction_threshold={}&steep_direction_threshold={}&suppress_dither={}&preserve_thin_lines={}\
+             &blend_strength={}&blend_weights={}\
+             &antialias_at_1x={}&alpha_mode={}&preserve_outlines={}&ycbcr_matrix={}",
+            self.factor,
+            self.x,
+            self.y,
+            self.width,
+            self.height,
+            config.equal_color_tolerance,
+            config.center_direction_bias,
+            config.dominant_direction_threshold,
+            config.steep_direction_threshold,
+            config.suppress_dither,
+            config.preserve_thin_lines,
+            config.blend_strength,
+            match config.blend_weights {
+                BlendWeights::Classic => "classic",
+                BlendWeights::Soft => "soft",
+            },
+            config.antialias_at_1x,
+            match config.alpha_mode {
+                AlphaMode::Blend => "blend".to_string(),
+                AlphaMode::Threshold(threshold) => threshold.to_string(),
+                AlphaMode::Max => "max".to_string(),
+            },
+            config.preserve_outlines.is_some(),
+            match config.ycbcr_matrix {
+                YCbCrMatrix::Rec601 => "601",
+                YCbCrMatrix::Rec709 => "709",
+                YCbCrMatrix::Rec2020 => "2020",
+            },
+        )
+    }
+}
+
+fn set<T: std::str::FromStr>(field: &mut T, value: &str) {
+    if let Ok(value) = value.parse() {
+        *field = value;
+    }
+}
+
+/// Scale only the requested region of the image, along with a margin around it for context.
+fn preview(image: &RgbaImage, params: &Params) -> RgbaImage {
+    let left = params.x.saturating_sub(REGION_MARGIN);
+    let top = params.y.saturating_sub(REGION_MARGIN);
+    let right = (params.x + params.width + REGION_MARGIN).min(image.width());
+    let bottom = (params.y + params.height + REGION_MARGIN).min(image.height());
+    let region = imageops::crop_imm(image, left, top, right - left, bottom - top).to_image();
+
+    let factor = params.factor;
+    let scaled = xbrz::scale_rgba_with_config(
+        &region,
+        region.width() as usize,
+        region.height() as usize,
+        factor as usize,
+        &params.config,
+    );
+    let scaled = RgbaImage::from_raw(region.width() * factor, region.height() * factor, scaled)
+        .expect("scaled image has the right size");
+
+    imageops::crop_imm(
+        &scaled,
+        (params.x - left) * factor,
+        (params.y - top) * factor,
+        params.width * factor,
+        params.height * factor,
+    )
+    .to_image()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A connection that reads a request from memory and collects the response.
+    struct MemoryStream {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for MemoryStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for MemoryStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn image() -> RgbaImage {
+        RgbaImage::from_fn(20, 10, |x, y| {
+            image::Rgba([x as u8 * 12, y as u8 * 25, 90, 255])
+        })
+    }
+
+    /// Send a request line and return the status line, content type and body of the response.
+    fn get(request_line: &str, profile: &Profile) -> (String, String, Vec<u8>) {
+        let request = format!("{request_line}\r\nHost: localhost\r\n\r\n");
+        let mut stream = MemoryStream {
+            request: Cursor::new(request.into_bytes()),
+            response: Vec::new(),
+        };
+        handle(&mut stream, &image(), profile).unwrap();
+
+        let response = stream.response;
+        let end = response
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .expect("response has headers");
+        let head = String::from_utf8(response[..end].to_vec()).unwrap();
+        let status = head.lines().next().unwrap().to_string();
+        let content_type = head
+            .lines()
+            .find_map(|line| line.strip_prefix("Content-Type: "))
+            .unwrap()
+            .to_string();
+        (status, content_type, response[end + 4..].to_vec())
+    }
+
+    #[test]
+    fn page_is_filled_in_with_the_image_and_settings() {
+        let profile = Profile {
+            scale: ScaleFactor::new(3),
+            ..Profile::default()
+        };
+        let (status, content_type, body) = get("GET / HTTP/1.1", &profile);
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(content_type, "text/html");
+
+        let page = String::from_utf8(body).unwrap();
+        assert!(!page.contains("{{"), "unfilled placeholder");
+        assert!(page.contains("factor=3&x=0&y=0&width=20&height=10"));
+    }
+
+    #[test]
+    fn preview_scales_the_requested_region() {
+        let (status, content_type, body) = get(
+            "GET /preview?factor=3&x=5&y=2&width=6&height=4 HTTP/1.1",
+            &Profile::default(),
+        );
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(content_type, "image/png");
+
+        let preview = image::load_from_memory_with_format(&body, ImageFormat::Png)
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(preview.dimensions(), (18, 12));
+
+        // the region is scaled with its surroundings, so it matches the same part of the whole image
+        let whole = image();
+        let scaled = xbrz::scale_rgba(&whole, 20, 10, 3);
+        let scaled = RgbaImage::from_raw(60, 30, scaled).unwrap();
+        let expected = imageops::crop_imm(&scaled, 15, 6, 18, 12).to_image();
+        assert_eq!(preview, expected);
+    }
+
+    #[test]
+    fn profile_holds_the_settings_from_the_query() {
+        let (status, _, body) = get(
+            "GET /profile?factor=4&alpha_mode=max&preserve_outlines=true HTTP/1.1",
+            &Profile::default(),
+        );
+        assert_eq!(status, "HTTP/1.1 200 OK");
+
+        let profile: Profile = toml::from_str(std::str::from_utf8(&body).unwrap()).unwrap();
+        assert_eq!(profile.scale.map(ScaleFactor::get), Some(4));
+        assert_eq!(profile.config.alpha_mode, AlphaMode::Max);
+        assert!(profile.config.preserve_outlines.is_some());
+    }
+
+    #[test]
+    fn other_requests_are_refused() {
+        let profile = Profile::default();
+        let (status, _, _) = get("GET /missing HTTP/1.1", &profile);
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+        let (status, _, _) = get("POST /preview HTTP/1.1", &profile);
+        assert_eq!(status, "HTTP/1.1 405 Method Not Allowed");
+    }
+
+    #[test]
+    fn params_are_clamped_to_the_image() {
+        let params = Params::parse(
+            "factor=9&x=50&y=3&width=0&height=100&blend_weights=soft&equal_color_tolerance=oops",
+            &image(),
+            &Profile::default(),
+        );
+        assert_eq!(params.factor, 6);
+        assert_eq!((params.x, params.y), (19, 3));
+        assert_eq!((params.width, params.height), (1, 7));
+        assert_eq!(params.config.blend_weights, BlendWeights::Soft);
+        assert_eq!(
+            params.config.equal_color_tolerance,
+            ScalerConfig::default().equal_color_tolerance
+        );
+    }
+}
@@ -0,0 +1,414 @@
+use std::path::Path;
+
+use clap::ValueEnum;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use xbrz::ScalerConfig;
+
+#[cfg(feature = "bcn")]
+use crate::bcn::{self, BlockFormat};
+
+/// Texture containers that scaled images can be written in, for game asset pipelines.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Container {
+    /// DirectDraw Surface
+    Dds,
+    /// Khronos Texture 2.0
+    Ktx2,
+}
+
+impl Container {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?;
+        if ext.eq_ignore_ascii_case("dds") {
+            Some(Container::Dds)
+        } else if ext.eq_ignore_ascii_case("ktx2") {
+            Some(Container::Ktx2)
+        } else {
+            None
+        }
+    }
+}
+
+/// How the smaller mip levels of a texture are made.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Mips {
+    /// Only the scaled image, with no mip chain
+    None,
+    /// Each level is the one above scaled down by half
+    Downscale,
+    /// Levels at whole factors of the source are scaled from it with xBRZ, and the rest are the
+    /// level above scaled down by half
+    Xbrz,
+}
+
+/// How the pixels of a texture are stored.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Compression {
+    /// Uncompressed 8-bit RGBA
+    None,
+    /// BC1 (DXT1), with 1-bit alpha
+    #[cfg(feature = "bcn")]
+    Bc1,
+    /// BC3 (DXT5), with full alpha
+    #[cfg(feature = "bcn")]
+    Bc3,
+}
+
+/// The mip chain of a texture, from the scaled image down to a single pixel.
+///
+/// For [`Mips::Xbrz`], `source` gives the unscaled image and the factor that `top` was scaled
+/// by, or `None` if `top` has since been resized or cropped, so that no level can be scaled from
+/// the source.
+pub fn mip_chain(
+    top: RgbaImage,
+    mips: Mips,
+    source: Option<(&RgbaImage, u32)>,
+    config: &ScalerConfig,
+) -> Vec<RgbaImage> {
+    let mut levels = vec![top];
+    if mips == Mips::None {
+        return levels;
+    }
+    while let Some(above) = levels.last().filter(|l| l.width() > 1 || l.height() > 1) {
+        let level = levels.len() as u32;
+        let next = match source {
+            Some((source, factor)) if mips == Mips::Xbrz && factor % (1 << level) == 0 => {
+                let factor = factor >> level;
+                let pixels = xbrz::scale_rgba_with_config(
+                    source,
+                    source.width() as usize,
+                    source.height() as usize,
+                    factor as usize,
+                    config,
+                );
+                RgbaImage::from_raw(source.width() * factor, source.height() * factor, pixels)
+                    .expect("scaled image has the scaled dimensions")
+            }
+            _ => {
+                let (width, height) = ((above.width() / 2).max(1), (above.height() / 2).max(1));
+                imageops::resize(above, width, height, FilterType::Triangle)
+            }
+        };
+        levels.push(next);
+    }
+    levels
+}
+
+/// The pixels of each level, stored as `compression` gives.
+fn level_data(levels: &[RgbaImage], compression: Compression) -> Vec<Vec<u8>> {
+    levels
+        .iter()
+        .map(|level| match compression {
+            Compression::None => level.as_raw().clone(),
+            #[cfg(feature = "bcn")]
+            Compression::Bc1 => bcn::encode(level, BlockFormat::Bc1),
+            #[cfg(feature = "bcn")]
+            Compression::Bc3 => bcn::encode(level, BlockFormat::Bc3),
+        })
+        .collect()
+}
+
+/// Write the mip chain in a texture container, largest level first.
+pub fn encode(levels: &[RgbaImage], container: Container, compression: Compression) -> Vec<u8> {
+    let data = level_data(levels, compression);
+    match container {
+        Container::Dds => encode_dds(levels, &data, compression),
+        Container::Ktx2 => encode_ktx2(levels, &data, compression),
+    }
+}
+
+fn push_u32s(out: &mut Vec<u8>, values: &[u32]) {
+    for value in values {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+#[cfg(feature = "bcn")]
+const DDSD_LINEARSIZE: u32 = 0x80000;
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+#[cfg(feature = "bcn")]
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x400000;
+
+/// A DDS file is a magic number, a 124 byte header with a 32 byte pixel format inside it, then the
+/// pixels of each level. Compressed textures are described by a FourCC, and uncompressed RGBA by
+/// its bit masks.
+fn encode_dds(levels: &[RgbaImage], data: &[Vec<u8>], compression: Compression) -> Vec<u8> {
+    let (width, height) = levels[0].dimensions();
+    let mip_count = levels.len() as u32;
+    let (size_flag, pitch_or_size, pixel_format) = match compression {
+        Compression::None => (
+            DDSD_PITCH,
+            width * 4,
+            [
+                32,
+                DDPF_RGB | DDPF_ALPHAPIXELS,
+                0,
+                32,
+                0x0000_00ff,
+                0x0000_ff00,
+                0x00ff_0000,
+                0xff00_0000,
+            ],
+        ),
+        #[cfg(feature = "bcn")]
+        Compression::Bc1 | Compression::Bc3 => {
+            let four_cc = match compression {
+                Compression::Bc1 => b"DXT1",
+                _ => b"DXT5",
+            };
+            (
+                DDSD_LINEARSIZE,
+                data[0].len() as u32,
+                [32, DDPF_FOURCC, u32::from_le_bytes(*four_cc), 0, 0, 0, 0, 0],
+            )
+        }
+    };
+    let mut caps = DDSCAPS_TEXTURE;
+    if mip_count > 1 {
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+
+    let mut out = b"DDS ".to_vec();
+    push_u32s(
+        &mut out,
+        &[
+            124,
+            DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT | size_flag,
+            height,
+            width,
+            pitch_or_size,
+            0,
+            mip_count,
+        ],
+    );
+    push_u32s(&mut out, &[0; 11]);
+    push_u32s(&mut out, &pixel_format);
+    push_u32s(&mut out, &[caps, 0, 0, 0, 0]);
+    for level in data {
+        out.extend_from_slice(level);
+    }
+    out
+}
+
+const KTX2_IDENTIFIER: [u8; 12] = *b"\xABKTX 20\xBB\r\n\x1A\n";
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+#[cfg(feature = "bcn")]
+const VK_FORMAT_BC1_RGBA_SRGB_BLOCK: u32 = 134;
+#[cfg(feature = "bcn")]
+const VK_FORMAT_BC3_SRGB_BLOCK: u32 = 138;
+
+/// The data format descriptor of a KTX2 file: a single Khronos basic descriptor block, which
+/// describes the texel blocks of the format and the channels within them.
+fn ktx2_dfd(compression: Compression) -> Vec<u8> {
+    // the colour model, which is RGBSDA for uncompressed formats, then the size of a texel block
+    // less one and its bytes; every format here has BT.709 primaries and the sRGB transfer function
+    let (color_model, block_dim, bytes_plane, samples): (u8, u8, u8, &[(u16, u8, u8)]) =
+        match compression {
+            // each sample is a bit offset, bit length and channel; alpha is linear even in sRGB
+            // formats, which the flag of 0x10 on its channel marks
+            Compression::None => (1, 0, 4, &[(0, 8, 0), (8, 8, 1), (16, 8, 2), (24, 8, 0x1f)]),
+            #[cfg(feature = "bcn")]
+            Compression::Bc1 => (128, 3, 8, &[(0, 64, 1)]),
+            #[cfg(feature = "bcn")]
+            Compression::Bc3 => (130, 3, 16, &[(0, 64, 0x1f), (64, 64, 0)]),
+        };
+    let block_size = 24 + 16 * samples.len() as u16;
+
+    let mut dfd = Vec::new();
+    push_u32s(&mut dfd, &[4 + u32::from(block_size), 0]);
+    dfd.extend_from_slice(&2u16.to_le_bytes());
+    dfd.extend_from_slice(&block_size.to_le_bytes());
+    dfd.extend_from_slice(&[color_model, 1, 2, 0]);
+    dfd.extend_from_slice(&[block_dim, block_dim, 0, 0]);
+    dfd.extend_from_slice(&[bytes_plane, 0, 0, 0, 0, 0, 0, 0]);
+    for &(offset, length, channel) in samples {
+        dfd.extend_from_slice(&offset.to_le_bytes());
+        dfd.extend_from_slice(&[length - 1, channel]);
+        // the sample position, then the lower and upper values
+        let upper = if length == 8 { 255 } else { u32::MAX };
+        push_u32s(&mut dfd, &[0, 0, upper]);
+    }
+    dfd
+}
+
+/// A KTX2 file is an identifier, a header, an index giving where each level is, the data format
+/// descriptor, then the levels themselves from the smallest up, each aligned to its texel block.
+fn encode_ktx2(levels: &[RgbaImage], data: &[Vec<u8>], compression: Compression) -> Vec<u8> {
+    let (width, height) = levels[0].dimensions();
+    let (vk_format, type_size, align) = match compression {
+        Compression::None => (VK_FORMAT_R8G8B8A8_SRGB, 1, 4),
+        #[cfg(feature = "bcn")]
+        Compression::Bc1 => (VK_FORMAT_BC1_RGBA_SRGB_BLOCK, 1, 8),
+        #[cfg(feature = "bcn")]
+        Compression::Bc3 => (VK_FORMAT_BC3_SRGB_BLOCK, 1, 16),
+    };
+    let dfd = ktx2_dfd(compression);
+    let level_count = levels.len();
+    // the identifier, the header, the index of the other sections, then 24 bytes per level
+    let dfd_offset = 12 + 9 * 4 + 4 * 4 + 2 * 8 + level_count * 24;
+
+    let mut offsets = vec![0; level_count];
+    let mut end = dfd_offset + dfd.len();
+    for (offset, level) in offsets.iter_mut().zip(data).rev() {
+        end = end.next_multiple_of(align);
+        *offset = end;
+        end += level.len();
+    }
+
+    let mut out = KTX2_IDENTIFIER.to_vec();
+    push_u32s(
+        &mut out,
+        &[
+            vk_format,
+            type_size,
+            width,
+            height,
+            0,
+            0,
+            1,
+            level_count as u32,
+            0,
+        ],
+    );
+    push_u32s(&mut out, &[dfd_offset as u32, dfd.len() as u32, 0, 0]);
+    out.extend_from_slice(&[0; 16]);
+    for (offset, level) in offsets.iter().zip(data) {
+        for value in [*offset, level.len(), level.len()] {
+            out.extend_from_slice(&(value as u64).to_le_bytes());
+        }
+    }
+    out.extend_from_slice(&dfd);
+    for (offset, level) in offsets.iter().zip(data).rev() {
+        out.resize(*offset, 0);
+        out.extend_from_slice(level);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> usize {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap()) as usize
+    }
+
+    fn levels() -> Vec<RgbaImage> {
+        let top = RgbaImage::from_fn(8, 4, |x, y| Rgba([x as u8 * 30, y as u8 * 60, 90, 255]));
+        mip_chain(top, Mips::Downscale, None, &ScalerConfig::default())
+    }
+
+    #[test]
+    fn mip_chain_halves_down_to_one_pixel() {
+        let sizes: Vec<_> = levels().iter().map(RgbaImage::dimensions).collect();
+        assert_eq!(sizes, [(8, 4), (4, 2), (2, 1), (1, 1)]);
+
+        // levels at whole factors of the source are scaled from it
+        let source = RgbaImage::from_pixel(3, 3, Rgba([200, 40, 40, 255]));
+        let top = RgbaImage::from_pixel(12, 12, Rgba([0, 0, 0, 255]));
+        let chain = mip_chain(
+            top,
+            Mips::Xbrz,
+            Some((&source, 4)),
+            &ScalerConfig::default(),
+        );
+        let sizes: Vec<_> = chain.iter().map(RgbaImage::width).collect();
+        assert_eq!(sizes, [12, 6, 3, 1]);
+        assert_eq!(chain[1].dimensions(), (6, 6));
+        assert_eq!(chain[1].get_pixel(3, 3).0, [200, 40, 40, 255]);
+    }
+
+    #[test]
+    fn dds_header_describes_uncompressed_rgba() {
+        let levels = levels();
+        let dds = encode(&levels, Container::Dds, Compression::None);
+        assert_eq!(&dds[..4], b"DDS ");
+        assert_eq!(u32_at(&dds, 4), 124);
+        let flags = u32_at(&dds, 8);
+        assert_eq!(flags & DDSD_PITCH, DDSD_PITCH);
+        assert_eq!(flags & DDSD_MIPMAPCOUNT, DDSD_MIPMAPCOUNT);
+        assert_eq!((u32_at(&dds, 12), u32_at(&dds, 16)), (4, 8));
+        assert_eq!(u32_at(&dds, 20), 8 * 4);
+        assert_eq!(u32_at(&dds, 28), 4);
+
+        // the pixel format, with RGBA in byte order
+        assert_eq!(u32_at(&dds, 76), 32);
+        assert_eq!(u32_at(&dds, 80), DDPF_RGB | DDPF_ALPHAPIXELS);
+        assert_eq!(u32_at(&dds, 88), 32);
+        let masks: Vec<u32> = (92..108).step_by(4).map(|i| u32_at(&dds, i)).collect();
+        assert_eq!(masks, [0xff, 0xff00, 0xff_0000, 0xff00_0000]);
+        assert_eq!(
+            u32_at(&dds, 108),
+            DDSCAPS_TEXTURE | DDSCAPS_COMPLEX | DDSCAPS_MIPMAP
+        );
+
+        // then every level, largest first
+        let data: Vec<u8> = levels.iter().flat_map(|l| l.as_raw().clone()).collect();
+        assert_eq!(&dds[128..], &data);
+    }
+
+    #[test]
+    fn ktx2_index_points_at_each_level() {
+        let levels = levels();
+        let ktx2 = encode(&levels, Container::Ktx2, Compression::None);
+        assert_eq!(ktx2[..12], KTX2_IDENTIFIER);
+        assert_eq!(u32_at(&ktx2, 12), VK_FORMAT_R8G8B8A8_SRGB);
+        assert_eq!((u32_at(&ktx2, 20), u32_at(&ktx2, 24)), (8, 4));
+        assert_eq!(u32_at(&ktx2, 40), 4);
+
+        // the data format descriptor follows the level index
+        let dfd_offset = u32_at(&ktx2, 48) as usize;
+        let dfd_len = u32_at(&ktx2, 52) as usize;
+        assert_eq!(dfd_offset, 80 + 4 * 24);
+        assert_eq!(dfd_len, ktx2_dfd(Compression::None).len());
+        assert_eq!(u32_at(&ktx2, dfd_offset), dfd_len as u32);
+
+        // levels are stored smallest first, each aligned to a whole pixel
+        let mut previous = ktx2.len();
+        for (i, level) in levels.iter().enumerate() {
+            let (offset, len) = (u64_at(&ktx2, 80 + i * 24), u64_at(&ktx2, 88 + i * 24));
+            assert_eq!(len, level.as_raw().len());
+            assert_eq!(offset % 4, 0);
+            assert!(offset + len <= previous, "level {i}");
+            assert_eq!(&ktx2[offset..offset + len], level.as_raw());
+            previous = offset;
+        }
+        assert!(previous >= dfd_offset + dfd_len);
+    }
+
+    #[cfg(feature = "bcn")]
+    #[test]
+    fn compressed_textures_are_described_by_their_block_format() {
+        let levels = levels();
+        let dds = encode(&levels, Container::Dds, Compression::Bc3);
+        assert_eq!(u32_at(&dds, 8) & DDSD_LINEARSIZE, DDSD_LINEARSIZE);
+        assert_eq!(u32_at(&dds, 20), 2 * 16);
+        assert_eq!(u32_at(&dds, 80), DDPF_FOURCC);
+        assert_eq!(&dds[84..88], b"DXT5");
+        // each level is at least one block
+        assert_eq!(dds.len(), 128 + (2 + 1 + 1 + 1) * 16);
+
+        let ktx2 = encode(&levels, Container::Ktx2, Compression::Bc1);
+        assert_eq!(u32_at(&ktx2, 12), VK_FORMAT_BC1_RGBA_SRGB_BLOCK);
+        for i in 0..levels.len() {
+            assert_eq!(u64_at(&ktx2, 80 + i * 24) % 8, 0);
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use image::{imageops, ImageError, Rgba, RgbaImage};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::{Reader, Writer};
+use xbrz::ScalerConfig;
+
+/// Pixels copied from each edge of a tile before scaling it, enough to cover the reach of the
+/// scaler so that the edges of each tile blend as if the tile was repeated.
+const TILE_PADDING: u32 = 4;
+
+#[derive(Debug)]
+pub enum TilesetError {
+    Io(io::Error),
+    Xml(quick_xml::Error),
+    Image(ImageError),
+    /// The tileset is missing an attribute, or uses a feature that isn't supported.
+    Format(String),
+    /// The scaled image couldn't be saved.
+    Save(ImageError),
+    /// The scaled tileset couldn't be written.
+    Write(io::Error),
+}
+
+impl fmt::Display for TilesetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TilesetError::Io(e) => write!(f, "{e}"),
+            TilesetError::Xml(e) => write!(f, "{e}"),
+            TilesetError::Image(e) => write!(f, "{e}"),
+            TilesetError::Format(e) => write!(f, "{e}"),
+            TilesetError::Save(e) => write!(f, "{e}"),
+            TilesetError::Write(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<quick_xml::Error> for TilesetError {
+    fn from(e: quick_xml::Error) -> Self {
+        TilesetError::Xml(e)
+    }
+}
+
+impl From<quick_xml::events::attributes::AttrError> for TilesetError {
+    fn from(e: quick_xml::events::attributes::AttrError) -> Self {
+        TilesetError::Xml(e.into())
+    }
+}
+
+/// How the tiles are laid out in the image of a tileset, in pixels.
+struct Layout {
+    tile_width: u32,
+    tile_height: u32,
+    spacing: u32,
+    margin: u32,
+    /// The `source` of the tileset image, relative to the tileset file.
+    source: String,
+    /// A colour that is treated as transparent, as 6 hex digits.
+    trans: Option<String>,
+}
+
+/// Scale the image of the Tiled tileset at `input` one tile at a time, saving it next to `output`
+/// along with a copy of the tileset with its tile size, spacing, margin, offsets and collision shapes
+/// multiplied by `factor`. Returns the path of the scaled image.
+///
+/// Only tilesets based on a single image are supported, not collections of images or tilesets
+/// embedded in a map.
+pub fn scale_tileset(
+    input: &Path,
+    output: &Path,
+    factor: u32,
+    config: &ScalerConfig,
+) -> Result<PathBuf, TilesetError> {
+    let xml = fs::read_to_string(input).map_err(TilesetError::Io)?;
+    let layout = read_layout(&xml)?;
+
+    let image_path = input.with_file_name(&layout.source);
+    let mut image = image::open(&image_path)
+        .map_err(TilesetError::Image)?
+        .into_rgba8();
+    if let Some(trans) = &layout.trans {
+        apply_colour_key(&mut image, trans)?;
+    }
+    let scaled = scale_tiles(&image, &layout, factor, config);
+
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let scaled_source = format!("{stem}.png");
+    let scaled_path = output.with_file_name(&scaled_source);
+    scaled.save(&scaled_path).map_err(TilesetError::Save)?;
+
+    let rewritten = rewrite(&xml, factor, &scaled_source)?;
+    fs::write(output, rewritten).map_err(TilesetError::Write)?;
+    Ok(scaled_path)
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, TilesetError> {
+    Ok(match element.try_get_attribute(name)? {
+        Some(attr) => Some(attr.unescape_value()?.into_owned()),
+        None => None,
+    })
+}
+
+fn number_attribute(element: &BytesStart, name: &str) -> Result<u32, TilesetError> {
+    match attribute(element, name)? {
+        Some(value) => value
+            .parse()
+            .map_err(|_| TilesetError::Format(format!("invalid {name} \"{value}\""))),
+        None => Ok(0),
+    }
+}
+
+fn read_layout(xml: &str) -> Result<Layout, TilesetError> {
+    let mut reader = Reader::from_str(xml);
+    let mut tileset = None;
+    let mut in_tile = false;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"tileset" => {
+                tileset = Some((
+                    number_attribute(&e, "tilewidth")?,
+                    number_attribute(&e, "tileheight")?,
+                    number_attribute(&e, "spacing")?,
+                    number_attribute(&e, "margin")?,
+                ));
+            }
+            Event::Start(e) if e.name().as_ref() == b"tile" => in_tile = true,
+            Event::End(e) if e.name().as_ref() == b"tile" => in_tile = false,
+            Event::Start(e) | Event::Empty(e) if e.name().as_ref() == b"image" => {
+                if in_tile {
+                    return Err(TilesetError::Format(
+                        "tilesets made from a collection of images aren't supported".into(),
+                    ));
+                }
+                let Some((tile_width, tile_height, spacing, margin)) = tileset else {
+                    return Err(TilesetError::Format("image outside of a tileset".into()));
+                };
+                if tile_width == 0 || tile_height == 0 {
+                    return Err(TilesetError::Format("tileset has no tile size".into()));
+                }
+                let Some(source) = attribute(&e, "source")? else {
+                    return Err(TilesetError::Format("tileset image has no source".into()));
+                };
+                return Ok(Layout {
+                    tile_width,
+                    tile_height,
+                    spacing,
+                    margin,
+                    source,
+                    trans: attribute(&e, "trans")?,
+                });
+            }
+            Event::Eof => return Err(TilesetError::Format("tileset has no image".into())),
+            _ => {}
+        }
+    }
+}
+
+/// Make every pixel matching the `trans` colour of a tileset transparent, so it isn't blended with
+/// its neighbours when scaling.
+fn apply_colour_key(image: &mut RgbaImage, trans: &str) -> Result<(), TilesetError> {
+    let hex = trans.trim_start_matches('#');
+    let key = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| TilesetError::Format(format!("invalid trans \"{trans}\"")))?;
+    let [_, r, g, b] = key.to_be_bytes();
+
+    for px in image.pixels_mut() {
+        if px.0[..3] == [r, g, b] {
+            *px = Rgba([0, 0, 0, 0]);
+        }
+    }
+    Ok(())
+}
+
+/// Scale each whole tile on its own, placing it at the same tile position in the scaled image.
+/// The margin, spacing and any partial tiles at the right or bottom edge are left transparent.
+fn scale_tiles(
+    image: &RgbaImage,
+    layout: &Layout,
+    factor: u32,
+    config: &ScalerConfig,
+) -> RgbaImage {
+    let (tw, th) = (layout.tile_width, layout.tile_height);
+    let columns =
+        (image.width() + layout.spacing).saturating_sub(layout.margin * 2) / (tw + layout.spacing);
+    let rows =
+        (image.height() + layout.spacing).saturating_sub(layout.margin * 2) / (th + layout.spacing);
+
+    let mut scaled = RgbaImage::new(image.width() * factor, image.height() * factor);
+    for row in 0..rows {
+        for column in 0..columns {
+            let x = layout.margin + column * (tw + layout.spacing);
+            let y = layout.margin + row * (th + layout.spacing);
+            let tile = pad_edges(&imageops::crop_imm(image, x, y, tw, th).to_image());
+
+            let pixels = xbrz::scale_rgba_with_config(
+                &tile,
+                tile.width() as usize,
+                tile.height() as usize,
+                factor as usize,
+                config,
+            );
+            let padded = RgbaImage::from_raw(tile.width() * factor, tile.height() * factor, pixels)
+                .expect("scaled image has the right size");
+            let pad = TILE_PADDING * factor;
+            let tile = imageops::crop_imm(&padded, pad, pad, tw * factor, th * factor);
+            imageops::replace(
+                &mut scaled,
+                &*tile,
+                (x * factor).into(),
+                (y * factor).into(),
+            );
+        }
+    }
+    scaled
+}
+
+/// Extend `tile` by [`TILE_PADDING`] pixels on each side, repeating its edge pixels.
+fn pad_edges(tile: &RgbaImage) -> RgbaImage {
+    let (width, height) = tile.dimensions();
+    RgbaImage::from_fn(
+        width + TILE_PADDING * 2,
+        height + TILE_PADDING * 2,
+        |x, y| {
+            let x = x.saturating_sub(TILE_PADDING).min(width - 1);
+            let y = y.saturating_sub(TILE_PADDING).min(height - 1);
+            *tile.get_pixel(x, y)
+        },
+    )
+}
+
+/// Multiply a number from a tileset attribute, keeping integers as integers.
+fn scale_number(value: &str, factor: u32) -> String {
+    match value.parse::<i64>() {
+        Ok(n) => (n * factor as i64).to_string(),
+        Err(_) => match value.parse::<f64>() {
+            Ok(n) => (n * factor as f64).to_string(),
+            Err(_) => value.to_string(),
+        },
+    }
+}
+
+/// The attributes holding pixel sizes or positions for each element of a tileset.
+fn scaled_attributes(element: &[u8]) -> &'static [&'static str] {
+    match element {
+        b"tileset" => &["tilewidth", "tileheight", "spacing", "margin"],
+        b"image" => &["width", "height"],
+        b"tileoffset" => &["x", "y"],
+        b"grid" => &["width", "height"],
+        b"object" => &["x", "y", "width", "height"],
+        b"polygon" | b"polyline" => &["points"],
+        _ => &[],
+    }
+}
+
+/// Copy the tileset XML, scaling the attributes of each element listed in [`scaled_attributes`]
+/// and pointing the tileset image at `source`. Everything else is copied unchanged.
+fn rewrite(xml: &str, factor: u32, source: &str) -> Result<Vec<u8>, TilesetError> {
+    let mut reader = Reader::from_str(xml);
+    let mut writer = Writer::new(Vec::new());
+
+    loop {
+        let event = match reader.read_event()? {
+            Event::Eof => break,
+            Event::Start(e) => Event::Start(rewrite_element(&e, factor, source)?),
+            Event::Empty(e) => Event::Empty(rewrite_element(&e, factor, source)?),
+            event => event,
+        };
+        writer.write_event(event).map_err(TilesetError::Io)?;
+    }
+    Ok(writer.into_inner())
+}
+
+fn rewrite_element(
+    element: &BytesStart,
+    factor: u32,
+    source: &str,
+) -> Result<BytesStart<'static>, TilesetError> {
+    let name = element.name();
+    let scaled = scaled_attributes(name.as_ref());
+    let is_image = name.as_ref() == b"image";
+
+    let mut rewritten = BytesStart::new(String::from_utf8_lossy(name.as_ref()).into_owned());
+    for attr in element.attributes() {
+        let attr = attr?;
+        let key = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+        let value = attr.unescape_value()?;
+
+        let value = match key.as_str() {
+            // the colour key is applied to the scaled image instead
+            "trans" if is_image => continue,
+            "source" if is_image => source.to_string(),
+            "points" if scaled.contains(&"points") => value
+                .split(' ')
+                .map(|point| {
+                    point
+                        .split(',')
+                        .map(|n| scale_number(n, factor))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+            key if scaled.contains(&key) => scale_number(&value, factor),
+            _ => value.into_owned(),
+        };
+        rewritten.push_attribute((key.as_str(), value.as_str()));
+    }
+    Ok(rewritten)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A layout of 4x3 tiles, with a margin of 1 pixel and 2 pixels between tiles.
+    fn layout() -> Layout {
+        Layout {
+            tile_width: 4,
+            tile_height: 3,
+            spacing: 2,
+            margin: 1,
+            source: "tiles.png".into(),
+            trans: None,
+        }
+    }
+
+    /// The colour of the tile at `(column, row)`.
+    fn tile_colour(column: u32, row: u32) -> Rgba<u8> {
+        Rgba([column as u8 * 80, row as u8 * 120, 200, 255])
+    }
+
+    /// The colour of the margin and spacing between tiles.
+    const GUTTER: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+    /// The column and row of the tile covering `(x, y)` in the [`layout`], if it isn't in the
+    /// margin or spacing.
+    fn tile_at(x: u32, y: u32) -> Option<(u32, u32)> {
+        let (column, tx) = (x.checked_sub(1)? / 6, x.checked_sub(1)? % 6);
+        let (row, ty) = (y.checked_sub(1)? / 5, y.checked_sub(1)? % 5);
+        (tx < 4 && ty < 3).then_some((column, row))
+    }
+
+    /// An image of 3x2 whole tiles of solid colours, then part of a tile on the right and bottom.
+    fn tiles() -> RgbaImage {
+        RgbaImage::from_fn(21, 12, |x, y| match tile_at(x, y) {
+            Some((column, row)) => tile_colour(column, row),
+            None => GUTTER,
+        })
+    }
+
+    #[test]
+    fn whole_tiles_are_scaled_in_place() {
+        let factor = 3;
+        let scaled = scale_tiles(&tiles(), &layout(), factor, &ScalerConfig::default());
+        assert_eq!(scaled.dimensions(), (63, 36));
+
+        let mut tile_pixels = 0;
+        for (x, y, pixel) in scaled.enumerate_pixels() {
+            let tile =
+                tile_at(x / factor, y / factor).filter(|&(column, row)| column < 3 && row < 2);
+            // solid tiles stay solid right up to their edges, and everything else is transparent
+            let expected = match tile {
+                Some((column, row)) => tile_colour(column, row),
+                None => Rgba([0, 0, 0, 0]),
+            };
+            assert_eq!(*pixel, expected, "({x}, {y})");
+            tile_pixels += tile.is_some() as u32;
+        }
+        assert_eq!(tile_pixels, 3 * 2 * 4 * 3 * factor * factor);
+    }
+
+    #[test]
+    fn tile_edges_are_extruded() {
+        let tile = RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let padded = pad_edges(&tile);
+        assert_eq!(
+            padded.dimensions(),
+            (3 + 2 * TILE_PADDING, 2 + 2 * TILE_PADDING)
+        );
+        for (x, y, pixel) in padded.enumerate_pixels() {
+            let expected = [
+                x.saturating_sub(TILE_PADDING).min(2) as u8,
+                y.saturating_sub(TILE_PADDING).min(1) as u8,
+                0,
+                255,
+            ];
+            assert_eq!(pixel.0, expected, "({x}, {y})");
+        }
+
+        // a patterned tile blends at its edges as if it was repeated, so it never fades out
+        let checkers = RgbaImage::from_fn(4, 3, |x, y| match (x + y) % 2 {
+            0 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        let layout = Layout {
+            margin: 0,
+            spacing: 0,
+            ..layout()
+        };
+        let scaled = scale_tiles(&checkers, &layout, 4, &ScalerConfig::default());
+        assert!(scaled.pixels().all(|pixel| pixel.0[3] == 255));
+    }
+
+    #[test]
+    fn tilesets_are_rewritten_at_the_scaled_size() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="tiles" tilewidth="4" tileheight="3" spacing="2" margin="1" tilecount="6" columns="3">
+ <tileoffset x="0" y="-1"/>
+ <image source="tiles.png" trans="ff00ff" width="21" height="12"/>
+ <tile id="2">
+  <objectgroup draworder="index">
+   <object id="1" x="0.5" y="1" width="3" height="2"/>
+   <object id="2" x="1" y="1"><polygon points="0,0 2,1 -1,1.5"/></object>
+  </objectgroup>
+ </tile>
+</tileset>
+"#;
+        let layout = read_layout(xml).unwrap();
+        assert_eq!(
+            (
+                layout.tile_width,
+                layout.tile_height,
+                layout.spacing,
+                layout.margin
+            ),
+            (4, 3, 2, 1)
+        );
+        assert_eq!(layout.source, "tiles.png");
+        assert_eq!(layout.trans.as_deref(), Some("ff00ff"));
+
+        let rewritten = String::from_utf8(rewrite(xml, 2, "big.png").unwrap()).unwrap();
+        assert_eq!(
+            rewritten,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.10" name="tiles" tilewidth="8" tileheight="6" spacing="4" margin="2" tilecount="6" columns="3">
+ <tileoffset x="0" y="-2"/>
+ <image source="big.png" width="42" height="24"/>
+ <tile id="2">
+  <objectgroup draworder="index">
+   <object id="1" x="1" y="2" width="6" height="4"/>
+   <object id="2" x="2" y="2"><polygon points="0,0 4,2 -2,3"/></object>
+  </objectgroup>
+ </tile>
+</tileset>
+"#
+        );
+    }
+
+    #[test]
+    fn colour_keys_become_transparent() {
+        let mut image = tiles();
+        apply_colour_key(&mut image, "#FF00FF").unwrap();
+        assert_eq!(*image.get_pixel(0, 0), Rgba([0, 0, 0, 0]));
+        assert_eq!(*image.get_pixel(1, 1), tile_colour(0, 0));
+        assert!(matches!(
+            apply_colour_key(&mut image, "f0f"),
+            Err(TilesetError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_tilesets_are_errors() {
+        for xml in [
+            r#"<tileset tilewidth="4" tileheight="3"></tileset>"#,
+            r#"<tileset tilewidth="4"><image source="a.png"/></tileset>"#,
+            r#"<tileset tilewidth="4" tileheight="3"><image/></tileset>"#,
+            r#"<tileset tilewidth="4" tileheight="3"><tile id="0"><image source="a.png"/></tile></tileset>"#,
+            r#"<tileset tilewidth="four" tileheight="3"><image source="a.png"/></tileset>"#,
+        ] {
+            assert!(
+                matches!(read_layout(xml), Err(TilesetError::Format(_))),
+                "{xml}"
+            );
+        }
+    }
+}
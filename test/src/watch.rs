@@ -0,0 +1,343 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime};
+
+use image::ImageFormat;
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+use xbrz::ScalerConfig;
+
+use crate::error::{CliError, ErrorKind};
+
+/// How long to wait after a change before rescaling, so that the several events of a single save
+/// are handled together, and the file has been completely written.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum WatchError {
+    /// The source directory couldn't be read.
+    Input(io::Error),
+    /// The output directory couldn't be created.
+    Output(io::Error),
+    Notify(notify::Error),
+}
+
+impl fmt::Display for WatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WatchError::Input(e) => write!(f, "{e}"),
+            WatchError::Output(e) => write!(f, "{e}"),
+            WatchError::Notify(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<notify::Error> for WatchError {
+    fn from(e: notify::Error) -> Self {
+        WatchError::Notify(e)
+    }
+}
+
+/// Images found in `src_dir`, and the paths they are scaled to in `out_dir`.
+struct Dirs {
+    src_dir: PathBuf,
+    out_dir: PathBuf,
+}
+
+impl Dirs {
+    /// The path of the scaled copy of `path`, or `None` if `path` isn't an image in the source
+    /// directory. Images already in the output directory are skipped, in case it is inside
+    /// the source directory.
+    fn output_path(&self, path: &Path) -> Option<PathBuf> {
+        if path.starts_with(&self.out_dir) || ImageFormat::from_path(path).is_err() {
+            return None;
+        }
+        let relative = path.strip_prefix(&self.src_dir).ok()?;
+        Some(self.out_dir.join(relative))
+    }
+
+    /// Every image in the source directory and its subdirectories.
+    fn images(&self) -> io::Result<Vec<PathBuf>> {
+        let mut images = Vec::new();
+        let mut dirs = vec![self.src_dir.clone()];
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if self.output_path(&path).is_some() {
+                    images.push(path);
+                }
+            }
+        }
+        images.sort();
+        Ok(images)
+    }
+
+    /// The images to scale before watching starts, with the paths they are scaled to: those whose
+    /// scaled copy is missing, or older than they are.
+    fn stale_images(&self) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+        Ok(self
+            .images()?
+            .into_iter()
+            .filter_map(|input| {
+                let output = self
+                    .output_path(&input)
+                    .expect("images are in the source directory");
+                (modified(&output) < modified(&input)).then_some((input, output))
+            })
+            .collect())
+    }
+
+    /// What to do about each path that changed while watching. Images that are there are scaled
+    /// again, and the scaled copies of images that aren't are removed. Anything that isn't an image
+    /// in the source directory is left alone.
+    fn actions(&self, changed: &BTreeSet<PathBuf>) -> Vec<Action> {
+        changed
+            .iter()
+            .filter_map(|input| {
+                let output = self.output_path(input)?;
+                if input.is_file() {
+                    Some(Action::Rescale(input.clone(), output))
+                } else if !input.exists() {
+                    Some(Action::Remove(output))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// A change to make to the output directory.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// Scale the image at the first path to the second
+    Rescale(PathBuf, PathBuf),
+    /// Remove the scaled copy of an image that was removed
+    Remove(PathBuf),
+}
+
+/// Whether an event may have changed which images there are or what they hold, rather than only
+/// reading them or touching their permissions or times.
+fn is_change(kind: &EventKind) -> bool {
+    !matches!(
+        kind,
+        EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_))
+    )
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Scale the image at `input` and save it at `output`, creating its directory if needed.
+pub fn scale_file(
+    input: &Path,
+    output: &Path,
+    factor: u32,
+    config: &ScalerConfig,
+) -> Result<(), CliError> {
+    let image = image::open(input)
+        .map_err(|e| CliError::new(ErrorKind::Input, format!("Image read error: {e}")))?
+        .into_rgba8();
+    let scaled = xbrz::try_scale_rgba_with_config(
+        &image,
+        image.width() as usize,
+        image.height() as usize,
+        factor as usize,
+        config,
+    )?;
+    let save_error = |e: &dyn fmt::Display| {
+        CliError::new(ErrorKind::Output, format!("Error saving new image: {e}"))
+    };
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| save_error(&e))?;
+    }
+    image::save_buffer(
+        output,
+        &scaled,
+        image.width() * factor,
+        image.height() * factor,
+        image::ExtendedColorType::Rgba8,
+    )
+    .map_err(|e| save_error(&e))
+}
+
+/// Scale `input` to `output`, reporting the result, as errors in one image shouldn't stop the others
+/// from being scaled.
+fn rescale(input: &Path, output: &Path, factor: u32, config: &ScalerConfig, quiet: bool) {
+    match scale_file(input, output, factor, config) {
+        Ok(()) if !quiet => println!("Scaled {} to {}", input.display(), output.display()),
+        Ok(()) => {}
+        Err(e) => eprintln!("Error scaling {}: {}", input.display(), e.message),
+    }
+}
+
+/// Scale every image in `src_dir` into the same place in `out_dir`, skipping images whose scaled
+/// copy is newer than they are, then keep watching `src_dir`, scaling images again as they change
+/// and removing the scaled copies of images that are removed. Only returns if watching fails.
+///
+/// With `quiet`, only images that can't be scaled are reported.
+pub fn watch(
+    src_dir: &Path,
+    out_dir: &Path,
+    factor: u32,
+    config: &ScalerConfig,
+    quiet: bool,
+) -> Result<(), WatchError> {
+    // the source directory is found first, so that no output directory is left behind if it's missing
+    let canonical_src = src_dir.canonicalize().map_err(WatchError::Input)?;
+    fs::create_dir_all(out_dir).map_err(WatchError::Output)?;
+    let dirs = Dirs {
+        src_dir: canonical_src,
+        out_dir: out_dir.canonicalize().map_err(WatchError::Output)?,
+    };
+
+    for (input, output) in dirs.stale_images().map_err(WatchError::Input)? {
+        rescale(&input, &output, factor, config, quiet);
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dirs.src_dir, RecursiveMode::Recursive)?;
+    if !quiet {
+        println!("Watching {} for changes", src_dir.display());
+    }
+
+    loop {
+        // wait for a change, then gather up any more that follow close behind it
+        let mut changed = BTreeSet::new();
+        let mut next = rx
+            .recv()
+            .map_err(|_| notify::Error::generic("watcher stopped"))?;
+        loop {
+            let event = next?;
+            if is_change(&event.kind) {
+                changed.extend(event.paths);
+            }
+            match rx.recv_timeout(DEBOUNCE) {
+                Ok(event) => next = event,
+                Err(_) => break,
+            }
+        }
+
+        for action in dirs.actions(&changed) {
+            match action {
+                Action::Rescale(input, output) => rescale(&input, &output, factor, config, quiet),
+                Action::Remove(output) => {
+                    if fs::remove_file(&output).is_ok() && !quiet {
+                        println!("Removed {}", output.display());
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use notify::event::{AccessKind, CreateKind, DataChange, MetadataKind, RemoveKind};
+
+    use super::*;
+
+    /// A source directory holding `a.png`, `sub/b.png`, `notes.txt` and an output directory of its
+    /// own, `out`, which must be skipped.
+    fn dirs(name: &str) -> Dirs {
+        let root = std::env::temp_dir().join(format!("xbrz-watch-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("sub")).unwrap();
+        fs::create_dir_all(root.join("out")).unwrap();
+        for file in ["a.png", "sub/b.png", "notes.txt", "out/a.png"] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+        let root = root.canonicalize().unwrap();
+        Dirs {
+            out_dir: root.join("out"),
+            src_dir: root,
+        }
+    }
+
+    fn set_modified(path: &Path, seconds: u64) {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn only_images_in_the_source_directory_have_outputs() {
+        let dirs = dirs("outputs");
+        let src = &dirs.src_dir;
+        assert_eq!(
+            dirs.output_path(&src.join("sub/b.png")),
+            Some(dirs.out_dir.join("sub/b.png"))
+        );
+        assert_eq!(dirs.output_path(&src.join("notes.txt")), None);
+        assert_eq!(dirs.output_path(&dirs.out_dir.join("a.png")), None);
+        assert_eq!(dirs.output_path(Path::new("/elsewhere/c.png")), None);
+        assert_eq!(
+            dirs.images().unwrap(),
+            [src.join("a.png"), src.join("sub/b.png")]
+        );
+    }
+
+    #[test]
+    fn images_with_up_to_date_copies_are_not_scaled_again() {
+        let dirs = dirs("stale");
+        let (src, out) = (&dirs.src_dir, &dirs.out_dir);
+        set_modified(&src.join("a.png"), 1000);
+        set_modified(&out.join("a.png"), 2000);
+        // b.png has no scaled copy yet
+        assert_eq!(
+            dirs.stale_images().unwrap(),
+            [(src.join("sub/b.png"), out.join("sub/b.png"))]
+        );
+
+        set_modified(&src.join("a.png"), 3000);
+        assert_eq!(dirs.stale_images().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn changed_images_are_rescaled_and_removed_images_are_removed() {
+        let dirs = dirs("actions");
+        let (src, out) = (&dirs.src_dir, &dirs.out_dir);
+        fs::remove_file(src.join("sub/b.png")).unwrap();
+        let changed = BTreeSet::from([
+            src.join("a.png"),
+            src.join("sub/b.png"),
+            src.join("sub"),
+            src.join("notes.txt"),
+            out.join("a.png"),
+        ]);
+        assert_eq!(
+            dirs.actions(&changed),
+            [
+                Action::Rescale(src.join("a.png"), out.join("a.png")),
+                Action::Remove(out.join("sub/b.png")),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_and_metadata_changes_are_ignored() {
+        assert!(!is_change(&EventKind::Access(AccessKind::Any)));
+        assert!(!is_change(&EventKind::Modify(ModifyKind::Metadata(
+            MetadataKind::WriteTime
+        ))));
+        assert!(is_change(&EventKind::Create(CreateKind::File)));
+        assert!(is_change(&EventKind::Modify(ModifyKind::Data(
+            DataChange::Content
+        ))));
+        assert!(is_change(&EventKind::Remove(RemoveKind::File)));
+    }
+}
@@ -0,0 +1,134 @@
+//! The exit codes and error reports of xbrz-cli, which scripts and build systems rely on.
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use serde_json::Value;
+
+fn cli(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_xbrz-cli"))
+        .args(args)
+        .output()
+        .expect("xbrz-cli runs")
+}
+
+/// A directory of its own for each test, emptied before it is used.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("xbrz-exit-codes-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// The exit code, and the JSON report printed to stderr.
+fn json_error(args: &[&str]) -> (i32, Value) {
+    let output = cli(&[args, &["--json-errors"]].concat());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let report: Value = serde_json::from_str(stderr.trim_end())
+        .unwrap_or_else(|e| panic!("{args:?} printed {stderr:?}, not JSON: {e}"));
+    (output.status.code().unwrap(), report)
+}
+
+fn assert_error(args: &[&str], kind: &str, code: i32) {
+    let (exit_code, report) = json_error(args);
+    assert_eq!(exit_code, code, "{args:?}: {report}");
+    assert_eq!(report["exit_code"], code, "{args:?}");
+    assert_eq!(report["error"]["kind"], kind, "{args:?}");
+    assert!(report["error"]["message"]
+        .as_str()
+        .is_some_and(|m| !m.is_empty()));
+}
+
+#[test]
+fn missing_inputs_are_input_errors() {
+    let dir = temp_dir("missing");
+    let out = dir.join("out.png");
+    let out = out.to_str().unwrap();
+
+    assert_error(&["missing.png", "-o", out], "input", 3);
+    assert_error(&["compare", "missing.png", "-o", out], "input", 3);
+    assert_error(&["icon", "missing.png", "-o", "icon.ico"], "input", 3);
+    assert_error(&["cursor", "missing.cur"], "input", 3);
+    assert_error(&["aseprite", "missing.aseprite", "-o", out], "input", 3);
+    assert_error(&["font", "missing.fnt", "-o", "out.fnt"], "input", 3);
+    assert_error(&["tileset", "missing.tsx", "-o", "out.tsx"], "input", 3);
+    let out_dir = dir.join("scaled");
+    assert_error(
+        &["watch", "missing", "--out-dir", out_dir.to_str().unwrap()],
+        "input",
+        3,
+    );
+    assert!(!out_dir.exists());
+    assert_error(&["run", "missing.json"], "input", 3);
+    assert_error(
+        &["images/yoshi.png", "--profile", "missing.toml"],
+        "input",
+        3,
+    );
+}
+
+#[test]
+fn unwritable_outputs_are_output_errors() {
+    let dir = temp_dir("unwritable");
+    let out = dir.join("no/such/dir/out.png");
+    assert_error(
+        &["images/yoshi.png", "-o", out.to_str().unwrap()],
+        "output",
+        5,
+    );
+
+    let font = dir.join("font.fnt");
+    std::fs::write(
+        &font,
+        "info size=8\ncommon lineHeight=8 base=6 scaleW=8 scaleH=8 pages=1\n\
+         page id=0 file=\"font.png\"\nchars count=0\n",
+    )
+    .unwrap();
+    std::fs::copy("images/yoshi.png", dir.join("font.png")).unwrap();
+    let out = dir.join("no/such/dir/out.fnt");
+    assert_error(
+        &["font", font.to_str().unwrap(), "-o", out.to_str().unwrap()],
+        "output",
+        5,
+    );
+}
+
+#[test]
+fn usage_errors_exit_with_2() {
+    assert_error(&["images/yoshi.png", "--scale", "9"], "usage", 2);
+    assert_error(
+        &["compare", "images/yoshi.png", "--algorithms", "bicubic"],
+        "usage",
+        2,
+    );
+
+    // without --json-errors, clap reports them itself
+    let output = cli(&["images/yoshi.png", "--scale", "9"]);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("error:"), "{stderr}");
+    assert!(serde_json::from_str::<Value>(&stderr).is_err());
+}
+
+#[test]
+fn other_errors_exit_with_their_own_codes() {
+    let dir = temp_dir("other");
+    let out = dir.join("compare.png");
+    assert_error(
+        &[
+            "compare",
+            "images/yoshi.png",
+            "-o",
+            out.to_str().unwrap(),
+            "--factors",
+            "5",
+            "--algorithms",
+            "hqx",
+        ],
+        "scale",
+        4,
+    );
+
+    let output = cli(&["images/yoshi.png", "--hash", "--json-errors"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stderr.is_empty());
+}
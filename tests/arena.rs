@@ -0,0 +1,121 @@
+//! Checks that scaling with a warmed-up `ScaleArena` performs no heap allocations.
+//!
+//! This lives in its own test binary because it replaces the global allocator.
+//! With the `forbid_unsafe` feature the images are copied as they are scaled, so it is skipped.
+#![cfg(not(feature = "forbid_unsafe"))]
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|c| c.set(c.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|c| c.set(c.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+fn allocations() -> usize {
+    ALLOCATIONS.with(|c| c.get())
+}
+
+fn checkerboard(width: usize, height: usize) -> Vec<u8> {
+    (0..width * height)
+        .flat_map(|i| {
+            if (i % width + i / width).is_multiple_of(2) {
+                [0, 0, 0, 255]
+            } else {
+                [255, 128, 0, 255]
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn scale_rgba_into_does_not_allocate() {
+    let (width, height) = (24, 16);
+    let source = checkerboard(width, height);
+    let config = xbrz::ScalerConfig::default();
+    let mut arena = xbrz::ScaleArena::new();
+    let mut destinations: Vec<Vec<u8>> = (1..=6)
+        .map(|factor| vec![0u8; width * height * factor * factor * 4])
+        .collect();
+    let mut narrow_destination = vec![0u8; width / 2 * 2 * 4 * 2 * 4];
+
+    // first call initialises the lookup table and grows the arena
+    xbrz::scale_rgba_into(
+        &source,
+        width,
+        height,
+        2,
+        &config,
+        &mut destinations[1],
+        &mut arena,
+    );
+
+    let before = allocations();
+    for (i, destination) in destinations.iter_mut().enumerate() {
+        xbrz::scale_rgba_into(
+            &source,
+            width,
+            height,
+            i + 1,
+            &config,
+            destination,
+            &mut arena,
+        );
+    }
+    // narrower images reuse the existing buffer
+    xbrz::scale_rgba_into(
+        &source[..width * 4 * 2],
+        width / 2,
+        4,
+        2,
+        &config,
+        &mut narrow_destination,
+        &mut arena,
+    );
+    let after = allocations();
+    assert_eq!(0, after - before);
+
+    for (i, destination) in destinations.iter().enumerate() {
+        assert_eq!(
+            &xbrz::scale_rgba(&source, width, height, i + 1),
+            destination
+        );
+    }
+}
+
+#[test]
+fn sprites_allocate_only_their_output() {
+    let source = checkerboard(16, 16);
+    let config = xbrz::ScalerConfig::default();
+    let mut arena = xbrz::ScaleArena::new();
+    let mut destination = vec![0u8; 16 * 16 * 3 * 3 * 4];
+    // first call initialises the lookup table
+    xbrz::scale_rgba(&source, 16, 16, 3);
+
+    // a fresh arena is enough for images this narrow, and they aren't handed to other threads
+    let before = allocations();
+    xbrz::scale_rgba_into(&source, 16, 16, 3, &config, &mut destination, &mut arena);
+    let scaled = xbrz::scale_rgba_with_config(&source, 16, 16, 3, &config);
+    let after = allocations();
+    assert_eq!(1, after - before);
+    assert_eq!(scaled, destination);
+}
@@ -0,0 +1,201 @@
+//! Scale pixel art assets with xBRZ at build time, from a Cargo build script.
+//!
+//! Add this crate to `[build-dependencies]`, then scale a directory of sprites into `OUT_DIR`
+//! from `build.rs`:
+//!
+//! ```no_run
+//! let out_dir = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("sprites");
+//! xbrz_build::scale_dir("assets/sprites", out_dir, 4).unwrap();
+//! ```
+//!
+//! The scaled sprites can then be embedded with
+//! `include_bytes!(concat!(env!("OUT_DIR"), "/sprites/player.png"))`.
+//!
+//! Only sprites that have changed since they were last scaled are scaled again, and Cargo is told
+//! to rerun the build script when anything in the source directory changes.
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use image::{ImageError, ImageFormat};
+use xbrz::ScalerConfig;
+
+/// Records the factor and configuration the scaled sprites were made with, so that changing
+/// either scales them all again even though the sprites themselves haven't changed.
+const STAMP_FILE: &str = ".xbrz-build";
+
+/// The error returned when the sprites can't be scaled.
+#[derive(Debug)]
+pub enum BuildError {
+    Io(io::Error),
+    /// A sprite couldn't be read or written.
+    Image {
+        path: PathBuf,
+        error: ImageError,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Io(e) => write!(f, "{e}"),
+            BuildError::Image { path, error } => write!(f, "{}: {error}", path.display()),
+        }
+    }
+}
+
+impl Error for BuildError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BuildError::Io(e) => Some(e),
+            BuildError::Image { error, .. } => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for BuildError {
+    fn from(e: io::Error) -> Self {
+        BuildError::Io(e)
+    }
+}
+
+/// Scale every PNG file in `src_dir` and its subdirectories by `factor`, saving each at the same path
+/// under `dst_dir`, and return the paths of all of the scaled files.
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_dir(
+    src_dir: impl AsRef<Path>,
+    dst_dir: impl AsRef<Path>,
+    factor: usize,
+) -> Result<Vec<PathBuf>, BuildError> {
+    scale_dir_with_config(src_dir, dst_dir, factor, &ScalerConfig::default())
+}
+
+/// Scale every PNG file in `src_dir` and its subdirectories by `factor`, with custom tuning
+/// parameters, saving each at the same path under `dst_dir`.
+///
+/// This is the same as [`scale_dir`], except that the algorithm is configured by `config`
+/// instead of using [`ScalerConfig::default()`].
+///
+/// # Panics
+///
+/// Panics if `factor` is not one of 1, 2, 3, 4, 5 or 6.
+pub fn scale_dir_with_config(
+    src_dir: impl AsRef<Path>,
+    dst_dir: impl AsRef<Path>,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Result<Vec<PathBuf>, BuildError> {
+    let (src_dir, dst_dir) = (src_dir.as_ref(), dst_dir.as_ref());
+    assert!(
+        xbrz::ScaleFactor::new(factor).is_some(),
+        "scale factor must be between 1 and 6"
+    );
+    println!("cargo:rerun-if-changed={}", src_dir.display());
+
+    fs::create_dir_all(dst_dir)?;
+    let stamp_path = dst_dir.join(STAMP_FILE);
+    let stamp = format!("{factor} {config:?}");
+    let stamp_matches = fs::read_to_string(&stamp_path).is_ok_and(|s| s == stamp);
+
+    let mut outputs = Vec::new();
+    for sprite in sprites(src_dir)? {
+        let relative = sprite
+            .strip_prefix(src_dir)
+            .expect("sprites are in src_dir");
+        let output = dst_dir.join(relative);
+        if !stamp_matches || modified(&output) < modified(&sprite) {
+            scale_file(&sprite, &output, factor, config)?;
+        }
+        outputs.push(output);
+    }
+    fs::write(stamp_path, stamp)?;
+    Ok(outputs)
+}
+
+/// Every PNG file in `dir` and its subdirectories.
+fn sprites(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut sprites = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if ImageFormat::from_path(&path).is_ok_and(|f| f == ImageFormat::Png) {
+                sprites.push(path);
+            }
+        }
+    }
+    sprites.sort();
+    Ok(sprites)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn scale_file(
+    input: &Path,
+    output: &Path,
+    factor: usize,
+    config: &ScalerConfig,
+) -> Result<(), BuildError> {
+    let image_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| BuildError::Image { path, error }
+    };
+    let image = image::open(input).map_err(image_error(input))?.into_rgba8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let scaled = xbrz::scale_rgba_with_config(&image, width, height, factor, config);
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    image::save_buffer(
+        output,
+        &scaled,
+        (width * factor) as u32,
+        (height * factor) as u32,
+        image::ExtendedColorType::Rgba8,
+    )
+    .map_err(image_error(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_changed_sprites_are_scaled_again() {
+        let root = std::env::temp_dir().join(format!("xbrz-build-test-{}", std::process::id()));
+        let (src, dst) = (root.join("src"), root.join("dst"));
+        fs::create_dir_all(src.join("sub")).unwrap();
+        let sprite = image::RgbaImage::from_fn(4, 3, |x, y| {
+            image::Rgba([x as u8 * 60, y as u8 * 80, 0, 255])
+        });
+        sprite.save(src.join("a.png")).unwrap();
+        sprite.save(src.join("sub/b.png")).unwrap();
+        fs::write(src.join("notes.txt"), "not a sprite").unwrap();
+
+        let outputs = scale_dir(&src, &dst, 3).unwrap();
+        assert_eq!(outputs, [dst.join("a.png"), dst.join("sub/b.png")]);
+        assert_eq!(image::open(&outputs[0]).unwrap().width(), 12);
+
+        // unchanged sprites are left alone, but a new factor scales them all again
+        let scaled_at = modified(&outputs[1]);
+        fs::remove_file(&outputs[0]).unwrap();
+        scale_dir(&src, &dst, 3).unwrap();
+        assert!(outputs[0].exists());
+        assert_eq!(modified(&outputs[1]), scaled_at);
+        scale_dir(&src, &dst, 2).unwrap();
+        assert_eq!(image::open(&outputs[1]).unwrap().width(), 8);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}